@@ -28,6 +28,7 @@ fn benchmark_model_registry(c: &mut Criterion) {
                 template: Some("chatml".to_string()),
                 ctx_len: Some(black_box(4096)),
                 n_threads: Some(black_box(4)),
+                tags: Vec::new(),
             };
             registry.register(black_box(entry));
         })
@@ -42,6 +43,7 @@ fn benchmark_model_registry(c: &mut Criterion) {
             template: Some("chatml".to_string()),
             ctx_len: Some(4096),
             n_threads: Some(4),
+            tags: Vec::new(),
         };
         registry.register(entry);
     }
@@ -4,10 +4,19 @@
 /// allowing tools like Claude Code to work with shimmy in local networks.
 ///
 /// Reference: https://docs.claude.com/claude/reference/messages_post
+use crate::engine::{FinishReason, GenOptions, LoadedModel};
 use crate::{api::ChatMessage, AppState};
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{sse::Event, IntoResponse},
+    Json,
+};
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::sync::Arc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 use uuid::Uuid;
 
 /// Anthropic Messages API request format
@@ -26,6 +35,9 @@ pub struct AnthropicMessageRequest {
     pub top_k: Option<i32>,
     #[serde(default)]
     pub stream: Option<bool>,
+    /// Custom sequences that end generation with `stop_reason: "stop_sequence"`
+    #[serde(default)]
+    pub stop_sequences: Option<Vec<String>>,
 }
 
 /// Anthropic message format - supports complex content blocks
@@ -115,6 +127,7 @@ impl From<AnthropicMessage> for ChatMessage {
         ChatMessage {
             role: msg.role,
             content,
+            images: Vec::new(),
         }
     }
 }
@@ -122,8 +135,26 @@ impl From<AnthropicMessage> for ChatMessage {
 /// Anthropic Messages API endpoint: POST /v1/messages
 pub async fn messages(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<AnthropicMessageRequest>,
+    headers: HeaderMap,
+    Json(mut req): Json<AnthropicMessageRequest>,
 ) -> impl IntoResponse {
+    let client_id = crate::audit::client_id(&headers);
+    let Some(model_name) = state
+        .registry
+        .resolve_model_name(&req.model, state.server_config.default_model.as_deref())
+    else {
+        tracing::error!("No model matches '{}'", req.model);
+        let error_response = serde_json::json!({
+            "type": "error",
+            "error": {
+                "type": "not_found_error",
+                "message": format!("No available model matches '{}'", req.model)
+            }
+        });
+        return (axum::http::StatusCode::NOT_FOUND, Json(error_response)).into_response();
+    };
+    req.model = model_name;
+
     // Convert Anthropic format to our internal format
     let internal_messages: Vec<ChatMessage> =
         req.messages.into_iter().map(|msg| msg.into()).collect();
@@ -131,7 +162,14 @@ pub async fn messages(
     // Find the model
     let Some(spec) = state.registry.to_spec(&req.model) else {
         tracing::error!("Model '{}' not found in registry", req.model);
-        return axum::http::StatusCode::NOT_FOUND.into_response();
+        let error_response = serde_json::json!({
+            "type": "error",
+            "error": {
+                "type": "not_found_error",
+                "message": state.registry.model_not_found_message(&req.model)
+            }
+        });
+        return (axum::http::StatusCode::NOT_FOUND, Json(error_response)).into_response();
     };
 
     // Extract system message if present
@@ -153,6 +191,14 @@ pub async fn messages(
     if let Some(k) = req.top_k {
         options.top_k = k;
     }
+    if let Err(e) = options.clamp_sampling(state.server_config.strict_sampling) {
+        return engine_error_response(&e.into());
+    }
+    options
+        .stop_tokens
+        .extend(state.registry.stop_tokens(&req.model));
+    let stop_sequences = req.stop_sequences.take().unwrap_or_default();
+    options.stop_tokens.extend(stop_sequences.iter().cloned());
 
     // Prepare the prompt using the same logic as OpenAI compatibility
     let (system_prompt, conversation_pairs) =
@@ -173,14 +219,86 @@ pub async fn messages(
         }
     }
 
+    if let Err(message) = state.server_config.precheck_prompt_length(&prompt) {
+        tracing::warn!("Rejecting request for '{}': {}", req.model, message);
+        let error_response = serde_json::json!({
+            "type": "error",
+            "error": {
+                "type": "invalid_request_error",
+                "message": message
+            }
+        });
+        return (axum::http::StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+    }
+
     // Load the model and generate response
-    let Ok(loaded_model) = state.engine.load(&spec).await else {
-        tracing::error!("Failed to load model '{}'", req.model);
-        return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    let loaded_model = match state
+        .server_config
+        .retry_transient("Model load", || {
+            state.model_pool.get_or_load(&*state.engine, &spec)
+        })
+        .await
+    {
+        Ok(loaded_model) => loaded_model,
+        Err(e) => {
+            tracing::error!("Failed to load model '{}': {}", req.model, e);
+            return engine_error_response(&e);
+        }
     };
+    let prompt_tokens = loaded_model.count_tokens(&prompt);
+    if let Err(message) = state.server_config.check_prompt_length(prompt_tokens) {
+        tracing::warn!("Rejecting request for '{}': {}", req.model, message);
+        state.model_pool.release(&req.model).await;
+        let error_response = serde_json::json!({
+            "type": "error",
+            "error": {
+                "type": "invalid_request_error",
+                "message": message
+            }
+        });
+        return (axum::http::StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+    }
+    options.max_tokens =
+        crate::engine::resolve_max_tokens(Some(req.max_tokens), spec.ctx_len, prompt_tokens);
+
+    if options.stream {
+        return stream_message(
+            &state,
+            loaded_model,
+            req.model,
+            prompt,
+            options,
+            stop_sequences,
+            client_id,
+        );
+    }
 
-    match loaded_model.generate(&prompt, options, None).await {
-        Ok(response) => {
+    let stop_tokens = options.stop_tokens.clone();
+    let cancel = options
+        .cancel
+        .guard(state.observability.cancel_recorder(&req.model));
+    let result = loaded_model
+        .generate_with_reason(&prompt, options, None)
+        .await;
+    cancel.disarm();
+    state.model_pool.release(&req.model).await;
+    let (response, status) = match &result {
+        Ok((response, _)) => (response.as_str(), 200),
+        Err(e) => ("", crate::api_errors::status_for(e).as_u16()),
+    };
+    if let Some(audit) = &state.audit_logger {
+        audit.record(&req.model, &client_id, &prompt, response, status, |t| {
+            loaded_model.count_tokens(t)
+        });
+    }
+    state
+        .server_config
+        .prompt_log
+        .record(&req.model, &prompt, response, status);
+    match result {
+        Ok((response, finish_reason)) => {
+            let (stop_reason, stop_sequence) =
+                stop_reason(finish_reason, &stop_tokens, &stop_sequences);
             let anthropic_response = AnthropicMessageResponse {
                 id: format!("msg_{}", Uuid::new_v4()),
                 response_type: "message".to_string(),
@@ -190,11 +308,11 @@ pub async fn messages(
                     text: response.clone(),
                 }],
                 model: req.model,
-                stop_reason: "end_turn".to_string(),
-                stop_sequence: None,
+                stop_reason: stop_reason.to_string(),
+                stop_sequence,
                 usage: AnthropicUsage {
-                    input_tokens: estimate_tokens(&prompt),
-                    output_tokens: estimate_tokens(&response),
+                    input_tokens: loaded_model.count_tokens(&prompt),
+                    output_tokens: loaded_model.count_tokens(&response),
                 },
             };
 
@@ -202,11 +320,177 @@ pub async fn messages(
         }
         Err(e) => {
             tracing::error!("Generation failed: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            engine_error_response(&e)
         }
     }
 }
 
+/// Anthropic `stop_reason` and `stop_sequence` for a finished generation.
+/// `stop_tokens` are the `GenOptions::stop_tokens` the engine matched
+/// against; only a match on one of the client's `stop_sequences` counts as
+/// `stop_sequence`, while template and model stop tokens end the turn.
+fn stop_reason(
+    reason: FinishReason,
+    stop_tokens: &[String],
+    stop_sequences: &[String],
+) -> (&'static str, Option<String>) {
+    match reason {
+        FinishReason::Length => ("max_tokens", None),
+        FinishReason::StopSequence(matched) => match stop_tokens
+            .get(matched)
+            .filter(|stop| stop_sequences.contains(stop))
+        {
+            Some(stop) => ("stop_sequence", Some(stop.clone())),
+            None => ("end_turn", None),
+        },
+        FinishReason::Stop | FinishReason::Repetition => ("end_turn", None),
+    }
+}
+
+fn sse_event(event_type: &str, data: serde_json::Value) -> Event {
+    Event::default().event(event_type).data(data.to_string())
+}
+
+/// Stream a reply as Anthropic server-sent events: `message_start`,
+/// `content_block_start`, `ping`, a `content_block_delta` per token,
+/// `content_block_stop`, `message_delta` (stop reason and output token count)
+/// and `message_stop`
+fn stream_message(
+    state: &AppState,
+    loaded: Arc<dyn LoadedModel>,
+    model: String,
+    prompt: String,
+    options: GenOptions,
+    stop_sequences: Vec<String>,
+    client_id: String,
+) -> axum::response::Response {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Event>();
+    let id = format!("msg_{}", Uuid::new_v4());
+    let input_tokens = loaded.count_tokens(&prompt);
+    let audit = state.audit_logger.clone();
+    let prompt_log = state.server_config.prompt_log.clone();
+    let model_pool = state.model_pool.clone();
+    let observability = state.observability.clone();
+    let cancel = options.cancel.clone();
+    let stop_tokens = options.stop_tokens.clone();
+
+    tokio::spawn(async move {
+        let _ = tx.send(sse_event(
+            "message_start",
+            json!({
+                "type": "message_start",
+                "message": {
+                    "id": id,
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": model,
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": {"input_tokens": input_tokens, "output_tokens": 0}
+                }
+            }),
+        ));
+        let _ = tx.send(sse_event(
+            "content_block_start",
+            json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": {"type": "text", "text": ""}
+            }),
+        ));
+        let _ = tx.send(sse_event("ping", json!({"type": "ping"})));
+
+        let tx_tokens = tx.clone();
+        let on_token = {
+            let cancel = cancel.clone();
+            move |token| {
+                let delta = sse_event(
+                    "content_block_delta",
+                    json!({
+                        "type": "content_block_delta",
+                        "index": 0,
+                        "delta": {"type": "text_delta", "text": token}
+                    }),
+                );
+                cancel.send_or_cancel(&tx_tokens, delta);
+            }
+        };
+        let result = loaded
+            .generate_with_reason(&prompt, options, Some(Box::new(on_token)))
+            .await;
+        model_pool.release(&model).await;
+        if cancel.is_cancelled() {
+            observability.record_cancelled(&model).await;
+        }
+
+        let (response, status) = match &result {
+            Ok((text, _)) => (text.as_str(), 200),
+            Err(e) => ("", crate::api_errors::status_for(e).as_u16()),
+        };
+        if let Some(audit) = &audit {
+            audit.record(&model, &client_id, &prompt, response, status, |t| {
+                loaded.count_tokens(t)
+            });
+        }
+        prompt_log.record(&model, &prompt, response, status);
+        match result {
+            Ok((text, finish_reason)) => {
+                let (stop_reason, stop_sequence) =
+                    stop_reason(finish_reason, &stop_tokens, &stop_sequences);
+                let _ = tx.send(sse_event(
+                    "content_block_stop",
+                    json!({"type": "content_block_stop", "index": 0}),
+                ));
+                let _ = tx.send(sse_event(
+                    "message_delta",
+                    json!({
+                        "type": "message_delta",
+                        "delta": {"stop_reason": stop_reason, "stop_sequence": stop_sequence},
+                        "usage": {"output_tokens": loaded.count_tokens(&text)}
+                    }),
+                ));
+                let _ = tx.send(sse_event("message_stop", json!({"type": "message_stop"})));
+            }
+            Err(e) => {
+                tracing::error!("Generation failed: {}", e);
+                let _ = tx.send(sse_event(
+                    "error",
+                    json!({
+                        "type": "error",
+                        "error": {"type": "api_error", "message": e.to_string()}
+                    }),
+                ));
+            }
+        }
+    });
+
+    let stream = UnboundedReceiverStream::new(rx).map(Ok::<Event, std::convert::Infallible>);
+    state.server_config.sse_response(stream)
+}
+
+/// Map an engine failure to an Anthropic-style error; untyped failures are 500s
+fn engine_error_response(err: &anyhow::Error) -> axum::response::Response {
+    use axum::http::StatusCode;
+
+    let status = crate::engine::EngineError::find(err)
+        .map(crate::api_errors::engine_status)
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let error_type = match status {
+        StatusCode::BAD_REQUEST => "invalid_request_error",
+        StatusCode::NOT_FOUND => "not_found_error",
+        _ => "api_error",
+    };
+    let error_response = serde_json::json!({
+        "type": "error",
+        "error": {
+            "type": error_type,
+            "message": err.to_string()
+        }
+    });
+    (status, Json(error_response)).into_response()
+}
+
 /// Extract system message and conversation pairs from messages
 /// This mimics the logic from openai_compat.rs but adapted for Anthropic format
 fn extract_system_and_pairs(
@@ -255,12 +539,6 @@ fn extract_system_and_pairs(
     (system_message, pairs)
 }
 
-/// Simple token estimation (rough approximation)
-fn estimate_tokens(text: &str) -> usize {
-    // Very rough estimate: ~1 token per 4 characters
-    (text.len() as f32 / 4.0).ceil() as usize
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -306,18 +584,22 @@ mod tests {
             ChatMessage {
                 role: "system".to_string(),
                 content: "You are a helpful assistant".to_string(),
+                images: Vec::new(),
             },
             ChatMessage {
                 role: "user".to_string(),
                 content: "Hello".to_string(),
+                images: Vec::new(),
             },
             ChatMessage {
                 role: "assistant".to_string(),
                 content: "Hi there!".to_string(),
+                images: Vec::new(),
             },
             ChatMessage {
                 role: "user".to_string(),
                 content: "How are you?".to_string(),
+                images: Vec::new(),
             },
         ];
 
@@ -334,6 +616,7 @@ mod tests {
         let messages = vec![ChatMessage {
             role: "user".to_string(),
             content: "Hello".to_string(),
+            images: Vec::new(),
         }];
 
         let (system, pairs) =
@@ -344,8 +627,117 @@ mod tests {
         assert_eq!(pairs[0], ("Hello", None));
     }
 
+    #[test]
+    fn test_stop_reason_mapping() {
+        // Template stop first, then the client's sequences
+        let stop_tokens = vec![
+            "<|im_end|>".to_string(),
+            "END".to_string(),
+            "STOP".to_string(),
+        ];
+        let custom = vec!["END".to_string(), "STOP".to_string()];
+        assert_eq!(
+            stop_reason(FinishReason::Length, &stop_tokens, &custom),
+            ("max_tokens", None)
+        );
+        assert_eq!(
+            stop_reason(FinishReason::Stop, &stop_tokens, &custom),
+            ("end_turn", None)
+        );
+        // The sequence that actually matched is reported
+        assert_eq!(
+            stop_reason(FinishReason::StopSequence(2), &stop_tokens, &custom),
+            ("stop_sequence", Some("STOP".to_string()))
+        );
+        assert_eq!(
+            stop_reason(FinishReason::StopSequence(1), &stop_tokens, &custom),
+            ("stop_sequence", Some("END".to_string()))
+        );
+        // A template or model-override stop token is a normal end of turn
+        assert_eq!(
+            stop_reason(FinishReason::StopSequence(0), &stop_tokens, &custom),
+            ("end_turn", None)
+        );
+        assert_eq!(
+            stop_reason(FinishReason::StopSequence(0), &stop_tokens, &[]),
+            ("end_turn", None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_messages_releases_model() {
+        use crate::engine::mock::{MockEngine, Reply};
+        use crate::model_manager::KeepAlive;
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Prompt)),
+            registry,
+        ));
+        // keep_alive 0, as set by an earlier /api/generate request
+        state
+            .model_pool
+            .set_keep_alive("echo", KeepAlive::from_secs(0))
+            .await;
+
+        for stream in [false, true] {
+            let request = serde_json::from_value(json!({
+                "model": "echo",
+                "max_tokens": 16,
+                "stream": stream,
+                "messages": [{"role": "user", "content": "hi"}]
+            }))
+            .unwrap();
+            let response = messages(State(state.clone()), HeaderMap::new(), Json(request))
+                .await
+                .into_response();
+            assert_eq!(response.status(), axum::http::StatusCode::OK);
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert!(!state.model_pool.is_loaded("echo").await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_messages_unmatched_auto_tag_is_not_found_error() {
+        use crate::engine::mock::{MockEngine, Reply};
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Prompt)),
+            registry,
+        ));
+
+        let request = serde_json::from_value(json!({
+            "model": "auto:vision",
+            "max_tokens": 16,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        let response = messages(State(state), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed["error"]["type"], "not_found_error");
+        assert_eq!(
+            parsed["error"]["message"],
+            "No available model matches 'auto:vision'"
+        );
+    }
+
     #[test]
     fn test_token_estimation() {
+        use crate::engine::estimate_tokens;
+
         assert_eq!(estimate_tokens(""), 0);
         assert_eq!(estimate_tokens("test"), 1); // 4 chars = 1 token
         assert_eq!(estimate_tokens("hello world"), 3); // 11 chars = 2.75 -> 3 tokens
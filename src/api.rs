@@ -1,7 +1,8 @@
 use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
 use axum::{
     extract::State,
-    response::{sse::Event, IntoResponse, Sse},
+    http::{header, HeaderMap, HeaderValue},
+    response::{sse::Event, IntoResponse},
     Json,
 };
 use futures_util::StreamExt;
@@ -9,10 +10,10 @@ use serde::{Deserialize, Serialize};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
 use crate::invariant_ppt::shimmy_invariants;
-use crate::{engine::GenOptions, templates::TemplateFamily, AppState};
+use crate::{engine::GenOptions, model_manager::KeepAlive, templates::TemplateFamily, AppState};
 use std::sync::Arc;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct GenerateRequest {
     pub model: String,
     pub prompt: Option<String>,             // raw mode
@@ -28,12 +29,45 @@ pub struct GenerateRequest {
     pub max_tokens: Option<usize>,
     #[serde(default)]
     pub stream: Option<bool>,
+    /// Response cache TTL for this request; 0 bypasses the cache (no lookup, no store)
+    #[serde(default)]
+    pub cache_ttl_secs: Option<u64>,
+    /// Strip a leading space/BOS token from the output (default true)
+    #[serde(default)]
+    pub trim_leading: Option<bool>,
+    /// Sampler chain order, e.g. ["top_k", "temperature"] (llama.cpp models)
+    #[serde(default)]
+    pub samplers: Option<Vec<String>>,
+    /// Stop when output loops on a repeated n-gram (llama.cpp models)
+    #[serde(default)]
+    pub stop_on_repeat: Option<crate::engine::RepeatStop>,
+    /// Report the applied sampling settings in `X-Shimmy-Params`
+    /// (default: the server's `--echo-params`)
+    #[serde(default)]
+    pub include_sampling_params: Option<bool>,
+    /// Return the prompt followed by the completion, like the legacy
+    /// completions `echo`
+    #[serde(default)]
+    pub echo: Option<bool>,
+    /// Seconds the model stays loaded once idle, for this and later requests;
+    /// -1 keeps it loaded, 0 unloads it when the request finishes (Ollama's
+    /// `keep_alive`)
+    #[serde(default)]
+    pub keep_alive: Option<i64>,
+    /// Keep generating past the context window by dropping the oldest
+    /// tokens (llama.cpp models); default stops at the window with `length`
+    #[serde(default)]
+    pub context_shift: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(from = "crate::openai_compat::RawChatMessage")]
 pub struct ChatMessage {
     pub role: String,
     pub content: String,
+    /// Image URLs (data: or http(s)) from OpenAI-style content arrays
+    #[serde(skip_serializing)]
+    pub images: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -43,14 +77,72 @@ pub struct GenerateResponse {
 
 pub async fn generate(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<GenerateRequest>,
+    headers: HeaderMap,
+    Json(mut req): Json<GenerateRequest>,
 ) -> impl IntoResponse {
+    let client_id = crate::audit::client_id(&headers);
+    let Some(model_name) = state
+        .registry
+        .resolve_model_name(&req.model, state.server_config.default_model.as_deref())
+    else {
+        tracing::error!("No model matches '{}'", req.model);
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(crate::api_errors::ErrorResponse {
+                error: format!("No available model matches '{}'", req.model),
+            }),
+        )
+            .into_response();
+    };
+    req.model = model_name;
     let Some(spec) = state.registry.to_spec(&req.model) else {
         tracing::error!("Model '{}' not found in registry", req.model);
-        return axum::http::StatusCode::NOT_FOUND.into_response();
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(crate::api_errors::ErrorResponse {
+                error: state.registry.model_not_found_message(&req.model),
+            }),
+        )
+            .into_response();
     };
-    let engine = &state.engine;
-    let loaded = match engine.load(&spec).await {
+    // Construct prompt
+    let prompt = if let Some(ms) = &req.messages {
+        let fam = spec
+            .template
+            .as_deref()
+            .and_then(TemplateFamily::from_name)
+            .unwrap_or(TemplateFamily::OpenChat);
+        let pairs = state.server_config.prepare_messages(
+            ms.iter()
+                .map(|m| (m.role.clone(), m.content.clone()))
+                .collect::<Vec<_>>(),
+        );
+        fam.render(req.system.as_deref(), &pairs, None)
+    } else {
+        req.prompt.unwrap_or_default()
+    };
+    if let Err(message) = state.server_config.precheck_prompt_length(&prompt) {
+        tracing::warn!("Rejecting request for '{}': {}", req.model, message);
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(crate::api_errors::ErrorResponse { error: message }),
+        )
+            .into_response();
+    }
+
+    if let Some(secs) = req.keep_alive {
+        state
+            .model_pool
+            .set_keep_alive(&req.model, KeepAlive::from_secs(secs))
+            .await;
+    }
+    let loaded = match state
+        .server_config
+        .retry_transient("Model load", || {
+            state.model_pool.get_or_load(&*state.engine, &spec)
+        })
+        .await
+    {
         Ok(loaded) => loaded,
         Err(e) => {
             tracing::error!(
@@ -58,26 +150,16 @@ pub async fn generate(
                 req.model,
                 e
             );
-            return axum::http::StatusCode::BAD_GATEWAY.into_response();
+            return (
+                crate::api_errors::status_for(&e),
+                Json(crate::api_errors::ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+                .into_response();
         }
     };
 
-    // Construct prompt
-    let prompt = if let Some(ms) = &req.messages {
-        let fam = match spec.template.as_deref() {
-            Some("chatml") => TemplateFamily::ChatML,
-            Some("llama3") | Some("llama-3") => TemplateFamily::Llama3,
-            _ => TemplateFamily::OpenChat,
-        };
-        let pairs = ms
-            .iter()
-            .map(|m| (m.role.clone(), m.content.clone()))
-            .collect::<Vec<_>>();
-        fam.render(req.system.as_deref(), &pairs, None)
-    } else {
-        req.prompt.unwrap_or_default()
-    };
-
     let mut opts = GenOptions::default();
     if let Some(t) = req.temperature {
         opts.temperature = t;
@@ -88,12 +170,55 @@ pub async fn generate(
     if let Some(k) = req.top_k {
         opts.top_k = k;
     }
-    if let Some(m) = req.max_tokens {
-        opts.max_tokens = m;
+    let prompt_tokens = loaded.count_tokens(&prompt);
+
+    if let Err(message) = state.server_config.check_prompt_length(prompt_tokens) {
+        tracing::warn!("Rejecting request for '{}': {}", req.model, message);
+        state.model_pool.release(&req.model).await;
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(crate::api_errors::ErrorResponse { error: message }),
+        )
+            .into_response();
     }
+    let usage = context_usage_headers(&req.model, prompt_tokens, spec.ctx_len);
+    opts.context_shift = req.context_shift.unwrap_or(false);
+    opts.max_tokens = crate::engine::resolve_generation_budget(
+        req.max_tokens,
+        spec.ctx_len,
+        prompt_tokens,
+        opts.context_shift,
+    );
     if let Some(s) = req.stream {
         opts.stream = s;
     }
+    if let Some(trim) = req.trim_leading {
+        opts.trim_leading = trim;
+    }
+    if let Some(samplers) = req.samplers.take() {
+        opts.samplers = samplers;
+    }
+    opts.stop_on_repeat = req.stop_on_repeat;
+    if let Err(e) = crate::engine::validate_samplers(&opts.samplers)
+        .and_then(|()| opts.clamp_sampling(state.server_config.strict_sampling))
+    {
+        state.model_pool.release(&req.model).await;
+        return (
+            crate::api_errors::engine_status(&e),
+            Json(crate::api_errors::ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+            .into_response();
+    }
+    opts.stop_tokens
+        .extend(state.registry.stop_tokens(&req.model));
+    let echo = req.echo.unwrap_or(false);
+    let params = sampling_params_headers(
+        &opts,
+        req.include_sampling_params
+            .unwrap_or(state.server_config.echo_params),
+    );
 
     if opts.stream {
         // SSE streaming
@@ -101,30 +226,133 @@ pub async fn generate(
         let mut opts_clone = opts.clone();
         opts_clone.stream = false; // internal generation collects tokens while we push per token
         let prompt_clone = prompt.clone();
+        let audit = state.audit_logger.clone();
+        let prompt_log = state.server_config.prompt_log.clone();
+        let model_pool = state.model_pool.clone();
+        let observability = state.observability.clone();
+        let model_name = req.model.clone();
         tokio::spawn(async move {
+            if echo {
+                let _ = tx.send(prompt_clone.clone());
+            }
             let tx_tokens = tx.clone();
-            let _ = loaded
-                .generate(
-                    &prompt_clone,
-                    opts_clone,
-                    Some(Box::new(move |tok| {
-                        let _ = tx_tokens.send(tok);
-                    })),
-                )
+            let cancel = opts_clone.cancel.clone();
+            let on_token = {
+                let cancel = cancel.clone();
+                move |tok| cancel.send_or_cancel(&tx_tokens, tok)
+            };
+            let result = loaded
+                .generate(&prompt_clone, opts_clone, Some(Box::new(on_token)))
                 .await;
+            model_pool.release(&model_name).await;
+            if cancel.is_cancelled() {
+                observability.record_cancelled(&model_name).await;
+            }
+            let (response, status) = match &result {
+                Ok(full) => (full.as_str(), 200),
+                Err(e) => ("", crate::api_errors::status_for(e).as_u16()),
+            };
+            if let Some(audit) = &audit {
+                audit.record(
+                    &model_name,
+                    &client_id,
+                    &prompt_clone,
+                    response,
+                    status,
+                    |t| loaded.count_tokens(t),
+                );
+            }
+            prompt_log.record(&model_name, &prompt_clone, response, status);
             let _ = tx.send("[DONE]".into());
         });
         let stream = UnboundedReceiverStream::new(rx)
             .map(|s| Ok::<Event, std::convert::Infallible>(Event::default().data(s)));
-        Sse::new(stream).into_response()
+        (usage, params, state.server_config.sse_response(stream)).into_response()
     } else {
-        match loaded.generate(&prompt, opts, None).await {
+        let cache_key = is_cacheable(&opts, req.cache_ttl_secs).then(|| {
+            crate::cache::response_cache::CacheKey::new(
+                &prompt,
+                &req.model,
+                opts.max_tokens,
+                opts.temperature,
+                opts.top_p,
+                &opts.stop_tokens,
+            )
+            .with_trim_leading(opts.trim_leading)
+            .with_stop_on_repeat(opts.stop_on_repeat)
+            .with_context_shift(opts.context_shift)
+        });
+        let max_age = req
+            .cache_ttl_secs
+            .map(std::time::Duration::from_secs)
+            .unwrap_or_else(|| state.response_cache.default_ttl());
+        if let Some(key) = &cache_key {
+            if let Some(cached) = state.response_cache.get(key).await {
+                if let Some(audit) = &state.audit_logger {
+                    audit.record(&req.model, &client_id, &prompt, &cached, 200, |t| {
+                        loaded.count_tokens(t)
+                    });
+                }
+                state
+                    .server_config
+                    .prompt_log
+                    .record(&req.model, &prompt, &cached, 200);
+                state.model_pool.release(&req.model).await;
+                return (
+                    cache_headers(&opts, max_age, Some(true)),
+                    usage,
+                    params,
+                    Json(GenerateResponse {
+                        response: echo_prompt(&prompt, cached, echo),
+                    }),
+                )
+                    .into_response();
+            }
+        }
+        let headers = cache_headers(&opts, max_age, cache_key.as_ref().map(|_| false));
+
+        let started = std::time::Instant::now();
+        let cancel = opts
+            .cancel
+            .guard(state.observability.cancel_recorder(&req.model));
+        let result = loaded.generate(&prompt, opts, None).await;
+        cancel.disarm();
+        state.model_pool.release(&req.model).await;
+        match result {
             Ok(full) => {
                 tracing::debug!(
                     "Generation completed successfully for model '{}'",
                     req.model
                 );
-                Json(GenerateResponse { response: full }).into_response()
+                if let Some(audit) = &state.audit_logger {
+                    audit.record(&req.model, &client_id, &prompt, &full, 200, |t| {
+                        loaded.count_tokens(t)
+                    });
+                }
+                state
+                    .server_config
+                    .prompt_log
+                    .record(&req.model, &prompt, &full, 200);
+                if let Some(key) = cache_key {
+                    state
+                        .response_cache
+                        .put_with_ttl(
+                            key,
+                            full.clone(),
+                            started.elapsed(),
+                            req.cache_ttl_secs.map(std::time::Duration::from_secs),
+                        )
+                        .await;
+                }
+                (
+                    headers,
+                    usage,
+                    params,
+                    Json(GenerateResponse {
+                        response: echo_prompt(&prompt, full, echo),
+                    }),
+                )
+                    .into_response()
             }
             Err(e) => {
                 tracing::error!(
@@ -132,22 +360,255 @@ pub async fn generate(
                     req.model,
                     e
                 );
-                axum::http::StatusCode::BAD_GATEWAY.into_response()
+                let status = crate::api_errors::status_for(&e);
+                if let Some(audit) = &state.audit_logger {
+                    audit.record(&req.model, &client_id, &prompt, "", status.as_u16(), |t| {
+                        loaded.count_tokens(t)
+                    });
+                }
+                state
+                    .server_config
+                    .prompt_log
+                    .record(&req.model, &prompt, "", status.as_u16());
+                (
+                    status,
+                    Json(crate::api_errors::ErrorResponse {
+                        error: e.to_string(),
+                    }),
+                )
+                    .into_response()
             }
         }
     }
 }
 
+/// Upper bound on batch items generated at once
+const BATCH_CONCURRENCY: usize = 4;
+
+/// One `/api/batch` item outcome; exactly one of `response` and `error` is set
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchItemResult {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Status the item would have had as its own `/api/generate` call
+    pub status: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub results: Vec<BatchItemResult>,
+}
+
+/// Split a batch body into generate requests: a JSON array, or one request
+/// per line (JSONL). Items that don't parse become per-item errors.
+fn parse_batch(body: &str) -> Result<Vec<Result<GenerateRequest, String>>, String> {
+    let parse_item = |value: Result<serde_json::Value, serde_json::Error>| {
+        value
+            .and_then(serde_json::from_value)
+            .map_err(|e| format!("invalid request: {}", e))
+    };
+    let trimmed = body.trim_start();
+    if trimmed.starts_with('[') {
+        let items: Vec<serde_json::Value> = serde_json::from_str(trimmed)
+            .map_err(|e| format!("invalid batch JSON array: {}", e))?;
+        return Ok(items.into_iter().map(|v| parse_item(Ok(v))).collect());
+    }
+    Ok(body
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| parse_item(serde_json::from_str(line)))
+        .collect())
+}
+
+/// Run many independent non-streaming generations in one call. Results come
+/// back in input order; a failing item doesn't fail the batch.
+pub async fn batch(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: String,
+) -> impl IntoResponse {
+    let items = match parse_batch(&body) {
+        Ok(items) if !items.is_empty() => items,
+        result => {
+            let error = result
+                .err()
+                .unwrap_or_else(|| "batch contains no requests".to_string());
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(crate::api_errors::ErrorResponse { error }),
+            )
+                .into_response();
+        }
+    };
+    if let Err(error) = state.server_config.check_batch_size(items.len()) {
+        tracing::warn!("Rejecting batch: {}", error);
+        return (
+            axum::http::StatusCode::PAYLOAD_TOO_LARGE,
+            Json(crate::api_errors::ErrorResponse { error }),
+        )
+            .into_response();
+    }
+
+    let results = futures_util::stream::iter(items.into_iter().enumerate())
+        .map(|(index, item)| run_batch_item(state.clone(), headers.clone(), index, item))
+        .buffered(BATCH_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    Json(BatchResponse { results }).into_response()
+}
+
+/// Generate one batch item through the regular `/api/generate` handler, so
+/// batch items get the same model resolution, caching and audit logging
+async fn run_batch_item(
+    state: Arc<AppState>,
+    headers: HeaderMap,
+    index: usize,
+    item: Result<GenerateRequest, String>,
+) -> BatchItemResult {
+    let failed = |status: u16, error: String| BatchItemResult {
+        index,
+        response: None,
+        error: Some(error),
+        status,
+    };
+    let mut req = match item {
+        Ok(req) => req,
+        Err(error) => return failed(400, error),
+    };
+    req.stream = Some(false);
+
+    let response = generate(State(state), headers, Json(req))
+        .await
+        .into_response();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap_or_default();
+    if status.is_success() {
+        match serde_json::from_slice::<GenerateResponse>(&body) {
+            Ok(parsed) => BatchItemResult {
+                index,
+                response: Some(parsed.response),
+                error: None,
+                status: status.as_u16(),
+            },
+            Err(e) => failed(500, format!("unreadable generate response: {}", e)),
+        }
+    } else {
+        let error = serde_json::from_slice::<crate::api_errors::ErrorResponse>(&body)
+            .map(|e| e.error)
+            .unwrap_or_else(|_| status.canonical_reason().unwrap_or("error").to_string());
+        failed(status.as_u16(), error)
+    }
+}
+
+/// The prompt followed by the completion when `echo` is set; the completion
+/// alone otherwise. Cached and audited text never includes the prompt.
+pub(crate) fn echo_prompt(prompt: &str, completion: String, echo: bool) -> String {
+    if echo {
+        format!("{}{}", prompt, completion)
+    } else {
+        completion
+    }
+}
+
+/// Only reproducible responses are cached: non-streaming greedy generations,
+/// unless the request opts out with `cache_ttl_secs: 0`
+fn is_cacheable(opts: &GenOptions, cache_ttl_secs: Option<u64>) -> bool {
+    !opts.stream && opts.temperature <= 0.0 && cache_ttl_secs != Some(0)
+}
+
+/// Share of the context window above which a prompt is worth flagging
+const CONTEXT_USAGE_WARN_RATIO: f64 = 0.9;
+
+/// `X-Context-Usage: used/total` for a prompt of `prompt_tokens` in a model
+/// with a `ctx_len` window, so clients can tell why replies come back short
+pub(crate) fn context_usage_headers(
+    model: &str,
+    prompt_tokens: usize,
+    ctx_len: usize,
+) -> HeaderMap {
+    if ctx_len > 0 && prompt_tokens as f64 > ctx_len as f64 * CONTEXT_USAGE_WARN_RATIO {
+        tracing::debug!(
+            "Prompt for '{}' uses {} of {} context tokens; little room is left for the reply",
+            model,
+            prompt_tokens,
+            ctx_len
+        );
+    }
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&format!("{}/{}", prompt_tokens, ctx_len)) {
+        headers.insert("x-context-usage", value);
+    }
+    headers
+}
+
+/// `X-Shimmy-Params` header with the effective sampling settings as JSON, when
+/// `enabled`
+pub(crate) fn sampling_params_headers(opts: &GenOptions, enabled: bool) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if !enabled {
+        return headers;
+    }
+    let report = crate::engine::SamplingReport::new(opts);
+    match serde_json::to_string(&report)
+        .ok()
+        .and_then(|json| HeaderValue::from_str(&json).ok())
+    {
+        Some(value) => {
+            headers.insert("x-shimmy-params", value);
+        }
+        None => tracing::debug!("Sampling params not representable as a header"),
+    }
+    headers
+}
+
+/// Greedy or seeded generations reproduce the same output for the same input
+pub(crate) fn is_deterministic(opts: &GenOptions) -> bool {
+    opts.temperature <= 0.0 || opts.seed.is_some()
+}
+
+/// Caching headers for a generation response. Only deterministic generations
+/// may be kept downstream (for `max_age`); `X-Cache` reports the response
+/// cache lookup and is omitted when the cache wasn't consulted.
+pub(crate) fn cache_headers(
+    opts: &GenOptions,
+    max_age: std::time::Duration,
+    cache_hit: Option<bool>,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let cache_control = if is_deterministic(opts) && !max_age.is_zero() {
+        format!("private, max-age={}", max_age.as_secs())
+    } else {
+        "no-store".to_string()
+    };
+    if let Ok(value) = HeaderValue::from_str(&cache_control) {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    if let Some(hit) = cache_hit {
+        headers.insert(
+            "x-cache",
+            HeaderValue::from_static(if hit { "HIT" } else { "MISS" }),
+        );
+    }
+    headers
+}
+
 // WebSocket endpoint: client connects to /ws/generate, sends a single JSON GenerateRequest text frame.
 // Server streams each token as a Text frame and finally sends a JSON {"done":true} frame.
 pub async fn ws_generate(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_ws_generate(state, socket))
+    let client_id = crate::audit::client_id(&headers);
+    ws.on_upgrade(move |socket| handle_ws_generate(state, socket, client_id))
 }
 
-async fn handle_ws_generate(state: Arc<AppState>, mut socket: WebSocket) {
+async fn handle_ws_generate(state: Arc<AppState>, mut socket: WebSocket, client_id: String) {
     // Expect first message with request JSON
     let Some(Ok(first)) = socket.recv().await else {
         return;
@@ -157,7 +618,7 @@ async fn handle_ws_generate(state: Arc<AppState>, mut socket: WebSocket) {
         WsMessage::Binary(b) => String::from_utf8_lossy(&b).to_string(),
         _ => return,
     };
-    let req: GenerateRequest = match serde_json::from_str(&req_json) {
+    let mut req: GenerateRequest = match serde_json::from_str(&req_json) {
         Ok(r) => r,
         Err(e) => {
             let _ = socket
@@ -168,34 +629,71 @@ async fn handle_ws_generate(state: Arc<AppState>, mut socket: WebSocket) {
             return;
         }
     };
-    let Some(spec) = state.registry.to_spec(&req.model) else {
-        let _ = socket
-            .send(WsMessage::Text("{\"error\":\"model not found\"}".into()))
-            .await;
+    let Some(model_name) = state
+        .registry
+        .resolve_model_name(&req.model, state.server_config.default_model.as_deref())
+    else {
+        let error = serde_json::json!({
+            "error": format!("No available model matches '{}'", req.model)
+        });
+        let _ = socket.send(WsMessage::Text(error.to_string())).await;
         return;
     };
-    let Ok(loaded) = state.engine.load(&spec).await else {
-        let _ = socket
-            .send(WsMessage::Text("{\"error\":\"load failed\"}".into()))
-            .await;
+    req.model = model_name;
+    let Some(spec) = state.registry.to_spec(&req.model) else {
+        let error = serde_json::json!({
+            "error": state.registry.model_not_found_message(&req.model)
+        });
+        let _ = socket.send(WsMessage::Text(error.to_string())).await;
         return;
     };
-
     // Build prompt (reuse logic)
     let prompt = if let Some(ms) = &req.messages {
-        let fam = match spec.template.as_deref() {
-            Some("chatml") => TemplateFamily::ChatML,
-            Some("llama3") | Some("llama-3") => TemplateFamily::Llama3,
-            _ => TemplateFamily::OpenChat,
-        };
-        let pairs = ms
-            .iter()
-            .map(|m| (m.role.clone(), m.content.clone()))
-            .collect::<Vec<_>>();
+        let fam = spec
+            .template
+            .as_deref()
+            .and_then(TemplateFamily::from_name)
+            .unwrap_or(TemplateFamily::OpenChat);
+        let pairs = state.server_config.prepare_messages(
+            ms.iter()
+                .map(|m| (m.role.clone(), m.content.clone()))
+                .collect::<Vec<_>>(),
+        );
         fam.render(req.system.as_deref(), &pairs, None)
     } else {
         req.prompt.clone().unwrap_or_default()
     };
+    if let Err(message) = state.server_config.precheck_prompt_length(&prompt) {
+        let error = serde_json::json!({ "error": message });
+        let _ = socket.send(WsMessage::Text(error.to_string())).await;
+        return;
+    }
+
+    if let Some(secs) = req.keep_alive {
+        state
+            .model_pool
+            .set_keep_alive(&req.model, KeepAlive::from_secs(secs))
+            .await;
+    }
+    let Ok(loaded) = state
+        .server_config
+        .retry_transient("Model load", || {
+            state.model_pool.get_or_load(&*state.engine, &spec)
+        })
+        .await
+    else {
+        let _ = socket
+            .send(WsMessage::Text("{\"error\":\"load failed\"}".into()))
+            .await;
+        return;
+    };
+    let prompt_tokens = loaded.count_tokens(&prompt);
+    if let Err(message) = state.server_config.check_prompt_length(prompt_tokens) {
+        state.model_pool.release(&req.model).await;
+        let error = serde_json::json!({ "error": message });
+        let _ = socket.send(WsMessage::Text(error.to_string())).await;
+        return;
+    }
 
     let mut opts = GenOptions::default();
     if let Some(t) = req.temperature {
@@ -207,9 +705,30 @@ async fn handle_ws_generate(state: Arc<AppState>, mut socket: WebSocket) {
     if let Some(k) = req.top_k {
         opts.top_k = k;
     }
-    if let Some(m) = req.max_tokens {
-        opts.max_tokens = m;
+    opts.context_shift = req.context_shift.unwrap_or(false);
+    opts.max_tokens = crate::engine::resolve_generation_budget(
+        req.max_tokens,
+        spec.ctx_len,
+        prompt_tokens,
+        opts.context_shift,
+    );
+    if let Some(trim) = req.trim_leading {
+        opts.trim_leading = trim;
+    }
+    if let Some(samplers) = req.samplers.take() {
+        opts.samplers = samplers;
+    }
+    opts.stop_on_repeat = req.stop_on_repeat;
+    if let Err(e) = crate::engine::validate_samplers(&opts.samplers)
+        .and_then(|()| opts.clamp_sampling(state.server_config.strict_sampling))
+    {
+        state.model_pool.release(&req.model).await;
+        let error = serde_json::json!({ "error": e.to_string() });
+        let _ = socket.send(WsMessage::Text(error.to_string())).await;
+        return;
     }
+    opts.stop_tokens
+        .extend(state.registry.stop_tokens(&req.model));
     // Force internal non-stream; we push per-token ourselves
     let mut internal = opts.clone();
     internal.stream = false;
@@ -217,17 +736,35 @@ async fn handle_ws_generate(state: Arc<AppState>, mut socket: WebSocket) {
     tokio::spawn({
         let prompt = prompt.clone();
         let tx_done = tx.clone();
+        let audit = state.audit_logger.clone();
+        let prompt_log = state.server_config.prompt_log.clone();
+        let model_pool = state.model_pool.clone();
+        let observability = state.observability.clone();
+        let model_name = req.model.clone();
         async move {
             let tx_tokens = tx.clone();
-            let _ = loaded
-                .generate(
-                    &prompt,
-                    internal,
-                    Some(Box::new(move |tok| {
-                        let _ = tx_tokens.send(tok);
-                    })),
-                )
+            let cancel = internal.cancel.clone();
+            let on_token = {
+                let cancel = cancel.clone();
+                move |tok| cancel.send_or_cancel(&tx_tokens, tok)
+            };
+            let result = loaded
+                .generate(&prompt, internal, Some(Box::new(on_token)))
                 .await;
+            model_pool.release(&model_name).await;
+            if cancel.is_cancelled() {
+                observability.record_cancelled(&model_name).await;
+            }
+            let (response, status) = match &result {
+                Ok(full) => (full.as_str(), 200),
+                Err(e) => ("", crate::api_errors::status_for(e).as_u16()),
+            };
+            if let Some(audit) = &audit {
+                audit.record(&model_name, &client_id, &prompt, response, status, |t| {
+                    loaded.count_tokens(t)
+                });
+            }
+            prompt_log.record(&model_name, &prompt, response, status);
             let _ = tx_done.send("[DONE]".into());
         }
     });
@@ -242,6 +779,162 @@ async fn handle_ws_generate(state: Arc<AppState>, mut socket: WebSocket) {
     let _ = socket.send(WsMessage::Text("{\"done\":true}".into())).await;
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RenderRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub system: Option<String>,
+    /// Template family to render with instead of the model's own
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenderResponse {
+    pub model: String,
+    pub template: String,
+    pub prompt: String,
+}
+
+/// Render chat messages into the prompt `/api/generate` would send to the model
+pub async fn render(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<RenderRequest>,
+) -> impl IntoResponse {
+    let Some(model_name) = state
+        .registry
+        .resolve_model_name(&req.model, state.server_config.default_model.as_deref())
+    else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(crate::api_errors::ErrorResponse {
+                error: state.registry.model_not_found_message(&req.model),
+            }),
+        )
+            .into_response();
+    };
+    let Some(spec) = state.registry.to_spec(&model_name) else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(crate::api_errors::ErrorResponse {
+                error: state.registry.model_not_found_message(&model_name),
+            }),
+        )
+            .into_response();
+    };
+
+    let fam = match req.template.as_deref() {
+        Some(name) => match TemplateFamily::from_name(name) {
+            Some(fam) => fam,
+            None => {
+                return (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    Json(crate::api_errors::ErrorResponse {
+                        error: format!(
+                            "Unknown template '{}'. Available templates: {}",
+                            name,
+                            TemplateFamily::NAMES.join(", ")
+                        ),
+                    }),
+                )
+                    .into_response();
+            }
+        },
+        None => spec
+            .template
+            .as_deref()
+            .and_then(TemplateFamily::from_name)
+            .unwrap_or(TemplateFamily::OpenChat),
+    };
+    let pairs = state.server_config.prepare_messages(
+        req.messages
+            .iter()
+            .map(|m| (m.role.clone(), m.content.clone()))
+            .collect::<Vec<_>>(),
+    );
+
+    Json(RenderResponse {
+        model: model_name,
+        template: fam.name().to_string(),
+        prompt: fam.render(req.system.as_deref(), &pairs, None),
+    })
+    .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WarmRequest {
+    pub model: String,
+    /// Static prompt prefix (e.g. RAG context) later requests will start with
+    pub prompt: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WarmResponse {
+    pub model: String,
+    pub cached_tokens: usize,
+}
+
+/// Prefill a prompt prefix into the pooled model's KV cache without
+/// generating, so the next request sharing that prefix starts faster
+pub async fn warm(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<WarmRequest>,
+) -> impl IntoResponse {
+    let spec = match state
+        .registry
+        .resolve_model_name(&req.model, state.server_config.default_model.as_deref())
+    {
+        Some(name) => state.registry.to_spec(&name),
+        None => None,
+    };
+    let Some(spec) = spec else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(crate::api_errors::ErrorResponse {
+                error: state.registry.model_not_found_message(&req.model),
+            }),
+        )
+            .into_response();
+    };
+
+    let warmed = match state
+        .server_config
+        .retry_transient("Model load", || {
+            state.model_pool.get_or_load(&*state.engine, &spec)
+        })
+        .await
+    {
+        Ok(loaded) => {
+            let prompt_tokens = loaded.count_tokens(&req.prompt);
+            if let Err(message) = state.server_config.check_prompt_length(prompt_tokens) {
+                tracing::warn!("Rejecting warm-up for '{}': {}", req.model, message);
+                return (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    Json(crate::api_errors::ErrorResponse { error: message }),
+                )
+                    .into_response();
+            }
+            loaded.warm(&req.prompt).await
+        }
+        Err(e) => Err(e),
+    };
+    match warmed {
+        Ok(cached_tokens) => Json(WarmResponse {
+            model: spec.name,
+            cached_tokens,
+        })
+        .into_response(),
+        Err(e) => (
+            crate::api_errors::status_for(&e),
+            Json(crate::api_errors::ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ModelListResponse {
     pub models: Vec<ModelInfo>,
@@ -270,10 +963,13 @@ pub async fn list_models(State(state): State<Arc<AppState>>) -> impl IntoRespons
         });
     }
 
-    // Add discovered models
-    for (name, discovered) in &state.registry.discovered_models {
+    // Add discovered models, by name
+    for name in state.registry.list_all_available() {
+        let Some(discovered) = state.registry.discovered_models.get(&name) else {
+            continue;
+        };
         models.push(ModelInfo {
-            name: name.clone(),
+            name,
             size_bytes: Some(discovered.size_bytes),
             model_type: Some(discovered.model_type.clone()),
             parameter_count: discovered.parameter_count.clone(),
@@ -328,16 +1024,42 @@ pub async fn discover_models(State(_state): State<Arc<AppState>>) -> impl IntoRe
 use axum::extract::Path;
 
 pub async fn load_model(
-    State(_state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
     Path(name): Path<String>,
 ) -> impl IntoResponse {
-    // Simple model loading endpoint - future enhancement
-    // Dynamic model loading: Model is loaded fresh for each request for isolation
-    // For now, return a placeholder response
-    Json(serde_json::json!({
-        "message": format!("Model {} load requested", name),
-        "status": "pending"
-    }))
+    let Some(spec) = state.registry.to_spec(&name) else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(crate::api_errors::ErrorResponse {
+                error: state.registry.model_not_found_message(&name),
+            }),
+        )
+            .into_response();
+    };
+    // Loads into the shared pool, subject to --memory-ceiling-mb
+    let estimate = crate::util::memory::LoadEstimate::for_spec(&spec);
+    match state
+        .server_config
+        .retry_transient("Model load", || {
+            state.model_pool.get_or_load(&*state.engine, &spec)
+        })
+        .await
+    {
+        Ok(_) => Json(serde_json::json!({
+            "model": name,
+            "status": "loaded",
+            "estimated_memory": estimate,
+        }))
+        .into_response(),
+        Err(e) => (
+            crate::api_errors::status_for(&e),
+            Json(serde_json::json!({
+                "error": e.to_string(),
+                "estimated_memory": estimate,
+            })),
+        )
+            .into_response(),
+    }
 }
 
 pub async fn unload_model(
@@ -352,6 +1074,90 @@ pub async fn unload_model(
     }))
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModelMetadataResponse {
+    pub model: String,
+    pub gguf_version: u32,
+    pub architecture: Option<String>,
+    pub context_length: Option<u64>,
+    pub quantization: Option<String>,
+    /// `tokenizer.ggml.model`, e.g. `llama` (SentencePiece) or `gpt2` (BPE)
+    pub tokenizer: Option<String>,
+    pub chat_template: Option<String>,
+    pub tensor_count: usize,
+    /// Every header key/value; long arrays such as vocabularies are left out
+    pub metadata: std::collections::BTreeMap<String, serde_json::Value>,
+}
+
+/// GGUF header metadata of a model, for debugging template and context issues
+pub async fn model_metadata(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let error = |status: axum::http::StatusCode, error: String| {
+        (status, Json(crate::api_errors::ErrorResponse { error })).into_response()
+    };
+    let spec = match state
+        .registry
+        .resolve_model_name(&name, state.server_config.default_model.as_deref())
+    {
+        Some(resolved) => state.registry.to_spec(&resolved),
+        None => None,
+    };
+    let Some(spec) = spec else {
+        return error(
+            axum::http::StatusCode::NOT_FOUND,
+            state.registry.model_not_found_message(&name),
+        );
+    };
+    let is_gguf = spec
+        .base_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gguf"));
+    if !is_gguf {
+        return error(
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "Model '{}' is not a GGUF file ({}); metadata is only available for GGUF models",
+                spec.name,
+                spec.base_path.display()
+            ),
+        );
+    }
+
+    let path = spec.base_path.clone();
+    let info = tokio::task::spawn_blocking(move || crate::engine::gguf::read_gguf_info(&path))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+    match info {
+        Ok(info) => Json(ModelMetadataResponse {
+            model: spec.name,
+            gguf_version: info.version,
+            architecture: info.architecture().map(str::to_string),
+            context_length: info.context_length(),
+            quantization: info.quantization().map(str::to_string),
+            tokenizer: info
+                .metadata_str("tokenizer.ggml.model")
+                .map(str::to_string),
+            chat_template: info
+                .metadata_str("tokenizer.chat_template")
+                .map(str::to_string),
+            tensor_count: info.tensors.len(),
+            metadata: info.metadata,
+        })
+        .into_response(),
+        Err(e) => error(
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "Failed to read GGUF metadata from {}: {}",
+                spec.base_path.display(),
+                e
+            ),
+        ),
+    }
+}
+
 pub async fn model_status(
     State(_state): State<Arc<AppState>>,
     Path(name): Path<String>,
@@ -397,6 +1203,7 @@ pub async fn execute_workflow(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::mock::{MockEngine, MockStats, Reply};
 
     #[test]
     fn test_generate_request_parsing() {
@@ -466,17 +1273,13 @@ mod tests {
         let request = GenerateRequest {
             model: "test".to_string(),
             prompt: Some("Hello".to_string()),
-            messages: None,
-            system: None,
             max_tokens: Some(50),
-            temperature: None,
-            top_p: None,
-            top_k: None,
             stream: Some(false),
+            ..Default::default()
         };
 
         // Exercise handler code path (will fail gracefully due to no model)
-        let _result = generate(State(state), Json(request)).await;
+        let _result = generate(State(state), HeaderMap::new(), Json(request)).await;
         // Test completed successfully
     }
 
@@ -512,13 +1315,12 @@ mod tests {
         let req = GenerateRequest {
             model: "test".to_string(),
             prompt: Some("Hello".to_string()),
-            messages: None,
-            system: None,
             max_tokens: Some(100),
             temperature: Some(0.7),
             top_p: Some(0.9),
             top_k: Some(40),
             stream: Some(false),
+            ..Default::default()
         };
 
         assert_eq!(req.model, "test");
@@ -531,6 +1333,7 @@ mod tests {
         let msg = ChatMessage {
             role: "user".to_string(),
             content: "Hello world".to_string(),
+            images: Vec::new(),
         };
 
         assert_eq!(msg.role, "user");
@@ -659,14 +1462,11 @@ mod tests {
         use crate::model_registry::{ModelEntry, Registry};
 
         let mut registry = Registry::default();
-        registry.register(ModelEntry {
-            name: "stream-test".to_string(),
-            base_path: "./test.safetensors".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            ModelEntry::new("stream-test", "./test.safetensors")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
 
         let engine = Box::new(InferenceEngineAdapter::new());
         let state = Arc::new(AppState::new(engine, registry));
@@ -674,61 +1474,127 @@ mod tests {
         let request = GenerateRequest {
             model: "stream-test".to_string(),
             prompt: Some("Test prompt".to_string()),
-            messages: None,
-            system: None,
             max_tokens: Some(50),
             temperature: Some(0.7),
             top_p: Some(0.9),
             top_k: Some(40),
             stream: Some(true), // Enable streaming (line 54)
+            ..Default::default()
         };
 
         // Exercise streaming path (lines 54-64)
-        let _result = generate(State(state), Json(request)).await;
+        let _result = generate(State(state), HeaderMap::new(), Json(request)).await;
         // Test completed successfully
     }
 
+    #[tokio::test]
+    async fn test_render_template_override_leaves_registry_unchanged() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(
+            ModelEntry::new("render-test", "./test.safetensors")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
+        let state = Arc::new(AppState::new(
+            Box::new(InferenceEngineAdapter::new()),
+            registry,
+        ));
+        let request = |template: Option<&str>| RenderRequest {
+            model: "render-test".to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: "Hi".to_string(),
+                images: Vec::new(),
+            }],
+            system: None,
+            template: template.map(str::to_string),
+        };
+        async fn body(response: axum::response::Response) -> serde_json::Value {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice(&bytes).unwrap()
+        }
+
+        let overridden = render(State(state.clone()), Json(request(Some("llama3"))))
+            .await
+            .into_response();
+        assert_eq!(overridden.status(), axum::http::StatusCode::OK);
+        let overridden = body(overridden).await;
+        assert_eq!(overridden["template"], "llama3");
+        assert!(overridden["prompt"]
+            .as_str()
+            .unwrap()
+            .contains("<|start_header_id|>user<|end_header_id|>"));
+
+        // Without an override the model's configured template is still used
+        let default = body(
+            render(State(state.clone()), Json(request(None)))
+                .await
+                .into_response(),
+        )
+        .await;
+        assert_eq!(default["template"], "chatml");
+        assert!(default["prompt"]
+            .as_str()
+            .unwrap()
+            .contains("<|im_start|>user"));
+        assert_eq!(
+            state
+                .registry
+                .to_spec("render-test")
+                .unwrap()
+                .template
+                .as_deref(),
+            Some("chatml")
+        );
+
+        let unknown = render(State(state), Json(request(Some("alpaca"))))
+            .await
+            .into_response();
+        assert_eq!(unknown.status(), axum::http::StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn test_generate_handler_with_messages() {
         use crate::engine::adapter::InferenceEngineAdapter;
         use crate::model_registry::{ModelEntry, Registry};
 
         let mut registry = Registry::default();
-        registry.register(ModelEntry {
-            name: "messages-test".to_string(),
-            base_path: "./test.safetensors".into(),
-            lora_path: None,
-            template: Some("llama3".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            ModelEntry::new("messages-test", "./test.safetensors")
+                .with_template("llama3")
+                .with_ctx_len(2048),
+        );
 
         let engine = Box::new(InferenceEngineAdapter::new());
         let state = Arc::new(AppState::new(engine, registry));
 
         let request = GenerateRequest {
             model: "messages-test".to_string(),
-            prompt: None,
             messages: Some(vec![
                 ChatMessage {
                     role: "user".to_string(),
                     content: "Hello".to_string(),
+                    images: Vec::new(),
                 },
                 ChatMessage {
                     role: "assistant".to_string(),
                     content: "Hi there!".to_string(),
+                    images: Vec::new(),
                 },
             ]),
             system: Some("You are a helpful assistant".to_string()),
             max_tokens: Some(100),
-            temperature: None,
-            top_p: None,
-            top_k: None,
             stream: Some(false),
+            ..Default::default()
         };
 
         // Exercise messages path with system prompt (lines 35-42)
-        let _result = generate(State(state), Json(request)).await;
+        let _result = generate(State(state), HeaderMap::new(), Json(request)).await;
         // Test completed successfully
     }
 
@@ -811,14 +1677,11 @@ mod tests {
         use axum::extract::ws::WebSocketUpgrade;
 
         let mut registry = Registry::default();
-        registry.register(ModelEntry {
-            name: "ws-test".to_string(),
-            base_path: "./test.safetensors".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            ModelEntry::new("ws-test", "./test.safetensors")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
 
         let engine = Box::new(InferenceEngineAdapter::new());
         let _state = Arc::new(AppState::new(engine, registry));
@@ -892,14 +1755,11 @@ mod tests {
         let mut registry = Registry::default();
 
         // Add a registered model
-        registry.register(ModelEntry {
-            name: "registered-model".to_string(),
-            base_path: "./registered.gguf".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            ModelEntry::new("registered-model", "./registered.gguf")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
 
         // The registry might have discovered models too
         // Exercise both paths in list_models handler (lines 155-175)
@@ -919,6 +1779,7 @@ mod tests {
         let messages = Some(vec![ChatMessage {
             role: "user".to_string(),
             content: "Hello".to_string(),
+            images: Vec::new(),
         }]);
 
         let system = Some("System message");
@@ -1011,13 +1872,12 @@ mod tests {
         let req = GenerateRequest {
             model: "test".to_string(),
             prompt: Some("test prompt".to_string()),
-            messages: None,
-            system: None,
             max_tokens: Some(50),
             temperature: Some(0.7),
             top_p: Some(0.9),
             top_k: Some(40),
             stream: Some(false),
+            ..Default::default()
         };
 
         let debug_str = format!("{:?}", req);
@@ -1027,6 +1887,7 @@ mod tests {
         let chat_msg = ChatMessage {
             role: "user".to_string(),
             content: "hello".to_string(),
+            images: Vec::new(),
         };
 
         let debug_str = format!("{:?}", chat_msg);
@@ -1127,6 +1988,1032 @@ mod tests {
         assert!(request.messages.is_some());
         assert_eq!(request.messages.as_ref().unwrap().len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_generate_writes_audit_record() {
+        use crate::audit::{AuditConfig, AuditLogger};
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let dir = tempfile::tempdir().unwrap();
+        let audit_path = dir.path().join("audit.jsonl");
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let mut state = AppState::new(Box::new(MockEngine::new(Reply::Echo)), registry);
+        state.audit_logger = Some(AuditLogger::new(AuditConfig {
+            path: audit_path.clone(),
+            include_content: false,
+            redactor: Default::default(),
+        }));
+        let state = Arc::new(state);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-client-id", "tester".parse().unwrap());
+        let request = GenerateRequest {
+            model: "echo".to_string(),
+            prompt: Some("top secret".to_string()),
+            stream: Some(false),
+            ..Default::default()
+        };
+        let response = generate(State(state), headers, Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+
+        let mut content = String::new();
+        for _ in 0..50 {
+            content = tokio::fs::read_to_string(&audit_path)
+                .await
+                .unwrap_or_default();
+            if !content.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let line: serde_json::Value = serde_json::from_str(content.trim()).unwrap();
+        assert_eq!(line["model"], "echo");
+        assert_eq!(line["client_id"], "tester");
+        assert_eq!(line["status"], 200);
+        assert!(line["prompt_hash"].as_str().unwrap().len() == 64);
+        assert!(!content.contains("top secret"));
+    }
+
+    #[tokio::test]
+    async fn test_audit_redacts_pii_but_engine_sees_original() {
+        use crate::audit::{AuditConfig, AuditLogger};
+        use crate::model_registry::{ModelEntry, Registry};
+        use crate::redact::Redactor;
+
+        let dir = tempfile::tempdir().unwrap();
+        let audit_path = dir.path().join("audit.jsonl");
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let mut state = AppState::new(Box::new(MockEngine::new(Reply::Echo)), registry);
+        state.audit_logger = Some(AuditLogger::new(AuditConfig {
+            path: audit_path.clone(),
+            include_content: true,
+            redactor: Redactor::new(&[r"[\w.+-]+@[\w-]+\.[\w.]+"]).unwrap(),
+        }));
+        let state = Arc::new(state);
+
+        let mut request = raw_request("echo");
+        request.prompt = Some("email jane@example.com please".to_string());
+        let response = generate(State(state), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        // The mock echoes its prompt, so the engine got the unredacted text
+        assert!(String::from_utf8_lossy(&body).contains("jane@example.com"));
+
+        let mut content = String::new();
+        for _ in 0..50 {
+            content = tokio::fs::read_to_string(&audit_path)
+                .await
+                .unwrap_or_default();
+            if !content.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let line: serde_json::Value = serde_json::from_str(content.trim()).unwrap();
+        assert_eq!(line["prompt"], "email [REDACTED] please");
+        assert_eq!(line["response"], "echo: email [REDACTED] please");
+        assert!(!content.contains("jane@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_transient_load_failure_is_retried_once() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let flaky_state = |retries: u32| {
+            let engine = MockEngine::new(Reply::Echo).with_transient_failures(1);
+            let stats = engine.stats.clone();
+            let mut state = AppState::new(Box::new(engine), registry.clone());
+            state.server_config.transient_retries = retries;
+            state.server_config.transient_retry_delay = std::time::Duration::from_millis(1);
+            (Arc::new(state), stats)
+        };
+
+        let (state, stats) = flaky_state(1);
+        let response = generate(State(state), HeaderMap::new(), Json(raw_request("echo")))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert_eq!(stats.loads_of("echo"), 2);
+
+        // With retries disabled the first failure reaches the client
+        let (state, stats) = flaky_state(0);
+        let response = generate(State(state), HeaderMap::new(), Json(raw_request("echo")))
+            .await
+            .into_response();
+        assert_eq!(
+            response.status(),
+            axum::http::StatusCode::INSUFFICIENT_STORAGE
+        );
+        assert_eq!(stats.loads_of("echo"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_load_endpoint_reports_estimate_and_enforces_ceiling() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("echo.gguf");
+        std::fs::write(&path, vec![0u8; 2048]).unwrap();
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", path));
+        let state_with_ceiling = |ceiling: Option<u64>| {
+            let mut state = AppState::new(Box::new(MockEngine::new(Reply::Echo)), registry.clone());
+            state.model_pool =
+                crate::model_manager::ModelManager::new().with_memory_ceiling(ceiling, false);
+            Arc::new(state)
+        };
+
+        let state = state_with_ceiling(Some(1024));
+        let response = load_model(State(state.clone()), Path("echo".to_string()))
+            .await
+            .into_response();
+        assert_eq!(
+            response.status(),
+            axum::http::StatusCode::INSUFFICIENT_STORAGE
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(json["error"].as_str().unwrap().contains("memory ceiling"));
+        assert_eq!(json["estimated_memory"]["total_bytes"], 2048);
+        assert!(!state.model_pool.is_loaded("echo").await);
+
+        let state = state_with_ceiling(None);
+        let response = load_model(State(state.clone()), Path("echo".to_string()))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert!(state.model_pool.is_loaded("echo").await);
+    }
+
+    #[tokio::test]
+    async fn test_generate_max_tokens_uses_remaining_context() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("small-ctx", "./small.gguf").with_ctx_len(100));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::MaxTokens)),
+            registry,
+        ));
+        let prompt = vec!["word"; 40].join(" ");
+
+        for (requested, expected) in [(None, "60"), (Some(500), "60"), (Some(10), "10")] {
+            let mut request = raw_request("small-ctx");
+            request.prompt = Some(prompt.clone());
+            request.max_tokens = requested;
+            let response = generate(State(state.clone()), HeaderMap::new(), Json(request))
+                .await
+                .into_response();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let parsed: GenerateResponse = serde_json::from_slice(&body).unwrap();
+            assert_eq!(parsed.response, expected, "requested {:?}", requested);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_rejects_prompt_over_token_limit() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let engine = MockEngine::new(Reply::Echo);
+        let loads = engine.stats.clone();
+        let mut state = AppState::new(Box::new(engine), registry);
+        state.server_config.max_prompt_tokens = Some(8);
+        let state = Arc::new(state);
+
+        // Far too long for any tokenizer: rejected without loading the model
+        let mut request = raw_request("echo");
+        request.prompt = Some("x".repeat(1000));
+        let response = generate(State(state.clone()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(loads.total_loads(), 0);
+
+        // Counted with the model's tokenizer (one token per word here), so
+        // ten short words are 10 tokens where the estimate would say 5
+        let mut request = raw_request("echo");
+        request.prompt = Some("a b c d e f g h i j".to_string());
+        let response = generate(State(state.clone()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let parsed: crate::api_errors::ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert!(parsed.error.contains("10 tokens"));
+        assert!(parsed.error.contains("maximum of 8"));
+
+        let mut request = raw_request("echo");
+        request.prompt = Some("x".repeat(100));
+        let response = generate(State(state), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_generate_cache_ttl_override() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("counter", "./counter.gguf"));
+        let engine = MockEngine::new(Reply::Count);
+        let calls = engine.stats.clone();
+        let state = Arc::new(AppState::new(Box::new(engine), registry));
+
+        let send = |temperature: f32, cache_ttl_secs: Option<u64>| {
+            let state = state.clone();
+            async move {
+                let mut request = raw_request("counter");
+                request.temperature = Some(temperature);
+                request.cache_ttl_secs = cache_ttl_secs;
+                let response = generate(State(state), HeaderMap::new(), Json(request))
+                    .await
+                    .into_response();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<GenerateResponse>(&body)
+                    .unwrap()
+                    .response
+            }
+        };
+
+        // Greedy requests are cached under the request's TTL
+        assert_eq!(send(0.0, Some(60)).await, "1");
+        assert_eq!(send(0.0, Some(60)).await, "1");
+        // 0 forces a fresh computation and doesn't replace the cached entry
+        assert_eq!(send(0.0, Some(0)).await, "2");
+        assert_eq!(send(0.0, None).await, "1");
+        // Sampled requests are never cached
+        assert_eq!(send(0.7, None).await, "3");
+        assert_eq!(send(0.7, None).await, "4");
+        assert_eq!(
+            calls.generations.load(std::sync::atomic::Ordering::SeqCst),
+            4
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_cache_key_covers_output_options() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("counter", "./counter.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Count)),
+            registry,
+        ));
+
+        let send = |request: GenerateRequest| {
+            let state = state.clone();
+            async move {
+                let response = generate(State(state), HeaderMap::new(), Json(request))
+                    .await
+                    .into_response();
+                response.headers()["x-cache"].to_str().unwrap().to_string()
+            }
+        };
+        let greedy = || {
+            let mut request = raw_request("counter");
+            request.temperature = Some(0.0);
+            request
+        };
+
+        assert_eq!(send(greedy()).await, "MISS");
+        assert_eq!(send(greedy()).await, "HIT");
+        // A differently trimmed reply is not served from the cache
+        let untrimmed = || GenerateRequest {
+            trim_leading: Some(false),
+            ..greedy()
+        };
+        assert_eq!(send(untrimmed()).await, "MISS");
+        assert_eq!(send(untrimmed()).await, "HIT");
+        // Nor is one loop detection may have cut short
+        let repeat_checked = || GenerateRequest {
+            stop_on_repeat: Some(crate::engine::RepeatStop::default()),
+            ..greedy()
+        };
+        assert_eq!(send(repeat_checked()).await, "MISS");
+        assert_eq!(send(repeat_checked()).await, "HIT");
+        // Nor one that ran past the context window
+        let shifted = || GenerateRequest {
+            context_shift: Some(true),
+            ..greedy()
+        };
+        assert_eq!(send(shifted()).await, "MISS");
+        assert_eq!(send(shifted()).await, "HIT");
+    }
+
+    #[tokio::test]
+    async fn test_generate_cache_headers() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("counter", "./counter.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Count)),
+            registry,
+        ));
+
+        let send = |temperature: f32| {
+            let state = state.clone();
+            async move {
+                let mut request = raw_request("counter");
+                request.temperature = Some(temperature);
+                request.cache_ttl_secs = Some(60);
+                let response = generate(State(state), HeaderMap::new(), Json(request))
+                    .await
+                    .into_response();
+                let header = |name: &str| {
+                    response
+                        .headers()
+                        .get(name)
+                        .map(|v| v.to_str().unwrap().to_string())
+                };
+                (header("cache-control"), header("x-cache"))
+            }
+        };
+
+        let (cache_control, x_cache) = send(0.0).await;
+        assert_eq!(cache_control.as_deref(), Some("private, max-age=60"));
+        assert_eq!(x_cache.as_deref(), Some("MISS"));
+        let (_, x_cache) = send(0.0).await;
+        assert_eq!(x_cache.as_deref(), Some("HIT"));
+
+        // Sampled generations bypass the cache and must not be stored downstream
+        let (cache_control, x_cache) = send(0.7).await;
+        assert_eq!(cache_control.as_deref(), Some("no-store"));
+        assert_eq!(x_cache, None);
+    }
+
+    #[tokio::test]
+    async fn test_generate_echoes_sampling_params_on_request() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("counter", "./counter.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Count)),
+            registry,
+        ));
+
+        let params = |include: Option<bool>| {
+            let state = state.clone();
+            async move {
+                let mut request = raw_request("counter");
+                request.temperature = Some(0.0);
+                request.include_sampling_params = include;
+                let response = generate(State(state), HeaderMap::new(), Json(request))
+                    .await
+                    .into_response();
+                response.headers().get("x-shimmy-params").map(|v| {
+                    serde_json::from_str::<serde_json::Value>(v.to_str().unwrap()).unwrap()
+                })
+            }
+        };
+
+        let echoed = params(Some(true)).await.expect("X-Shimmy-Params header");
+        assert_eq!(echoed["mode"], "greedy");
+        assert_eq!(echoed["temperature"], 0.0);
+        assert_eq!(echoed["samplers"], serde_json::json!([]));
+        assert!(params(None).await.is_none());
+    }
+
+    #[test]
+    fn test_cache_headers_seeded_generation_is_cacheable() {
+        let opts = GenOptions {
+            temperature: 0.8,
+            seed: Some(7),
+            ..Default::default()
+        };
+        let headers = cache_headers(&opts, std::time::Duration::from_secs(30), None);
+        assert_eq!(headers[header::CACHE_CONTROL], "private, max-age=30");
+        assert!(headers.get("x-cache").is_none());
+
+        let headers = cache_headers(&opts, std::time::Duration::ZERO, Some(false));
+        assert_eq!(headers[header::CACHE_CONTROL], "no-store");
+        assert_eq!(headers["x-cache"], "MISS");
+    }
+
+    #[tokio::test]
+    async fn test_generate_reports_context_usage() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("small-ctx", "./small.gguf").with_ctx_len(100));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::MaxTokens)),
+            registry,
+        ));
+
+        let mut request = raw_request("small-ctx");
+        request.prompt = Some(vec!["word"; 95].join(" "));
+        let response = generate(State(state), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert_eq!(response.headers()["x-context-usage"], "95/100");
+    }
+
+    async fn post_batch(body: &str) -> (axum::http::StatusCode, serde_json::Value) {
+        post_batch_with(crate::server::ServerConfig::default(), body).await
+    }
+
+    async fn post_batch_with(
+        config: crate::server::ServerConfig,
+        body: &str,
+    ) -> (axum::http::StatusCode, serde_json::Value) {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let mut state = AppState::new(Box::new(MockEngine::new(Reply::Echo)), registry);
+        state.server_config = config;
+        let response = batch(State(Arc::new(state)), HeaderMap::new(), body.to_string())
+            .await
+            .into_response();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_batch_returns_results_in_input_order() {
+        let body = serde_json::json!([
+            {"model": "echo", "prompt": "one"},
+            {"model": "echo", "prompt": "two"},
+            {"model": "echo", "prompt": "three"}
+        ])
+        .to_string();
+        let (status, parsed) = post_batch(&body).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        let responses: Vec<_> = parsed["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["response"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(responses, ["echo: one", "echo: two", "echo: three"]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_failed_items_do_not_abort_others() {
+        let body = [
+            r#"{"model": "echo", "prompt": "first"}"#,
+            r#"{"model": "missing", "prompt": "second"}"#,
+            r#"{"prompt": "no model"}"#,
+            r#"{"model": "echo", "prompt": "last"}"#,
+        ]
+        .join("\n");
+        let (status, parsed) = post_batch(&body).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        let results = parsed["results"].as_array().unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0]["response"], "echo: first");
+        assert_eq!(results[1]["status"], 404);
+        assert!(results[1]["error"].is_string());
+        assert_eq!(results[2]["status"], 400);
+        assert_eq!(results[3]["response"], "echo: last");
+        assert_eq!(results[3]["index"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_batch_rejects_empty_or_malformed_body() {
+        let (status, _) = post_batch("").await;
+        assert_eq!(status, axum::http::StatusCode::BAD_REQUEST);
+        let (status, _) = post_batch("[{").await;
+        assert_eq!(status, axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_batch_rejects_too_many_items() {
+        let config = crate::server::ServerConfig {
+            max_batch_items: 2,
+            ..Default::default()
+        };
+        let item = r#"{"model": "echo", "prompt": "hi"}"#;
+        let (status, parsed) = post_batch_with(config.clone(), &[item; 3].join("\n")).await;
+        assert_eq!(status, axum::http::StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(parsed["error"].as_str().unwrap().contains("maximum of 2"));
+
+        let (status, parsed) = post_batch_with(config, &[item; 2].join("\n")).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(parsed["results"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_generate_rejects_unknown_sampler() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Echo)),
+            registry,
+        ));
+
+        let mut request = raw_request("echo");
+        request.samplers = Some(vec!["top_k".to_string(), "typical".to_string()]);
+        let response = generate(State(state.clone()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let parsed: crate::api_errors::ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert!(parsed.error.contains("unknown sampler 'typical'"));
+
+        let mut request = raw_request("echo");
+        request.samplers = Some(vec!["top_k".to_string(), "temperature".to_string()]);
+        let response = generate(State(state), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+    }
+
+    fn raw_request(model: &str) -> GenerateRequest {
+        GenerateRequest {
+            model: model.to_string(),
+            prompt: Some("hi".to_string()),
+            stream: Some(false),
+            ..Default::default()
+        }
+    }
+
+    /// Send `request` to `/ws/generate` on a local server and collect the
+    /// text frames that come back
+    async fn ws_request(state: Arc<AppState>, request: serde_json::Value) -> Vec<String> {
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let app = axum::Router::new()
+            .route("/ws/generate", axum::routing::get(ws_generate))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (mut socket, _) =
+            tokio_tungstenite::connect_async(format!("ws://{}/ws/generate", addr))
+                .await
+                .unwrap();
+        socket
+            .send(Message::Text(request.to_string()))
+            .await
+            .unwrap();
+        let mut frames = Vec::new();
+        while let Some(Ok(Message::Text(frame))) = socket.next().await {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    #[tokio::test]
+    async fn test_ws_generate_honours_context_shift() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("budget", "./budget.gguf").with_ctx_len(16));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::MaxTokens)),
+            registry,
+        ));
+
+        let request = |context_shift: bool| {
+            serde_json::json!({
+                "model": "budget",
+                "prompt": "hi",
+                "max_tokens": 100,
+                "context_shift": context_shift,
+            })
+        };
+        // Clamped to the 15 tokens left in the window unless shifting
+        let frames = ws_request(state.clone(), request(false)).await;
+        assert_eq!(frames, ["15", r#"{"done":true}"#]);
+        let frames = ws_request(state, request(true)).await;
+        assert_eq!(frames, ["100", r#"{"done":true}"#]);
+    }
+
+    #[tokio::test]
+    async fn test_ws_generate_clamps_sampling() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let state = || {
+            let mut registry = Registry::default();
+            registry.register(ModelEntry::new("echo", "./echo.gguf"));
+            AppState::new(Box::new(MockEngine::new(Reply::Prompt)), registry)
+        };
+        let request = serde_json::json!({
+            "model": "echo",
+            "prompt": "hi",
+            "temperature": -1.0,
+            "top_p": 1.5,
+        });
+
+        let frames = ws_request(Arc::new(state()), request.clone()).await;
+        assert_eq!(frames, ["hi", r#"{"done":true}"#]);
+
+        // --strict-sampling rejects the same request
+        let mut strict = state();
+        strict.server_config.strict_sampling = true;
+        let frames = ws_request(Arc::new(strict), request).await;
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].contains("sampling values out of range"));
+    }
+
+    #[tokio::test]
+    async fn test_ws_generate_keep_alive_releases_model() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Prompt)),
+            registry,
+        ));
+
+        let request = serde_json::json!({"model": "echo", "prompt": "hi", "keep_alive": 0});
+        let frames = ws_request(state.clone(), request).await;
+        assert_eq!(frames, ["hi", r#"{"done":true}"#]);
+        assert!(!state.model_pool.is_loaded("echo").await);
+
+        // Also when the request is rejected after loading
+        let request = serde_json::json!({"model": "echo", "prompt": "hi", "samplers": ["bogus"]});
+        let frames = ws_request(state.clone(), request).await;
+        assert_eq!(frames.len(), 1);
+        assert!(!state.model_pool.is_loaded("echo").await);
+    }
+
+    fn slow_token_state() -> (Arc<AppState>, Arc<MockStats>) {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("slow", "./slow.gguf"));
+        let engine = MockEngine::new(Reply::Endless);
+        let stats = engine.stats.clone();
+        (Arc::new(AppState::new(Box::new(engine), registry)), stats)
+    }
+
+    async fn wait_until(done: impl Fn() -> bool) -> bool {
+        for _ in 0..100 {
+            if done() {
+                return true;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    /// Cancelled-request count, once the guard's spawned record has landed
+    async fn cancelled_requests(state: &AppState) -> u64 {
+        for _ in 0..100 {
+            let cancelled = state.observability.metrics().await.cancelled_requests;
+            if cancelled > 0 {
+                return cancelled;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        0
+    }
+
+    #[tokio::test]
+    async fn test_generate_client_disconnect_cancels_backend() {
+        use std::sync::atomic::Ordering;
+
+        let (state, stats) = slow_token_state();
+        let request = tokio::spawn(generate(
+            State(state.clone()),
+            HeaderMap::new(),
+            Json(raw_request("slow")),
+        ));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // axum drops the handler future when the client hangs up
+        request.abort();
+        assert!(request.await.is_err_and(|e| e.is_cancelled()));
+        assert!(wait_until(|| stats.cancelled.load(Ordering::SeqCst)).await);
+        assert_eq!(cancelled_requests(&state).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_disconnect_cancels_backend() {
+        use std::sync::atomic::Ordering;
+
+        let (state, stats) = slow_token_state();
+        let mut request = raw_request("slow");
+        request.stream = Some(true);
+        let response = generate(State(state.clone()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        let mut body = response.into_body().into_data_stream();
+        assert!(body.next().await.unwrap().is_ok());
+
+        drop(body);
+        assert!(wait_until(|| stats.cancelled.load(Ordering::SeqCst)).await);
+        assert_eq!(cancelled_requests(&state).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_generate_echo_prepends_prompt() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Echo)),
+            registry,
+        ));
+        let body = |echo: Option<bool>, stream: bool| {
+            let state = state.clone();
+            async move {
+                let mut request = raw_request("echo");
+                request.echo = echo;
+                request.stream = Some(stream);
+                let response = generate(State(state), HeaderMap::new(), Json(request))
+                    .await
+                    .into_response();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(bytes.to_vec()).unwrap()
+            }
+        };
+
+        let echoed: GenerateResponse =
+            serde_json::from_str(&body(Some(true), false).await).unwrap();
+        assert_eq!(echoed.response, "hiecho: hi");
+        let plain: GenerateResponse = serde_json::from_str(&body(None, false).await).unwrap();
+        assert_eq!(plain.response, "echo: hi");
+
+        // Streaming sends the prompt as the first event
+        let events = body(Some(true), true).await;
+        assert!(events.trim_start().starts_with("data: hi\n"), "{}", events);
+    }
+
+    #[tokio::test]
+    async fn test_generate_keep_alive_sets_model_residency() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Echo)),
+            registry,
+        ));
+        let send = |keep_alive: Option<i64>| {
+            let mut request = raw_request("echo");
+            request.keep_alive = keep_alive;
+            generate(State(state.clone()), HeaderMap::new(), Json(request))
+        };
+
+        // 0: unloaded as soon as the response is ready
+        let response = send(Some(0)).await.into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert!(!state.model_pool.is_loaded("echo").await);
+        // ...and when the request is rejected after loading
+        let mut request = raw_request("echo");
+        request.samplers = Some(vec!["bogus".to_string()]);
+        let response = generate(State(state.clone()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        assert!(!state.model_pool.is_loaded("echo").await);
+
+        // -1: stays loaded, including past the idle timeout
+        let response = send(Some(-1)).await.into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert!(state.model_pool.is_loaded("echo").await);
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(state
+            .model_pool
+            .unload_idle(Some(std::time::Duration::from_millis(1)))
+            .await
+            .is_empty());
+
+        // Later requests without keep_alive keep the last setting
+        let response = send(None).await.into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert_eq!(
+            state.model_pool.keep_alive("echo").await,
+            Some(KeepAlive::Forever)
+        );
+        assert!(state.model_pool.is_loaded("echo").await);
+    }
+
+    #[tokio::test]
+    async fn test_model_metadata_reports_gguf_header() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let dir = tempfile::tempdir().unwrap();
+        let gguf = dir.path().join("tiny.gguf");
+        std::fs::write(
+            &gguf,
+            crate::engine::gguf::tests::synthetic_gguf(
+                &[
+                    ("general.architecture", "llama"),
+                    ("tokenizer.ggml.model", "llama"),
+                ],
+                &[("llama.context_length", 8192), ("general.file_type", 15)],
+                &[("token_embd.weight", 64)],
+            ),
+        )
+        .unwrap();
+        let mut registry = Registry::default();
+        for (name, path) in [("tiny", gguf), ("st", dir.path().join("model.safetensors"))] {
+            registry.register(ModelEntry::new(name, path));
+        }
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Echo)),
+            registry,
+        ));
+        let fetch = |name: &str| model_metadata(State(state.clone()), Path(name.to_string()));
+
+        let response = fetch("tiny").await.into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let meta: ModelMetadataResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(meta.architecture.as_deref(), Some("llama"));
+        assert_eq!(meta.context_length, Some(8192));
+        assert_eq!(meta.quantization.as_deref(), Some("Q4_K_M"));
+        assert_eq!(meta.tokenizer.as_deref(), Some("llama"));
+        assert_eq!(meta.tensor_count, 1);
+        assert_eq!(meta.metadata["llama.context_length"], 8192);
+
+        let response = fetch("st").await.into_response();
+        assert_eq!(
+            response.status(),
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+        let response = fetch("missing").await.into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_warm_prefix_then_generate_hits_prefix_cache() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("rag", "./rag.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Text("answer")).with_prefix_cache()),
+            registry,
+        ));
+        let context = "Context: the sky is blue because of Rayleigh scattering.";
+
+        let response = warm(
+            State(state.clone()),
+            Json(WarmRequest {
+                model: "rag".to_string(),
+                prompt: context.to_string(),
+            }),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let warmed: WarmResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(warmed.cached_tokens, 9);
+
+        let mut request = raw_request("rag");
+        request.prompt = Some(format!("{} Why is the sky blue?", context));
+        let response = generate(State(state.clone()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+
+        let spec = state.registry.to_spec("rag").unwrap();
+        let loaded = state
+            .model_pool
+            .get_or_load(&*state.engine, &spec)
+            .await
+            .unwrap();
+        let stats = loaded.prefix_cache_stats().unwrap();
+        // The warm call missed; the real request reused the warmed context
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.reused_tokens, 9);
+    }
+
+    #[tokio::test]
+    async fn test_warm_unsupported_model_is_an_error() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("echo", "./echo.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Echo)),
+            registry,
+        ));
+        let request = |model: &str| WarmRequest {
+            model: model.to_string(),
+            prompt: "context".to_string(),
+        };
+
+        let response = warm(State(state.clone()), Json(request("echo")))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_IMPLEMENTED);
+        let response = warm(State(state), Json(request("missing")))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_warm_rejects_overlong_prompt() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("rag", "./rag.gguf"));
+        let mut state = AppState::new(
+            Box::new(MockEngine::new(Reply::Text("answer")).with_prefix_cache()),
+            registry,
+        );
+        state.server_config.max_prompt_tokens = Some(5);
+        let state = Arc::new(state);
+        let request = |prompt: &str| WarmRequest {
+            model: "rag".to_string(),
+            prompt: prompt.to_string(),
+        };
+
+        let response = warm(State(state.clone()), Json(request(&["word"; 6].join(" "))))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        let response = warm(State(state), Json(request(&["word"; 5].join(" "))))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_generate_resolves_auto_tag() {
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        for (name, tags) in [("general", vec![]), ("coder", vec!["code".to_string()])] {
+            registry.register(ModelEntry::new(name, format!("./{}.gguf", name)).with_tags(tags));
+        }
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Echo)),
+            registry,
+        ));
+
+        let response = generate(
+            State(state.clone()),
+            HeaderMap::new(),
+            Json(raw_request("auto:code")),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let parsed: GenerateResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed.response, "coder: hi");
+
+        let response = generate(
+            State(state),
+            HeaderMap::new(),
+            Json(raw_request("auto:vision")),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let parsed: crate::api_errors::ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed.error, "No available model matches 'auto:vision'");
+    }
 }
 
 #[cfg(feature = "vision")]
@@ -1,4 +1,5 @@
 // Improved API error handling
+use crate::engine::EngineError;
 use axum::{http::StatusCode, response::Json};
 use serde::{Deserialize, Serialize};
 
@@ -36,6 +37,36 @@ impl From<ApiError> for (StatusCode, Json<ErrorResponse>) {
     }
 }
 
+/// HTTP status for a typed engine failure
+pub fn engine_status(err: &EngineError) -> StatusCode {
+    match err {
+        EngineError::ModelNotFound { .. } => StatusCode::NOT_FOUND,
+        EngineError::LoadFailed { .. } => StatusCode::BAD_GATEWAY,
+        EngineError::InvalidModelFile { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        EngineError::UnsupportedArchitecture { .. }
+        | EngineError::UnsupportedQuantization { .. } => StatusCode::NOT_IMPLEMENTED,
+        EngineError::InsufficientMemory { .. } => StatusCode::INSUFFICIENT_STORAGE,
+        EngineError::ResourceBusy { .. } => StatusCode::SERVICE_UNAVAILABLE,
+        EngineError::ContextExceeded { .. } => StatusCode::BAD_REQUEST,
+        EngineError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+        // 499 "client closed request", as used by nginx
+        EngineError::Cancelled => {
+            StatusCode::from_u16(499).unwrap_or(StatusCode::SERVICE_UNAVAILABLE)
+        }
+        EngineError::Unsupported { .. } => StatusCode::NOT_IMPLEMENTED,
+        EngineError::InvalidOptions { .. } => StatusCode::BAD_REQUEST,
+        EngineError::MemoryExceeded { .. } => StatusCode::INSUFFICIENT_STORAGE,
+    }
+}
+
+/// Status for any engine failure; untyped errors are treated as upstream
+/// failures (502), which is what handlers returned before typed errors.
+pub fn status_for(err: &anyhow::Error) -> StatusCode {
+    EngineError::find(err)
+        .map(engine_status)
+        .unwrap_or(StatusCode::BAD_GATEWAY)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -199,4 +230,91 @@ mod tests {
         assert_eq!(StatusCode::BAD_GATEWAY.as_u16(), 502);
         assert_eq!(StatusCode::BAD_REQUEST.as_u16(), 400);
     }
+
+    #[test]
+    fn test_status_codes_per_variant() {
+        let cases = [
+            (
+                EngineError::ModelNotFound { name: "x".into() },
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                EngineError::LoadFailed { reason: "x".into() },
+                StatusCode::BAD_GATEWAY,
+            ),
+            (
+                EngineError::InvalidModelFile { path: "x".into() },
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                EngineError::UnsupportedArchitecture {
+                    architecture: "x".into(),
+                },
+                StatusCode::NOT_IMPLEMENTED,
+            ),
+            (
+                EngineError::UnsupportedQuantization { detail: "x".into() },
+                StatusCode::NOT_IMPLEMENTED,
+            ),
+            (
+                EngineError::InsufficientMemory {
+                    path: "x".into(),
+                    size_bytes: 8 << 30,
+                },
+                StatusCode::INSUFFICIENT_STORAGE,
+            ),
+            (
+                EngineError::ResourceBusy {
+                    path: "x".into(),
+                    reason: "x".into(),
+                },
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                EngineError::Timeout {
+                    operation: "Model load".into(),
+                    after: std::time::Duration::from_secs(120),
+                },
+                StatusCode::GATEWAY_TIMEOUT,
+            ),
+            (EngineError::Cancelled, StatusCode::from_u16(499).unwrap()),
+            (
+                EngineError::Unsupported {
+                    feature: "vision".into(),
+                },
+                StatusCode::NOT_IMPLEMENTED,
+            ),
+            (
+                EngineError::InvalidOptions { reason: "x".into() },
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                EngineError::MemoryExceeded {
+                    model: "x".into(),
+                    estimated_bytes: 2 << 30,
+                    resident_bytes: 0,
+                    ceiling_bytes: 1 << 30,
+                },
+                StatusCode::INSUFFICIENT_STORAGE,
+            ),
+        ];
+        for (err, status) in cases {
+            assert_eq!(engine_status(&err), status, "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_status_for_finds_typed_errors_in_the_chain() {
+        let err: anyhow::Error = EngineError::ContextExceeded {
+            prompt_tokens: 5000,
+            ctx_len: 4096,
+        }
+        .into();
+        let err = err.context("generation failed");
+        assert_eq!(status_for(&err), StatusCode::BAD_REQUEST);
+
+        // Untyped errors map to bad gateway
+        let err = anyhow::anyhow!("backend exploded");
+        assert_eq!(status_for(&err), StatusCode::BAD_GATEWAY);
+    }
 }
@@ -0,0 +1,299 @@
+// Prompt/response audit log sink
+//
+// When enabled with `--audit-log <path>`, every generation request appends a
+// single JSON line to the audit file. Raw prompt/response text is only stored
+// when `--audit-include-content` is set; otherwise only a SHA-256 hash of the
+// prompt is recorded. The sink needs the `audit` feature. Stored text passes through the `--redact-config`
+// patterns first. Writes go through a channel to a background writer task
+// so inference is never blocked on disk I/O.
+
+use crate::redact::Redactor;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+/// Header clients can set to identify themselves in the audit log
+pub const CLIENT_ID_HEADER: &str = "x-client-id";
+
+/// Configuration for the audit log sink
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    pub path: PathBuf,
+    pub include_content: bool,
+    /// Applied to prompt/response text before it is written
+    pub redactor: Redactor,
+}
+
+/// One audit log line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: String,
+    pub model: String,
+    pub client_id: String,
+    pub prompt_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub status: u16,
+}
+
+/// Handle to the background audit writer. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct AuditLogger {
+    tx: mpsc::UnboundedSender<AuditRecord>,
+    include_content: bool,
+    redactor: Redactor,
+}
+
+impl AuditLogger {
+    /// Spawn the writer task and return a handle to it. Must be called from
+    /// within a tokio runtime.
+    pub fn new(config: AuditConfig) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run_writer(config.path, rx));
+        Self {
+            tx,
+            include_content: config.include_content,
+            redactor: config.redactor,
+        }
+    }
+
+    /// Queue a record for a completed request. Never blocks. Token counts
+    /// come from `count_tokens`, normally the loaded model's tokenizer.
+    pub fn record(
+        &self,
+        model: &str,
+        client_id: &str,
+        prompt: &str,
+        response: &str,
+        status: u16,
+        count_tokens: impl Fn(&str) -> usize,
+    ) {
+        let record = AuditRecord {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            model: model.to_string(),
+            client_id: client_id.to_string(),
+            prompt_hash: hash_prompt(prompt),
+            prompt: self
+                .include_content
+                .then(|| self.redactor.redact(prompt).into_owned()),
+            response: self
+                .include_content
+                .then(|| self.redactor.redact(response).into_owned()),
+            prompt_tokens: count_tokens(prompt),
+            completion_tokens: count_tokens(response),
+            status,
+        };
+        if self.tx.send(record).is_err() {
+            tracing::warn!("Audit writer has stopped; dropping audit record");
+        }
+    }
+}
+
+/// Extract the client id from request headers, falling back to "anonymous"
+pub fn client_id(headers: &axum::http::HeaderMap) -> String {
+    headers
+        .get(CLIENT_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .unwrap_or("anonymous")
+        .to_string()
+}
+
+/// Who a request counts against in per-user metrics: the OpenAI `user`
+/// field, else the bearer API key (hashed, never the key itself), else the
+/// client's forwarded IP, else the `x-client-id` header
+pub fn request_identity(user: Option<&str>, headers: &axum::http::HeaderMap) -> String {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+    if let Some(user) = user.map(str::trim).filter(|u| !u.is_empty()) {
+        return user.to_string();
+    }
+    if let Some(key) = header("authorization").and_then(|v| v.strip_prefix("Bearer ")) {
+        return format!("key:{}", &hash_prompt(key)[..12]);
+    }
+    if let Some(ip) = header("x-forwarded-for")
+        .and_then(|v| v.split(',').next())
+        .map(str::trim)
+        .or_else(|| header("x-real-ip"))
+    {
+        return format!("ip:{}", ip);
+    }
+    client_id(headers)
+}
+
+/// Stable stand-in for an identity in logs, so raw user ids stay out of them
+pub fn redact_identity(identity: &str) -> String {
+    format!("id:{}", &hash_prompt(identity)[..12])
+}
+
+#[cfg(feature = "audit")]
+fn hash_prompt(prompt: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(prompt.as_bytes()))
+}
+
+/// Without the `audit` feature `--audit-log` is rejected at startup, so only
+/// identities are hashed, with std's SipHash instead of SHA-256
+#[cfg(not(feature = "audit"))]
+fn hash_prompt(prompt: &str) -> String {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    prompt.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+async fn run_writer(path: PathBuf, mut rx: mpsc::UnboundedReceiver<AuditRecord>) {
+    let mut file = match tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await
+    {
+        Ok(f) => f,
+        Err(e) => {
+            tracing::error!("Failed to open audit log {}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    while let Some(record) = rx.recv().await {
+        let mut line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!("Failed to serialize audit record: {}", e);
+                continue;
+            }
+        };
+        line.push('\n');
+        if let Err(e) = file.write_all(line.as_bytes()).await {
+            tracing::error!("Failed to write audit record: {}", e);
+            continue;
+        }
+        let _ = file.flush().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::estimate_tokens;
+    use std::time::Duration;
+
+    #[test]
+    fn test_request_identity_fallbacks() {
+        let mut headers = axum::http::HeaderMap::new();
+        assert_eq!(request_identity(None, &headers), "anonymous");
+
+        headers.insert("x-forwarded-for", "10.0.0.7, 10.0.0.1".parse().unwrap());
+        assert_eq!(request_identity(None, &headers), "ip:10.0.0.7");
+
+        headers.insert("authorization", "Bearer sk-secret".parse().unwrap());
+        let key = request_identity(None, &headers);
+        assert!(key.starts_with("key:"));
+        assert!(!key.contains("sk-secret"));
+
+        assert_eq!(request_identity(Some("alice"), &headers), "alice");
+        assert_eq!(request_identity(Some(" "), &headers), key);
+        assert!(!redact_identity("alice").contains("alice"));
+    }
+
+    async fn read_lines(path: &std::path::Path, expected: usize) -> Vec<serde_json::Value> {
+        for _ in 0..50 {
+            if let Ok(content) = tokio::fs::read_to_string(path).await {
+                let lines: Vec<_> = content
+                    .lines()
+                    .map(|l| serde_json::from_str(l).unwrap())
+                    .collect();
+                if lines.len() >= expected {
+                    return lines;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("audit log never reached {} lines", expected);
+    }
+
+    #[tokio::test]
+    async fn test_audit_record_has_expected_fields_and_redacts_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let logger = AuditLogger::new(AuditConfig {
+            path: path.clone(),
+            include_content: false,
+            redactor: Redactor::default(),
+        });
+
+        logger.record(
+            "phi3",
+            "client-a",
+            "secret prompt",
+            "secret answer",
+            200,
+            estimate_tokens,
+        );
+
+        let lines = read_lines(&path, 1).await;
+        let line = &lines[0];
+        assert_eq!(line["model"], "phi3");
+        assert_eq!(line["client_id"], "client-a");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["prompt_hash"], hash_prompt("secret prompt"));
+        assert!(line["timestamp"].is_string());
+        assert!(line["prompt_tokens"].as_u64().unwrap() > 0);
+        assert!(line["completion_tokens"].as_u64().unwrap() > 0);
+        assert!(line.get("prompt").is_none());
+        assert!(line.get("response").is_none());
+
+        let raw = tokio::fs::read_to_string(&path).await.unwrap();
+        assert!(!raw.contains("secret prompt"));
+        assert!(!raw.contains("secret answer"));
+    }
+
+    #[tokio::test]
+    async fn test_audit_include_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let logger = AuditLogger::new(AuditConfig {
+            path: path.clone(),
+            include_content: true,
+            redactor: Redactor::default(),
+        });
+
+        logger.record("phi3", "anonymous", "hello", "world", 200, |t| t.len());
+        logger.record("phi3", "anonymous", "again", "", 502, estimate_tokens);
+
+        let lines = read_lines(&path, 2).await;
+        assert_eq!(lines[0]["prompt"], "hello");
+        assert_eq!(lines[0]["response"], "world");
+        assert_eq!(lines[0]["prompt_tokens"], 5);
+        assert_eq!(lines[1]["status"], 502);
+    }
+
+    #[test]
+    fn test_client_id_from_headers() {
+        let mut headers = axum::http::HeaderMap::new();
+        assert_eq!(client_id(&headers), "anonymous");
+        headers.insert(CLIENT_ID_HEADER, "team-42".parse().unwrap());
+        assert_eq!(client_id(&headers), "team-42");
+    }
+
+    #[cfg(feature = "audit")]
+    #[test]
+    fn test_prompt_hash_is_sha256_hex() {
+        let hash = hash_prompt("abc");
+        assert_eq!(
+            hash,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
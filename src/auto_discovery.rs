@@ -3,6 +3,64 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Directories given with `--models-dir`, searched by every discovery run
+static EXTRA_SEARCH_PATHS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// Whether `--follow-symlinks` was given
+static FOLLOW_SYMLINKS: OnceLock<bool> = OnceLock::new();
+
+/// How many directory levels below a search path are scanned, unless
+/// `SHIMMY_DISCOVERY_DEPTH` says otherwise
+pub const DEFAULT_DISCOVERY_DEPTH: usize = 4;
+
+/// Register the `--models-dir` directories for this process. Only the first
+/// call takes effect.
+pub fn set_extra_search_paths(paths: Vec<PathBuf>) {
+    let _ = EXTRA_SEARCH_PATHS.set(paths);
+}
+
+/// Register whether discovery follows symlinked directories (on unless
+/// `--no-follow-symlinks`). Only the first call takes effect.
+pub fn set_follow_symlinks(follow: bool) {
+    let _ = FOLLOW_SYMLINKS.set(follow);
+}
+
+/// Scan depth from `SHIMMY_DISCOVERY_DEPTH`, falling back to the default when
+/// unset or not a number
+fn discovery_depth_from_env() -> usize {
+    std::env::var("SHIMMY_DISCOVERY_DEPTH")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_DISCOVERY_DEPTH)
+}
+
+/// Cap on auto-discovered models from `SHIMMY_MAX_DISCOVERED`; unlimited when
+/// unset or not a number
+fn max_discovered_from_env() -> Option<usize> {
+    std::env::var("SHIMMY_MAX_DISCOVERED")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// A directory with symlinks resolved, so one reached through several links
+/// is only scanned once
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(dir: &Path) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(dir).ok().map(|m| (m.dev(), m.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(dir: &Path) -> Option<DirId> {
+    fs::canonicalize(dir).ok()
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiscoveredModel {
@@ -13,6 +71,16 @@ pub struct DiscoveredModel {
     pub model_type: String,
     pub parameter_count: Option<String>,
     pub quantization: Option<String>,
+    /// Human-readable name from an LM Studio or Jan catalog, when available
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// User settings from `model_overrides.json`, merged in during discovery
+    #[serde(default)]
+    pub overrides: crate::model_overrides::ModelOverride,
+    /// Purpose tags (`code`, `chat`, `vision`, ...) inferred from the file
+    /// name, listed on `/v1/models`
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,13 +115,82 @@ struct OllamaLayer {
     size: i64,
 }
 
+/// Catalog files LM Studio (`manifest.json`) and Jan (`model.json`) keep next to
+/// downloaded models
+const CATALOG_FILES: &[&str] = &["manifest.json", "model.json"];
+
+#[derive(Debug, Deserialize)]
+struct CatalogEntry {
+    #[serde(rename = "displayName", alias = "display_name")]
+    display_name: Option<String>,
+    name: Option<String>,
+    quantization: Option<String>,
+    #[serde(default)]
+    files: Vec<CatalogFile>,
+    #[serde(default)]
+    sources: Vec<CatalogFile>,
+    metadata: Option<CatalogMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CatalogFile {
+    #[serde(alias = "filename")]
+    name: Option<String>,
+    quantization: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CatalogMetadata {
+    quantization: Option<String>,
+}
+
+/// Display name and quantization taken from a catalog entry
+#[derive(Debug, Default, PartialEq)]
+struct CatalogInfo {
+    display_name: Option<String>,
+    quantization: Option<String>,
+}
+
+/// A raw PyTorch checkpoint found while scanning. Shimmy can't load these, so
+/// they are reported by `discover` instead of being registered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnconvertedCheckpoint {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+}
+
+impl UnconvertedCheckpoint {
+    pub fn conversion_hint(&self) -> &'static str {
+        "convert to GGUF with llama.cpp's convert_hf_to_gguf.py (run it on the directory \
+         with config.json), or re-save the weights as SafeTensors"
+    }
+}
+
 pub struct ModelAutoDiscovery {
     pub search_paths: Vec<PathBuf>,
+    /// Overlay file merged onto discovered models
+    pub overrides_path: PathBuf,
+    /// Directory levels scanned below each search path
+    pub max_depth: usize,
+    /// Descend into symlinked directories; already-visited directories are
+    /// skipped, so link cycles end. Symlinked model files, such as the
+    /// Hugging Face cache's snapshot links into `blobs/`, are always resolved.
+    pub follow_symlinks: bool,
+    /// Keep at most this many discovered models, most recently modified
+    /// first. Manually registered models are not discovered, so never capped.
+    pub max_discovered: Option<usize>,
 }
 
 impl ModelAutoDiscovery {
     pub fn new() -> Self {
+        Self::with_extra_dirs(EXTRA_SEARCH_PATHS.get().cloned().unwrap_or_default())
+    }
+
+    /// Default search paths plus `extra_dirs`, which are searched right after
+    /// `./models`. Paths already in the list are not repeated.
+    pub fn with_extra_dirs(extra_dirs: Vec<PathBuf>) -> Self {
         let mut search_paths = vec![PathBuf::from("./models")];
+        search_paths.extend(extra_dirs);
 
         // Add paths from environment variables
         if let Ok(shimmy_base) = std::env::var("SHIMMY_BASE_GGUF") {
@@ -80,6 +217,7 @@ impl ModelAutoDiscovery {
             search_paths.push(PathBuf::from(home.clone()).join(".cache/huggingface/hub"));
             search_paths.push(PathBuf::from(home.clone()).join(".ollama/models"));
             search_paths.push(PathBuf::from(home.clone()).join(".lmstudio/models"));
+            search_paths.push(PathBuf::from(home.clone()).join(".jan/models"));
             search_paths.push(PathBuf::from(home.clone()).join("models"));
             search_paths.push(PathBuf::from(home).join(".local/share/shimmy/models"));
         }
@@ -89,6 +227,7 @@ impl ModelAutoDiscovery {
             search_paths.push(PathBuf::from(user_profile.clone()).join(".cache\\huggingface\\hub"));
             search_paths.push(PathBuf::from(user_profile.clone()).join(".ollama\\models"));
             search_paths.push(PathBuf::from(user_profile.clone()).join(".lmstudio\\models"));
+            search_paths.push(PathBuf::from(user_profile.clone()).join(".jan\\models"));
             search_paths.push(PathBuf::from(user_profile.clone()).join("models"));
             search_paths
                 .push(PathBuf::from(user_profile.clone()).join("AppData\\Local\\shimmy\\models"));
@@ -117,7 +256,16 @@ impl ModelAutoDiscovery {
             }
         }
 
-        Self { search_paths }
+        let mut seen = std::collections::HashSet::new();
+        search_paths.retain(|path| seen.insert(path.clone()));
+
+        Self {
+            search_paths,
+            overrides_path: crate::model_overrides::ModelOverrides::default_path(),
+            max_depth: discovery_depth_from_env(),
+            follow_symlinks: FOLLOW_SYMLINKS.get().copied().unwrap_or(true),
+            max_discovered: max_discovered_from_env(),
+        }
     }
 
     #[allow(dead_code)]
@@ -150,6 +298,11 @@ impl ModelAutoDiscovery {
         // Remove duplicates based on file hash or path
         discovered.sort_by(|a, b| a.path.cmp(&b.path));
         discovered.dedup_by(|a, b| a.path == b.path);
+        if let Some(max) = self.max_discovered {
+            Self::cap_discovered(&mut discovered, max);
+        }
+
+        crate::model_overrides::ModelOverrides::load(&self.overrides_path).apply(&mut discovered);
 
         // PPT Invariant: Validate discovery results before returning
         shimmy_invariants::assert_discovery_valid(discovered.len());
@@ -168,53 +321,226 @@ impl ModelAutoDiscovery {
         Ok(discovered)
     }
 
+    /// Keep the `max` most recently modified models (larger first on ties),
+    /// still in path order
+    fn cap_discovered(discovered: &mut Vec<DiscoveredModel>, max: usize) {
+        if discovered.len() <= max {
+            return;
+        }
+        let modified = |m: &DiscoveredModel| {
+            fs::metadata(&m.path)
+                .and_then(|meta| meta.modified())
+                .unwrap_or(std::time::UNIX_EPOCH)
+        };
+        discovered.sort_by_cached_key(|m| {
+            (
+                std::cmp::Reverse(modified(m)),
+                std::cmp::Reverse(m.size_bytes),
+            )
+        });
+        let skipped = discovered.len() - max;
+        discovered.truncate(max);
+        discovered.sort_by(|a, b| a.path.cmp(&b.path));
+        tracing::warn!(
+            "Discovered more models than SHIMMY_MAX_DISCOVERED={}; skipped the {} least recently modified",
+            max,
+            skipped
+        );
+    }
+
     fn scan_directory(&self, dir: &Path) -> Result<Vec<DiscoveredModel>> {
-        self.scan_directory_with_depth(dir, 0)
+        let mut visited = std::collections::HashSet::new();
+        visited.extend(dir_id(dir));
+        self.scan_directory_with_depth(dir, 0, &mut visited)
     }
 
-    fn scan_directory_with_depth(&self, dir: &Path, depth: usize) -> Result<Vec<DiscoveredModel>> {
-        // Prevent infinite recursion - limit depth to 4 levels for performance
-        if depth >= 4 {
-            return Ok(Vec::new());
+    /// Whether a subdirectory found while scanning should be entered: not a
+    /// skipped directory, not a symlink unless following them, and not
+    /// already scanned (which is what stops symlink cycles)
+    fn should_descend(
+        &self,
+        entry: &fs::DirEntry,
+        visited: &mut std::collections::HashSet<DirId>,
+    ) -> bool {
+        let path = entry.path();
+        if Self::is_skipped_subdirectory(&path) {
+            return false;
+        }
+        let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+        if is_symlink && !self.follow_symlinks {
+            return false;
         }
+        dir_id(&path).is_some_and(|id| visited.insert(id))
+    }
 
-        // Skip system directories that cause problems on macOS and other systems
-        if let Some(dir_name) = dir.file_name().and_then(|n| n.to_str()) {
-            // Skip hidden directories except known model directories
-            if dir_name.starts_with('.')
-                && dir_name != ".cache"
-                && dir_name != ".ollama"
-                && dir_name != ".local"
-            {
-                return Ok(Vec::new());
+    /// PyTorch checkpoints (`.pt`, `.pth`, `pytorch_model*.bin`) under the search
+    /// paths, which need converting before they can be served. Returns nothing
+    /// when `SHIMMY_DISCOVER_CHECKPOINTS=0`.
+    pub fn discover_unconverted(&self) -> Vec<UnconvertedCheckpoint> {
+        if std::env::var("SHIMMY_DISCOVER_CHECKPOINTS").is_ok_and(|v| v == "0") {
+            return Vec::new();
+        }
+        let mut found = Vec::new();
+        for search_path in &self.search_paths {
+            if search_path.is_dir() {
+                let mut visited = std::collections::HashSet::new();
+                visited.extend(dir_id(search_path));
+                self.scan_checkpoints(search_path, 0, &mut visited, &mut found);
             }
+        }
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+        found.dedup_by(|a, b| a.path == b.path);
+        found
+    }
 
-            // Skip problematic macOS directories
-            match dir_name {
-                "Library" | "Applications" | "System" | "Developer" | "usr" | "var" | "tmp"
-                | "private" | "Volumes" | "cores" | "dev" | "etc" | "home" | "net" | "proc"
-                | "opt" | "sbin" | "bin" => {
-                    return Ok(Vec::new());
+    fn scan_checkpoints(
+        &self,
+        dir: &Path,
+        depth: usize,
+        visited: &mut std::collections::HashSet<DirId>,
+        found: &mut Vec<UnconvertedCheckpoint>,
+    ) {
+        if depth >= self.max_depth || Self::is_skipped_directory(dir) {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if self.should_descend(&entry, visited) {
+                    self.scan_checkpoints(&path, depth + 1, visited, found);
                 }
-                _ => {}
+            } else if Self::is_pytorch_checkpoint(&path) {
+                found.push(UnconvertedCheckpoint {
+                    size_bytes: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+                    path,
+                });
             }
+        }
+    }
+
+    fn is_pytorch_checkpoint(path: &Path) -> bool {
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        ext == "pt" || ext == "pth" || (ext == "bin" && file_name.starts_with("pytorch_model"))
+    }
+
+    /// Hidden and OS system directories, which are never scanned
+    fn is_skipped_directory(dir: &Path) -> bool {
+        let Some(dir_name) = dir.file_name().and_then(|n| n.to_str()) else {
+            return false;
+        };
+        // Skip hidden directories except known model directories
+        if dir_name.starts_with('.')
+            && dir_name != ".cache"
+            && dir_name != ".ollama"
+            && dir_name != ".local"
+        {
+            return true;
+        }
+
+        // Skip problematic macOS directories
+        if matches!(
+            dir_name,
+            "Library"
+                | "Applications"
+                | "System"
+                | "Developer"
+                | "usr"
+                | "var"
+                | "tmp"
+                | "private"
+                | "Volumes"
+                | "cores"
+                | "dev"
+                | "etc"
+                | "home"
+                | "net"
+                | "proc"
+                | "opt"
+                | "sbin"
+                | "bin"
+        ) {
+            return true;
+        }
 
-            // Skip Windows system directories
-            #[cfg(windows)]
-            match dir_name.to_lowercase().as_str() {
-                "windows"
+        // Skip Windows system directories
+        #[cfg(windows)]
+        if matches!(
+            dir_name.to_lowercase().as_str(),
+            "windows"
                 | "program files"
                 | "program files (x86)"
                 | "programdata"
                 | "users"
                 | "system volume information"
                 | "$recycle.bin"
-                | "recovery" => {
-                    return Ok(Vec::new());
-                }
-                _ => {}
+                | "recovery"
+        ) {
+            return true;
+        }
+
+        false
+    }
+
+    /// Build/cache directories and non-LLM model directories, skipped while
+    /// recursing
+    fn is_skipped_subdirectory(path: &Path) -> bool {
+        let dir_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        if dir_name == "target"
+            || dir_name == "cmake"
+            || dir_name == "incremental"
+            || dir_name.starts_with(".git")
+            || dir_name.contains("whisper")
+            || dir_name.contains("wav2vec")
+            || dir_name.contains("bert")
+            || dir_name.contains("clip")
+        {
+            return true;
+        }
+        // Only scan directories that might contain LLM models
+        if path.to_string_lossy().contains("huggingface") {
+            let path_str = path.to_string_lossy().to_lowercase();
+            if !(path_str.contains("llama")
+                || path_str.contains("phi")
+                || path_str.contains("mistral")
+                || path_str.contains("qwen")
+                || path_str.contains("gemma")
+                || path_str.contains("gguf"))
+            {
+                return true;
             }
         }
+        false
+    }
+
+    fn scan_directory_with_depth(
+        &self,
+        dir: &Path,
+        depth: usize,
+        visited: &mut std::collections::HashSet<DirId>,
+    ) -> Result<Vec<DiscoveredModel>> {
+        // Limit recursion depth for performance (SHIMMY_DISCOVERY_DEPTH)
+        if depth >= self.max_depth {
+            return Ok(Vec::new());
+        }
+
+        // Skip system directories that cause problems on macOS and other systems
+        if Self::is_skipped_directory(dir) {
+            return Ok(Vec::new());
+        }
 
         let mut models = Vec::new();
         let mut model_files = Vec::new();
@@ -234,37 +560,11 @@ impl ModelAutoDiscovery {
 
             // Skip build and cache directories
             if path.is_dir() {
-                let dir_name = path
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("")
-                    .to_lowercase();
-                if dir_name == "target"
-                    || dir_name == "cmake"
-                    || dir_name == "incremental"
-                    || dir_name.starts_with(".git")
-                    || dir_name.contains("whisper")
-                    || dir_name.contains("wav2vec")
-                    || dir_name.contains("bert")
-                    || dir_name.contains("clip")
-                {
+                if !self.should_descend(&entry, visited) {
                     continue;
                 }
-                // Only scan directories that might contain LLM models
-                if path.to_string_lossy().contains("huggingface") {
-                    let path_str = path.to_string_lossy().to_lowercase();
-                    if !(path_str.contains("llama")
-                        || path_str.contains("phi")
-                        || path_str.contains("mistral")
-                        || path_str.contains("qwen")
-                        || path_str.contains("gemma")
-                        || path_str.contains("gguf"))
-                    {
-                        continue;
-                    }
-                }
                 // Recursively scan subdirectories with depth tracking
-                models.extend(self.scan_directory_with_depth(&path, depth + 1)?);
+                models.extend(self.scan_directory_with_depth(&path, depth + 1, visited)?);
             } else if self.is_model_file(&path) {
                 model_files.push(path);
             }
@@ -359,6 +659,7 @@ impl ModelAutoDiscovery {
                 let lora_path = files.iter().find_map(|path| self.find_lora_for_model(path));
 
                 grouped_models.push(DiscoveredModel {
+                    tags: crate::model_registry::tags_for_file(first_file, &model_name),
                     name: model_name,
                     path: descriptive_path,
                     lora_path,
@@ -366,6 +667,8 @@ impl ModelAutoDiscovery {
                     model_type: backend_type,
                     parameter_count,
                     quantization,
+                    display_name: None,
+                    overrides: Default::default(),
                 });
             }
         }
@@ -472,6 +775,8 @@ impl ModelAutoDiscovery {
             .to_string();
 
         let (model_type, parameter_count, quantization) = self.parse_filename(&filename);
+        let catalog = read_catalog_info(path, &filename).unwrap_or_default();
+        let quantization = catalog.quantization.or(quantization);
 
         // CRITICAL: All GGUF files must use Llama backend (PPT Invariant requirement)
         // GGUF is the llama.cpp format, regardless of model family name
@@ -495,6 +800,9 @@ impl ModelAutoDiscovery {
             model_type: backend_type,
             parameter_count,
             quantization,
+            display_name: catalog.display_name,
+            overrides: Default::default(),
+            tags: crate::model_registry::tags_for_file(path, &filename),
         })
     }
 
@@ -678,6 +986,10 @@ impl ModelAutoDiscovery {
                                         };
 
                                         let discovered = DiscoveredModel {
+                                            tags: crate::model_registry::tags_for_file(
+                                                &blob_path,
+                                                &display_name,
+                                            ),
                                             name: display_name,
                                             path: blob_path,
                                             lora_path: None,
@@ -685,6 +997,8 @@ impl ModelAutoDiscovery {
                                             model_type: "Ollama".to_string(),
                                             parameter_count: None,
                                             quantization: None,
+                                            display_name: None,
+                                            overrides: Default::default(),
                                         };
                                         models.push(discovered);
                                     }
@@ -789,6 +1103,38 @@ impl ModelAutoDiscovery {
     }
 }
 
+/// Look up catalog metadata for a model file. Catalogs that list files only
+/// apply to the files they list; otherwise the entry covers the whole directory.
+fn read_catalog_info(model_path: &Path, filename: &str) -> Option<CatalogInfo> {
+    let dir = model_path.parent()?;
+    CATALOG_FILES.iter().find_map(|catalog| {
+        let content = fs::read_to_string(dir.join(catalog)).ok()?;
+        let entry: CatalogEntry = serde_json::from_str(&content).ok()?;
+
+        let listed: Vec<&CatalogFile> = entry.files.iter().chain(&entry.sources).collect();
+        let file_quant = if listed.is_empty() {
+            None
+        } else {
+            let file = listed
+                .into_iter()
+                .find(|f| f.name.as_deref() == Some(filename))?;
+            file.quantization.clone()
+        };
+
+        let display_name = entry.display_name.or(entry.name);
+        let quantization = file_quant
+            .or(entry.quantization)
+            .or(entry.metadata.and_then(|m| m.quantization));
+        if display_name.is_none() && quantization.is_none() {
+            return None;
+        }
+        Some(CatalogInfo {
+            display_name,
+            quantization,
+        })
+    })
+}
+
 impl Default for ModelAutoDiscovery {
     fn default() -> Self {
         Self::new()
@@ -809,11 +1155,251 @@ mod tests {
             model_type: "Llama".to_string(),
             parameter_count: Some("7B".to_string()),
             quantization: Some("Q4_K_M".to_string()),
+            display_name: None,
+            overrides: Default::default(),
+            tags: Vec::new(),
         };
         assert_eq!(model.name, "test");
         assert_eq!(model.size_bytes, 1024);
     }
 
+    #[test]
+    fn test_extra_dirs_are_all_searched_once() {
+        let extra = vec![
+            PathBuf::from("/data/models;archive"),
+            PathBuf::from(r"D:\models"),
+            PathBuf::from("./models"),
+            PathBuf::from("/data/models;archive"),
+        ];
+        let discovery = ModelAutoDiscovery::with_extra_dirs(extra);
+        let count = |p: &str| {
+            discovery
+                .search_paths
+                .iter()
+                .filter(|s| s.as_path() == Path::new(p))
+                .count()
+        };
+        assert_eq!(count("/data/models;archive"), 1);
+        assert_eq!(count(r"D:\models"), 1);
+        assert_eq!(count("./models"), 1);
+        assert_eq!(
+            &discovery.search_paths[..3],
+            &[
+                PathBuf::from("./models"),
+                PathBuf::from("/data/models;archive"),
+                PathBuf::from(r"D:\models"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_override_survives_fresh_discovery() {
+        use crate::model_overrides::{ModelOverride, ModelOverrides};
+
+        let dir = tempfile::tempdir().unwrap();
+        // tempdir names start with '.', which discovery skips as hidden
+        let models_dir = dir.path().join("models");
+        fs::create_dir(&models_dir).unwrap();
+        let model_path = models_dir.join("phi-3-mini.safetensors");
+        fs::write(&model_path, b"weights").unwrap();
+        let overrides_path = dir.path().join("model_overrides.json");
+        let discovery = || ModelAutoDiscovery {
+            search_paths: vec![models_dir.clone()],
+            overrides_path: overrides_path.clone(),
+            max_depth: DEFAULT_DISCOVERY_DEPTH,
+            follow_symlinks: false,
+            max_discovered: None,
+        };
+
+        let first = discovery().discover_models().unwrap();
+        let model = first.iter().find(|m| m.path == model_path).unwrap();
+        assert!(model.overrides.is_empty());
+
+        ModelOverrides::load(&overrides_path)
+            .set(
+                &model_path,
+                ModelOverride {
+                    template: Some("llama3".to_string()),
+                    ctx_len: Some(8192),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let fresh = discovery().discover_models().unwrap();
+        let model = fresh.iter().find(|m| m.path == model_path).unwrap();
+        assert_eq!(model.overrides.template.as_deref(), Some("llama3"));
+        assert_eq!(model.overrides.ctx_len, Some(8192));
+    }
+
+    #[test]
+    fn test_pytorch_checkpoints_need_conversion() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_dir = dir.path().join("models").join("my-llama");
+        fs::create_dir_all(&model_dir).unwrap();
+        fs::write(model_dir.join("pytorch_model.bin"), b"torch").unwrap();
+        fs::write(model_dir.join("adapter.pth"), b"torch").unwrap();
+        fs::write(model_dir.join("model.safetensors"), b"weights").unwrap();
+        let discovery = ModelAutoDiscovery {
+            search_paths: vec![dir.path().join("models")],
+            overrides_path: dir.path().join("model_overrides.json"),
+            max_depth: DEFAULT_DISCOVERY_DEPTH,
+            follow_symlinks: false,
+            max_discovered: None,
+        };
+
+        let checkpoints = discovery.discover_unconverted();
+        let names: Vec<_> = checkpoints
+            .iter()
+            .map(|c| c.path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["adapter.pth", "pytorch_model.bin"]);
+        assert_eq!(checkpoints[1].size_bytes, 5);
+        assert!(checkpoints[1].conversion_hint().contains("GGUF"));
+
+        // Only the SafeTensors file is loadable
+        let models = discovery.discover_models().unwrap();
+        assert!(models.iter().all(
+            |m| !m.path.starts_with(&model_dir) || m.path.extension().unwrap() == "safetensors"
+        ));
+        assert!(models
+            .iter()
+            .any(|m| m.path == model_dir.join("model.safetensors")));
+    }
+
+    fn discovery_in(dir: &Path, follow_symlinks: bool) -> ModelAutoDiscovery {
+        ModelAutoDiscovery {
+            search_paths: vec![dir.join("models")],
+            overrides_path: dir.join("model_overrides.json"),
+            max_depth: DEFAULT_DISCOVERY_DEPTH,
+            follow_symlinks,
+            max_discovered: None,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_model_dir_found_only_when_following() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = dir.path().join("store").join("llama-7b");
+        fs::create_dir_all(&store).unwrap();
+        fs::write(store.join("llama-7b.Q4_K_M.gguf"), b"weights").unwrap();
+        fs::create_dir(dir.path().join("models")).unwrap();
+        std::os::unix::fs::symlink(&store, dir.path().join("models").join("linked")).unwrap();
+
+        let found = |follow| {
+            discovery_in(dir.path(), follow)
+                .discover_models()
+                .unwrap()
+                .into_iter()
+                .filter(|m| m.path.starts_with(dir.path()))
+                .count()
+        };
+        assert_eq!(found(false), 0);
+        assert_eq!(found(true), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hf_snapshot_links_resolve_to_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("models").join("models--org--phi-3-mini");
+        let snapshot = repo.join("snapshots").join("abc123");
+        fs::create_dir_all(&snapshot).unwrap();
+        fs::create_dir_all(repo.join("blobs")).unwrap();
+        fs::write(repo.join("blobs").join("9f86d081"), b"gguf weights").unwrap();
+        let link = snapshot.join("phi-3-mini.gguf");
+        std::os::unix::fs::symlink("../../blobs/9f86d081", &link).unwrap();
+
+        let models = discovery_in(dir.path(), false).discover_models().unwrap();
+        let model = models.iter().find(|m| m.path == link).unwrap();
+        assert_eq!(model.size_bytes, 12);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_cycle_terminates() {
+        let dir = tempfile::tempdir().unwrap();
+        let models_dir = dir.path().join("models");
+        fs::create_dir_all(models_dir.join("nested")).unwrap();
+        fs::write(models_dir.join("qwen-7b.gguf"), b"weights").unwrap();
+        std::os::unix::fs::symlink(&models_dir, models_dir.join("loop")).unwrap();
+        std::os::unix::fs::symlink(&models_dir, models_dir.join("nested").join("back")).unwrap();
+
+        let mut discovery = discovery_in(dir.path(), true);
+        discovery.max_depth = 64;
+        let found: Vec<_> = discovery
+            .discover_models()
+            .unwrap()
+            .into_iter()
+            .filter(|m| m.path.starts_with(dir.path()))
+            .collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, models_dir.join("qwen-7b.gguf"));
+    }
+
+    #[test]
+    fn test_max_discovered_keeps_most_recent() {
+        let dir = tempfile::tempdir().unwrap();
+        let models_dir = dir.path().join("models");
+        fs::create_dir(&models_dir).unwrap();
+        let now = std::time::SystemTime::now();
+        for (name, age_secs) in [
+            ("a-old", 400),
+            ("b-new", 10),
+            ("c-mid", 100),
+            ("d-older", 900),
+        ] {
+            let path = models_dir.join(format!("{}.gguf", name));
+            fs::write(&path, b"weights").unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(now - std::time::Duration::from_secs(age_secs))
+                .unwrap();
+        }
+
+        let mut discovery = discovery_in(dir.path(), false);
+        discovery.max_discovered = Some(2);
+        let names: Vec<String> = discovery
+            .discover_models()
+            .unwrap()
+            .into_iter()
+            .filter(|m| m.path.starts_with(dir.path()))
+            .map(|m| m.path.file_stem().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["b-new", "c-mid"]);
+
+        discovery.max_discovered = None;
+        let all = discovery.discover_models().unwrap();
+        assert_eq!(
+            all.iter()
+                .filter(|m| m.path.starts_with(dir.path()))
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_max_depth_limits_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let deep = dir.path().join("models").join("a").join("b");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("mistral-7b.gguf"), b"weights").unwrap();
+
+        let mut discovery = discovery_in(dir.path(), false);
+        let found = |d: &ModelAutoDiscovery| {
+            d.discover_models()
+                .unwrap()
+                .iter()
+                .any(|m| m.path.starts_with(&deep))
+        };
+        assert!(found(&discovery));
+        discovery.max_depth = 2;
+        assert!(!found(&discovery));
+    }
+
     #[test]
     fn test_model_auto_discovery_new() {
         let discovery = ModelAutoDiscovery::new();
@@ -828,4 +1414,96 @@ mod tests {
         assert_eq!(params, Some("7B".to_string()));
         assert_eq!(quant, Some("Q4_K_M".to_string()));
     }
+
+    #[test]
+    fn test_lmstudio_catalog_enriches_discovered_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_dir = dir
+            .path()
+            .join("lmstudio-community/Meta-Llama-3-8B-Instruct-GGUF");
+        fs::create_dir_all(&model_dir).unwrap();
+        let model_path = model_dir.join("llama3-instruct.gguf");
+        fs::write(&model_path, b"GGUF").unwrap();
+        fs::write(
+            model_dir.join("manifest.json"),
+            r#"{
+                "displayName": "Meta Llama 3 8B Instruct",
+                "files": [
+                    {"name": "llama3-instruct.gguf", "quantization": "Q5_K_M"},
+                    {"name": "llama3-instruct-q8.gguf", "quantization": "Q8_0"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let model = ModelAutoDiscovery::new()
+            .analyze_model_file(&model_path)
+            .unwrap();
+        assert_eq!(
+            model.display_name.as_deref(),
+            Some("Meta Llama 3 8B Instruct")
+        );
+        assert_eq!(model.quantization.as_deref(), Some("Q5_K_M"));
+        assert_eq!(model.name, "llama3-instruct");
+    }
+
+    #[test]
+    fn test_jan_catalog_uses_name_and_metadata_quantization() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_path = dir.path().join("mistral-ins.gguf");
+        fs::write(&model_path, b"GGUF").unwrap();
+        fs::write(
+            dir.path().join("model.json"),
+            r#"{
+                "id": "mistral-ins-7b",
+                "name": "Mistral Instruct 7B",
+                "sources": [{"filename": "mistral-ins.gguf", "url": "https://example.com"}],
+                "metadata": {"quantization": "Q4_0"}
+            }"#,
+        )
+        .unwrap();
+
+        let info = read_catalog_info(&model_path, "mistral-ins.gguf").unwrap();
+        assert_eq!(info.display_name.as_deref(), Some("Mistral Instruct 7B"));
+        assert_eq!(info.quantization.as_deref(), Some("Q4_0"));
+    }
+
+    #[test]
+    fn test_catalog_falls_back_to_filename_parsing() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_path = dir.path().join("phi-3b-q4_0.gguf");
+        fs::write(&model_path, b"GGUF").unwrap();
+        fs::write(
+            dir.path().join("manifest.json"),
+            r#"{"displayName": "Other Model", "files": [{"name": "other.gguf"}]}"#,
+        )
+        .unwrap();
+
+        let model = ModelAutoDiscovery::new()
+            .analyze_model_file(&model_path)
+            .unwrap();
+        assert!(model.display_name.is_none());
+        assert_eq!(model.quantization.as_deref(), Some("Q4_0"));
+
+        fs::write(dir.path().join("manifest.json"), "not json").unwrap();
+        assert!(read_catalog_info(&model_path, "phi-3b-q4_0.gguf").is_none());
+    }
+
+    #[test]
+    fn test_discovered_code_model_is_tagged() {
+        let dir = tempfile::tempdir().unwrap();
+        let coder = dir.path().join("qwen2.5-coder-7b-instruct-q4_k_m.gguf");
+        fs::write(&coder, b"GGUF").unwrap();
+        let plain = dir.path().join("phi-3b-q4_0.gguf");
+        fs::write(&plain, b"GGUF").unwrap();
+
+        let discovery = ModelAutoDiscovery::new();
+        let model = discovery.analyze_model_file(&coder).unwrap();
+        assert_eq!(model.tags, vec!["code", "chat"]);
+        assert!(discovery
+            .analyze_model_file(&plain)
+            .unwrap()
+            .tags
+            .is_empty());
+    }
 }
@@ -0,0 +1,126 @@
+// Bench results and baseline comparison
+//
+// `shimmy bench --save <file>` writes the run as JSON. `--baseline <file>`
+// compares throughput against a saved run and exits non-zero when tok/s
+// dropped by more than `--regression-threshold` percent, so `bench` can gate
+// CI on performance regressions.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// One bench run, as saved with `--save`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchResult {
+    pub model: String,
+    pub max_tokens: usize,
+    pub completion_tokens: usize,
+    pub elapsed_ms: u64,
+    pub tokens_per_sec: f64,
+}
+
+impl BenchResult {
+    pub fn new(
+        model: &str,
+        max_tokens: usize,
+        completion_tokens: usize,
+        elapsed: Duration,
+    ) -> Self {
+        let secs = elapsed.as_secs_f64();
+        Self {
+            model: model.to_string(),
+            max_tokens,
+            completion_tokens,
+            elapsed_ms: elapsed.as_millis() as u64,
+            tokens_per_sec: if secs > 0.0 {
+                completion_tokens as f64 / secs
+            } else {
+                0.0
+            },
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("writing bench results to {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("reading bench baseline {}", path.display()))?;
+        serde_json::from_str(&json)
+            .with_context(|| format!("parsing bench baseline {}", path.display()))
+    }
+}
+
+/// Throughput of a run relative to a baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchComparison {
+    pub baseline_tps: f64,
+    pub current_tps: f64,
+    /// Relative tok/s change in percent; negative is slower
+    pub change_pct: f64,
+    /// Slower than the baseline by more than the threshold
+    pub regressed: bool,
+}
+
+/// Compare `current` against `baseline`, flagging a tok/s drop larger than
+/// `threshold_pct` percent. A baseline without throughput can't regress.
+pub fn compare(
+    current: &BenchResult,
+    baseline: &BenchResult,
+    threshold_pct: f64,
+) -> BenchComparison {
+    let change_pct = if baseline.tokens_per_sec > 0.0 {
+        (current.tokens_per_sec - baseline.tokens_per_sec) / baseline.tokens_per_sec * 100.0
+    } else {
+        0.0
+    };
+    BenchComparison {
+        baseline_tps: baseline.tokens_per_sec,
+        current_tps: current.tokens_per_sec,
+        change_pct,
+        regressed: change_pct < -threshold_pct,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(tokens_per_sec: f64) -> BenchResult {
+        BenchResult {
+            model: "phi3".to_string(),
+            max_tokens: 64,
+            completion_tokens: 64,
+            elapsed_ms: 1000,
+            tokens_per_sec,
+        }
+    }
+
+    #[test]
+    fn test_slowdown_beyond_threshold_is_flagged() {
+        let comparison = compare(&run(85.0), &run(100.0), 10.0);
+        assert!(comparison.regressed);
+        assert!((comparison.change_pct + 15.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_small_variance_and_speedups_pass() {
+        assert!(!compare(&run(95.0), &run(100.0), 10.0).regressed);
+        assert!(!compare(&run(130.0), &run(100.0), 10.0).regressed);
+        assert!(!compare(&run(10.0), &run(0.0), 10.0).regressed);
+    }
+
+    #[test]
+    fn test_result_round_trips_through_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bench.json");
+        let result = BenchResult::new("phi3", 64, 32, Duration::from_millis(500));
+        assert_eq!(result.tokens_per_sec, 64.0);
+        result.save(&path).unwrap();
+        assert_eq!(BenchResult::load(&path).unwrap(), result);
+    }
+}
@@ -36,6 +36,9 @@ pub struct CacheKey {
     pub temperature: String, // Store as string to handle floating point comparison
     pub top_p: String,
     pub stop_sequences: Vec<String>,
+    pub trim_leading: bool,
+    pub stop_on_repeat: Option<crate::engine::RepeatStop>,
+    pub context_shift: bool,
 }
 
 impl CacheKey {
@@ -61,8 +64,32 @@ impl CacheKey {
             temperature: format!("{:.3}", temperature),
             top_p: format!("{:.3}", top_p),
             stop_sequences: stop_sequences.to_vec(),
+            trim_leading: true,
+            stop_on_repeat: None,
+            context_shift: false,
         }
     }
+
+    /// Key on whether the leading space/BOS token is stripped
+    pub fn with_trim_leading(mut self, trim_leading: bool) -> Self {
+        self.trim_leading = trim_leading;
+        self
+    }
+
+    /// Key on the loop detection that may have cut the response short
+    pub fn with_stop_on_repeat(
+        mut self,
+        stop_on_repeat: Option<crate::engine::RepeatStop>,
+    ) -> Self {
+        self.stop_on_repeat = stop_on_repeat;
+        self
+    }
+
+    /// Key on whether generation may run past the context window
+    pub fn with_context_shift(mut self, context_shift: bool) -> Self {
+        self.context_shift = context_shift;
+        self
+    }
 }
 
 /// Cached response entry
@@ -74,6 +101,9 @@ pub struct CachedResponse {
     pub access_count: u64,
     pub response_time: Duration,
     pub size_bytes: usize,
+    /// Per-entry TTL set by the request; `None` uses the cache's default TTL
+    #[serde(default)]
+    pub ttl: Option<Duration>,
 }
 
 impl CachedResponse {
@@ -88,6 +118,7 @@ impl CachedResponse {
             access_count: 1,
             response_time,
             size_bytes,
+            ttl: None,
         }
     }
 
@@ -102,6 +133,11 @@ impl CachedResponse {
             .unwrap_or_default()
             > ttl
     }
+
+    /// Check expiry against this entry's own TTL, falling back to `default_ttl`
+    pub fn is_expired_or_default(&self, default_ttl: Duration) -> bool {
+        self.is_expired(self.ttl.unwrap_or(default_ttl))
+    }
 }
 
 /// Response cache with LRU eviction and TTL
@@ -150,6 +186,11 @@ impl ResponseCache {
         }
     }
 
+    /// TTL for entries stored without a per-request override
+    pub fn default_ttl(&self) -> Duration {
+        self.config.default_ttl
+    }
+
     /// Get cached response if available and not expired
     pub async fn get(&self, key: &CacheKey) -> Option<String> {
         if !self.config.enabled {
@@ -160,7 +201,7 @@ impl ResponseCache {
         let mut stats = self.stats.write().await;
 
         if let Some(entry) = cache.get_mut(key) {
-            if entry.is_expired(self.config.default_ttl) {
+            if entry.is_expired_or_default(self.config.default_ttl) {
                 // Remove expired entry
                 cache.remove(key);
                 stats.misses += 1;
@@ -187,11 +228,27 @@ impl ResponseCache {
 
     /// Store response in cache
     pub async fn put(&self, key: CacheKey, response: String, response_time: Duration) {
-        if !self.config.enabled || response.len() > self.config.max_prompt_length {
+        self.put_with_ttl(key, response, response_time, None).await
+    }
+
+    /// Store response in cache with its own TTL instead of the default.
+    /// A zero TTL means the response must not be stored at all.
+    pub async fn put_with_ttl(
+        &self,
+        key: CacheKey,
+        response: String,
+        response_time: Duration,
+        ttl: Option<Duration>,
+    ) {
+        if !self.config.enabled
+            || response.len() > self.config.max_prompt_length
+            || ttl == Some(Duration::ZERO)
+        {
             return;
         }
 
-        let entry = CachedResponse::new(response, response_time);
+        let mut entry = CachedResponse::new(response, response_time);
+        entry.ttl = ttl;
         let entry_size = entry.size_bytes;
 
         let mut cache = self.cache.write().await;
@@ -240,7 +297,7 @@ impl ResponseCache {
         let initial_count = cache.len();
         let initial_size = stats.total_size_bytes;
 
-        cache.retain(|_, entry| !entry.is_expired(self.config.default_ttl));
+        cache.retain(|_, entry| !entry.is_expired_or_default(self.config.default_ttl));
 
         // Recalculate size
         stats.total_size_bytes = cache.values().map(|e| e.size_bytes).sum();
@@ -360,6 +417,50 @@ mod tests {
         assert!(cache.get(&key).await.is_none());
     }
 
+    #[tokio::test]
+    async fn test_entry_ttl_override_expires_sooner() {
+        let cache = ResponseCache::new();
+        let short = create_test_key("time sensitive", "test-model");
+        let normal = create_test_key("evergreen", "test-model");
+
+        cache
+            .put_with_ttl(
+                short.clone(),
+                "now".to_string(),
+                Duration::from_millis(10),
+                Some(Duration::from_millis(50)),
+            )
+            .await;
+        cache
+            .put(
+                normal.clone(),
+                "always".to_string(),
+                Duration::from_millis(10),
+            )
+            .await;
+        assert!(cache.get(&short).await.is_some());
+
+        sleep(Duration::from_millis(60)).await;
+        assert!(cache.get(&short).await.is_none());
+        assert_eq!(cache.get(&normal).await, Some("always".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_zero_ttl_is_not_stored() {
+        let cache = ResponseCache::new();
+        let key = create_test_key("no store", "test-model");
+        cache
+            .put_with_ttl(
+                key.clone(),
+                "fresh".to_string(),
+                Duration::from_millis(10),
+                Some(Duration::ZERO),
+            )
+            .await;
+        assert!(cache.get(&key).await.is_none());
+        assert_eq!(cache.stats().await.entries, 0);
+    }
+
     #[tokio::test]
     async fn test_cache_eviction() {
         let config = ResponseCacheConfig {
@@ -409,6 +510,13 @@ mod tests {
 
         assert_eq!(key1, key2);
         assert_ne!(key1, key3);
+        assert_ne!(key1, key2.clone().with_trim_leading(false));
+        assert_ne!(
+            key1,
+            key2.clone()
+                .with_stop_on_repeat(Some(crate::engine::RepeatStop::default()))
+        );
+        assert_ne!(key1, key2.with_context_shift(true));
     }
 
     #[tokio::test]
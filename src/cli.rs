@@ -1,3 +1,4 @@
+use crate::model_registry::ModelSort;
 use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
@@ -18,6 +19,17 @@ pub struct Cli {
     )]
     pub model_dirs: Option<String>,
 
+    /// Extra model directory to search; repeat for several. Paths are taken
+    /// as-is, so they may contain ';'
+    #[arg(long = "models-dir", global = true, value_name = "DIR")]
+    pub models_dir: Vec<std::path::PathBuf>,
+
+    /// Don't descend into symlinked directories during model discovery. They
+    /// are followed by default; cycles are detected. Scan depth is set with
+    /// SHIMMY_DISCOVERY_DEPTH.
+    #[arg(long, global = true)]
+    pub no_follow_symlinks: bool,
+
     /// GPU backend to use for llama.cpp inference
     #[arg(
         long,
@@ -33,6 +45,80 @@ pub struct Cli {
     /// Offload first N MoE layers' expert tensors to CPU
     #[arg(long, global = true, value_name = "N", conflicts_with = "cpu_moe")]
     pub n_cpu_moe: Option<usize>,
+
+    /// Pick MoE CPU offload per model from expert size vs free VRAM (--cpu-moe/--n-cpu-moe override)
+    #[arg(long, global = true)]
+    pub moe_auto: bool,
+
+    /// Give up on a model load after this many seconds
+    #[arg(long, global = true, value_name = "SECS", default_value_t = 120)]
+    pub load_timeout_secs: u64,
+
+    /// Run a single-token decode right after each model load (also SHIMMY_WARMUP=1)
+    #[arg(long, global = true)]
+    pub warmup: bool,
+
+    /// Refuse model loads whose estimated memory (weights + KV cache), on top
+    /// of models already loaded, would exceed this many MiB
+    #[arg(long, global = true, value_name = "MB")]
+    pub memory_ceiling_mb: Option<u64>,
+
+    /// Load models even when they exceed --memory-ceiling-mb
+    #[arg(long, global = true)]
+    pub force: bool,
+
+    /// Pretty-print JSON output (the default for CLI commands)
+    #[arg(long, global = true, conflicts_with = "compact")]
+    pub pretty: bool,
+
+    /// Print JSON output on a single line
+    #[arg(long, global = true)]
+    pub compact: bool,
+
+    /// Tokio worker threads for request handling (also SHIMMY_WORKER_THREADS;
+    /// default: the available CPUs, honouring container quotas; at least 2).
+    /// llama.cpp decoding runs on separate blocking threads.
+    #[arg(long, global = true, value_name = "N")]
+    pub worker_threads: Option<usize>,
+
+    /// Load environment variables from this file (default: ./.env if present).
+    /// Variables already set in the environment take precedence.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub env_file: Option<std::path::PathBuf>,
+}
+
+/// A probability: a number from 0.0 to 1.0
+fn parse_sample_rate(value: &str) -> Result<f64, String> {
+    let rate: f64 = value
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", value))?;
+    if (0.0..=1.0).contains(&rate) {
+        Ok(rate)
+    } else {
+        Err(format!("{} is not between 0.0 and 1.0", rate))
+    }
+}
+
+fn parse_model_sort(value: &str) -> Result<ModelSort, String> {
+    ModelSort::from_name(value).ok_or_else(|| {
+        format!(
+            "unknown sort key '{}'; expected one of: {}",
+            value,
+            ModelSort::NAMES.join(", ")
+        )
+    })
+}
+
+impl Cli {
+    /// Whether CLI JSON output should be pretty-printed
+    pub fn pretty_json(&self) -> bool {
+        !self.compact
+    }
+
+    /// `--memory-ceiling-mb` in bytes
+    pub fn memory_ceiling_bytes(&self) -> Option<u64> {
+        self.memory_ceiling_mb.map(|mb| mb * 1024 * 1024)
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -44,12 +130,90 @@ pub enum Command {
         /// Direct path to a specific model file (bypasses auto-discovery)
         #[arg(long)]
         model_path: Option<String>,
+        /// Append a JSON line per request to this audit log file
+        #[arg(long, value_name = "PATH")]
+        audit_log: Option<String>,
+        /// Store full prompt/response text in the audit log (default: prompt hash only)
+        #[arg(long, requires = "audit_log")]
+        audit_include_content: bool,
+        /// File of regexes (one per line) whose matches are replaced with [REDACTED] in the
+        /// audit log and sampled prompt logs
+        #[arg(long, value_name = "PATH")]
+        redact_config: Option<std::path::PathBuf>,
+        /// Fraction of generations (0.0-1.0) whose full prompt and response are
+        /// logged at debug level; the rest log only metadata
+        #[arg(long, value_name = "RATE", default_value_t = 0.0, value_parser = parse_sample_rate)]
+        log_prompt_sample_rate: f64,
+        /// Validate bind address and model configuration, print a summary, and exit
+        #[arg(long)]
+        dry_run: bool,
+        /// Print the dry-run summary as JSON
+        #[arg(long, requires = "dry_run")]
+        json: bool,
+        /// Disable gzip/deflate/br response compression
+        #[arg(long)]
+        no_compression: bool,
+        /// Reject prompts longer than N tokens (env: SHIMMY_MAX_PROMPT_TOKENS; default unlimited)
+        #[arg(long, value_name = "N")]
+        max_prompt_tokens: Option<usize>,
+        /// Reject /api/batch requests with more than N items
+        #[arg(long, value_name = "N", default_value_t = 100)]
+        max_batch_items: usize,
+        /// Unload models that have been idle for this many seconds (0 never unloads)
+        #[arg(long, value_name = "SECS", default_value_t = 300)]
+        idle_unload_secs: u64,
+        /// Send an SSE keep-alive comment after this many idle seconds on streams (0 disables)
+        #[arg(long, value_name = "SECS", default_value_t = 15)]
+        sse_keep_alive_secs: u64,
+        /// Once listening, print curl examples and open the index page in a browser
+        #[arg(long)]
+        open: bool,
+        /// Merge consecutive same-role chat messages and drop repeated system messages
+        #[arg(long)]
+        normalize_messages: bool,
+        /// Write a JSON readiness file (pid, bind address, model count) once listening
+        #[arg(long, value_name = "PATH")]
+        ready_file: Option<std::path::PathBuf>,
+        /// Retry a transiently failed model load this many times (0 disables)
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        transient_retries: u32,
+        /// On shutdown, write the final metrics (with per-model stats) to this JSON file
+        #[arg(long, value_name = "PATH")]
+        metrics_dump: Option<std::path::PathBuf>,
+        /// Model for chat requests that omit `model` (env: SHIMMY_DEFAULT_MODEL;
+        /// default: the only available model)
+        #[arg(long, value_name = "NAME")]
+        default_model: Option<String>,
+        /// Report the applied sampling settings on every response (X-Shimmy-Params)
+        #[arg(long)]
+        echo_params: bool,
+        /// Reject out-of-range sampling values (temperature outside [0, 2],
+        /// top_p outside [0, 1], negative top_k) instead of clamping them
+        #[arg(long)]
+        strict_sampling: bool,
+        /// Require this API key on requests (env: SHIMMY_API_KEY)
+        #[arg(long, value_name = "KEY")]
+        api_key: Option<String>,
+        /// Comma-separated paths reachable without the API key
+        #[arg(
+            long,
+            value_name = "PATHS",
+            value_delimiter = ',',
+            default_value = "/health"
+        )]
+        auth_exempt: Vec<String>,
     },
     /// List registered and auto-discovered models
     List {
         /// Output short list format (model names only)
         #[arg(short, long)]
         short: bool,
+        /// Query a running server (host:port or URL) and show which models are loaded
+        #[arg(long, value_name = "ADDR")]
+        remote: Option<String>,
+        /// Order models by name, size (largest first) or recency (newest first)
+        #[arg(long, value_name = "KEY", default_value = "name", value_parser = parse_model_sort)]
+        sort: ModelSort,
     },
     /// Refresh auto-discovery and list all available models
     Discover {
@@ -58,12 +222,33 @@ pub enum Command {
         llm_only: bool,
     },
     /// Load a model once (verifies base + optional LoRA)
-    Probe { name: String },
+    Probe {
+        #[arg(required_unless_present = "all")]
+        name: Option<String>,
+        /// Load and unload every available model, exiting non-zero if any fail
+        #[arg(long, conflicts_with = "name")]
+        all: bool,
+        /// Models to load at the same time with --all
+        #[arg(long, default_value_t = 2, requires = "all")]
+        concurrency: usize,
+        /// Print --all results as JSON
+        #[arg(long, requires = "all")]
+        json: bool,
+    },
     /// Simple throughput benchmark
     Bench {
         name: String,
         #[arg(long, default_value_t = 64)]
         max_tokens: usize,
+        /// Compare tok/s against results saved earlier with --save
+        #[arg(long, value_name = "FILE")]
+        baseline: Option<std::path::PathBuf>,
+        /// Save this run's results as JSON
+        #[arg(long, value_name = "FILE")]
+        save: Option<std::path::PathBuf>,
+        /// Fail when tok/s drops more than this percentage below the baseline
+        #[arg(long, value_name = "PCT", default_value_t = 10.0)]
+        regression_threshold: f64,
     },
     /// One-off generation (non-streaming) for quick manual testing
     Generate {
@@ -72,9 +257,21 @@ pub enum Command {
         prompt: String,
         #[arg(long, default_value_t = 64)]
         max_tokens: usize,
+        /// Print the prompt before the completion (prompt + completion)
+        #[arg(long)]
+        echo: bool,
+        /// Print tokens as they are generated
+        #[arg(long)]
+        stream: bool,
     },
     /// Show GPU backend information and capabilities
     GpuInfo,
+    /// List the features and backends compiled into this binary
+    Features {
+        /// Print as JSON (same shape as GET /api/features)
+        #[arg(long)]
+        json: bool,
+    },
     /// Initialize integration templates for deployment platforms
     Init {
         /// Template type: docker, kubernetes, railway, fly, fastapi, express
@@ -117,6 +314,27 @@ mod tests {
         let command = Command::Serve {
             bind: "auto".to_string(),
             model_path: None,
+            audit_log: None,
+            audit_include_content: false,
+            redact_config: None,
+            log_prompt_sample_rate: 0.0,
+            dry_run: false,
+            json: false,
+            no_compression: false,
+            max_prompt_tokens: None,
+            max_batch_items: 100,
+            idle_unload_secs: 300,
+            sse_keep_alive_secs: 15,
+            open: false,
+            normalize_messages: false,
+            ready_file: None,
+            transient_retries: 1,
+            metrics_dump: None,
+            default_model: None,
+            echo_params: false,
+            strict_sampling: false,
+            api_key: None,
+            auth_exempt: vec!["/health".to_string()],
         };
 
         // Test that we can access the bind field
@@ -133,6 +351,27 @@ mod tests {
         let command = Command::Serve {
             bind: "192.168.1.100:9000".to_string(),
             model_path: None,
+            audit_log: None,
+            audit_include_content: false,
+            redact_config: None,
+            log_prompt_sample_rate: 0.0,
+            dry_run: false,
+            json: false,
+            no_compression: false,
+            max_prompt_tokens: None,
+            max_batch_items: 100,
+            idle_unload_secs: 300,
+            sse_keep_alive_secs: 15,
+            open: false,
+            normalize_messages: false,
+            ready_file: None,
+            transient_retries: 1,
+            metrics_dump: None,
+            default_model: None,
+            echo_params: false,
+            strict_sampling: false,
+            api_key: None,
+            auth_exempt: vec!["/health".to_string()],
         };
 
         match command {
@@ -143,19 +382,207 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_serve_audit_log_flags() {
+        let cli = Cli::try_parse_from([
+            "shimmy",
+            "serve",
+            "--audit-log",
+            "audit.jsonl",
+            "--audit-include-content",
+        ])
+        .unwrap();
+        match cli.cmd {
+            Command::Serve {
+                audit_log,
+                audit_include_content,
+                ..
+            } => {
+                assert_eq!(audit_log.as_deref(), Some("audit.jsonl"));
+                assert!(audit_include_content);
+            }
+            _ => panic!("Expected Serve command"),
+        }
+
+        // Content logging only makes sense with an audit log
+        assert!(Cli::try_parse_from(["shimmy", "serve", "--audit-include-content"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_log_prompt_sample_rate() {
+        let cli =
+            Cli::try_parse_from(["shimmy", "serve", "--log-prompt-sample-rate", "0.05"]).unwrap();
+        match cli.cmd {
+            Command::Serve {
+                log_prompt_sample_rate,
+                ..
+            } => assert_eq!(log_prompt_sample_rate, 0.05),
+            _ => panic!("Expected Serve command"),
+        }
+        for bad in ["1.5", "-0.1", "often"] {
+            assert!(
+                Cli::try_parse_from(["shimmy", "serve", "--log-prompt-sample-rate", bad]).is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_cli_serve_dry_run_flags() {
+        let cli = Cli::try_parse_from(["shimmy", "serve", "--dry-run", "--json"]).unwrap();
+        match cli.cmd {
+            Command::Serve { dry_run, json, .. } => {
+                assert!(dry_run);
+                assert!(json);
+            }
+            _ => panic!("Expected Serve command"),
+        }
+
+        assert!(Cli::try_parse_from(["shimmy", "serve", "--json"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_serve_idle_unload_secs() {
+        let cli = Cli::try_parse_from(["shimmy", "serve", "--idle-unload-secs", "600"]).unwrap();
+        match cli.cmd {
+            Command::Serve {
+                idle_unload_secs, ..
+            } => assert_eq!(idle_unload_secs, 600),
+            _ => panic!("Expected Serve command"),
+        }
+
+        let cli = Cli::try_parse_from(["shimmy", "serve"]).unwrap();
+        match cli.cmd {
+            Command::Serve {
+                idle_unload_secs, ..
+            } => assert_eq!(idle_unload_secs, 300),
+            _ => panic!("Expected Serve command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_moe_auto_with_explicit_override() {
+        let cli = Cli::try_parse_from(["shimmy", "--moe-auto", "serve"]).unwrap();
+        assert!(cli.moe_auto);
+        assert!(!cli.cpu_moe);
+
+        // Explicit flags are still accepted and take precedence at load time
+        let cli =
+            Cli::try_parse_from(["shimmy", "serve", "--moe-auto", "--n-cpu-moe", "4"]).unwrap();
+        assert!(cli.moe_auto);
+        assert_eq!(cli.n_cpu_moe, Some(4));
+    }
+
+    #[test]
+    fn test_cli_symlinks_followed_by_default() {
+        let cli = Cli::try_parse_from(["shimmy", "serve"]).unwrap();
+        assert!(!cli.no_follow_symlinks);
+        let cli = Cli::try_parse_from(["shimmy", "serve", "--no-follow-symlinks"]).unwrap();
+        assert!(cli.no_follow_symlinks);
+    }
+
+    #[test]
+    fn test_cli_load_timeout_secs() {
+        let cli = Cli::try_parse_from(["shimmy", "serve"]).unwrap();
+        assert_eq!(cli.load_timeout_secs, 120);
+        let cli = Cli::try_parse_from(["shimmy", "serve", "--load-timeout-secs", "30"]).unwrap();
+        assert_eq!(cli.load_timeout_secs, 30);
+    }
+
+    #[test]
+    fn test_cli_warmup_flag() {
+        let cli = Cli::try_parse_from(["shimmy", "serve"]).unwrap();
+        assert!(!cli.warmup);
+        let cli = Cli::try_parse_from(["shimmy", "serve", "--warmup"]).unwrap();
+        assert!(cli.warmup);
+    }
+
+    #[test]
+    fn test_cli_auth_exempt_list() {
+        let cli = Cli::try_parse_from(["shimmy", "serve"]).unwrap();
+        match cli.cmd {
+            Command::Serve { auth_exempt, .. } => assert_eq!(auth_exempt, vec!["/health"]),
+            _ => panic!("Expected Serve command"),
+        }
+        let cli = Cli::try_parse_from([
+            "shimmy",
+            "serve",
+            "--api-key",
+            "sk-local",
+            "--auth-exempt",
+            "/health,/metrics",
+        ])
+        .unwrap();
+        match cli.cmd {
+            Command::Serve {
+                api_key,
+                auth_exempt,
+                ..
+            } => {
+                assert_eq!(api_key.as_deref(), Some("sk-local"));
+                assert_eq!(auth_exempt, vec!["/health", "/metrics"]);
+            }
+            _ => panic!("Expected Serve command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_models_dir_repeats() {
+        let cli = Cli::try_parse_from([
+            "shimmy",
+            "discover",
+            "--models-dir",
+            "/srv/models;old",
+            "--models-dir",
+            "/mnt/gguf",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.models_dir,
+            vec![
+                std::path::PathBuf::from("/srv/models;old"),
+                std::path::PathBuf::from("/mnt/gguf"),
+            ]
+        );
+        assert!(cli.model_dirs.is_none());
+    }
+
     #[test]
     fn test_cli_list_command() {
         let cli = Cli::try_parse_from(["shimmy", "list"]).unwrap();
-        matches!(cli.cmd, Command::List { short: false });
+        matches!(cli.cmd, Command::List { short: false, .. });
     }
 
     #[test]
     fn test_cli_list_short_command() {
         let cli = Cli::try_parse_from(["shimmy", "list", "--short"]).unwrap();
-        matches!(cli.cmd, Command::List { short: true });
+        matches!(cli.cmd, Command::List { short: true, .. });
 
         let cli = Cli::try_parse_from(["shimmy", "list", "-s"]).unwrap();
-        matches!(cli.cmd, Command::List { short: true });
+        matches!(cli.cmd, Command::List { short: true, .. });
+    }
+
+    #[test]
+    fn test_cli_list_remote_command() {
+        let cli = Cli::try_parse_from(["shimmy", "list", "--remote", "127.0.0.1:11435"]).unwrap();
+        match cli.cmd {
+            Command::List { remote, .. } => assert_eq!(remote.as_deref(), Some("127.0.0.1:11435")),
+            _ => panic!("Expected List command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_list_sort_key() {
+        let sort = |args: &[&str]| match Cli::try_parse_from(args).unwrap().cmd {
+            Command::List { sort, .. } => sort,
+            _ => panic!("Expected List command"),
+        };
+        assert_eq!(sort(&["shimmy", "list"]), ModelSort::Name);
+        assert_eq!(sort(&["shimmy", "list", "--sort", "size"]), ModelSort::Size);
+        assert_eq!(
+            sort(&["shimmy", "list", "-s", "--sort", "recency"]),
+            ModelSort::Recency
+        );
+        assert!(Cli::try_parse_from(["shimmy", "list", "--sort", "popularity"]).is_err());
     }
 
     #[test]
@@ -175,8 +602,12 @@ mod tests {
                 name,
                 prompt,
                 max_tokens,
+                echo,
+                stream,
             } => {
                 assert_eq!(name, "model");
+                assert!(!echo);
+                assert!(!stream);
                 assert_eq!(prompt, "test");
                 assert_eq!(max_tokens, 100);
             }
@@ -194,9 +625,34 @@ mod tests {
     fn test_cli_probe_command() {
         let cli = Cli::try_parse_from(["shimmy", "probe", "test-model"]).unwrap();
         match cli.cmd {
-            Command::Probe { name } => assert_eq!(name, "test-model"),
+            Command::Probe { name, all, .. } => {
+                assert_eq!(name.as_deref(), Some("test-model"));
+                assert!(!all);
+            }
+            _ => panic!("Expected Probe command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_probe_all() {
+        let cli = Cli::try_parse_from(["shimmy", "probe", "--all", "--concurrency", "4", "--json"])
+            .unwrap();
+        match cli.cmd {
+            Command::Probe {
+                name,
+                all,
+                concurrency,
+                json,
+            } => {
+                assert_eq!(name, None);
+                assert!(all);
+                assert_eq!(concurrency, 4);
+                assert!(json);
+            }
             _ => panic!("Expected Probe command"),
         }
+        assert!(Cli::try_parse_from(["shimmy", "probe"]).is_err());
+        assert!(Cli::try_parse_from(["shimmy", "probe", "phi3", "--all"]).is_err());
     }
 
     #[test]
@@ -204,7 +660,9 @@ mod tests {
         let cli =
             Cli::try_parse_from(["shimmy", "bench", "test-model", "--max-tokens", "128"]).unwrap();
         match cli.cmd {
-            Command::Bench { name, max_tokens } => {
+            Command::Bench {
+                name, max_tokens, ..
+            } => {
                 assert_eq!(name, "test-model");
                 assert_eq!(max_tokens, 128);
             }
@@ -216,11 +674,34 @@ mod tests {
     fn test_cli_bench_command_default_tokens() {
         let cli = Cli::try_parse_from(["shimmy", "bench", "test-model"]).unwrap();
         match cli.cmd {
-            Command::Bench { name, max_tokens } => {
+            Command::Bench {
+                name,
+                max_tokens,
+                baseline,
+                regression_threshold,
+                ..
+            } => {
                 assert_eq!(name, "test-model");
                 assert_eq!(max_tokens, 64); // Default value
+                assert_eq!(baseline, None);
+                assert_eq!(regression_threshold, 10.0);
             }
             _ => panic!("Expected Bench command"),
         }
     }
+
+    #[test]
+    fn test_cli_json_style_flags() {
+        let cli = Cli::try_parse_from(["shimmy", "features", "--json"]).unwrap();
+        assert!(cli.pretty_json());
+        let cli = Cli::try_parse_from(["shimmy", "features", "--json", "--compact"]).unwrap();
+        assert!(!cli.pretty_json());
+        assert!(Cli::try_parse_from(["shimmy", "--pretty", "--compact", "features"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_features_command() {
+        let cli = Cli::try_parse_from(["shimmy", "features", "--json"]).unwrap();
+        assert!(matches!(cli.cmd, Command::Features { json: true }));
+    }
 }
@@ -57,6 +57,27 @@ impl InferenceEngineAdapter {
         self
     }
 
+    /// Let the llama engine choose MoE offloading per model
+    #[cfg(feature = "llama")]
+    pub fn with_moe_auto(mut self, auto: bool) -> Self {
+        self.llama_engine = self.llama_engine.with_moe_auto(auto);
+        self
+    }
+
+    /// Bound how long the llama engine may spend loading one model
+    #[cfg(feature = "llama")]
+    pub fn with_load_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.llama_engine = self.llama_engine.with_load_timeout(timeout);
+        self
+    }
+
+    /// Warm up llama models with a single-token decode after load
+    #[cfg(feature = "llama")]
+    pub fn with_warmup(mut self, warmup: bool) -> Self {
+        self.llama_engine = self.llama_engine.with_warmup(warmup);
+        self
+    }
+
     /// Auto-detect best backend for model
     fn select_backend(&self, spec: &ModelSpec) -> BackendChoice {
         // Check file extension and path patterns to determine optimal backend
@@ -76,8 +97,7 @@ impl InferenceEngineAdapter {
                     }
                     #[cfg(not(feature = "llama"))]
                     {
-                        // This shouldn't happen with default features, but handle gracefully
-                        panic!("GGUF file detected but llama feature not enabled. Please install with --features llama");
+                        return BackendChoice::LlamaDisabled;
                     }
                 }
                 #[cfg(feature = "mlx")]
@@ -126,14 +146,8 @@ impl InferenceEngineAdapter {
             }
             #[cfg(not(feature = "llama"))]
             {
-                #[cfg(feature = "huggingface")]
-                {
-                    return BackendChoice::HuggingFace;
-                }
-                #[cfg(not(feature = "huggingface"))]
-                {
-                    panic!("Ollama blob detected but no backend enabled");
-                }
+                // Ollama blobs are GGUF files without the extension
+                return BackendChoice::LlamaDisabled;
             }
         }
 
@@ -151,6 +165,9 @@ impl InferenceEngineAdapter {
             }
             #[cfg(not(feature = "llama"))]
             {
+                if path_str.contains(".gguf") {
+                    return BackendChoice::LlamaDisabled;
+                }
                 #[cfg(feature = "huggingface")]
                 {
                     return BackendChoice::HuggingFace;
@@ -191,11 +208,21 @@ enum BackendChoice {
     #[allow(clippy::upper_case_acronyms)]
     MLX,
     SafeTensors,
+    /// A GGUF model in a build without llama.cpp
+    #[cfg(not(feature = "llama"))]
+    LlamaDisabled,
 }
 
 #[async_trait]
 impl InferenceEngine for InferenceEngineAdapter {
     async fn load(&self, spec: &ModelSpec) -> Result<Box<dyn LoadedModel>> {
+        let model = self.load_backend(spec).await?;
+        Ok(Box::new(super::trim::LeadingTrimModel::new(model)))
+    }
+}
+
+impl InferenceEngineAdapter {
+    async fn load_backend(&self, spec: &ModelSpec) -> Result<Box<dyn LoadedModel>> {
         // Select backend and load model directly (no caching for now to avoid complexity)
         let backend = self.select_backend(spec);
         match backend {
@@ -210,6 +237,8 @@ impl InferenceEngine for InferenceEngineAdapter {
             }
             #[cfg(feature = "llama")]
             BackendChoice::Llama => self.llama_engine.load(spec).await,
+            #[cfg(not(feature = "llama"))]
+            BackendChoice::LlamaDisabled => Err(super::llama::llama_disabled().into()),
             #[cfg(feature = "huggingface")]
             BackendChoice::HuggingFace => {
                 // Convert to UniversalModelSpec for huggingface backend (for HF model IDs)
@@ -251,6 +280,17 @@ impl LoadedModel for UniversalModelWrapper {
     ) -> Result<String> {
         self.model.generate(prompt, opts, on_token).await
     }
+
+    async fn generate_with_reason(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<(String, super::FinishReason)> {
+        self.model
+            .generate_with_reason(prompt, opts, on_token)
+            .await
+    }
 }
 
 // Note: Cached model references removed as they were unused placeholder code.
@@ -360,4 +400,26 @@ mod tests {
             assert_eq!(backend3, BackendChoice::MLX);
         }
     }
+
+    #[cfg(not(feature = "llama"))]
+    #[tokio::test]
+    async fn test_gguf_without_llama_is_unsupported() {
+        let adapter = InferenceEngineAdapter::new();
+        for path in [
+            "model.gguf",
+            "C:\\models\\phi3.gguf",
+            "/home/u/.ollama/models/blobs/sha256-abc",
+        ] {
+            let spec = create_test_spec("local", path);
+            assert_eq!(adapter.select_backend(&spec), BackendChoice::LlamaDisabled);
+        }
+
+        let err = match adapter.load(&create_test_spec("local", "model.gguf")).await {
+            Ok(_) => panic!("GGUF load should fail without llama"),
+            Err(e) => e,
+        };
+        let engine_err = super::super::EngineError::find(&err).unwrap();
+        assert_eq!(engine_err.code(), "unsupported");
+        assert!(err.to_string().contains("--features llama"));
+    }
 }
@@ -0,0 +1,112 @@
+// Cancellation of in-flight generation
+//
+// When an HTTP client disconnects, axum drops the handler future, but
+// llama.cpp decodes on the blocking pool and keeps going until it finishes.
+// Non-streaming handlers hold a `CancelGuard` for the token in
+// `GenOptions::cancel`; if the guard is dropped before it is disarmed, the
+// token flips and the backend stops at its next token with
+// `EngineError::Cancelled`. Streaming handlers generate in a spawned task
+// that outlives the response, so they cancel once a token can't be sent.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag a backend polls between tokens. The default token is never
+/// cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Send a streamed piece to the client, cancelling this token if the
+    /// client has gone away (the receiving end was dropped)
+    pub fn send_or_cancel<T>(&self, tx: &tokio::sync::mpsc::UnboundedSender<T>, item: T) {
+        if tx.send(item).is_err() {
+            self.cancel();
+        }
+    }
+
+    /// Guard that cancels this token, then runs `on_cancel`, if it is dropped
+    /// without `disarm` being called
+    pub fn guard(&self, on_cancel: impl FnOnce() + Send + 'static) -> CancelGuard {
+        CancelGuard {
+            token: self.clone(),
+            on_cancel: Some(Box::new(on_cancel)),
+        }
+    }
+}
+
+/// Cancels its token when dropped while still armed, e.g. along with a
+/// handler future whose client went away
+pub struct CancelGuard {
+    token: CancelToken,
+    on_cancel: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl CancelGuard {
+    /// The request finished on its own; dropping the guard no longer cancels
+    pub fn disarm(mut self) {
+        self.on_cancel = None;
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Some(on_cancel) = self.on_cancel.take() {
+            self.token.cancel();
+            on_cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_dropped_guard_cancels_and_disarmed_guard_does_not() {
+        let fired = Arc::new(AtomicUsize::new(0));
+
+        let token = CancelToken::new();
+        let counter = fired.clone();
+        drop(token.guard(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        assert!(token.is_cancelled());
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+
+        let token = CancelToken::new();
+        let counter = fired.clone();
+        token
+            .guard(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .disarm();
+        assert!(!token.is_cancelled());
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_send_to_dropped_stream_cancels() {
+        let token = CancelToken::new();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        token.send_or_cancel(&tx, "a");
+        assert!(!token.is_cancelled());
+
+        drop(rx);
+        token.send_or_cancel(&tx, "b");
+        assert!(token.is_cancelled());
+    }
+}
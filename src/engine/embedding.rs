@@ -0,0 +1,199 @@
+// Batched embeddings
+//
+// llama.cpp can pool several sequences in one forward pass. Inputs are split
+// into consecutive batches that fit a token budget (the context window) and a
+// sequence budget, so results come back in input order no matter how many
+// passes it takes.
+#![allow(dead_code)]
+
+use super::EngineError;
+use anyhow::{bail, Result};
+use std::ops::Range;
+
+/// Sequences encoded per forward pass unless `SHIMMY_EMBED_BATCH_SIZE` is set
+pub const DEFAULT_EMBED_BATCH_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddingBatchConfig {
+    /// Total tokens across all sequences in one pass; also the per-text limit
+    pub max_batch_tokens: usize,
+    /// Sequences in one pass
+    pub max_sequences: usize,
+}
+
+impl EmbeddingBatchConfig {
+    /// Token budget from the model's context, sequence budget from
+    /// `SHIMMY_EMBED_BATCH_SIZE`
+    pub fn from_env(ctx_len: usize) -> Self {
+        let max_sequences = std::env::var("SHIMMY_EMBED_BATCH_SIZE")
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_EMBED_BATCH_SIZE);
+        Self {
+            max_batch_tokens: ctx_len.max(1),
+            max_sequences,
+        }
+    }
+}
+
+/// Group inputs (by token count) into consecutive batches. A single text longer
+/// than the token budget is rejected with `EngineError::ContextExceeded`.
+pub fn plan_batches(
+    token_counts: &[usize],
+    config: &EmbeddingBatchConfig,
+) -> Result<Vec<Range<usize>>> {
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut tokens = 0;
+    for (i, &count) in token_counts.iter().enumerate() {
+        if count > config.max_batch_tokens {
+            return Err(EngineError::ContextExceeded {
+                prompt_tokens: count,
+                ctx_len: config.max_batch_tokens,
+            }
+            .into());
+        }
+        let full = i - start >= config.max_sequences || tokens + count > config.max_batch_tokens;
+        if full && i > start {
+            batches.push(start..i);
+            start = i;
+            tokens = 0;
+        }
+        tokens += count;
+    }
+    if start < token_counts.len() {
+        batches.push(start..token_counts.len());
+    }
+    Ok(batches)
+}
+
+/// Run `encode` over each planned batch and concatenate the results.
+/// `encode` receives the input index range and must return one embedding per
+/// input in that range, in order.
+pub fn embed_batched<F>(
+    token_counts: &[usize],
+    config: &EmbeddingBatchConfig,
+    mut encode: F,
+) -> Result<Vec<Vec<f32>>>
+where
+    F: FnMut(Range<usize>) -> Result<Vec<Vec<f32>>>,
+{
+    let mut out: Vec<Vec<f32>> = Vec::with_capacity(token_counts.len());
+    for range in plan_batches(token_counts, config)? {
+        let expected = range.len();
+        let batch = encode(range.clone())?;
+        if batch.len() != expected {
+            bail!(
+                "Embedding batch {:?} returned {} vectors, expected {}",
+                range,
+                batch.len(),
+                expected
+            );
+        }
+        for embedding in batch {
+            if let Some(first) = out.first() {
+                if embedding.len() != first.len() {
+                    bail!(
+                        "Embedding dimension changed from {} to {}",
+                        first.len(),
+                        embedding.len()
+                    );
+                }
+            }
+            out.push(embedding);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIM: usize = 8;
+
+    /// Encoder that tags each vector with its input index and token count
+    fn fake_encode(
+        counts: &[usize],
+        range: Range<usize>,
+        calls: &mut Vec<Range<usize>>,
+    ) -> Vec<Vec<f32>> {
+        calls.push(range.clone());
+        range
+            .map(|i| {
+                let mut v = vec![0.0; DIM];
+                v[0] = i as f32;
+                v[1] = counts[i] as f32;
+                v
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hundred_inputs_keep_order_and_dimension() {
+        let counts: Vec<usize> = (0..100).map(|i| 3 + i % 7).collect();
+        let config = EmbeddingBatchConfig {
+            max_batch_tokens: 40,
+            max_sequences: 8,
+        };
+        let mut calls = Vec::new();
+        let embeddings = embed_batched(&counts, &config, |range| {
+            Ok(fake_encode(&counts, range, &mut calls))
+        })
+        .unwrap();
+
+        assert_eq!(embeddings.len(), 100);
+        for (i, embedding) in embeddings.iter().enumerate() {
+            assert_eq!(embedding.len(), DIM);
+            assert_eq!(embedding[0], i as f32);
+            assert_eq!(embedding[1], counts[i] as f32);
+        }
+        // Many texts per pass, never over budget
+        assert!(calls.len() < 100 / 4);
+        for range in &calls {
+            assert!(range.len() <= config.max_sequences);
+            assert!(counts[range.clone()].iter().sum::<usize>() <= config.max_batch_tokens);
+        }
+    }
+
+    #[test]
+    fn test_text_longer_than_context_is_rejected() {
+        let config = EmbeddingBatchConfig {
+            max_batch_tokens: 16,
+            max_sequences: 4,
+        };
+        let err = plan_batches(&[4, 17, 2], &config).unwrap_err();
+        assert!(matches!(
+            EngineError::find(&err),
+            Some(EngineError::ContextExceeded {
+                prompt_tokens: 17,
+                ctx_len: 16
+            })
+        ));
+        // A text exactly at the limit gets a batch of its own
+        assert_eq!(
+            plan_batches(&[4, 16, 2], &config).unwrap(),
+            vec![0..1, 1..2, 2..3]
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_encoder_output_is_an_error() {
+        let config = EmbeddingBatchConfig {
+            max_batch_tokens: 100,
+            max_sequences: 2,
+        };
+        let short = embed_batched(&[1, 1, 1], &config, |range| {
+            Ok(vec![vec![0.0; DIM]; range.len() - 1])
+        });
+        assert!(short.is_err());
+
+        let mut dim = DIM;
+        let ragged = embed_batched(&[1, 1, 1], &config, |range| {
+            dim += 1;
+            Ok(vec![vec![0.0; dim]; range.len()])
+        });
+        assert!(ragged.is_err());
+    }
+}
@@ -0,0 +1,151 @@
+// Typed engine failures
+//
+// The `InferenceEngine`/`LoadedModel` traits still return `anyhow::Result` for
+// compatibility; backends wrap an `EngineError` in the `anyhow::Error` and HTTP
+// handlers recover it with `EngineError::find` (see `api_errors::status_for`).
+
+use std::time::Duration;
+use thiserror::Error;
+
+// Most variants are only constructed by feature-gated backends
+#[allow(dead_code)]
+#[derive(Error, Debug)]
+pub enum EngineError {
+    #[error("Model not found: {name}")]
+    ModelNotFound { name: String },
+
+    #[error("Failed to load model: {reason}")]
+    LoadFailed { reason: String },
+
+    #[error("{path} is not a valid GGUF file (bad magic number); re-download it or convert it with llama.cpp's convert_hf_to_gguf.py")]
+    InvalidModelFile { path: String },
+
+    #[error("Model architecture '{architecture}' is not supported by this llama.cpp build; upgrade shimmy or choose a supported model")]
+    UnsupportedArchitecture { architecture: String },
+
+    #[error("This quantization requires a newer llama.cpp build; upgrade shimmy or use a common quantization such as Q4_K_M ({detail})")]
+    UnsupportedQuantization { detail: String },
+
+    #[error(
+        "Not enough memory to load {path} ({:.1} GB); try a smaller model or quantization (Q4_K_M), fewer GPU layers, or MoE CPU offloading (--cpu-moe)",
+        *.size_bytes as f64 / 1e9
+    )]
+    InsufficientMemory { path: String, size_bytes: u64 },
+
+    #[error("{path} is busy ({reason}); try again shortly")]
+    ResourceBusy { path: String, reason: String },
+
+    #[error("Prompt is {prompt_tokens} tokens, which exceeds the model's context window of {ctx_len} tokens")]
+    ContextExceeded {
+        prompt_tokens: usize,
+        ctx_len: usize,
+    },
+
+    #[error("{operation} timed out after {}s", .after.as_secs())]
+    Timeout { operation: String, after: Duration },
+
+    #[error("Generation was cancelled")]
+    Cancelled,
+
+    #[error("Unsupported: {feature}")]
+    Unsupported { feature: String },
+
+    #[error("Invalid generation options: {reason}")]
+    InvalidOptions { reason: String },
+
+    #[error(
+        "Loading {model} needs an estimated {} MiB on top of {} MiB already loaded, exceeding the {} MiB memory ceiling (use --force to load anyway)",
+        .estimated_bytes >> 20, .resident_bytes >> 20, .ceiling_bytes >> 20
+    )]
+    MemoryExceeded {
+        model: String,
+        estimated_bytes: u64,
+        resident_bytes: u64,
+        ceiling_bytes: u64,
+    },
+}
+
+impl EngineError {
+    /// Find an `EngineError` anywhere in an anyhow error chain
+    pub fn find(err: &anyhow::Error) -> Option<&EngineError> {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<EngineError>())
+    }
+
+    /// Machine-readable error code, matching OpenAI's where one exists
+    pub fn code(&self) -> &'static str {
+        match self {
+            EngineError::ModelNotFound { .. } => "model_not_found",
+            EngineError::LoadFailed { .. } => "model_load_failed",
+            EngineError::InvalidModelFile { .. } => "invalid_model_file",
+            EngineError::UnsupportedArchitecture { .. } => "unsupported_architecture",
+            EngineError::UnsupportedQuantization { .. } => "unsupported_quantization",
+            EngineError::InsufficientMemory { .. } => "insufficient_memory",
+            EngineError::ResourceBusy { .. } => "resource_busy",
+            EngineError::ContextExceeded { .. } => "context_length_exceeded",
+            EngineError::Timeout { .. } => "timeout",
+            EngineError::Cancelled => "cancelled",
+            EngineError::Unsupported { .. } => "unsupported",
+            EngineError::InvalidOptions { .. } => "invalid_options",
+            EngineError::MemoryExceeded { .. } => "insufficient_memory",
+        }
+    }
+
+    /// Failures worth one more attempt: memory freed by an eviction racing
+    /// the load, or a model file briefly locked. An unclassified `LoadFailed`
+    /// is usually a bad file and would fail again.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            EngineError::InsufficientMemory { .. } | EngineError::ResourceBusy { .. }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_exceeded_survives_anyhow() {
+        let err: anyhow::Error = EngineError::ContextExceeded {
+            prompt_tokens: 5000,
+            ctx_len: 4096,
+        }
+        .into();
+        let err = err.context("generation failed");
+
+        assert!(matches!(
+            EngineError::find(&err),
+            Some(EngineError::ContextExceeded {
+                prompt_tokens: 5000,
+                ctx_len: 4096
+            })
+        ));
+    }
+
+    #[test]
+    fn test_only_memory_and_busy_failures_are_transient() {
+        let memory = EngineError::InsufficientMemory {
+            path: "x".into(),
+            size_bytes: 1,
+        };
+        let busy = EngineError::ResourceBusy {
+            path: "x".into(),
+            reason: "text file busy".into(),
+        };
+        assert!(memory.is_transient());
+        assert!(busy.is_transient());
+        assert!(!EngineError::LoadFailed { reason: "x".into() }.is_transient());
+        assert!(!EngineError::InvalidModelFile { path: "x".into() }.is_transient());
+    }
+
+    #[test]
+    fn test_timeout_message() {
+        let err = EngineError::Timeout {
+            operation: "Model load".into(),
+            after: Duration::from_secs(120),
+        };
+        assert_eq!(err.to_string(), "Model load timed out after 120s");
+    }
+}
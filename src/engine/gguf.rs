@@ -0,0 +1,403 @@
+// Minimal GGUF header reader
+//
+// Reads the metadata key/values and tensor table from the start of a GGUF
+// file without loading any weights, so callers can inspect a model (expert
+// tensor sizes, architecture, context length) before handing it to llama.cpp.
+
+#![allow(dead_code)]
+
+use anyhow::{anyhow, bail, Result};
+use std::collections::BTreeMap;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Arrays longer than this (e.g. tokenizer vocabularies) are skipped rather
+/// than kept in `GgufInfo::metadata`
+const MAX_STORED_ARRAY: u64 = 64;
+/// Guards against reading garbage lengths from corrupt files
+const MAX_STRING_LEN: u64 = 16 * 1024 * 1024;
+const MAX_TENSORS: u64 = 1_000_000;
+const DEFAULT_ALIGNMENT: u64 = 32;
+
+/// One entry in the GGUF tensor table
+#[derive(Debug, Clone)]
+pub struct GgufTensor {
+    pub name: String,
+    /// Bytes occupied in the data section, including alignment padding
+    pub size_bytes: u64,
+}
+
+/// Header contents of a GGUF file
+#[derive(Debug, Clone)]
+pub struct GgufInfo {
+    pub version: u32,
+    pub metadata: BTreeMap<String, serde_json::Value>,
+    pub tensors: Vec<GgufTensor>,
+}
+
+impl GgufInfo {
+    /// Look up a metadata value as an unsigned integer
+    pub fn metadata_u64(&self, key: &str) -> Option<u64> {
+        self.metadata.get(key).and_then(|v| v.as_u64())
+    }
+
+    /// Look up a metadata value as a string
+    pub fn metadata_str(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).and_then(|v| v.as_str())
+    }
+
+    /// `general.architecture`, e.g. `llama` or `clip`
+    pub fn architecture(&self) -> Option<&str> {
+        self.metadata_str("general.architecture")
+    }
+
+    /// `<arch>.context_length`: the context window the model was trained with
+    pub fn context_length(&self) -> Option<u64> {
+        self.metadata_u64(&format!("{}.context_length", self.architecture()?))
+    }
+
+    /// Quantization named by `general.file_type`, e.g. `Q4_K_M`
+    pub fn quantization(&self) -> Option<&'static str> {
+        let name = match self.metadata_u64("general.file_type")? {
+            0 => "F32",
+            1 => "F16",
+            2 => "Q4_0",
+            3 => "Q4_1",
+            7 => "Q8_0",
+            8 => "Q5_0",
+            9 => "Q5_1",
+            10 => "Q2_K",
+            11 => "Q3_K_S",
+            12 => "Q3_K_M",
+            13 => "Q3_K_L",
+            14 => "Q4_K_S",
+            15 => "Q4_K_M",
+            16 => "Q5_K_S",
+            17 => "Q5_K_M",
+            18 => "Q6_K",
+            19 => "IQ2_XXS",
+            20 => "IQ2_XS",
+            21 => "Q2_K_S",
+            22 => "IQ3_XS",
+            23 => "IQ3_XXS",
+            24 => "IQ1_S",
+            25 => "IQ4_NL",
+            26 => "IQ3_S",
+            27 => "IQ3_M",
+            28 => "IQ2_S",
+            29 => "IQ2_M",
+            30 => "IQ4_XS",
+            31 => "IQ1_M",
+            32 => "BF16",
+            36 => "TQ1_0",
+            37 => "TQ2_0",
+            _ => return None,
+        };
+        Some(name)
+    }
+
+    /// Whether the file holds a vision encoder: a CLIP/mmproj projector or a
+    /// model with a built-in vision tower
+    pub fn has_vision(&self) -> bool {
+        self.architecture() == Some("clip")
+            || self
+                .metadata
+                .keys()
+                .any(|k| k.starts_with("clip.vision") || k.contains(".vision."))
+    }
+
+    /// Whether the model pools hidden states into embeddings rather than
+    /// generating text
+    pub fn is_embedding(&self) -> bool {
+        const EMBEDDING_ARCHS: &[&str] = &["bert", "nomic-bert", "jina-bert-v2", "t5encoder"];
+        let Some(arch) = self.architecture() else {
+            return false;
+        };
+        EMBEDDING_ARCHS.contains(&arch)
+            || self
+                .metadata_u64(&format!("{}.pooling_type", arch))
+                .is_some_and(|p| p > 0)
+    }
+}
+
+/// Read the GGUF header and tensor table from `path`
+pub fn read_gguf_info(path: &Path) -> Result<GgufInfo> {
+    let file = std::fs::File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut r = BufReader::new(file);
+
+    let mut magic = [0u8; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != b"GGUF" {
+        bail!("{} is not a GGUF file", path.display());
+    }
+    let version = read_u32(&mut r)?;
+    if version < 2 {
+        bail!("Unsupported GGUF version {}", version);
+    }
+    let tensor_count = read_u64(&mut r)?;
+    let kv_count = read_u64(&mut r)?;
+    if tensor_count > MAX_TENSORS {
+        bail!("GGUF header claims {} tensors", tensor_count);
+    }
+
+    let mut metadata = BTreeMap::new();
+    for _ in 0..kv_count {
+        let key = read_string(&mut r)?;
+        let value_type = read_u32(&mut r)?;
+        if let Some(value) = read_value(&mut r, value_type)? {
+            metadata.insert(key, value);
+        }
+    }
+
+    let mut offsets = Vec::with_capacity(tensor_count as usize);
+    for _ in 0..tensor_count {
+        let name = read_string(&mut r)?;
+        let n_dims = read_u32(&mut r)?;
+        if n_dims > 8 {
+            bail!("Tensor {} has {} dimensions", name, n_dims);
+        }
+        for _ in 0..n_dims {
+            read_u64(&mut r)?;
+        }
+        let _ggml_type = read_u32(&mut r)?;
+        let offset = read_u64(&mut r)?;
+        offsets.push((name, offset));
+    }
+
+    let alignment = metadata
+        .get("general.alignment")
+        .and_then(|v| v.as_u64())
+        .filter(|a| *a > 0)
+        .unwrap_or(DEFAULT_ALIGNMENT);
+    let header_end = r.stream_position()?;
+    let data_start = header_end.div_ceil(alignment) * alignment;
+    let data_len = file_len.saturating_sub(data_start);
+
+    // Tensor sizes follow from the gap to the next tensor's offset, which
+    // avoids needing a size table for every ggml quantization type
+    let mut order: Vec<usize> = (0..offsets.len()).collect();
+    order.sort_by_key(|&i| offsets[i].1);
+    let mut sizes = vec![0u64; offsets.len()];
+    for (pos, &i) in order.iter().enumerate() {
+        let end = order
+            .get(pos + 1)
+            .map(|&next| offsets[next].1)
+            .unwrap_or(data_len);
+        sizes[i] = end.saturating_sub(offsets[i].1);
+    }
+
+    let tensors = offsets
+        .into_iter()
+        .zip(sizes)
+        .map(|((name, _), size_bytes)| GgufTensor { name, size_bytes })
+        .collect();
+
+    Ok(GgufInfo {
+        version,
+        metadata,
+        tensors,
+    })
+}
+
+fn read_bytes<const N: usize>(r: &mut impl Read) -> Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes(r)?))
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64> {
+    Ok(u64::from_le_bytes(read_bytes(r)?))
+}
+
+fn read_string<R: Read + Seek>(r: &mut R) -> Result<String> {
+    let len = read_u64(r)?;
+    if len > MAX_STRING_LEN {
+        bail!("GGUF string of {} bytes", len);
+    }
+    let mut buf = vec![0u8; len as usize];
+    r.read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// Read one metadata value. Returns `None` for values that were skipped.
+fn read_value<R: Read + Seek>(r: &mut R, value_type: u32) -> Result<Option<serde_json::Value>> {
+    use serde_json::Value;
+
+    let value = match value_type {
+        0 => Value::from(read_bytes::<1>(r)?[0]),
+        1 => Value::from(read_bytes::<1>(r)?[0] as i8),
+        2 => Value::from(u16::from_le_bytes(read_bytes(r)?)),
+        3 => Value::from(i16::from_le_bytes(read_bytes(r)?)),
+        4 => Value::from(u32::from_le_bytes(read_bytes(r)?)),
+        5 => Value::from(i32::from_le_bytes(read_bytes(r)?)),
+        6 => Value::from(f32::from_le_bytes(read_bytes(r)?)),
+        7 => Value::from(read_bytes::<1>(r)?[0] != 0),
+        8 => Value::from(read_string(r)?),
+        9 => {
+            let item_type = read_u32(r)?;
+            let count = read_u64(r)?;
+            if count > MAX_STORED_ARRAY {
+                skip_array(r, item_type, count)?;
+                return Ok(None);
+            }
+            let mut items = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                if let Some(item) = read_value(r, item_type)? {
+                    items.push(item);
+                }
+            }
+            Value::Array(items)
+        }
+        10 => Value::from(u64::from_le_bytes(read_bytes(r)?)),
+        11 => Value::from(i64::from_le_bytes(read_bytes(r)?)),
+        12 => Value::from(f64::from_le_bytes(read_bytes(r)?)),
+        other => return Err(anyhow!("Unknown GGUF value type {}", other)),
+    };
+    Ok(Some(value))
+}
+
+fn skip_array<R: Read + Seek>(r: &mut R, item_type: u32, count: u64) -> Result<()> {
+    let fixed = match item_type {
+        0 | 1 | 7 => Some(1),
+        2 | 3 => Some(2),
+        4..=6 => Some(4),
+        10..=12 => Some(8),
+        _ => None,
+    };
+    match fixed {
+        Some(size) => {
+            r.seek(SeekFrom::Current((size * count) as i64))?;
+        }
+        None => {
+            for _ in 0..count {
+                read_value(r, item_type)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn push_string(buf: &mut Vec<u8>, s: &str) {
+        buf.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        buf.extend_from_slice(s.as_bytes());
+    }
+
+    /// Build a GGUF v3 file with the given string/u32 metadata and tensors of
+    /// the given sizes
+    pub(crate) fn synthetic_gguf(
+        strings: &[(&str, &str)],
+        u32s: &[(&str, u32)],
+        tensors: &[(&str, u64)],
+    ) -> Vec<u8> {
+        let mut buf = b"GGUF".to_vec();
+        buf.extend_from_slice(&3u32.to_le_bytes());
+        buf.extend_from_slice(&(tensors.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&((strings.len() + u32s.len() + 1) as u64).to_le_bytes());
+        for (key, value) in strings {
+            push_string(&mut buf, key);
+            buf.extend_from_slice(&8u32.to_le_bytes());
+            push_string(&mut buf, value);
+        }
+        for (key, value) in u32s {
+            push_string(&mut buf, key);
+            buf.extend_from_slice(&4u32.to_le_bytes());
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        // A long array, as tokenizer vocabularies are
+        push_string(&mut buf, "tokenizer.ggml.scores");
+        buf.extend_from_slice(&9u32.to_le_bytes());
+        buf.extend_from_slice(&6u32.to_le_bytes());
+        buf.extend_from_slice(&100u64.to_le_bytes());
+        buf.extend(std::iter::repeat_n(0u8, 400));
+
+        let mut offset = 0u64;
+        for (name, size) in tensors {
+            push_string(&mut buf, name);
+            buf.extend_from_slice(&1u32.to_le_bytes());
+            buf.extend_from_slice(&size.to_le_bytes());
+            buf.extend_from_slice(&0u32.to_le_bytes());
+            buf.extend_from_slice(&offset.to_le_bytes());
+            offset += size;
+        }
+        while !buf.len().is_multiple_of(32) {
+            buf.push(0);
+        }
+        buf.extend(std::iter::repeat_n(0u8, offset as usize));
+        buf
+    }
+
+    #[test]
+    fn test_reads_metadata_and_tensor_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.gguf");
+        std::fs::write(
+            &path,
+            synthetic_gguf(
+                &[("general.architecture", "qwen2moe")],
+                &[("qwen2moe.context_length", 32768)],
+                &[("token_embd.weight", 96), ("blk.0.ffn_up_exps.weight", 256)],
+            ),
+        )
+        .unwrap();
+
+        let info = read_gguf_info(&path).unwrap();
+        assert_eq!(info.version, 3);
+        assert_eq!(info.metadata_str("general.architecture"), Some("qwen2moe"));
+        assert_eq!(info.metadata_u64("qwen2moe.context_length"), Some(32768));
+        assert!(!info.metadata.contains_key("tokenizer.ggml.scores"));
+        let sizes: Vec<(&str, u64)> = info
+            .tensors
+            .iter()
+            .map(|t| (t.name.as_str(), t.size_bytes))
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![("token_embd.weight", 96), ("blk.0.ffn_up_exps.weight", 256)]
+        );
+    }
+
+    #[test]
+    fn test_detects_vision_and_embedding_models() {
+        let dir = tempfile::tempdir().unwrap();
+        let read = |strings: &[(&str, &str)], u32s: &[(&str, u32)]| {
+            let path = dir.path().join("model.gguf");
+            std::fs::write(&path, synthetic_gguf(strings, u32s, &[("w", 32)])).unwrap();
+            read_gguf_info(&path).unwrap()
+        };
+
+        let mmproj = read(&[("general.architecture", "clip")], &[]);
+        assert!(mmproj.has_vision());
+        assert!(!mmproj.is_embedding());
+
+        let embed = read(
+            &[("general.architecture", "qwen3")],
+            &[("qwen3.pooling_type", 3)],
+        );
+        assert!(embed.is_embedding());
+        assert!(!embed.has_vision());
+
+        let chat = read(
+            &[("general.architecture", "llama")],
+            &[("llama.context_length", 8192)],
+        );
+        assert!(!chat.is_embedding());
+        assert!(!chat.has_vision());
+    }
+
+    #[test]
+    fn test_rejects_non_gguf() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.gguf");
+        std::fs::write(&path, b"not a model").unwrap();
+        assert!(read_gguf_info(&path).is_err());
+    }
+}
@@ -1,10 +1,13 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use serde::Deserialize;
 use std::path::Path;
 use std::process::Command;
 use tokio::process::Command as TokioCommand;
 
-use super::{GenOptions, ModelBackend, UniversalEngine, UniversalModel, UniversalModelSpec};
+use super::{
+    FinishReason, GenOptions, ModelBackend, UniversalEngine, UniversalModel, UniversalModelSpec,
+};
 
 #[derive(Debug)]
 pub struct HuggingFaceEngine {
@@ -136,12 +139,12 @@ except Exception as e:
 
 #[async_trait]
 impl UniversalModel for HuggingFaceModel {
-    async fn generate(
+    async fn generate_with_reason(
         &self,
         prompt: &str,
         opts: GenOptions,
         on_token: Option<Box<dyn FnMut(String) + Send>>,
-    ) -> Result<String> {
+    ) -> Result<(String, FinishReason)> {
         let generation_script = format!(
             r#"
 import torch
@@ -191,7 +194,10 @@ input_text = '''{}'''
 if generated_text.startswith(input_text):
     generated_text = generated_text[len(input_text):].strip()
 
-print(generated_text)
+# Generation ran out of budget when it produced max_new_tokens tokens
+new_tokens = outputs.shape[-1] - inputs["input_ids"].shape[-1]
+finish_reason = "length" if new_tokens >= {} else "stop"
+print(json.dumps({{"text": generated_text, "finish_reason": finish_reason}}))
 "#,
             self.base_model_id,
             self.base_model_id,
@@ -218,7 +224,8 @@ model = PeftModel.from_pretrained(model, '{}')"#,
             } else {
                 "use_cache=True".to_string()
             },
-            prompt.replace("'", r"\'")
+            prompt.replace("'", r"\'"),
+            opts.max_tokens
         );
 
         let mut cmd = TokioCommand::new(&self.python_path);
@@ -234,15 +241,7 @@ model = PeftModel.from_pretrained(model, '{}')"#,
         }
 
         let result = String::from_utf8_lossy(&output.stdout);
-        let lines: Vec<&str> = result.lines().collect();
-
-        // Find the actual generated text (after loading messages)
-        let generated_text = lines
-            .iter()
-            .rev()
-            .find(|line| !line.trim().is_empty())
-            .unwrap_or(&"")
-            .to_string();
+        let (generated_text, reason) = parse_generation_output(&result);
 
         // Handle streaming callback if provided
         if let Some(mut callback) = on_token {
@@ -251,7 +250,34 @@ model = PeftModel.from_pretrained(model, '{}')"#,
             }
         }
 
-        Ok(generated_text)
+        Ok((generated_text, reason))
+    }
+}
+
+/// Text and finish reason from the script's final JSON line; a bare last
+/// line (older scripts) is taken as the text with `Stop`
+fn parse_generation_output(stdout: &str) -> (String, FinishReason) {
+    #[derive(Deserialize)]
+    struct Output {
+        text: String,
+        finish_reason: String,
+    }
+
+    let last = stdout
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("");
+    match serde_json::from_str::<Output>(last) {
+        Ok(output) => {
+            let reason = if output.finish_reason == "length" {
+                FinishReason::Length
+            } else {
+                FinishReason::Stop
+            };
+            (output.text, reason)
+        }
+        Err(_) => (last.to_string(), FinishReason::Stop),
     }
 }
 
@@ -260,6 +286,23 @@ mod tests {
     use super::*;
     use crate::engine::{GenOptions, ModelBackend, UniversalModelSpec};
 
+    #[test]
+    fn test_parse_generation_output_reads_finish_reason() {
+        let stdout = "Loading model...\n{\"text\": \"hi there\", \"finish_reason\": \"length\"}\n";
+        assert_eq!(
+            parse_generation_output(stdout),
+            ("hi there".to_string(), FinishReason::Length)
+        );
+        assert_eq!(
+            parse_generation_output("{\"text\": \"done\", \"finish_reason\": \"stop\"}"),
+            ("done".to_string(), FinishReason::Stop)
+        );
+        assert_eq!(
+            parse_generation_output("plain text\n\n"),
+            ("plain text".to_string(), FinishReason::Stop)
+        );
+    }
+
     #[test]
     fn test_default_creates_new_instance() {
         let engine = HuggingFaceEngine::default();
@@ -374,6 +417,12 @@ mod tests {
             seed: Some(42),
             stream: false,
             stop_tokens: Vec::new(),
+            trim_leading: true,
+            samplers: Vec::new(),
+            stop_on_repeat: None,
+            context_shift: false,
+            cancel: crate::engine::CancelToken::default(),
+            clamped: Vec::new(),
         };
 
         assert_eq!(opts.max_tokens, 100);
@@ -2,7 +2,13 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
-use super::{GenOptions, InferenceEngine, LoadedModel, ModelSpec};
+#[cfg(feature = "llama")]
+use super::prefix::{PrefixCache, PrefixCacheStats};
+#[cfg(feature = "llama")]
+use super::shift::{ContextWindow, Step};
+#[cfg(feature = "llama")]
+use super::FinishReason;
+use super::{EngineError, GenOptions, InferenceEngine, LoadedModel, ModelSpec};
 
 /// Smart thread detection optimized for inference performance
 /// Matches Ollama's approach: use physical cores with intelligent limits
@@ -39,8 +45,65 @@ fn get_optimal_thread_count() -> i32 {
     optimal
 }
 
+/// One stage of the llama.cpp sampler chain
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SamplerStage {
+    Temperature(f32),
+    TopP(f32),
+    TopK(i32),
+    RepeatPenalty(f32),
+    Greedy,
+}
+
+/// Error for GGUF work in a build without the `llama` feature, telling the
+/// user how to get a build that can serve it
+#[cfg_attr(feature = "llama", allow(dead_code))]
+pub fn llama_disabled() -> EngineError {
+    EngineError::Unsupported {
+        feature: "GGUF models need llama.cpp, but this shimmy binary was built without the \
+                  `llama` feature. Install a full build with `cargo install shimmy --features llama` \
+                  or use a release binary"
+            .to_string(),
+    }
+}
+
+/// The sampler chain for a request. Temperature 0 means pure argmax, as in
+/// OpenAI's API, so top_p/top_k/penalties are skipped and output is
+/// reproducible. `opts.samplers` reorders (or trims) the stages ahead of the
+/// final greedy pick; names are checked by `validate_samplers`.
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+fn sampler_stages(opts: &GenOptions) -> Vec<SamplerStage> {
+    if opts.temperature <= 0.0 {
+        return vec![SamplerStage::Greedy];
+    }
+    if opts.samplers.is_empty() {
+        return vec![
+            SamplerStage::Temperature(opts.temperature),
+            SamplerStage::TopP(opts.top_p),
+            SamplerStage::TopK(opts.top_k),
+            SamplerStage::RepeatPenalty(opts.repeat_penalty),
+            SamplerStage::Greedy,
+        ];
+    }
+    let mut stages: Vec<_> = opts
+        .samplers
+        .iter()
+        .filter_map(|name| match name.as_str() {
+            "temperature" => Some(SamplerStage::Temperature(opts.temperature)),
+            "top_p" => Some(SamplerStage::TopP(opts.top_p)),
+            "top_k" => Some(SamplerStage::TopK(opts.top_k)),
+            "repeat_penalty" => Some(SamplerStage::RepeatPenalty(opts.repeat_penalty)),
+            _ => None,
+        })
+        .collect();
+    stages.push(SamplerStage::Greedy);
+    stages
+}
+
 #[cfg(feature = "llama")]
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tracing::info;
 
 #[cfg(feature = "llama")]
@@ -63,10 +126,25 @@ fn get_or_init_backend() -> Result<&'static shimmy_llama_cpp_2::llama_backend::L
     result.as_ref().map_err(|e| anyhow!("{}", e))
 }
 
-#[derive(Default)]
 pub struct LlamaEngine {
     gpu_backend: GpuBackend,
     moe_config: MoeConfig,
+    #[allow(dead_code)]
+    load_timeout: Duration,
+    /// Run a one-token decode right after load (`--warmup` / `SHIMMY_WARMUP=1`)
+    #[allow(dead_code)]
+    warmup: bool,
+}
+
+impl Default for LlamaEngine {
+    fn default() -> Self {
+        Self {
+            gpu_backend: GpuBackend::default(),
+            moe_config: MoeConfig::default(),
+            load_timeout: super::DEFAULT_LOAD_TIMEOUT,
+            warmup: super::warmup_from_env(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -78,6 +156,9 @@ struct MoeConfig {
     cpu_moe_all: bool,
     #[allow(dead_code)]
     n_cpu_moe: Option<usize>,
+    /// Decide per model when neither explicit setting is given (`--moe-auto`)
+    #[allow(dead_code)]
+    auto: bool,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -232,6 +313,8 @@ impl LlamaEngine {
         Self {
             gpu_backend: GpuBackend::detect_best(),
             moe_config: MoeConfig::default(),
+            load_timeout: super::DEFAULT_LOAD_TIMEOUT,
+            warmup: super::warmup_from_env(),
         }
     }
 
@@ -250,6 +333,8 @@ impl LlamaEngine {
         Self {
             gpu_backend,
             moe_config: MoeConfig::default(),
+            load_timeout: super::DEFAULT_LOAD_TIMEOUT,
+            warmup: super::warmup_from_env(),
         }
     }
 
@@ -299,10 +384,32 @@ impl LlamaEngine {
         self.moe_config = MoeConfig {
             cpu_moe_all,
             n_cpu_moe,
+            auto: self.moe_config.auto,
         };
         self
     }
 
+    /// Choose MoE offloading automatically at load time
+    #[allow(dead_code)]
+    pub fn with_moe_auto(mut self, auto: bool) -> Self {
+        self.moe_config.auto = auto;
+        self
+    }
+
+    /// Give up on a model load that takes longer than `timeout`
+    #[allow(dead_code)]
+    pub fn with_load_timeout(mut self, timeout: Duration) -> Self {
+        self.load_timeout = timeout;
+        self
+    }
+
+    /// Warm up each model with a single-token decode once it is loaded
+    #[allow(dead_code)]
+    pub fn with_warmup(mut self, warmup: bool) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
     /// Calculate adaptive batch size based on context length to prevent GGML assert failures
     /// with large prompts (Issue #140)
     #[allow(dead_code)]
@@ -347,238 +454,473 @@ impl InferenceEngine for LlamaEngine {
     async fn load(&self, spec: &ModelSpec) -> Result<Box<dyn LoadedModel>> {
         #[cfg(feature = "llama")]
         {
-            use anyhow::anyhow;
-            use shimmy_llama_cpp_2 as llama;
-            use std::num::NonZeroU32;
-
-            // Use global singleton backend (fixes Issue #128: BackendAlreadyInitialized)
-            let be = get_or_init_backend()?;
-
-            // Configure GPU acceleration based on backend
-            let n_gpu_layers = self.gpu_backend.gpu_layers();
-            info!(
-                "Loading model with {} GPU layers ({:?} backend)",
-                n_gpu_layers, self.gpu_backend
-            );
+            if !spec.base_path.exists() {
+                return Err(EngineError::ModelNotFound {
+                    name: spec.base_path.display().to_string(),
+                }
+                .into());
+            }
+
+            // llama.cpp loads synchronously and can take minutes on a corrupt or
+            // huge file, so keep it off the async workers and bound it
+            let name = spec.base_path.display().to_string();
+            let base_path = spec.base_path.clone();
+            let spec = spec.clone();
+            let gpu_backend = self.gpu_backend.clone();
+            let moe_config = self.moe_config.clone();
+            let mut loaded = super::load_blocking(&name, self.load_timeout, move || {
+                Self::load_model(&spec, &gpu_backend, &moe_config)
+            })
+            .await?;
+            loaded.warmup_latency = super::warm_up(&loaded, self.warmup, &base_path).await;
+            Ok(Box::new(loaded))
+        }
+        #[cfg(not(feature = "llama"))]
+        {
+            let _ = spec; // silence unused warning
+            Err(llama_disabled().into())
+        }
+    }
+}
+
+#[cfg(feature = "llama")]
+impl LlamaEngine {
+    /// Blocking part of `load`; runs on the blocking pool
+    fn load_model(
+        spec: &ModelSpec,
+        gpu_backend: &GpuBackend,
+        moe_config: &MoeConfig,
+    ) -> Result<LlamaLoaded> {
+        use shimmy_llama_cpp_2 as llama;
+        use std::num::NonZeroU32;
+
+        // Use global singleton backend (fixes Issue #128: BackendAlreadyInitialized)
+        let be = get_or_init_backend()?;
+
+        // Configure GPU acceleration based on backend
+        let n_gpu_layers = gpu_backend.gpu_layers();
+        info!(
+            "Loading model with {} GPU layers ({:?} backend)",
+            n_gpu_layers, gpu_backend
+        );
 
-            let mut model_params =
-                llama::model::params::LlamaModelParams::default().with_n_gpu_layers(n_gpu_layers);
-
-            // Apply MoE CPU offloading if configured
-            // Enable MoE CPU offloading (Issue #108 fix)
-            if let Some(n) = self.moe_config.n_cpu_moe {
-                info!("MoE: Offloading first {} expert layers to CPU", n);
-                model_params = model_params.with_n_cpu_moe(n);
-            } else if self.moe_config.cpu_moe_all {
-                info!("MoE: Offloading ALL expert tensors to CPU (saves ~80-85% VRAM)");
-                model_params = model_params.with_cpu_moe_all();
+        let mut model_params =
+            llama::model::params::LlamaModelParams::default().with_n_gpu_layers(n_gpu_layers);
+
+        // Apply MoE CPU offloading if configured
+        // Enable MoE CPU offloading (Issue #108 fix)
+        if let Some(n) = moe_config.n_cpu_moe {
+            info!("MoE: Offloading first {} expert layers to CPU", n);
+            model_params = model_params.with_n_cpu_moe(n);
+        } else if moe_config.cpu_moe_all {
+            info!("MoE: Offloading ALL expert tensors to CPU (saves ~80-85% VRAM)");
+            model_params = model_params.with_cpu_moe_all();
+        } else if moe_config.auto && n_gpu_layers > 0 {
+            match super::moe::auto_decision(&spec.base_path) {
+                super::moe::MoeDecision::OffloadFirst(n) => {
+                    model_params = model_params.with_n_cpu_moe(n);
+                }
+                super::moe::MoeDecision::OffloadAll => {
+                    model_params = model_params.with_cpu_moe_all();
+                }
+                super::moe::MoeDecision::KeepOnGpu => {}
             }
+        }
 
-            // Attempt to load the model with better error handling
-            let model = match llama::model::LlamaModel::load_from_file(
-                be,
-                &spec.base_path,
-                &model_params,
-            ) {
+        // Attempt to load the model with better error handling
+        let model =
+            match llama::model::LlamaModel::load_from_file(be, &spec.base_path, &model_params) {
                 Ok(model) => model,
                 Err(e) => {
-                    // Check if this looks like a memory allocation failure
-                    let error_msg = format!("{}", e);
-                    if error_msg.contains("failed to allocate")
-                        || error_msg.contains("CPU_REPACK buffer")
-                    {
-                        let file_size = std::fs::metadata(&spec.base_path)
-                            .map(|m| m.len())
-                            .unwrap_or(0);
-                        let size_gb = file_size as f64 / 1_024_000_000.0;
-
-                        return Err(anyhow!(
-                            "Memory allocation failed for model {} ({:.1}GB). \n\
-                            💡 Possible solutions:\n\
-                            • Use a smaller model (7B instead of 14B parameters)\n\
-                            • Add more system RAM (model needs ~{}GB)\n\
-                            • Enable model quantization (Q4_K_M, Q5_K_M)\n\
-                            • MoE CPU offloading is temporarily disabled (Issue #108)\n\
-                            Original error: {}",
-                            spec.base_path.display(),
-                            size_gb,
-                            (size_gb * 1.5) as u32, // Rough estimate of RAM needed
-                            e
-                        ));
-                    }
-
-                    // Re-throw other errors as-is
-                    return Err(e.into());
+                    return Err(super::load_error::classify(&spec.base_path, &e.to_string()).into())
                 }
             };
-            let ctx_params = llama::context::params::LlamaContextParams::default()
-                .with_n_ctx(NonZeroU32::new(spec.ctx_len as u32))
-                .with_n_batch(Self::calculate_adaptive_batch_size(spec.ctx_len))
-                .with_n_ubatch(512)
-                .with_n_threads(spec.n_threads.unwrap_or_else(get_optimal_thread_count))
-                .with_n_threads_batch(spec.n_threads.unwrap_or_else(get_optimal_thread_count));
-            let ctx_tmp = model.new_context(be, ctx_params)?;
-            if let Some(ref lora) = spec.lora_path {
-                // Check if it's a SafeTensors file and convert if needed
-                let lora_path = if lora.extension().and_then(|s| s.to_str()) == Some("safetensors")
-                {
-                    // For now, provide helpful error message for SafeTensors files
-                    return Err(anyhow!(
-                        "SafeTensors LoRA detected: {}. Please convert to GGUF format first.",
+        let ctx_params = llama::context::params::LlamaContextParams::default()
+            .with_n_ctx(NonZeroU32::new(spec.ctx_len as u32))
+            .with_n_batch(Self::calculate_adaptive_batch_size(spec.ctx_len))
+            .with_n_ubatch(512)
+            .with_n_threads(spec.n_threads.unwrap_or_else(get_optimal_thread_count))
+            .with_n_threads_batch(spec.n_threads.unwrap_or_else(get_optimal_thread_count));
+        let ctx_tmp = model
+            .new_context(be, ctx_params)
+            .map_err(|e| EngineError::LoadFailed {
+                reason: format!("failed to create context: {}", e),
+            })?;
+        if let Some(ref lora) = spec.lora_path {
+            // Check if it's a SafeTensors file and convert if needed
+            let lora_path = if lora.extension().and_then(|s| s.to_str()) == Some("safetensors") {
+                // For now, provide helpful error message for SafeTensors files
+                return Err(EngineError::Unsupported {
+                    feature: format!(
+                        "SafeTensors LoRA adapter {}; please convert it to GGUF first",
                         lora.display()
-                    ));
-                } else {
-                    lora.clone()
-                };
-
-                let mut adapter = model.lora_adapter_init(&lora_path)?;
-                ctx_tmp
-                    .lora_adapter_set(&mut adapter, 1.0)
-                    .map_err(|e| anyhow!("lora set: {e:?}"))?;
-                info!(adapter=%lora_path.display(), "LoRA adapter attached");
-            }
-            // Store both model and context together to maintain proper lifetimes
-            // The context lifetime is tied to &model; storing both in the same struct ensures safety
-            let ctx: llama::context::LlamaContext<'static> =
-                unsafe { std::mem::transmute(ctx_tmp) };
-            Ok(Box::new(LlamaLoaded {
+                    ),
+                }
+                .into());
+            } else {
+                lora.clone()
+            };
+
+            let mut adapter =
+                model
+                    .lora_adapter_init(&lora_path)
+                    .map_err(|e| EngineError::LoadFailed {
+                        reason: format!("LoRA adapter {}: {}", lora_path.display(), e),
+                    })?;
+            ctx_tmp
+                .lora_adapter_set(&mut adapter, 1.0)
+                .map_err(|e| EngineError::LoadFailed {
+                    reason: format!("lora set: {e:?}"),
+                })?;
+            info!(adapter=%lora_path.display(), "LoRA adapter attached");
+        }
+        // Store both model and context together to maintain proper lifetimes
+        // The context lifetime is tied to &model; storing both in the same struct ensures safety
+        let ctx: llama::context::LlamaContext<'static> = unsafe { std::mem::transmute(ctx_tmp) };
+        Ok(LlamaLoaded {
+            state: Arc::new(LlamaState {
                 model,
                 ctx: Mutex::new(ctx),
-            }))
-        }
-        #[cfg(not(feature = "llama"))]
-        {
-            let _ = spec; // silence unused warning
-            Ok(Box::new(LlamaFallback))
-        }
+                prefix_cache: Mutex::new(PrefixCache::new()),
+            }),
+            warmup_latency: None,
+        })
     }
 }
 
 #[cfg(feature = "llama")]
 struct LlamaLoaded {
+    /// Shared with the blocking-pool threads that decode
+    state: Arc<LlamaState>,
+    warmup_latency: Option<Duration>,
+}
+
+#[cfg(feature = "llama")]
+struct LlamaState {
     model: shimmy_llama_cpp_2::model::LlamaModel,
     ctx: Mutex<shimmy_llama_cpp_2::context::LlamaContext<'static>>,
+    /// Tokens held in `ctx`'s KV cache; always locked after `ctx`
+    prefix_cache: Mutex<PrefixCache<shimmy_llama_cpp_2::token::LlamaToken>>,
 }
 
 #[cfg(feature = "llama")]
-// The llama.cpp context & model use raw pointers internally and are !Send by default.
-// We wrap access in a Mutex and only perform FFI calls while holding the lock, so it's
-// sound to mark the container Send + Sync for our usage (single-threaded mutable access).
-unsafe impl Send for LlamaLoaded {}
-#[cfg(feature = "llama")]
-unsafe impl Sync for LlamaLoaded {}
+impl LlamaLoaded {
+    /// Run `work` on the blocking pool. Decoding holds the context lock for
+    /// the whole generation, which must not pin an async worker thread.
+    async fn on_blocking_pool<T, F>(&self, work: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&LlamaState) -> Result<T> + Send + 'static,
+    {
+        let state = Arc::clone(&self.state);
+        tokio::task::spawn_blocking(move || work(&state)).await?
+    }
+}
 
 #[cfg(feature = "llama")]
-#[async_trait]
-impl LoadedModel for LlamaLoaded {
-    async fn generate(
+impl LlamaState {
+    /// Bring the KV cache up to `tokens`, decoding only what isn't already
+    /// cached from an earlier prompt. Logits are kept for the last token.
+    fn prefill(
+        ctx: &mut shimmy_llama_cpp_2::context::LlamaContext<'static>,
+        cache: &mut PrefixCache<shimmy_llama_cpp_2::token::LlamaToken>,
+        tokens: &[shimmy_llama_cpp_2::token::LlamaToken],
+    ) -> Result<()> {
+        use shimmy_llama_cpp_2::llama_batch::LlamaBatch;
+
+        let mut reused = cache.reuse(tokens);
+        if reused == 0 || !ctx.clear_kv_cache_seq(Some(0), Some(reused as u32), None)? {
+            // Some architectures can't drop part of a sequence; start over
+            ctx.clear_kv_cache();
+            cache.clear();
+            reused = 0;
+        }
+
+        let mut batch = LlamaBatch::new(tokens.len() - reused, 1);
+        for (i, &token) in tokens.iter().enumerate().skip(reused) {
+            // Only request logits for the last token in the initial batch
+            batch.add(token, i as i32, &[0], i == tokens.len() - 1)?;
+        }
+        ctx.decode(&mut batch)?;
+        cache.extend(&tokens[reused..]);
+        Ok(())
+    }
+
+    fn lock_context(
+        &self,
+    ) -> Result<(
+        std::sync::MutexGuard<'_, shimmy_llama_cpp_2::context::LlamaContext<'static>>,
+        std::sync::MutexGuard<'_, PrefixCache<shimmy_llama_cpp_2::token::LlamaToken>>,
+    )> {
+        let ctx = self
+            .ctx
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock context: {}", e))?;
+        let cache = self
+            .prefix_cache
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock prefix cache: {}", e))?;
+        Ok((ctx, cache))
+    }
+
+    fn generate(
         &self,
         prompt: &str,
         opts: GenOptions,
         mut on_token: Option<Box<dyn FnMut(String) + Send>>,
-    ) -> Result<String> {
+    ) -> Result<(String, FinishReason)> {
         use shimmy_llama_cpp_2::{
             llama_batch::LlamaBatch,
             model::{AddBos, Special},
             sampling::LlamaSampler,
         };
-        let mut ctx = self
-            .ctx
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Failed to lock context: {}", e))?;
+        super::validate_samplers(&opts.samplers)?;
+        let (mut ctx, mut cache) = self.lock_context()?;
         let tokens = self.model.str_to_token(prompt, AddBos::Always)?;
-
-        // Create batch with explicit logits configuration
-        let mut batch = LlamaBatch::new(tokens.len(), 1);
-        for (i, &token) in tokens.iter().enumerate() {
-            // Only request logits for the last token in the initial batch
-            let logits = i == tokens.len() - 1;
-            batch.add(token, i as i32, &[0], logits)?;
+        let ctx_len = ctx.n_ctx() as usize;
+        if tokens.len() >= ctx_len {
+            return Err(EngineError::ContextExceeded {
+                prompt_tokens: tokens.len(),
+                ctx_len,
+            }
+            .into());
         }
-        ctx.decode(&mut batch)?;
-
-        let mut sampler = LlamaSampler::chain_simple([
-            LlamaSampler::temp(opts.temperature),
-            LlamaSampler::top_p(opts.top_p, 1),
-            LlamaSampler::top_k(opts.top_k),
-            // API changed order: (repeat_last_n, freq_penalty, presence_penalty, penalty)
-            LlamaSampler::penalties(64, 0.0, 0.0, opts.repeat_penalty),
-            LlamaSampler::greedy(),
-        ])
+        // Pooled models reuse their context across requests; only the part
+        // of the prompt that differs from what is cached gets decoded
+        Self::prefill(&mut ctx, &mut cache, &tokens)?;
+
+        let mut sampler = LlamaSampler::chain_simple(sampler_stages(&opts).into_iter().map(
+            |stage| match stage {
+                SamplerStage::Temperature(t) => LlamaSampler::temp(t),
+                SamplerStage::TopP(p) => LlamaSampler::top_p(p, 1),
+                SamplerStage::TopK(k) => LlamaSampler::top_k(k),
+                // API changed order: (repeat_last_n, freq_penalty, presence_penalty, penalty)
+                SamplerStage::RepeatPenalty(penalty) => {
+                    LlamaSampler::penalties(64, 0.0, 0.0, penalty)
+                }
+                SamplerStage::Greedy => LlamaSampler::greedy(),
+            },
+        ))
         .with_tokens(tokens.iter().copied());
 
         let mut out = String::new();
-        let mut all_tokens = tokens;
+        let mut window = ContextWindow::new(ctx_len, tokens.len(), opts.context_shift);
+        // After a shift the cache positions no longer match the prompt
+        let mut track_prefix = true;
+        // Running out the loop means max_tokens (or a full window) was hit
+        let mut finish_reason = FinishReason::Length;
+        let mut repeats = opts.stop_on_repeat.map(super::repeat::RepeatDetector::new);
+        let mut utf8 = super::utf8::Utf8Decoder::new();
 
         for _ in 0..opts.max_tokens {
+            if opts.cancel.is_cancelled() {
+                return Err(EngineError::Cancelled.into());
+            }
             // Sample from the last (and only) position with logits
             let token = sampler.sample(&ctx, -1);
             if self.model.is_eog_token(token) {
+                finish_reason = FinishReason::Stop;
                 break;
             }
-            // Use Plaintext to avoid re-tokenizing control tokens into special forms
-            let piece = self.model.token_to_str(token, Special::Plaintext)?;
+            if repeats.as_mut().is_some_and(|r| r.push(token.0)) {
+                finish_reason = FinishReason::Repetition;
+                break;
+            }
+            // Use Plaintext to avoid re-tokenizing control tokens into special forms.
+            // Decode bytes, not strings: a multibyte character can span tokens.
+            let bytes = self.model.token_to_bytes(token, Special::Plaintext)?;
+            let piece = utf8.push(&bytes);
             out.push_str(&piece);
 
-            // Check for stop tokens before emitting
-            let should_stop = opts
-                .stop_tokens
-                .iter()
-                .any(|stop_token| out.contains(stop_token));
-            if should_stop {
-                // Remove the stop token from the output, ensuring UTF-8 validity
-                for stop_token in &opts.stop_tokens {
-                    if let Some(pos) = out.rfind(stop_token) {
-                        // Find the character boundary before the stop token
-                        // This prevents truncating in the middle of multi-byte Unicode characters
-                        let truncate_pos = out
-                            .char_indices()
-                            .take_while(|(byte_pos, _)| *byte_pos <= pos)
-                            .last()
-                            .map(|(byte_pos, _)| byte_pos)
-                            .unwrap_or(0);
-                        out.truncate(truncate_pos);
-                        break;
-                    }
-                }
+            // Check for stop sequences before emitting; the cut is always on
+            // a character boundary and drops tokenizer word-spacing before it
+            if let Some((cut, matched)) = super::stop::find_stop(&out, &opts.stop_tokens) {
+                out.truncate(cut);
+                finish_reason = FinishReason::StopSequence(matched);
                 break;
             }
 
             // Handle UTF-8 aware token streaming (Issue #139 fix)
-            if let Some(cb) = on_token.as_mut() {
+            if let Some(cb) = on_token.as_mut().filter(|_| !piece.is_empty()) {
                 cb(piece.clone());
             }
 
+            match window.make_room() {
+                Step::Room => {}
+                Step::Shift(shift) => {
+                    let end = shift.end() as u32;
+                    if !ctx.clear_kv_cache_seq(Some(0), Some(shift.keep as u32), Some(end))? {
+                        tracing::warn!("This model's KV cache can't be shifted; stopping at the context window");
+                        break;
+                    }
+                    ctx.kv_cache_seq_add(0, Some(end), None, -(shift.discard as i32))?;
+                    cache.clear();
+                    track_prefix = false;
+                }
+                Step::Full => break,
+            }
             let mut step = LlamaBatch::new(1, 1);
-            step.add(token, all_tokens.len() as i32, &[0], true)?;
+            step.add(token, window.position() as i32, &[0], true)?;
             ctx.decode(&mut step)?;
-            all_tokens.push(token);
+            window.push();
+            if track_prefix {
+                cache.extend(&[token]);
+            }
         }
 
-        Ok(out)
+        // Bytes of a character cut short by max_tokens; a stop sequence cut
+        // already dropped them
+        if !matches!(finish_reason, FinishReason::StopSequence(_)) {
+            let tail = utf8.finish();
+            if !tail.is_empty() {
+                out.push_str(&tail);
+                if let Some(cb) = on_token.as_mut() {
+                    cb(tail);
+                }
+            }
+        }
+
+        Ok((out, finish_reason))
+    }
+
+    fn warm(&self, prefix: &str) -> Result<usize> {
+        use shimmy_llama_cpp_2::model::AddBos;
+
+        let (mut ctx, mut cache) = self.lock_context()?;
+        let tokens = self.model.str_to_token(prefix, AddBos::Always)?;
+        let ctx_len = ctx.n_ctx() as usize;
+        if tokens.len() >= ctx_len {
+            return Err(EngineError::ContextExceeded {
+                prompt_tokens: tokens.len(),
+                ctx_len,
+            }
+            .into());
+        }
+        Self::prefill(&mut ctx, &mut cache, &tokens)?;
+        Ok(cache.len())
+    }
+
+    fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        use super::embedding::{embed_batched, EmbeddingBatchConfig};
+        use shimmy_llama_cpp_2::{
+            context::params::LlamaContextParams, llama_batch::LlamaBatch, model::AddBos,
+        };
+        use std::num::NonZeroU32;
+
+        // Holding the generation lock keeps embedding and generation from
+        // allocating contexts for the same model at once
+        let gen_ctx = self
+            .ctx
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock context: {}", e))?;
+        let config = EmbeddingBatchConfig::from_env(gen_ctx.n_ctx() as usize);
+
+        let tokens = inputs
+            .iter()
+            .map(|text| self.model.str_to_token(text, AddBos::Always))
+            .collect::<Result<Vec<_>, _>>()?;
+        let counts: Vec<usize> = tokens.iter().map(Vec::len).collect();
+
+        // Pooled embeddings need their own context; n_ubatch must cover the
+        // whole batch for non-causal embedding models
+        let params = LlamaContextParams::default()
+            .with_n_ctx(NonZeroU32::new(config.max_batch_tokens as u32))
+            .with_n_batch(config.max_batch_tokens as u32)
+            .with_n_ubatch(config.max_batch_tokens as u32)
+            .with_n_seq_max(config.max_sequences as u32)
+            .with_embeddings(true);
+        let mut ctx = self
+            .model
+            .new_context(get_or_init_backend()?, params)
+            .map_err(|e| anyhow::anyhow!("Failed to create embedding context: {}", e))?;
+
+        embed_batched(&counts, &config, |range| {
+            ctx.clear_kv_cache();
+            let n_tokens = counts[range.clone()].iter().sum();
+            let mut batch = LlamaBatch::new(n_tokens, range.len() as i32);
+            for (seq, i) in range.clone().enumerate() {
+                batch.add_sequence(&tokens[i], seq as i32, false)?;
+            }
+            ctx.decode(&mut batch)?;
+            (0..range.len())
+                .map(|seq| {
+                    ctx.embeddings_seq_ith(seq as i32)
+                        .map(|embedding| embedding.to_vec())
+                        .map_err(|e| {
+                            anyhow::Error::from(EngineError::Unsupported {
+                                feature: format!("embeddings for this model ({})", e),
+                            })
+                        })
+                })
+                .collect()
+        })
     }
 }
 
-/// Fallback implementation when llama.cpp feature is not enabled
-/// Returns informative message directing users to enable the feature
-#[cfg(not(feature = "llama"))]
-struct LlamaFallback;
+#[cfg(feature = "llama")]
+// The llama.cpp context & model use raw pointers internally and are !Send by default.
+// We wrap access in a Mutex and only perform FFI calls while holding the lock, so it's
+// sound to mark the container Send + Sync for our usage (single-threaded mutable access).
+unsafe impl Send for LlamaState {}
+#[cfg(feature = "llama")]
+unsafe impl Sync for LlamaState {}
 
-#[cfg(not(feature = "llama"))]
+#[cfg(feature = "llama")]
 #[async_trait]
-impl LoadedModel for LlamaFallback {
+impl LoadedModel for LlamaLoaded {
     async fn generate(
         &self,
         prompt: &str,
-        _opts: GenOptions,
-        mut on_token: Option<Box<dyn FnMut(String) + Send>>,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
     ) -> Result<String> {
-        let fallback_msg =
-            "Llama.cpp support not enabled. Build with --features llama for full functionality.";
-        if let Some(cb) = on_token.as_mut() {
-            cb(fallback_msg.to_string());
-        }
-        Ok(format!("[INFO] {} Input: {}", fallback_msg, prompt))
+        self.generate_with_reason(prompt, opts, on_token)
+            .await
+            .map(|(text, _)| text)
+    }
+
+    async fn generate_with_reason(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<(String, FinishReason)> {
+        let prompt = prompt.to_string();
+        self.on_blocking_pool(move |state| state.generate(&prompt, opts, on_token))
+            .await
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        use shimmy_llama_cpp_2::model::AddBos;
+        self.state
+            .model
+            .str_to_token(text, AddBos::Always)
+            .map(|tokens| tokens.len())
+            .unwrap_or_else(|_| super::estimate_tokens(text))
+    }
+
+    fn warmup_latency(&self) -> Option<Duration> {
+        self.warmup_latency
+    }
+
+    async fn warm(&self, prefix: &str) -> Result<usize> {
+        let prefix = prefix.to_string();
+        self.on_blocking_pool(move |state| state.warm(&prefix))
+            .await
+    }
+
+    fn prefix_cache_stats(&self) -> Option<PrefixCacheStats> {
+        self.state
+            .prefix_cache
+            .lock()
+            .ok()
+            .map(|cache| cache.stats())
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        let inputs = inputs.to_vec();
+        self.on_blocking_pool(move |state| state.embed(&inputs))
+            .await
     }
 }
 
@@ -586,6 +928,62 @@ impl LoadedModel for LlamaFallback {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_temperature_zero_is_pure_greedy() {
+        let greedy = |top_p: f32, top_k: i32| {
+            sampler_stages(&GenOptions {
+                temperature: 0.0,
+                top_p,
+                top_k,
+                ..Default::default()
+            })
+        };
+        assert_eq!(greedy(0.9, 40), vec![SamplerStage::Greedy]);
+        // Same chain (and so the same argmax output) whatever top_p/top_k say
+        assert_eq!(greedy(0.9, 40), greedy(0.1, 1));
+    }
+
+    #[test]
+    fn test_nonzero_temperature_keeps_default_chain() {
+        let stages = sampler_stages(&GenOptions {
+            temperature: 0.7,
+            top_p: 0.9,
+            top_k: 40,
+            repeat_penalty: 1.1,
+            ..Default::default()
+        });
+        assert_eq!(
+            stages,
+            vec![
+                SamplerStage::Temperature(0.7),
+                SamplerStage::TopP(0.9),
+                SamplerStage::TopK(40),
+                SamplerStage::RepeatPenalty(1.1),
+                SamplerStage::Greedy,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sampler_order_is_passed_to_chain() {
+        let stages = sampler_stages(&GenOptions {
+            temperature: 0.7,
+            top_p: 0.9,
+            top_k: 40,
+            samplers: vec!["top_k".into(), "temperature".into(), "top_p".into()],
+            ..Default::default()
+        });
+        assert_eq!(
+            stages,
+            vec![
+                SamplerStage::TopK(40),
+                SamplerStage::Temperature(0.7),
+                SamplerStage::TopP(0.9),
+                SamplerStage::Greedy,
+            ]
+        );
+    }
+
     #[test]
     fn test_llama_engine_initialization() {
         let engine = LlamaEngine::new();
@@ -0,0 +1,162 @@
+// Classification of GGUF load failures
+//
+// llama.cpp reports load failures as free-form strings ("unknown model
+// architecture: 'foo'", "failed to allocate CPU_REPACK buffer", ...), and the
+// Rust bindings often reduce them to a bare "null result". `classify` maps the
+// common signatures to specific `EngineError` variants with a hint on what to
+// do, falling back to the file's magic number when the message says nothing.
+#![allow(dead_code)]
+
+use super::EngineError;
+use std::io::Read;
+use std::path::Path;
+
+/// Map a raw llama.cpp load error for the model at `path` to an `EngineError`.
+/// The raw text is kept at debug level.
+pub fn classify(path: &Path, raw: &str) -> EngineError {
+    tracing::debug!("llama.cpp failed to load {}: {}", path.display(), raw);
+    classify_message(path, raw)
+        .or_else(|| check_magic(path))
+        .unwrap_or_else(|| EngineError::LoadFailed {
+            reason: format!("{}: {}", path.display(), raw),
+        })
+}
+
+fn classify_message(path: &Path, raw: &str) -> Option<EngineError> {
+    let lower = raw.to_ascii_lowercase();
+    let has = |needles: &[&str]| needles.iter().any(|n| lower.contains(n));
+
+    if has(&[
+        "unknown model architecture",
+        "unsupported model architecture",
+    ]) {
+        return Some(EngineError::UnsupportedArchitecture {
+            architecture: quoted(raw).unwrap_or("unknown").to_string(),
+        });
+    }
+    if has(&[
+        "invalid magic",
+        "bad magic",
+        "not a gguf",
+        "failed to read magic",
+    ]) {
+        return Some(EngineError::InvalidModelFile {
+            path: path.display().to_string(),
+        });
+    }
+    if has(&[
+        "invalid ggml type",
+        "unknown ggml type",
+        "unsupported ggml type",
+        "invalid tensor type",
+        "unsupported tensor type",
+    ]) {
+        return Some(EngineError::UnsupportedQuantization {
+            detail: raw.trim().to_string(),
+        });
+    }
+    if has(&[
+        "failed to allocate",
+        "cpu_repack buffer",
+        "out of memory",
+        "outofdevicememory",
+        "cudamalloc failed",
+    ]) {
+        return Some(EngineError::InsufficientMemory {
+            path: path.display().to_string(),
+            size_bytes: std::fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+        });
+    }
+    if has(&[
+        "resource temporarily unavailable",
+        "device or resource busy",
+        "text file busy",
+        "resource busy",
+    ]) {
+        return Some(EngineError::ResourceBusy {
+            path: path.display().to_string(),
+            reason: raw.trim().to_string(),
+        });
+    }
+    None
+}
+
+/// A file that doesn't start with `GGUF` was never going to load; say so
+/// instead of passing on an opaque message
+fn check_magic(path: &Path) -> Option<EngineError> {
+    let mut magic = [0u8; 4];
+    let read = std::fs::File::open(path).and_then(|mut f| f.read_exact(&mut magic));
+    (read.is_ok() && &magic != b"GGUF").then(|| EngineError::InvalidModelFile {
+        path: path.display().to_string(),
+    })
+}
+
+/// First single-quoted word, e.g. the architecture in llama.cpp's message
+fn quoted(raw: &str) -> Option<&str> {
+    let start = raw.find('\'')? + 1;
+    let len = raw[start..].find('\'')?;
+    Some(&raw[start..start + len]).filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_llama_errors_map_to_classified_variants() {
+        let path = Path::new("/models/missing.gguf");
+        let cases = [
+            (
+                "llama_model_load: error loading model: error loading model architecture: unknown model architecture: 'qwen9moe'",
+                "unsupported_architecture",
+            ),
+            (
+                "gguf_init_from_file_impl: invalid magic characters: 'lmgg', expected 'GGUF'",
+                "invalid_model_file",
+            ),
+            (
+                "llama_model_load: error loading model: tensor 'blk.0.attn_q.weight' has invalid ggml type 39 (NONE)",
+                "unsupported_quantization",
+            ),
+            (
+                "ggml_backend_cpu_buffer_type_alloc_buffer: failed to allocate buffer of size 8589934592",
+                "insufficient_memory",
+            ),
+            ("ggml_vulkan: ErrorOutOfDeviceMemory", "insufficient_memory"),
+            (
+                "llama_model_load: error loading model: mmap failed: Resource temporarily unavailable",
+                "resource_busy",
+            ),
+            ("null result from llama cpp", "model_load_failed"),
+        ];
+        for (raw, code) in cases {
+            assert_eq!(classify(path, raw).code(), code, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn test_classified_messages_are_actionable() {
+        let path = Path::new("/models/missing.gguf");
+        let arch = classify(path, "unknown model architecture: 'qwen9moe'");
+        assert!(matches!(
+            &arch,
+            EngineError::UnsupportedArchitecture { architecture } if architecture == "qwen9moe"
+        ));
+        let quant = classify(path, "tensor 'x' has invalid ggml type 39");
+        assert!(quant.to_string().contains("newer llama.cpp build"));
+    }
+
+    #[test]
+    fn test_opaque_error_falls_back_to_file_magic() {
+        let dir = tempfile::tempdir().unwrap();
+        let html = dir.path().join("model.gguf");
+        std::fs::write(&html, b"<!DOCTYPE html>").unwrap();
+        let err = classify(&html, "null result from llama cpp");
+        assert_eq!(err.code(), "invalid_model_file");
+
+        let gguf = dir.path().join("real.gguf");
+        std::fs::write(&gguf, b"GGUF\x03\x00\x00\x00").unwrap();
+        let err = classify(&gguf, "null result from llama cpp");
+        assert!(matches!(err, EngineError::LoadFailed { .. }));
+    }
+}
@@ -0,0 +1,335 @@
+// Scripted engine shared by handler and pool tests
+//
+// `MockEngine` loads `MockModel`s that answer according to a `Reply`, with
+// optional load delays and failures. Everything a test might assert on (loads
+// per model, concurrency peaks, generation count, requested budgets,
+// cancellation) lands in the shared `MockStats`.
+
+use super::prefix::PrefixCache;
+use super::{EngineError, FinishReason, GenOptions, InferenceEngine, LoadedModel, ModelSpec};
+use crate::engine::PrefixCacheStats;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Words emitted by `Reply::Words`
+const WORDS: [&str; 5] = ["one", "two", "three", "four", "five"];
+
+/// What a `MockModel` generates
+#[derive(Clone, Copy, Debug)]
+pub enum Reply {
+    /// `"<model name>: <prompt>"`
+    Echo,
+    /// The prompt itself
+    Prompt,
+    /// This text, as a single token
+    Text(&'static str),
+    /// These tokens, streamed one by one and joined as the result
+    Tokens(&'static [&'static str]),
+    /// The `max_tokens` it was asked for
+    MaxTokens,
+    /// How many generations the engine has run so far
+    Count,
+    /// Up to five words, cut at `max_tokens` (finishing with `Length`)
+    Words,
+    /// A token every 10ms on the blocking pool, as llama.cpp generation
+    /// runs, until the cancel token fires
+    Endless,
+    /// This error
+    Fail(fn() -> EngineError),
+}
+
+/// What the engine and its models have seen
+#[derive(Default)]
+pub struct MockStats {
+    /// Loads started, per model name
+    pub loads: Mutex<HashMap<String, usize>>,
+    in_flight: AtomicUsize,
+    /// Most loads in progress at once
+    pub peak_loading: AtomicUsize,
+    /// Models loaded (or loading) and not yet dropped
+    pub live: AtomicUsize,
+    /// Most models live at once
+    pub peak_live: AtomicUsize,
+    /// Generations started
+    pub generations: AtomicUsize,
+    /// `max_tokens` of every generation, in order
+    pub max_tokens: Mutex<Vec<usize>>,
+    /// Set when an `Endless` generation saw its cancel token
+    pub cancelled: AtomicBool,
+}
+
+impl MockStats {
+    /// Loads started across all models
+    pub fn total_loads(&self) -> usize {
+        self.loads.lock().unwrap().values().sum()
+    }
+
+    /// Loads started for `name`
+    pub fn loads_of(&self, name: &str) -> usize {
+        self.loads.lock().unwrap().get(name).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Clone)]
+pub struct MockEngine {
+    reply: Reply,
+    load_delay: Duration,
+    token_delay: Duration,
+    transient_failures: usize,
+    broken_prefix: Option<&'static str>,
+    vision: bool,
+    prefix_cache: bool,
+    pub stats: Arc<MockStats>,
+}
+
+impl MockEngine {
+    pub fn new(reply: Reply) -> Self {
+        Self {
+            reply,
+            load_delay: Duration::ZERO,
+            token_delay: Duration::ZERO,
+            transient_failures: 0,
+            broken_prefix: None,
+            vision: false,
+            prefix_cache: false,
+            stats: Arc::default(),
+        }
+    }
+
+    /// Sleep this long in every load
+    pub fn with_load_delay(mut self, delay: Duration) -> Self {
+        self.load_delay = delay;
+        self
+    }
+
+    /// Pause this long between streamed tokens
+    pub fn with_token_delay(mut self, delay: Duration) -> Self {
+        self.token_delay = delay;
+        self
+    }
+
+    /// Fail the first `n` loads with a transient out-of-memory error
+    pub fn with_transient_failures(mut self, n: usize) -> Self {
+        self.transient_failures = n;
+        self
+    }
+
+    /// Fail loads of models whose name starts with `prefix`
+    pub fn with_broken_models(mut self, prefix: &'static str) -> Self {
+        self.broken_prefix = Some(prefix);
+        self
+    }
+
+    /// Answer images with `"vision <n> bytes: <prompt>"`
+    pub fn with_vision(mut self) -> Self {
+        self.vision = true;
+        self
+    }
+
+    /// Keep a word-level prefix cache, like `LlamaLoaded`'s, and support
+    /// warming it
+    pub fn with_prefix_cache(mut self) -> Self {
+        self.prefix_cache = true;
+        self
+    }
+
+    /// A model as `load` would return it, without counting a load
+    pub fn model(&self, name: &str) -> MockModel {
+        self.stats.live.fetch_add(1, Ordering::SeqCst);
+        MockModel {
+            name: name.to_string(),
+            engine: self.clone(),
+            prefix: self.prefix_cache.then(|| Mutex::new(PrefixCache::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl InferenceEngine for MockEngine {
+    async fn load(&self, spec: &ModelSpec) -> anyhow::Result<Box<dyn LoadedModel>> {
+        let stats = &self.stats;
+        let started = {
+            let mut loads = stats.loads.lock().unwrap();
+            let count = loads.entry(spec.name.clone()).or_default();
+            *count += 1;
+            loads.values().sum::<usize>()
+        };
+        let loading = stats.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        stats.peak_loading.fetch_max(loading, Ordering::SeqCst);
+        let live = stats.live.fetch_add(1, Ordering::SeqCst) + 1;
+        stats.peak_live.fetch_max(live, Ordering::SeqCst);
+
+        if !self.load_delay.is_zero() {
+            tokio::time::sleep(self.load_delay).await;
+        }
+        stats.in_flight.fetch_sub(1, Ordering::SeqCst);
+        stats.live.fetch_sub(1, Ordering::SeqCst);
+
+        if started <= self.transient_failures {
+            return Err(EngineError::InsufficientMemory {
+                path: spec.base_path.display().to_string(),
+                size_bytes: 8 << 30,
+            }
+            .into());
+        }
+        if self
+            .broken_prefix
+            .is_some_and(|prefix| spec.name.starts_with(prefix))
+        {
+            return Err(EngineError::LoadFailed {
+                reason: "bad magic".into(),
+            }
+            .into());
+        }
+        Ok(Box::new(self.model(&spec.name)))
+    }
+}
+
+pub struct MockModel {
+    name: String,
+    engine: MockEngine,
+    prefix: Option<Mutex<PrefixCache<String>>>,
+}
+
+impl MockModel {
+    /// Feed `text` through the prefix cache, returning the cached length
+    fn prefill(&self, text: &str) -> Option<usize> {
+        let mut cache = self.prefix.as_ref()?.lock().unwrap();
+        let tokens: Vec<String> = text.split_whitespace().map(str::to_string).collect();
+        let reused = cache.reuse(&tokens);
+        cache.extend(&tokens[reused..]);
+        Some(cache.len())
+    }
+
+    /// Emit tokens on the blocking pool until cancelled
+    async fn run_endless(
+        &self,
+        opts: GenOptions,
+        mut on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> anyhow::Result<(String, FinishReason)> {
+        let stats = self.engine.stats.clone();
+        tokio::task::spawn_blocking(move || {
+            for _ in 0..500 {
+                if opts.cancel.is_cancelled() {
+                    stats.cancelled.store(true, Ordering::SeqCst);
+                    return Err(EngineError::Cancelled.into());
+                }
+                if let Some(cb) = on_token.as_mut() {
+                    cb("tok ".to_string());
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Ok(("done".to_string(), FinishReason::Stop))
+        })
+        .await?
+    }
+}
+
+impl Drop for MockModel {
+    fn drop(&mut self) {
+        self.engine.stats.live.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl LoadedModel for MockModel {
+    async fn generate(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> anyhow::Result<String> {
+        self.generate_with_reason(prompt, opts, on_token)
+            .await
+            .map(|(text, _)| text)
+    }
+
+    async fn generate_with_reason(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        mut on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> anyhow::Result<(String, FinishReason)> {
+        let stats = &self.engine.stats;
+        let generation = stats.generations.fetch_add(1, Ordering::SeqCst) + 1;
+        stats.max_tokens.lock().unwrap().push(opts.max_tokens);
+        self.prefill(prompt);
+
+        let tokens: Vec<String> = match self.engine.reply {
+            Reply::Echo => vec![format!("{}: {}", self.name, prompt)],
+            Reply::Prompt => vec![prompt.to_string()],
+            Reply::Text(text) => vec![text.to_string()],
+            Reply::Tokens(tokens) => tokens.iter().map(|t| t.to_string()).collect(),
+            Reply::MaxTokens => vec![opts.max_tokens.to_string()],
+            Reply::Count => vec![generation.to_string()],
+            Reply::Words => {
+                let words: Vec<&str> = WORDS.into_iter().take(opts.max_tokens).collect();
+                let reason = if words.len() < WORDS.len() {
+                    FinishReason::Length
+                } else {
+                    FinishReason::Stop
+                };
+                if let Some(cb) = on_token.as_mut() {
+                    for word in &words {
+                        cb(format!("{} ", word));
+                    }
+                }
+                return Ok((words.join(" "), reason));
+            }
+            Reply::Endless => return self.run_endless(opts, on_token).await,
+            Reply::Fail(error) => return Err(error().into()),
+        };
+        if let Some(cb) = on_token.as_mut() {
+            for (i, token) in tokens.iter().enumerate() {
+                if i > 0 && !self.engine.token_delay.is_zero() {
+                    tokio::time::sleep(self.engine.token_delay).await;
+                }
+                cb(token.clone());
+            }
+        }
+        Ok((tokens.concat(), FinishReason::Stop))
+    }
+
+    /// One token per word, unlike the chars/4 estimate
+    fn count_tokens(&self, text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+
+    async fn generate_vision(
+        &self,
+        image_data: &[u8],
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> anyhow::Result<(String, FinishReason)> {
+        if !self.engine.vision {
+            return Err(EngineError::Unsupported {
+                feature: "vision input for this model".to_string(),
+            }
+            .into());
+        }
+        if let Reply::Endless = self.engine.reply {
+            return self.run_endless(opts, on_token).await;
+        }
+        Ok((
+            format!("vision {} bytes: {}", image_data.len(), prompt),
+            FinishReason::Length,
+        ))
+    }
+
+    async fn warm(&self, prefix: &str) -> anyhow::Result<usize> {
+        self.prefill(prefix).ok_or_else(|| {
+            EngineError::Unsupported {
+                feature: "prefix cache warming for this model".to_string(),
+            }
+            .into()
+        })
+    }
+
+    fn prefix_cache_stats(&self) -> Option<PrefixCacheStats> {
+        Some(self.prefix.as_ref()?.lock().unwrap().stats())
+    }
+}
@@ -1,7 +1,89 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Default limit for a single model load (`--load-timeout-secs`)
+pub const DEFAULT_LOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Upper bound for the computed `max_tokens` default when a client omits it
+pub const MAX_DEFAULT_MAX_TOKENS: usize = 2048;
+
+/// Rough token estimate (~1 token per 4 characters) for backends without a tokenizer
+pub fn estimate_tokens(text: &str) -> usize {
+    (text.len() as f32 / 4.0).ceil() as usize
+}
+
+/// Resolve the effective `max_tokens` for a request against the model's context window.
+///
+/// An omitted value defaults to the remaining context (`ctx_len - prompt_tokens`),
+/// capped at `MAX_DEFAULT_MAX_TOKENS`. An explicit value larger than the remaining
+/// context is clamped to it.
+pub fn resolve_max_tokens(requested: Option<usize>, ctx_len: usize, prompt_tokens: usize) -> usize {
+    let remaining = ctx_len.saturating_sub(prompt_tokens).max(1);
+    match requested {
+        None => remaining.min(MAX_DEFAULT_MAX_TOKENS),
+        Some(max_tokens) if max_tokens > remaining => {
+            tracing::warn!(
+                "max_tokens {} exceeds remaining context ({} of {} tokens used by prompt); clamping to {}",
+                max_tokens,
+                prompt_tokens,
+                ctx_len,
+                remaining
+            );
+            remaining
+        }
+        Some(max_tokens) => max_tokens,
+    }
+}
+
+/// `resolve_max_tokens`, except that with `context_shift` generation may run
+/// past the context window: an explicit value is kept as is, and an omitted
+/// one is `MAX_DEFAULT_MAX_TOKENS`
+pub fn resolve_generation_budget(
+    requested: Option<usize>,
+    ctx_len: usize,
+    prompt_tokens: usize,
+    context_shift: bool,
+) -> usize {
+    if context_shift {
+        requested.unwrap_or(MAX_DEFAULT_MAX_TOKENS)
+    } else {
+        resolve_max_tokens(requested, ctx_len, prompt_tokens)
+    }
+}
+
+/// Run blocking model-load work on the blocking pool, giving up after `timeout`.
+///
+/// On timeout the worker thread is left to finish on its own and whatever it
+/// produces is dropped; the caller gets `EngineError::Timeout` straight away.
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+pub async fn load_blocking<T, F>(name: &str, timeout: Duration, work: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match tokio::time::timeout(timeout, tokio::task::spawn_blocking(work)).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(join_err)) => Err(EngineError::LoadFailed {
+            reason: format!("{}: load worker failed: {}", name, join_err),
+        }
+        .into()),
+        Err(_) => {
+            tracing::warn!(
+                "Loading {} did not finish within {}s; abandoning it",
+                name,
+                timeout.as_secs()
+            );
+            Err(EngineError::Timeout {
+                operation: format!("Loading model {}", name),
+                after: timeout,
+            }
+            .into())
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GenOptions {
@@ -14,6 +96,32 @@ pub struct GenOptions {
     pub stream: bool,
     #[serde(default)]
     pub stop_tokens: Vec<String>,
+    /// Drop a leading space/BOS token from the output (see `trim`)
+    #[serde(default = "default_trim_leading")]
+    pub trim_leading: bool,
+    /// Sampler chain by stage name (see `SAMPLER_NAMES`), in order; stages
+    /// left out are skipped. Empty keeps the backend's default chain.
+    #[serde(default)]
+    pub samplers: Vec<String>,
+    /// Halt when generation falls into a repeated n-gram loop (see `repeat`)
+    #[serde(default)]
+    pub stop_on_repeat: Option<RepeatStop>,
+    /// Keep generating past a full context window by dropping earlier tokens
+    /// (see `shift`); off stops with `FinishReason::Length`
+    #[serde(default)]
+    pub context_shift: bool,
+    /// Flipped when the requesting client goes away (see `cancel`); backends
+    /// stop at the next token
+    #[serde(skip)]
+    pub cancel: CancelToken,
+    /// Out-of-range sampling values adjusted by `clamp_sampling`, e.g.
+    /// "top_p 1.5 -> 1"
+    #[serde(skip)]
+    pub clamped: Vec<String>,
+}
+
+fn default_trim_leading() -> bool {
+    true
 }
 
 impl Default for GenOptions {
@@ -27,6 +135,159 @@ impl Default for GenOptions {
             seed: None,
             stream: true,
             stop_tokens: Vec::new(),
+            trim_leading: true,
+            samplers: Vec::new(),
+            stop_on_repeat: None,
+            context_shift: false,
+            cancel: CancelToken::default(),
+            clamped: Vec::new(),
+        }
+    }
+}
+
+/// Accepted range per sampling setting; values outside it are clamped
+pub const TEMPERATURE_RANGE: (f32, f32) = (0.0, 2.0);
+pub const TOP_P_RANGE: (f32, f32) = (0.0, 1.0);
+
+impl GenOptions {
+    /// Bring temperature, top_p and top_k into range, so buggy clients get a
+    /// sensible generation instead of a llama.cpp error. Each adjustment is
+    /// logged and kept in `clamped`. With `strict`, out-of-range values are
+    /// rejected instead.
+    pub fn clamp_sampling(&mut self, strict: bool) -> std::result::Result<(), EngineError> {
+        let mut clamped = Vec::new();
+        let mut clamp = |name: &str, value: f32, (lo, hi): (f32, f32)| {
+            let fixed = if value.is_nan() {
+                lo
+            } else {
+                value.clamp(lo, hi)
+            };
+            if fixed != value {
+                clamped.push(format!("{} {} -> {}", name, value, fixed));
+            }
+            fixed
+        };
+        let temperature = clamp("temperature", self.temperature, TEMPERATURE_RANGE);
+        let top_p = clamp("top_p", self.top_p, TOP_P_RANGE);
+        if self.top_k < 0 {
+            clamped.push(format!("top_k {} -> 0", self.top_k));
+        }
+        if clamped.is_empty() {
+            return Ok(());
+        }
+        if strict {
+            return Err(EngineError::InvalidOptions {
+                reason: format!(
+                    "sampling values out of range ({}); temperature must be within [{}, {}], top_p within [{}, {}], top_k >= 0",
+                    clamped.join(", "),
+                    TEMPERATURE_RANGE.0,
+                    TEMPERATURE_RANGE.1,
+                    TOP_P_RANGE.0,
+                    TOP_P_RANGE.1
+                ),
+            });
+        }
+        self.temperature = temperature;
+        self.top_p = top_p;
+        self.top_k = self.top_k.max(0);
+        for adjustment in &clamped {
+            tracing::warn!("Clamped out-of-range sampling value: {}", adjustment);
+        }
+        self.clamped.extend(clamped);
+        Ok(())
+    }
+}
+
+/// Sampler stages that `GenOptions::samplers` can order
+pub const SAMPLER_NAMES: &[&str] = &["temperature", "top_p", "top_k", "repeat_penalty"];
+
+/// Check a `samplers` list: only known stages, each at most once
+pub fn validate_samplers(samplers: &[String]) -> std::result::Result<(), EngineError> {
+    let mut seen = std::collections::HashSet::new();
+    for name in samplers {
+        let reason = if !SAMPLER_NAMES.contains(&name.as_str()) {
+            format!(
+                "unknown sampler '{}'; expected one of: {}",
+                name,
+                SAMPLER_NAMES.join(", ")
+            )
+        } else if !seen.insert(name.as_str()) {
+            format!("sampler '{}' is listed more than once", name)
+        } else {
+            continue;
+        };
+        return Err(EngineError::InvalidOptions { reason });
+    }
+    Ok(())
+}
+
+/// Sampling settings a generation actually ran with, after defaults and
+/// clamping, for clients that ask for them (`include_sampling_params`)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SamplingReport {
+    /// "greedy" when temperature <= 0 (argmax, other samplers skipped),
+    /// otherwise "sampled"
+    pub mode: &'static str,
+    pub temperature: f32,
+    pub top_p: f32,
+    pub top_k: i32,
+    pub repeat_penalty: f32,
+    pub seed: Option<u32>,
+    pub max_tokens: usize,
+    /// Sampler stages applied before the final pick, in order
+    pub samplers: Vec<String>,
+    pub stop_tokens: Vec<String>,
+    /// Requested values that were out of range (see `clamp_sampling`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub clamped: Vec<String>,
+}
+
+impl SamplingReport {
+    pub fn new(opts: &GenOptions) -> Self {
+        let greedy = opts.temperature <= 0.0;
+        let samplers = if greedy {
+            Vec::new()
+        } else if opts.samplers.is_empty() {
+            SAMPLER_NAMES.iter().map(|s| s.to_string()).collect()
+        } else {
+            opts.samplers.clone()
+        };
+        Self {
+            mode: if greedy { "greedy" } else { "sampled" },
+            temperature: opts.temperature,
+            top_p: opts.top_p,
+            top_k: opts.top_k,
+            repeat_penalty: opts.repeat_penalty,
+            seed: opts.seed,
+            max_tokens: opts.max_tokens,
+            samplers,
+            stop_tokens: opts.stop_tokens.clone(),
+            clamped: opts.clamped.clone(),
+        }
+    }
+}
+
+/// Why a generation ended, reported to clients as `finish_reason`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// End-of-sequence token was produced
+    Stop,
+    /// One of `GenOptions::stop_tokens` was produced; holds its index there
+    StopSequence(usize),
+    /// `max_tokens` was reached
+    Length,
+    /// Generation fell into a loop caught by `GenOptions::stop_on_repeat`
+    Repetition,
+}
+
+impl FinishReason {
+    /// OpenAI `finish_reason`, which doesn't distinguish stop sequences
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FinishReason::Stop | FinishReason::StopSequence(_) => "stop",
+            FinishReason::Length => "length",
+            FinishReason::Repetition => "repetition",
         }
     }
 }
@@ -104,12 +365,24 @@ pub trait UniversalEngine: Send + Sync {
 #[async_trait]
 #[cfg(feature = "huggingface")]
 pub trait UniversalModel: Send + Sync {
+    /// Generate and report why generation ended
+    async fn generate_with_reason(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<(String, FinishReason)>;
+
     async fn generate(
         &self,
         prompt: &str,
         opts: GenOptions,
         on_token: Option<Box<dyn FnMut(String) + Send>>,
-    ) -> Result<String>;
+    ) -> Result<String> {
+        self.generate_with_reason(prompt, opts, on_token)
+            .await
+            .map(|(text, _)| text)
+    }
 }
 
 // Legacy trait for backward compatibility
@@ -118,6 +391,47 @@ pub trait InferenceEngine: Send + Sync {
     async fn load(&self, spec: &ModelSpec) -> Result<Box<dyn LoadedModel>>;
 }
 
+/// Prompt for the post-load warmup decode
+const WARMUP_PROMPT: &str = "Hello";
+
+/// Whether post-load warmup was requested via `SHIMMY_WARMUP=1`
+pub fn warmup_from_env() -> bool {
+    std::env::var("SHIMMY_WARMUP").is_ok_and(|v| v == "1")
+}
+
+/// Decode a single token on a trivial prompt right after load, so the first
+/// real request doesn't pay for lazy backend setup. Returns the warmup
+/// latency, or `None` when disabled, skipped for an embedding-only model at
+/// `path`, or failed (a failed warmup never fails the load).
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+pub async fn warm_up(model: &dyn LoadedModel, enabled: bool, path: &Path) -> Option<Duration> {
+    if !enabled {
+        return None;
+    }
+    if gguf::read_gguf_info(path).is_ok_and(|info| info.is_embedding()) {
+        tracing::debug!("Skipping warmup for embedding model {}", path.display());
+        return None;
+    }
+    let opts = GenOptions {
+        max_tokens: 1,
+        temperature: 0.0,
+        stream: false,
+        ..Default::default()
+    };
+    let started = Instant::now();
+    match model.generate(WARMUP_PROMPT, opts, None).await {
+        Ok(_) => {
+            let latency = started.elapsed();
+            tracing::info!("Warmed up {} in {}ms", path.display(), latency.as_millis());
+            Some(latency)
+        }
+        Err(e) => {
+            tracing::warn!("Warmup decode for {} failed: {}", path.display(), e);
+            None
+        }
+    }
+}
+
 #[async_trait]
 pub trait LoadedModel: Send + Sync {
     async fn generate(
@@ -127,20 +441,94 @@ pub trait LoadedModel: Send + Sync {
         on_token: Option<Box<dyn FnMut(String) + Send>>,
     ) -> Result<String>;
 
+    /// Generate and report why generation ended. Every backend that can end
+    /// for a reason other than finishing its reply (`max_tokens`, stop
+    /// sequences) overrides this; the default reports `Stop`.
+    async fn generate_with_reason(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<(String, FinishReason)> {
+        let text = self.generate(prompt, opts, on_token).await?;
+        Ok((text, FinishReason::Stop))
+    }
+
+    /// Count prompt tokens. Backends with a tokenizer should override the estimate.
+    fn count_tokens(&self, text: &str) -> usize {
+        estimate_tokens(text)
+    }
+
     async fn generate_vision(
         &self,
         _image_data: &[u8],
         _prompt: &str,
         _opts: GenOptions,
         _on_token: Option<Box<dyn FnMut(String) + Send>>,
-    ) -> Result<String> {
+    ) -> Result<(String, FinishReason)> {
         // Default implementation returns error - vision models should override
-        Err(anyhow!("Vision not supported by this model"))
+        Err(EngineError::Unsupported {
+            feature: "vision input for this model".to_string(),
+        }
+        .into())
+    }
+
+    /// How long the post-load warmup decode took, if one ran
+    fn warmup_latency(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Pooled embedding per input, in input order. Backends that support
+    /// embeddings (llama.cpp) override this.
+    async fn embed(&self, _inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        Err(EngineError::Unsupported {
+            feature: "embeddings for this model".to_string(),
+        }
+        .into())
+    }
+
+    /// Decode `prefix` into the model's KV cache without generating, so a
+    /// later prompt starting with it skips that prefill. Returns the number
+    /// of cached tokens. Backends with a prefix cache (llama.cpp) override this.
+    async fn warm(&self, _prefix: &str) -> Result<usize> {
+        Err(EngineError::Unsupported {
+            feature: "prefix cache warming for this model".to_string(),
+        }
+        .into())
+    }
+
+    /// Prefix cache hits and misses, for backends that keep one
+    fn prefix_cache_stats(&self) -> Option<PrefixCacheStats> {
+        None
     }
 }
 
+pub mod cancel;
+pub use cancel::CancelToken;
+pub mod embedding;
+pub mod error;
+pub use error::EngineError;
+
+pub mod gguf;
+pub mod load_error;
+#[cfg(test)]
+pub mod mock;
+pub mod prefix;
+pub use prefix::PrefixCacheStats;
+pub mod repeat;
+pub use repeat::RepeatStop;
+pub mod stop;
+pub mod trim;
+pub mod utf8;
+
 pub mod llama;
 
+// Only the llama backend offloads experts and shifts its KV cache
+#[cfg(feature = "llama")]
+pub mod moe;
+#[cfg(feature = "llama")]
+pub mod shift;
+
 #[cfg(feature = "huggingface")]
 pub mod huggingface;
 
@@ -152,3 +540,191 @@ pub mod mlx;
 
 pub mod adapter;
 pub mod safetensors_native;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_max_tokens_defaults_to_remaining_context() {
+        assert_eq!(resolve_max_tokens(None, 4096, 1000), 2048);
+        assert_eq!(resolve_max_tokens(None, 2048, 1500), 548);
+    }
+
+    #[test]
+    fn test_resolve_max_tokens_clamps_explicit_value() {
+        assert_eq!(resolve_max_tokens(Some(1000), 2048, 1500), 548);
+        assert_eq!(resolve_max_tokens(Some(100), 2048, 1500), 100);
+    }
+
+    #[test]
+    fn test_generation_budget_with_context_shift_ignores_window() {
+        assert_eq!(
+            resolve_generation_budget(Some(8000), 2048, 1500, true),
+            8000
+        );
+        assert_eq!(
+            resolve_generation_budget(Some(8000), 2048, 1500, false),
+            548
+        );
+        assert_eq!(resolve_generation_budget(None, 2048, 1500, true), 2048);
+        assert_eq!(resolve_generation_budget(None, 2048, 1500, false), 548);
+    }
+
+    #[test]
+    fn test_resolve_max_tokens_with_full_context() {
+        // A prompt that fills the window still leaves room for one token
+        assert_eq!(resolve_max_tokens(None, 512, 600), 1);
+        assert_eq!(resolve_max_tokens(Some(64), 512, 600), 1);
+    }
+
+    #[test]
+    fn test_validate_samplers() {
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(validate_samplers(&[]).is_ok());
+        assert!(validate_samplers(&names(&["top_k", "temperature"])).is_ok());
+
+        let err = validate_samplers(&names(&["top_k", "mirostat"])).unwrap_err();
+        assert!(matches!(err, EngineError::InvalidOptions { .. }));
+        assert!(err.to_string().contains("mirostat"));
+        assert!(err.to_string().contains("temperature, top_p"));
+        assert!(validate_samplers(&names(&["top_p", "top_p"])).is_err());
+    }
+
+    #[test]
+    fn test_sampling_report_reflects_greedy_and_custom_chains() {
+        let greedy = SamplingReport::new(&GenOptions {
+            temperature: 0.0,
+            ..Default::default()
+        });
+        assert_eq!(greedy.mode, "greedy");
+        assert!(greedy.samplers.is_empty());
+
+        let sampled = SamplingReport::new(&GenOptions::default());
+        assert_eq!(sampled.mode, "sampled");
+        assert_eq!(sampled.samplers, SAMPLER_NAMES);
+
+        let custom = SamplingReport::new(&GenOptions {
+            samplers: vec!["top_k".to_string(), "temperature".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(custom.samplers, vec!["top_k", "temperature"]);
+    }
+
+    #[test]
+    fn test_out_of_range_sampling_is_clamped_with_warning() {
+        let mut opts = GenOptions {
+            top_p: 1.5,
+            ..Default::default()
+        };
+        opts.clamp_sampling(false).unwrap();
+        assert_eq!(opts.top_p, 1.0);
+        assert_eq!(opts.clamped, vec!["top_p 1.5 -> 1"]);
+        assert_eq!(SamplingReport::new(&opts).clamped, opts.clamped);
+
+        let mut opts = GenOptions {
+            temperature: -0.5,
+            top_k: -1,
+            ..Default::default()
+        };
+        opts.clamp_sampling(false).unwrap();
+        assert_eq!((opts.temperature, opts.top_k), (0.0, 0));
+        assert_eq!(opts.clamped.len(), 2);
+
+        // In-range values are left alone and nothing is reported
+        let mut opts = GenOptions::default();
+        opts.clamp_sampling(true).unwrap();
+        assert!(opts.clamped.is_empty());
+        let json = serde_json::to_value(SamplingReport::new(&opts)).unwrap();
+        assert!(json.get("clamped").is_none());
+    }
+
+    #[test]
+    fn test_strict_sampling_rejects_instead_of_clamping() {
+        let mut opts = GenOptions {
+            temperature: 3.0,
+            ..Default::default()
+        };
+        let err = opts.clamp_sampling(true).unwrap_err();
+        assert!(matches!(err, EngineError::InvalidOptions { .. }));
+        assert!(err.to_string().contains("temperature 3 -> 2"));
+        assert_eq!(opts.temperature, 3.0);
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_load_blocking_times_out_without_stalling_runtime() {
+        let started = std::time::Instant::now();
+        let load = load_blocking("stuck.gguf", Duration::from_millis(50), || {
+            std::thread::sleep(Duration::from_millis(800));
+            Ok(())
+        });
+        // The runtime keeps serving other tasks while the load is stuck
+        let (result, ticks) = tokio::join!(load, async {
+            let mut ticks = 0;
+            for _ in 0..5 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                ticks += 1;
+            }
+            ticks
+        });
+
+        let err = result.unwrap_err();
+        assert!(matches!(
+            EngineError::find(&err),
+            Some(EngineError::Timeout { .. })
+        ));
+        assert_eq!(ticks, 5);
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_load_blocking_returns_work_result() {
+        let value = load_blocking("ok.gguf", DEFAULT_LOAD_TIMEOUT, || Ok(7))
+            .await
+            .unwrap();
+        assert_eq!(value, 7);
+    }
+
+    #[tokio::test]
+    async fn test_warm_up_decodes_one_token_when_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chat.gguf");
+        std::fs::write(
+            &path,
+            gguf::tests::synthetic_gguf(&[("general.architecture", "llama")], &[], &[]),
+        )
+        .unwrap();
+        let engine = mock::MockEngine::new(mock::Reply::Text("Hi"));
+        let model = engine.model("chat");
+
+        assert_eq!(warm_up(&model, false, &path).await, None);
+        assert!(engine.stats.max_tokens.lock().unwrap().is_empty());
+
+        let latency = warm_up(&model, true, &path).await;
+        assert!(latency.is_some());
+        assert_eq!(*engine.stats.max_tokens.lock().unwrap(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_warm_up_skips_embedding_models() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("embed.gguf");
+        std::fs::write(
+            &path,
+            gguf::tests::synthetic_gguf(&[("general.architecture", "bert")], &[], &[]),
+        )
+        .unwrap();
+        let engine = mock::MockEngine::new(mock::Reply::Text("Hi"));
+        let model = engine.model("chat");
+
+        assert_eq!(warm_up(&model, true, &path).await, None);
+        assert!(engine.stats.max_tokens.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+// Automatic MoE expert offload (`--moe-auto`)
+//
+// Compares a GGUF model's size against free VRAM and picks how many layers'
+// expert tensors to keep on the CPU. `--cpu-moe` / `--n-cpu-moe` still win
+// when given explicitly.
+
+use super::gguf::GgufInfo;
+use std::fmt;
+use std::path::Path;
+
+/// VRAM kept free for the KV cache, compute buffers and the driver
+const VRAM_RESERVE_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Where a model's expert tensors should live
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoeDecision {
+    /// Everything fits in VRAM (or the model has no experts)
+    KeepOnGpu,
+    /// Offload the expert tensors of the first N layers
+    OffloadFirst(usize),
+    /// Offload every layer's expert tensors
+    OffloadAll,
+}
+
+impl fmt::Display for MoeDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoeDecision::KeepOnGpu => write!(f, "keep experts on GPU"),
+            MoeDecision::OffloadFirst(n) => write!(f, "offload experts of first {} layers", n),
+            MoeDecision::OffloadAll => write!(f, "offload all expert tensors"),
+        }
+    }
+}
+
+/// Sizes that drive the offload decision
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoeFootprint {
+    pub model_bytes: u64,
+    pub expert_bytes: u64,
+    /// Number of layers that carry expert tensors
+    pub expert_layers: usize,
+}
+
+impl MoeFootprint {
+    /// Sum tensor sizes from a GGUF tensor table. Expert tensors are named
+    /// `blk.<layer>.ffn_{gate,up,down}_exps.weight`.
+    pub fn from_gguf(info: &GgufInfo) -> Self {
+        let mut layers = std::collections::BTreeSet::new();
+        let mut footprint = MoeFootprint {
+            model_bytes: 0,
+            expert_bytes: 0,
+            expert_layers: 0,
+        };
+        for tensor in &info.tensors {
+            footprint.model_bytes = footprint.model_bytes.saturating_add(tensor.size_bytes);
+            if tensor.name.contains("_exps") {
+                footprint.expert_bytes = footprint.expert_bytes.saturating_add(tensor.size_bytes);
+                if let Some(layer) = tensor
+                    .name
+                    .strip_prefix("blk.")
+                    .and_then(|rest| rest.split('.').next())
+                {
+                    layers.insert(layer.to_string());
+                }
+            }
+        }
+        footprint.expert_layers = layers.len();
+        footprint
+    }
+
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self::from_gguf(&super::gguf::read_gguf_info(path)?))
+    }
+}
+
+/// Decide how many layers' experts to move to the CPU so the rest of the
+/// model fits in `vram_free_bytes`
+pub fn decide_moe_offload(footprint: &MoeFootprint, vram_free_bytes: u64) -> MoeDecision {
+    if footprint.expert_bytes == 0 || footprint.expert_layers == 0 {
+        return MoeDecision::KeepOnGpu;
+    }
+    let usable = vram_free_bytes.saturating_sub(VRAM_RESERVE_BYTES);
+    if footprint.model_bytes <= usable {
+        return MoeDecision::KeepOnGpu;
+    }
+
+    let overflow = footprint.model_bytes.saturating_sub(usable);
+    let per_layer = footprint.expert_bytes / footprint.expert_layers as u64;
+    if per_layer == 0 || overflow >= footprint.expert_bytes {
+        return MoeDecision::OffloadAll;
+    }
+    let layers = overflow.div_ceil(per_layer) as usize;
+    if layers >= footprint.expert_layers {
+        MoeDecision::OffloadAll
+    } else {
+        MoeDecision::OffloadFirst(layers)
+    }
+}
+
+/// Work out the offload for a model file, logging why. Falls back to keeping
+/// experts on the GPU when VRAM or the model layout can't be determined.
+pub fn auto_decision(path: &Path) -> MoeDecision {
+    let Some(vram) = free_vram_bytes() else {
+        tracing::warn!("MoE auto: no GPU device reports free VRAM; not offloading experts");
+        return MoeDecision::KeepOnGpu;
+    };
+    let footprint = match MoeFootprint::from_path(path) {
+        Ok(footprint) => footprint,
+        Err(e) => {
+            tracing::warn!("MoE auto: could not read {}: {}", path.display(), e);
+            return MoeDecision::KeepOnGpu;
+        }
+    };
+    let decision = decide_moe_offload(&footprint, vram);
+    tracing::info!(
+        "MoE auto: {} ({} MB model, {} MB experts over {} layers, {} MB VRAM free)",
+        decision,
+        footprint.model_bytes / (1024 * 1024),
+        footprint.expert_bytes / (1024 * 1024),
+        footprint.expert_layers,
+        vram / (1024 * 1024)
+    );
+    decision
+}
+
+/// Free memory on the first GPU device llama.cpp registered. `None` when
+/// there is no GPU backend.
+pub fn free_vram_bytes() -> Option<u64> {
+    use shimmy_llama_cpp_sys_2 as sys;
+
+    // SAFETY: the device handle comes from ggml's static registry and stays
+    // valid for the life of the process; both out-pointers are live locals.
+    unsafe {
+        let dev = sys::ggml_backend_dev_by_type(sys::GGML_BACKEND_DEVICE_TYPE_GPU);
+        if dev.is_null() {
+            return None;
+        }
+        let (mut free, mut total) = (0usize, 0usize);
+        sys::ggml_backend_dev_memory(dev, &mut free, &mut total);
+        Some(free as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GB: u64 = 1024 * 1024 * 1024;
+
+    fn footprint(model_gb: u64, expert_gb: u64, layers: usize) -> MoeFootprint {
+        MoeFootprint {
+            model_bytes: model_gb * GB,
+            expert_bytes: expert_gb * GB,
+            expert_layers: layers,
+        }
+    }
+
+    #[test]
+    fn test_model_that_fits_stays_on_gpu() {
+        assert_eq!(
+            decide_moe_offload(&footprint(10, 8, 32), 24 * GB),
+            MoeDecision::KeepOnGpu
+        );
+    }
+
+    #[test]
+    fn test_dense_model_is_never_offloaded() {
+        assert_eq!(
+            decide_moe_offload(&footprint(40, 0, 0), 8 * GB),
+            MoeDecision::KeepOnGpu
+        );
+    }
+
+    #[test]
+    fn test_partial_offload_covers_the_overflow() {
+        // 30 GB model, 24 GB of experts over 24 layers (1 GB each), 24 GB free:
+        // 23 GB usable, so 7 layers' experts must move to the CPU
+        assert_eq!(
+            decide_moe_offload(&footprint(30, 24, 24), 24 * GB),
+            MoeDecision::OffloadFirst(7)
+        );
+    }
+
+    #[test]
+    fn test_offload_all_when_dense_part_barely_fits() {
+        assert_eq!(
+            decide_moe_offload(&footprint(60, 48, 48), 8 * GB),
+            MoeDecision::OffloadAll
+        );
+        assert_eq!(
+            decide_moe_offload(&footprint(30, 24, 24), 0),
+            MoeDecision::OffloadAll
+        );
+    }
+
+    #[test]
+    fn test_footprint_from_gguf_tensors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("moe.gguf");
+        std::fs::write(
+            &path,
+            crate::engine::gguf::tests::synthetic_gguf(
+                &[("general.architecture", "mixtral")],
+                &[],
+                &[
+                    ("token_embd.weight", 64),
+                    ("blk.0.attn_q.weight", 32),
+                    ("blk.0.ffn_up_exps.weight", 128),
+                    ("blk.0.ffn_down_exps.weight", 128),
+                    ("blk.1.ffn_up_exps.weight", 128),
+                ],
+            ),
+        )
+        .unwrap();
+
+        let fp = MoeFootprint::from_path(&path).unwrap();
+        assert_eq!(fp.model_bytes, 480);
+        assert_eq!(fp.expert_bytes, 384);
+        assert_eq!(fp.expert_layers, 2);
+    }
+}
@@ -0,0 +1,117 @@
+// Prompt prefix reuse
+//
+// A pooled llama.cpp model keeps one context across requests. Instead of
+// clearing its KV cache every time, the backend remembers which tokens the
+// cache holds and only decodes the part of a new prompt that differs. RAG
+// prompts that share a long static context then skip most of the prefill, and
+// `/api/warm` can fill the cache with that context ahead of time.
+#![allow(dead_code)]
+
+use serde::Serialize;
+
+/// Hit/miss counts for one model's prefix cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PrefixCacheStats {
+    /// Prompts that reused at least one cached token
+    pub hits: u64,
+    pub misses: u64,
+    /// Prompt tokens that did not need to be decoded again
+    pub reused_tokens: u64,
+}
+
+/// The tokens currently held in a context's KV cache, in position order
+#[derive(Debug)]
+pub struct PrefixCache<T> {
+    tokens: Vec<T>,
+    stats: PrefixCacheStats,
+}
+
+impl<T> Default for PrefixCache<T> {
+    fn default() -> Self {
+        Self {
+            tokens: Vec::new(),
+            stats: PrefixCacheStats::default(),
+        }
+    }
+}
+
+impl<T: PartialEq + Clone> PrefixCache<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of leading `prompt` tokens already in the cache. At least the
+    /// last prompt token is left to decode, since sampling needs its logits.
+    /// The cache forgets everything after the reused part; the caller drops
+    /// those positions from the KV cache and decodes `prompt[reused..]`.
+    pub fn reuse(&mut self, prompt: &[T]) -> usize {
+        let common = self
+            .tokens
+            .iter()
+            .zip(prompt)
+            .take_while(|(cached, token)| cached == token)
+            .count();
+        let reused = common.min(prompt.len().saturating_sub(1));
+        self.tokens.truncate(reused);
+        if reused > 0 {
+            self.stats.hits += 1;
+            self.stats.reused_tokens += reused as u64;
+        } else {
+            self.stats.misses += 1;
+        }
+        reused
+    }
+
+    /// Record tokens that have been decoded into the KV cache after the
+    /// reused part
+    pub fn extend(&mut self, decoded: &[T]) {
+        self.tokens.extend_from_slice(decoded);
+    }
+
+    pub fn clear(&mut self) {
+        self.tokens.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn stats(&self) -> PrefixCacheStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_prefix_is_reused_and_tail_forgotten() {
+        let mut cache = PrefixCache::new();
+        assert_eq!(cache.reuse(&[1, 2, 3]), 0);
+        cache.extend(&[1, 2, 3, 9, 9]);
+
+        // Same context, different question
+        assert_eq!(cache.reuse(&[1, 2, 3, 4, 5]), 3);
+        assert_eq!(cache.len(), 3);
+        cache.extend(&[4, 5]);
+
+        // Identical prompt still decodes its last token
+        assert_eq!(cache.reuse(&[1, 2, 3, 4, 5]), 4);
+        assert_eq!(cache.reuse(&[7, 8]), 0);
+        assert!(cache.is_empty());
+
+        assert_eq!(
+            cache.stats(),
+            PrefixCacheStats {
+                hits: 2,
+                misses: 2,
+                reused_tokens: 7,
+            }
+        );
+    }
+}
@@ -0,0 +1,132 @@
+// Repetition-loop detection
+//
+// Small models can fall into loops ("I'm sorry. I'm sorry. I'm sorry.") that
+// `repeat_penalty` doesn't break, burning the whole token budget. With
+// `GenOptions::stop_on_repeat` set, generation halts once the most recent
+// n-gram of tokens has occurred more than `max_repeats` times, and reports
+// `FinishReason::Repetition`.
+#![allow(dead_code)]
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Loop-detection settings for `GenOptions::stop_on_repeat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RepeatStop {
+    /// Length in tokens of the n-gram that is tracked
+    #[serde(default = "default_ngram")]
+    pub ngram: usize,
+    /// Stop once an n-gram has occurred more than this many times
+    #[serde(default = "default_max_repeats")]
+    pub max_repeats: usize,
+}
+
+fn default_ngram() -> usize {
+    8
+}
+
+fn default_max_repeats() -> usize {
+    4
+}
+
+impl Default for RepeatStop {
+    fn default() -> Self {
+        Self {
+            ngram: default_ngram(),
+            max_repeats: default_max_repeats(),
+        }
+    }
+}
+
+/// Counts n-grams over generated tokens as they are sampled
+#[derive(Debug)]
+pub struct RepeatDetector<T> {
+    config: RepeatStop,
+    recent: Vec<T>,
+    counts: HashMap<Vec<T>, usize>,
+}
+
+impl<T: Hash + Eq + Clone> RepeatDetector<T> {
+    pub fn new(config: RepeatStop) -> Self {
+        Self {
+            config: RepeatStop {
+                ngram: config.ngram.max(1),
+                ..config
+            },
+            recent: Vec::new(),
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Record the next generated token; `true` once the n-gram it completes
+    /// has repeated more than `max_repeats` times
+    pub fn push(&mut self, token: T) -> bool {
+        self.recent.push(token);
+        if self.recent.len() > self.config.ngram {
+            self.recent.remove(0);
+        }
+        if self.recent.len() < self.config.ngram {
+            return false;
+        }
+        let count = self.counts.entry(self.recent.clone()).or_default();
+        *count += 1;
+        *count > self.config.max_repeats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Drive a mock token stream the way `LlamaState::generate`
+    /// does: returns the tokens kept and whether a loop stopped generation
+    fn run(stream: &[u32], max_tokens: usize, stop: Option<RepeatStop>) -> (Vec<u32>, bool) {
+        let mut detector = stop.map(RepeatDetector::new);
+        let mut out = Vec::new();
+        for &token in stream.iter().take(max_tokens) {
+            if detector.as_mut().is_some_and(|d| d.push(token)) {
+                return (out, true);
+            }
+            out.push(token);
+        }
+        (out, false)
+    }
+
+    #[test]
+    fn test_repeating_ngram_stops_early_when_enabled() {
+        // 4 distinct tokens, then a 3-token loop forever
+        let stream: Vec<u32> = [10, 11, 12, 13]
+            .into_iter()
+            .chain([1, 2, 3].into_iter().cycle().take(300))
+            .collect();
+        let stop = RepeatStop {
+            ngram: 3,
+            max_repeats: 2,
+        };
+
+        let (out, repeated) = run(&stream, 256, Some(stop));
+        assert!(repeated);
+        // The loop is seen twice in full, then stopped on the third pass
+        assert_eq!(out, vec![10, 11, 12, 13, 1, 2, 3, 1, 2, 3, 1, 2]);
+
+        let (out, repeated) = run(&stream, 256, None);
+        assert!(!repeated);
+        assert_eq!(out.len(), 256);
+    }
+
+    #[test]
+    fn test_varied_output_is_not_stopped() {
+        let stream: Vec<u32> = (0..200).collect();
+        let (out, repeated) = run(&stream, 200, Some(RepeatStop::default()));
+        assert!(!repeated);
+        assert_eq!(out.len(), 200);
+    }
+
+    #[test]
+    fn test_config_defaults_fill_missing_fields() {
+        let stop: RepeatStop = serde_json::from_str(r#"{"max_repeats": 2}"#).unwrap();
+        assert_eq!(stop.ngram, 8);
+        assert_eq!(stop.max_repeats, 2);
+    }
+}
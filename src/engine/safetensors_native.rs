@@ -14,7 +14,7 @@ use tracing::{debug, info, warn};
 // use crate::cache::{ModelCache, ModelMetadata};
 // use crate::cache::model_cache;
 
-use super::{GenOptions, InferenceEngine, LoadedModel, ModelSpec};
+use super::{FinishReason, GenOptions, InferenceEngine, LoadedModel, ModelSpec};
 
 // Memory-mapped file support for large models
 use memmap2::MmapOptions;
@@ -533,6 +533,17 @@ impl LoadedModel for SafeTensorsModel {
         opts: GenOptions,
         on_token: Option<Box<dyn FnMut(String) + Send>>,
     ) -> Result<String> {
+        self.generate_with_reason(prompt, opts, on_token)
+            .await
+            .map(|(text, _)| text)
+    }
+
+    async fn generate_with_reason(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<(String, FinishReason)> {
         info!(
             "Generating with SafeTensors model: prompt length = {}",
             prompt.len()
@@ -544,7 +555,7 @@ impl LoadedModel for SafeTensorsModel {
 
         // Simple template-based generation for now
         // In a full implementation, this would do actual forward pass through the model
-        let response = self.simple_generate(prompt, &opts).await?;
+        let (response, reason) = self.simple_generate(prompt, &opts).await?;
 
         // Handle streaming callback
         if let Some(mut callback) = on_token {
@@ -555,12 +566,16 @@ impl LoadedModel for SafeTensorsModel {
             }
         }
 
-        Ok(response)
+        Ok((response, reason))
     }
 }
 
 impl SafeTensorsModel {
-    async fn simple_generate(&self, prompt: &str, opts: &GenOptions) -> Result<String> {
+    async fn simple_generate(
+        &self,
+        prompt: &str,
+        opts: &GenOptions,
+    ) -> Result<(String, FinishReason)> {
         // This is a simplified implementation for demonstration
         // A full implementation would:
         // 1. Run forward pass through transformer layers
@@ -590,13 +605,11 @@ impl SafeTensorsModel {
 
         // Respect max_tokens setting
         let words: Vec<&str> = response.split_whitespace().collect();
-        let limited_response = if words.len() > opts.max_tokens {
-            words[..opts.max_tokens].join(" ")
-        } else {
-            response
-        };
+        if words.len() > opts.max_tokens {
+            return Ok((words[..opts.max_tokens].join(" "), FinishReason::Length));
+        }
 
-        Ok(limited_response)
+        Ok((response, FinishReason::Stop))
     }
 }
 
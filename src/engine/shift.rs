@@ -0,0 +1,178 @@
+// Context shifting ("infinite generation")
+//
+// Generation normally stops with `FinishReason::Length` once prompt plus
+// output fill the context window. With `GenOptions::context_shift` set, the
+// backend instead drops older tokens from the KV cache and slides the rest
+// down, like llama.cpp's `--context-shift`: the start of the prompt is kept,
+// half of what follows it is discarded, and decoding carries on. The model no
+// longer sees the dropped text, so long runs can lose track of it.
+
+/// Positions to drop from the KV cache: `[keep, keep + discard)`. Everything
+/// after them moves down by `discard`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shift {
+    pub keep: usize,
+    pub discard: usize,
+}
+
+impl Shift {
+    /// First position after the discarded range
+    pub fn end(&self) -> usize {
+        self.keep + self.discard
+    }
+}
+
+/// What the backend must do before decoding the next token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// The next position is free
+    Room,
+    /// Apply this shift to the KV cache first
+    Shift(Shift),
+    /// The window is full and shifting is off: finish with `Length`
+    Full,
+}
+
+/// Decide what has to happen before a token is decoded at `n_past` in a
+/// window of `ctx_len` positions whose first `keep` are never discarded
+pub fn plan_step(ctx_len: usize, keep: usize, n_past: usize, shift: bool) -> Step {
+    if n_past < ctx_len {
+        return Step::Room;
+    }
+    if !shift {
+        return Step::Full;
+    }
+    Step::Shift(Shift {
+        keep,
+        discard: (n_past.saturating_sub(keep) / 2).max(1),
+    })
+}
+
+/// Tracks the positions used in one sequence's context window
+#[derive(Debug)]
+pub struct ContextWindow {
+    ctx_len: usize,
+    /// Leading positions never discarded (the start of the prompt)
+    keep: usize,
+    n_past: usize,
+    shift: bool,
+}
+
+impl ContextWindow {
+    /// A window of `ctx_len` positions already holding `prompt_len` tokens.
+    /// At most half the window is reserved for the prompt, so a shift
+    /// always frees room.
+    pub fn new(ctx_len: usize, prompt_len: usize, shift: bool) -> Self {
+        Self {
+            ctx_len,
+            keep: prompt_len.min(ctx_len / 2),
+            n_past: prompt_len,
+            shift,
+        }
+    }
+
+    /// Position the next decoded token goes to
+    pub fn position(&self) -> usize {
+        self.n_past
+    }
+
+    /// Make sure `position` is free, shifting if the window is full
+    pub fn make_room(&mut self) -> Step {
+        let step = plan_step(self.ctx_len, self.keep, self.n_past, self.shift);
+        if let Step::Shift(shift) = step {
+            self.n_past -= shift.discard;
+            tracing::warn!(
+                "Context window of {} tokens is full; dropping {} earlier tokens to keep generating (context shift)",
+                self.ctx_len,
+                shift.discard
+            );
+        }
+        step
+    }
+
+    /// Record a token decoded at `position`
+    pub fn push(&mut self) {
+        self.n_past += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_step() {
+        // Free positions left
+        assert_eq!(plan_step(8, 3, 7, true), Step::Room);
+        assert_eq!(plan_step(8, 3, 7, false), Step::Room);
+        // Full without shifting ends generation
+        assert_eq!(plan_step(8, 3, 8, false), Step::Full);
+        // Full with shifting keeps the prompt start and halves the rest
+        assert_eq!(
+            plan_step(8, 3, 8, true),
+            Step::Shift(Shift {
+                keep: 3,
+                discard: 2
+            })
+        );
+        assert_eq!(
+            plan_step(8, 0, 8, true),
+            Step::Shift(Shift {
+                keep: 0,
+                discard: 4
+            })
+        );
+        // Always frees at least one position
+        assert_eq!(
+            plan_step(4, 3, 4, true),
+            Step::Shift(Shift {
+                keep: 3,
+                discard: 1
+            })
+        );
+        assert_eq!(
+            Shift {
+                keep: 3,
+                discard: 2
+            }
+            .end(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_shift_keeps_prompt_start_and_halves_the_rest() {
+        let mut window = ContextWindow::new(8, 3, true);
+        for position in 3..8 {
+            assert_eq!(window.position(), position);
+            assert_eq!(window.make_room(), Step::Room);
+            window.push();
+        }
+        // 8 used, 3 kept: drop 2 of the 5 after them
+        assert_eq!(
+            window.make_room(),
+            Step::Shift(Shift {
+                keep: 3,
+                discard: 2
+            })
+        );
+        assert_eq!(window.position(), 6);
+        window.push();
+        assert_eq!(window.make_room(), Step::Room);
+
+        // A prompt longer than half the window only keeps half
+        let mut window = ContextWindow::new(8, 7, true);
+        window.push();
+        assert_eq!(
+            window.make_room(),
+            Step::Shift(Shift {
+                keep: 4,
+                discard: 2
+            })
+        );
+
+        // Without shifting, a full window ends generation
+        let mut window = ContextWindow::new(4, 4, false);
+        assert_eq!(window.make_room(), Step::Full);
+    }
+}
@@ -0,0 +1,123 @@
+// Stop-sequence matching on generated text
+//
+// Tokenizers disagree about where word-leading whitespace lives: BPE
+// vocabularies carry it on the token (`" User"`), SentencePiece marks it with
+// `▁` which usually decodes to a space, and a prompt-configured stop may or may
+// not include it. Matching ignores leading spaces on the stop and cuts any
+// spaces before the match, so `User:` and ` User:` stop at the same place
+// whichever way the model tokenizes.
+#![allow(dead_code)]
+
+/// Characters tokenizers attach to the start of a word: a plain space (BPE,
+/// and SentencePiece once decoded) or a raw SentencePiece `▁`
+const WORD_SPACE: &[char] = &[' ', '\u{2581}'];
+
+/// Byte offset at which `text` should be cut because it contains one of
+/// `stops`, with the index of the stop that matched, or `None` if no stop
+/// sequence has been produced yet. When several match, the earliest cut wins.
+pub fn find_stop(text: &str, stops: &[String]) -> Option<(usize, usize)> {
+    stops
+        .iter()
+        .enumerate()
+        .filter_map(|(index, stop)| {
+            let core = stop.trim_start_matches(WORD_SPACE);
+            // A stop made only of spaces is matched literally
+            let needle = if core.is_empty() { stop.as_str() } else { core };
+            if needle.is_empty() {
+                return None;
+            }
+            let pos = text.find(needle)?;
+            if core.is_empty() {
+                return Some((pos, index));
+            }
+            Some((text[..pos].trim_end_matches(WORD_SPACE).len(), index))
+        })
+        .min_by_key(|&(cut, _)| cut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed pieces one at a time the way the generation loop does and return
+    /// the text kept once a stop matches
+    fn run(pieces: &[&str], stops: &[&str]) -> (String, bool) {
+        let stops: Vec<String> = stops.iter().map(|s| s.to_string()).collect();
+        let mut out = String::new();
+        for piece in pieces {
+            out.push_str(piece);
+            if let Some((cut, _)) = find_stop(&out, &stops) {
+                out.truncate(cut);
+                return (out, true);
+            }
+        }
+        (out, false)
+    }
+
+    #[test]
+    fn test_space_prefixed_and_bare_streams_stop_alike() {
+        // BPE: the space travels with the word
+        let bpe = [
+            "Sure", ",", " here", " it", " is", ".\n", " User", ":", " next",
+        ];
+        // Tokenizer that emits the space as its own piece
+        let split = ["Sure", ",", " here", " it", " is", ".\n", " ", "User", ":"];
+        // Raw SentencePiece markers that were not decoded to spaces
+        let spm = ["Sure", ",", "▁here", "▁it", "▁is", ".\n", "▁User", ":"];
+
+        for stop in ["User:", " User:"] {
+            assert_eq!(
+                run(&bpe, &[stop]),
+                ("Sure, here it is.\n".into(), true),
+                "{:?}",
+                stop
+            );
+            assert_eq!(
+                run(&split, &[stop]),
+                ("Sure, here it is.\n".into(), true),
+                "{:?}",
+                stop
+            );
+            assert_eq!(
+                run(&spm, &[stop]),
+                ("Sure,▁here▁it▁is.\n".into(), true),
+                "{:?}",
+                stop
+            );
+        }
+    }
+
+    #[test]
+    fn test_stop_at_start_of_output() {
+        for pieces in [&["User", ":"][..], &[" User", ":"][..]] {
+            assert_eq!(run(pieces, &[" User:"]), (String::new(), true));
+            assert_eq!(run(pieces, &["User:"]), (String::new(), true));
+        }
+    }
+
+    #[test]
+    fn test_no_over_trigger_on_partial_or_newline_stops() {
+        // Only leading spaces are relaxed; a newline in the stop still counts
+        assert_eq!(
+            run(&["A", " User", ":", " hi"], &["\nUser:"]),
+            ("A User: hi".into(), false)
+        );
+        assert_eq!(
+            run(&["A", "\n", "User", ":"], &["\nUser:"]),
+            ("A".into(), true)
+        );
+        // A prefix of the stop is not a match
+        assert_eq!(
+            run(&[" User", " said"], &["User:"]),
+            (" User said".into(), false)
+        );
+    }
+
+    #[test]
+    fn test_earliest_stop_wins_and_whitespace_stop_is_literal() {
+        let stops = ["</s>".to_string(), "User:".to_string()];
+        assert_eq!(find_stop("ok User: x </s>", &stops), Some((2, 1)));
+        assert_eq!(find_stop("a  b", &["  ".to_string()]), Some((1, 0)));
+        assert_eq!(find_stop("anything", &[String::new()]), None);
+    }
+}
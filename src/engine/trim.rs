@@ -0,0 +1,256 @@
+// Leading-output cleanup
+//
+// Chat templates usually end with `assistant\n`, so many models open their
+// reply with a space-prefixed token (`" Hello"`) or echo a BOS marker as text.
+// `GenOptions::trim_leading` drops a leading BOS/special token and a single
+// leading space, identically for streamed pieces and for the final text.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+
+use super::{FinishReason, GenOptions, LoadedModel};
+
+/// Special tokens that leak into output as text at the start of a reply
+const LEADING_SPECIALS: &[&str] = &["<s>", "<bos>", "<|begin_of_text|>", "<|startoftext|>"];
+
+fn strip_specials(mut text: &str) -> &str {
+    while let Some(rest) = LEADING_SPECIALS
+        .iter()
+        .find_map(|special| text.strip_prefix(special))
+    {
+        text = rest;
+    }
+    text
+}
+
+/// `text` without leading special tokens and one leading space
+pub fn trim_leading(text: &str) -> &str {
+    let text = strip_specials(text);
+    text.strip_prefix(' ').unwrap_or(text)
+}
+
+/// Applies `trim_leading` to a token stream. Pieces are held back only until
+/// the start of the reply is known not to be a special token or a lone space.
+#[derive(Debug, Default)]
+pub struct LeadingTrim {
+    pending: String,
+    started: bool,
+}
+
+impl LeadingTrim {
+    /// Text to emit for `piece`, or `None` while the start is undecided
+    pub fn push(&mut self, piece: &str) -> Option<String> {
+        if self.started {
+            return Some(piece.to_string());
+        }
+        self.pending.push_str(piece);
+        let head = strip_specials(&self.pending);
+        let undecided = head.is_empty()
+            || head == " "
+            || LEADING_SPECIALS
+                .iter()
+                .any(|special| special.starts_with(head));
+        if undecided {
+            return None;
+        }
+        self.finish()
+    }
+
+    /// Flush anything still held back once generation ends
+    pub fn finish(&mut self) -> Option<String> {
+        if self.started {
+            return None;
+        }
+        self.started = true;
+        let out = trim_leading(&self.pending).to_string();
+        self.pending.clear();
+        (!out.is_empty()).then_some(out)
+    }
+}
+
+type TokenCallback = Box<dyn FnMut(String) + Send>;
+type SharedTrim = Arc<Mutex<(LeadingTrim, TokenCallback)>>;
+
+/// Route `on_token` through a `LeadingTrim`. The returned handle must be
+/// flushed with `flush_trimmed` after generation.
+fn trimmed_callback(
+    on_token: Option<TokenCallback>,
+) -> (Option<TokenCallback>, Option<SharedTrim>) {
+    let Some(on_token) = on_token else {
+        return (None, None);
+    };
+    let shared = Arc::new(Mutex::new((LeadingTrim::default(), on_token)));
+    let inner = shared.clone();
+    let callback: TokenCallback = Box::new(move |piece: String| {
+        let mut guard = inner.lock().unwrap_or_else(|e| e.into_inner());
+        let (trim, on_token) = &mut *guard;
+        if let Some(out) = trim.push(&piece) {
+            on_token(out);
+        }
+    });
+    (Some(callback), Some(shared))
+}
+
+fn flush_trimmed(shared: Option<SharedTrim>) {
+    if let Some(shared) = shared {
+        let mut guard = shared.lock().unwrap_or_else(|e| e.into_inner());
+        let (trim, on_token) = &mut *guard;
+        if let Some(out) = trim.finish() {
+            on_token(out);
+        }
+    }
+}
+
+/// Wraps a loaded model so every backend honours `GenOptions::trim_leading`
+pub struct LeadingTrimModel {
+    inner: Box<dyn LoadedModel>,
+}
+
+impl LeadingTrimModel {
+    pub fn new(inner: Box<dyn LoadedModel>) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl LoadedModel for LeadingTrimModel {
+    async fn generate(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<String> {
+        self.generate_with_reason(prompt, opts, on_token)
+            .await
+            .map(|(text, _)| text)
+    }
+
+    async fn generate_with_reason(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<(String, FinishReason)> {
+        if !opts.trim_leading {
+            return self
+                .inner
+                .generate_with_reason(prompt, opts, on_token)
+                .await;
+        }
+        let (on_token, shared) = trimmed_callback(on_token);
+        let result = self
+            .inner
+            .generate_with_reason(prompt, opts, on_token)
+            .await;
+        flush_trimmed(shared);
+        let (text, reason) = result?;
+        Ok((trim_leading(&text).to_string(), reason))
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        self.inner.count_tokens(text)
+    }
+
+    fn warmup_latency(&self) -> Option<std::time::Duration> {
+        self.inner.warmup_latency()
+    }
+
+    async fn generate_vision(
+        &self,
+        image_data: &[u8],
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<(String, FinishReason)> {
+        if !opts.trim_leading {
+            return self
+                .inner
+                .generate_vision(image_data, prompt, opts, on_token)
+                .await;
+        }
+        let (on_token, shared) = trimmed_callback(on_token);
+        let result = self
+            .inner
+            .generate_vision(image_data, prompt, opts, on_token)
+            .await;
+        flush_trimmed(shared);
+        let (text, reason) = result?;
+        Ok((trim_leading(&text).to_string(), reason))
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.inner.embed(inputs).await
+    }
+
+    async fn warm(&self, prefix: &str) -> Result<usize> {
+        self.inner.warm(prefix).await
+    }
+
+    fn prefix_cache_stats(&self) -> Option<super::PrefixCacheStats> {
+        self.inner.prefix_cache_stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::mock::{MockEngine, Reply};
+
+    async fn run(pieces: &'static [&'static str], trim_leading: bool) -> (String, String) {
+        let model = MockEngine::new(Reply::Tokens(pieces)).model("stream");
+        let model = LeadingTrimModel::new(Box::new(model));
+        let streamed = Arc::new(Mutex::new(String::new()));
+        let sink = streamed.clone();
+        let opts = GenOptions {
+            trim_leading,
+            ..Default::default()
+        };
+        let full = model
+            .generate(
+                "prompt",
+                opts,
+                Some(Box::new(move |tok| sink.lock().unwrap().push_str(&tok))),
+            )
+            .await
+            .unwrap();
+        let streamed = streamed.lock().unwrap().clone();
+        (streamed, full)
+    }
+
+    #[tokio::test]
+    async fn test_leading_space_trimmed_when_enabled() {
+        let (streamed, full) = run(&[" Hello", " world"], true).await;
+        assert_eq!(streamed, "Hello world");
+        assert_eq!(full, "Hello world");
+    }
+
+    #[tokio::test]
+    async fn test_leading_space_kept_when_disabled() {
+        let (streamed, full) = run(&[" Hello", " world"], false).await;
+        assert_eq!(streamed, " Hello world");
+        assert_eq!(full, " Hello world");
+    }
+
+    #[tokio::test]
+    async fn test_split_bos_and_lone_space_tokens_trimmed() {
+        let (streamed, full) = run(&["<", "s>", " ", "Hi", " there"], true).await;
+        assert_eq!(streamed, "Hi there");
+        assert_eq!(full, "Hi there");
+    }
+
+    #[test]
+    fn test_trim_leading_only_removes_one_space() {
+        assert_eq!(trim_leading("<|begin_of_text|>  indented"), " indented");
+        assert_eq!(trim_leading("<smth>"), "<smth>");
+        assert_eq!(trim_leading(""), "");
+    }
+
+    #[test]
+    fn test_undecided_start_is_flushed() {
+        let mut trim = LeadingTrim::default();
+        assert_eq!(trim.push("<"), None);
+        assert_eq!(trim.finish(), Some("<".to_string()));
+        assert_eq!(trim.push("x"), Some("x".to_string()));
+    }
+}
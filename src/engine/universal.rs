@@ -53,13 +53,15 @@ struct UniversalModelAdapter {
 
 #[async_trait]
 impl UniversalModel for UniversalModelAdapter {
-    async fn generate(
+    async fn generate_with_reason(
         &self,
         prompt: &str,
         opts: super::GenOptions,
         on_token: Option<Box<dyn FnMut(String) + Send>>,
-    ) -> Result<String> {
-        self.model.generate(prompt, opts, on_token).await
+    ) -> Result<(String, super::FinishReason)> {
+        self.model
+            .generate_with_reason(prompt, opts, on_token)
+            .await
     }
 }
 
@@ -367,6 +369,12 @@ mod tests {
             seed: Some(42),
             stream: true,
             stop_tokens: Vec::new(),
+            trim_leading: true,
+            samplers: Vec::new(),
+            stop_on_repeat: None,
+            context_shift: false,
+            cancel: crate::engine::CancelToken::default(),
+            clamped: Vec::new(),
         };
 
         let result = adapter.generate("Hello world", opts, None).await;
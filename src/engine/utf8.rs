@@ -0,0 +1,98 @@
+// Byte-accurate token decoding
+//
+// llama.cpp tokens are byte sequences, and a multibyte character (emoji, CJK)
+// can be split across two tokens. Decoding each token to a `String` on its own
+// either fails or corrupts the character, so the generate loop feeds raw token
+// bytes through `Utf8Decoder`, which only releases complete UTF-8 sequences.
+#![allow(dead_code)]
+
+/// Buffers incomplete trailing bytes until a later token completes them
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one token's bytes and return the text that is now complete, which
+    /// may be empty. Bytes that can never be valid UTF-8 become U+FFFD.
+    pub fn push(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let mut out = String::new();
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(text) => {
+                    out.push_str(text);
+                    self.pending.clear();
+                    return out;
+                }
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    // Checked by from_utf8 above
+                    out.push_str(std::str::from_utf8(&self.pending[..valid]).unwrap_or_default());
+                    match e.error_len() {
+                        // Incomplete sequence at the end: wait for more bytes
+                        None => {
+                            self.pending.drain(..valid);
+                            return out;
+                        }
+                        Some(invalid) => {
+                            out.push(char::REPLACEMENT_CHARACTER);
+                            self.pending.drain(..valid + invalid);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// End of generation: an incomplete trailing sequence (e.g. cut off by
+    /// `max_tokens`) becomes a single U+FFFD instead of an error
+    pub fn finish(&mut self) -> String {
+        let tail = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        tail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode a mock byte-level token stream the way `LlamaLoaded` does,
+    /// returning each streamed piece
+    fn stream(tokens: &[&[u8]]) -> Vec<String> {
+        let mut decoder = Utf8Decoder::new();
+        let mut pieces: Vec<String> = tokens.iter().map(|t| decoder.push(t)).collect();
+        pieces.push(decoder.finish());
+        pieces.retain(|p| !p.is_empty());
+        pieces
+    }
+
+    #[test]
+    fn test_multibyte_char_split_across_tokens_is_reassembled() {
+        let wave = "👋".as_bytes();
+        let cjk = "世".as_bytes();
+        let pieces = stream(&[
+            b"hi ",
+            &wave[..1],
+            &wave[1..3],
+            &[wave[3], b' ', cjk[0]],
+            &cjk[1..],
+        ]);
+        assert_eq!(pieces, vec!["hi ", "👋 ", "世"]);
+        assert!(!pieces.concat().contains(char::REPLACEMENT_CHARACTER));
+    }
+
+    #[test]
+    fn test_truncated_tail_and_invalid_bytes_become_replacement() {
+        let cjk = "界".as_bytes();
+        // max_tokens cut the last character short
+        assert_eq!(stream(&[b"ok", &cjk[..2]]), vec!["ok", "\u{FFFD}"]);
+        // A stray continuation byte is replaced; decoding carries on
+        assert_eq!(stream(&[b"a\x80b"]), vec!["a\u{FFFD}b"]);
+    }
+}
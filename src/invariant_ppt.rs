@@ -9,8 +9,37 @@ lazy_static::lazy_static! {
     static ref FAILED_INVARIANTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
 }
 
-/// Core invariant assertion - logs and enforces semantic contracts
-pub fn assert_invariant(condition: bool, message: &str, context: Option<&str>) {
+#[cfg(test)]
+thread_local! {
+    static STRICT_OVERRIDE: std::cell::Cell<Option<bool>> = const { std::cell::Cell::new(None) };
+}
+
+/// Whether recoverable invariants panic. Set by `SHIMMY_STRICT_INVARIANTS`
+/// (`1`/`true` or `0`/`false`); defaults to on in debug and test builds and
+/// off in release builds.
+pub fn strict_invariants() -> bool {
+    #[cfg(test)]
+    if let Some(strict) = STRICT_OVERRIDE.with(|o| o.get()) {
+        return strict;
+    }
+    match std::env::var("SHIMMY_STRICT_INVARIANTS").ok().as_deref() {
+        Some("1") | Some("true") => true,
+        Some("0") | Some("false") => false,
+        _ => cfg!(debug_assertions),
+    }
+}
+
+/// Run `f` with strict mode forced on or off for the current thread
+#[cfg(test)]
+pub fn with_strict_invariants<R>(strict: bool, f: impl FnOnce() -> R) -> R {
+    let previous = STRICT_OVERRIDE.with(|o| o.replace(Some(strict)));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+    STRICT_OVERRIDE.with(|o| o.set(previous));
+    result.unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// Record that an invariant was checked, and whether it failed
+fn record_invariant(condition: bool, message: &str, context: Option<&str>) -> String {
     let full_message = match context {
         Some(ctx) => format!("{} [{}]", message, ctx),
         None => message.to_string(),
@@ -21,15 +50,41 @@ pub fn assert_invariant(condition: bool, message: &str, context: Option<&str>) {
         log.insert(full_message.clone());
     }
 
-    // Enforce the invariant
     if !condition {
         if let Ok(mut failed) = FAILED_INVARIANTS.lock() {
             failed.push(full_message.clone());
         }
+    }
+    full_message
+}
+
+/// Core invariant assertion - logs and enforces semantic contracts.
+/// Reserved for genuine internal bugs; use `check_invariant` for anything
+/// that bad user data can trip.
+pub fn assert_invariant(condition: bool, message: &str, context: Option<&str>) {
+    let full_message = record_invariant(condition, message, context);
+
+    // Enforce the invariant
+    if !condition {
         panic!("INVARIANT VIOLATION: {}", full_message);
     }
 }
 
+/// Invariant that a malformed model file or unusual path can violate. Panics
+/// like `assert_invariant` in strict mode; otherwise logs a warning and
+/// returns `false` so one bad input can't take the process down.
+pub fn check_invariant(condition: bool, message: &str, context: Option<&str>) -> bool {
+    let full_message = record_invariant(condition, message, context);
+
+    if !condition {
+        if strict_invariants() {
+            panic!("INVARIANT VIOLATION: {}", full_message);
+        }
+        tracing::warn!("Invariant violation: {}", full_message);
+    }
+    condition
+}
+
 /// Property-based test helper - tests behaviors across input ranges
 #[cfg(test)]
 pub fn property_test<F>(name: &str, test_fn: F)
@@ -138,7 +193,7 @@ pub fn failed_invariants() -> Vec<String> {
 
 /// Shimmy-specific invariant helpers
 pub mod shimmy_invariants {
-    use super::assert_invariant;
+    use super::{assert_invariant, check_invariant};
 
     /// Model loading invariants
     #[cfg(test)]
@@ -197,25 +252,27 @@ pub mod shimmy_invariants {
         );
     }
 
-    /// Model discovery invariants
-    pub fn assert_discovery_valid(models_found: usize) {
+    /// Model discovery invariants. Returns `false` (outside strict mode) when
+    /// the count is implausible.
+    pub fn assert_discovery_valid(models_found: usize) -> bool {
         // usize is always >= 0, so we check for reasonable bounds instead
-        assert_invariant(
+        check_invariant(
             models_found < 10000, // Sanity check for reasonable model counts
             "Model discovery must return reasonable count",
             Some("discovery"),
-        );
+        )
     }
 
-    /// Backend selection invariants
-    pub fn assert_backend_selection_valid(file_path: &str, backend: &str) {
-        assert_invariant(
+    /// Backend selection invariants. Returns `false` (outside strict mode)
+    /// when a discovered file doesn't fit its backend.
+    pub fn assert_backend_selection_valid(file_path: &str, backend: &str) -> bool {
+        let mut valid = check_invariant(
             !file_path.is_empty(),
             "File path for backend selection must not be empty",
             Some("backend_selection"),
         );
 
-        assert_invariant(
+        valid &= check_invariant(
             !backend.is_empty(),
             "Selected backend must not be empty",
             Some("backend_selection"),
@@ -223,12 +280,13 @@ pub mod shimmy_invariants {
 
         // GGUF files must use Llama backend
         if file_path.to_lowercase().ends_with(".gguf") {
-            assert_invariant(
+            valid &= check_invariant(
                 backend == "llama" || backend == "Llama",
                 "GGUF files must use Llama backend",
                 Some("backend_selection"),
             );
         }
+        valid
     }
 }
 
@@ -252,6 +310,30 @@ mod tests {
         assert_invariant(false, "This should fail", None);
     }
 
+    #[test]
+    fn test_invalid_backend_selection_warns_when_not_strict() {
+        let valid = with_strict_invariants(false, || {
+            shimmy_invariants::assert_backend_selection_valid("", "llama")
+        });
+        assert!(!valid);
+        let valid = with_strict_invariants(false, || {
+            shimmy_invariants::assert_backend_selection_valid("models/bad.gguf", "HuggingFace")
+        });
+        assert!(!valid);
+        assert!(shimmy_invariants::assert_backend_selection_valid(
+            "models/good.gguf",
+            "Llama"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "INVARIANT VIOLATION")]
+    fn test_invalid_backend_selection_panics_when_strict() {
+        with_strict_invariants(true, || {
+            shimmy_invariants::assert_backend_selection_valid("", "llama")
+        });
+    }
+
     #[test]
     fn test_property_test_success() {
         property_test("always_true", || true);
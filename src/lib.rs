@@ -4,7 +4,9 @@
 pub mod anthropic_compat;
 pub mod api;
 pub mod api_errors;
+pub mod audit;
 pub mod auto_discovery;
+pub mod bench;
 pub mod cache;
 pub mod cli;
 pub mod discovery;
@@ -13,10 +15,15 @@ pub mod error;
 pub mod main_integration;
 pub mod metrics;
 pub mod model_manager;
+pub mod model_overrides;
 pub mod model_registry;
+pub mod model_snapshot;
 pub mod observability;
 pub mod openai_compat;
 pub mod port_manager;
+pub mod probe;
+pub mod prompt_log;
+pub mod redact;
 pub mod rustchain_compat;
 pub mod safetensors_adapter;
 pub mod server;
@@ -28,7 +35,11 @@ pub mod vision;
 pub mod vision_license;
 pub mod util {
     pub mod diag;
+    pub mod env_file;
+    pub mod features;
+    pub mod json_output;
     pub mod memory;
+    pub mod workers;
 }
 pub mod invariant_ppt;
 pub mod workflow;
@@ -45,7 +56,10 @@ pub struct AppState {
     pub engine: Box<dyn engine::InferenceEngine>,
     pub registry: model_registry::Registry,
     pub observability: observability::ObservabilityManager,
+    pub model_pool: model_manager::ModelManager,
     pub response_cache: cache::ResponseCache,
+    pub audit_logger: Option<audit::AuditLogger>,
+    pub server_config: server::ServerConfig,
     #[cfg(feature = "vision")]
     pub vision_license_manager: Option<crate::vision_license::VisionLicenseManager>,
 }
@@ -59,7 +73,10 @@ impl AppState {
             engine,
             registry,
             observability: observability::ObservabilityManager::new(),
+            model_pool: model_manager::ModelManager::new(),
             response_cache: cache::ResponseCache::new(),
+            audit_logger: None,
+            server_config: server::ServerConfig::default(),
             #[cfg(feature = "vision")]
             vision_license_manager: Some(crate::vision_license::VisionLicenseManager::new()),
         }
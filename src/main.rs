@@ -4,16 +4,24 @@
 mod anthropic_compat;
 mod api;
 mod api_errors;
+mod audit;
 mod auto_discovery;
+mod bench;
 mod cache;
 mod cli;
 mod engine;
 mod invariant_ppt;
 mod main_integration;
+mod model_manager;
+mod model_overrides;
 mod model_registry;
+mod model_snapshot;
 mod observability;
 mod openai_compat;
 mod port_manager;
+mod probe;
+mod prompt_log;
+mod redact;
 mod server;
 mod templates;
 #[cfg(feature = "vision")]
@@ -22,7 +30,11 @@ mod vision;
 mod vision_license;
 mod util {
     pub mod diag;
+    pub mod env_file;
+    pub mod features;
+    pub mod json_output;
     pub mod memory;
+    pub mod workers;
 }
 
 use clap::Parser;
@@ -35,7 +47,10 @@ pub struct AppState {
     pub engine: Box<dyn engine::InferenceEngine>,
     pub registry: Registry,
     pub observability: observability::ObservabilityManager,
+    pub model_pool: model_manager::ModelManager,
     pub response_cache: cache::ResponseCache,
+    pub audit_logger: Option<audit::AuditLogger>,
+    pub server_config: server::ServerConfig,
     #[cfg(feature = "vision")]
     pub vision_license_manager: Option<crate::vision_license::VisionLicenseManager>,
 }
@@ -47,7 +62,10 @@ impl AppState {
             engine,
             registry,
             observability: observability::ObservabilityManager::new(),
+            model_pool: model_manager::ModelManager::new(),
             response_cache: cache::ResponseCache::new(),
+            audit_logger: None,
+            server_config: server::ServerConfig::default(),
             #[cfg(feature = "vision")]
             vision_license_manager: None,
         };
@@ -61,6 +79,104 @@ impl AppState {
     }
 }
 
+/// Validate the serve configuration without binding or loading anything.
+/// Returns the process exit code.
+fn run_dry_run(registry: &Registry, addr: std::net::SocketAddr, json: bool, pretty: bool) -> i32 {
+    // Mirror serve: fall back to discovered models when only the default entry is registered
+    let mut registry = registry.clone();
+    if registry.list().len() <= 1 {
+        registry.auto_register_discovered();
+    }
+
+    // The built-in phi3-lora entry is a placeholder unless SHIMMY_BASE_GGUF points somewhere
+    let base_gguf_configured = std::env::var("SHIMMY_BASE_GGUF").is_ok();
+    let mut entries: Vec<&ModelEntry> = registry
+        .list()
+        .into_iter()
+        .filter(|e| e.name != "phi3-lora" || base_gguf_configured || e.base_path.exists())
+        .collect();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let problem = if entries.is_empty() {
+        Some("no models available".to_string())
+    } else {
+        entries
+            .iter()
+            .find_map(|e| Registry::validate_entry(e).err())
+    };
+
+    if json {
+        let summary = serde_json::json!({
+            "status": if problem.is_none() { "ok" } else { "error" },
+            "bind": addr.to_string(),
+            "models": entries
+                .iter()
+                .map(|e| serde_json::json!({
+                    "name": e.name,
+                    "path": e.base_path,
+                    "template": e.template,
+                }))
+                .collect::<Vec<_>>(),
+            "error": problem,
+        });
+        println!(
+            "{}",
+            util::json_output::render(&summary, pretty).unwrap_or_default()
+        );
+    } else {
+        println!("🔎 Dry run: configuration check");
+        println!("   Bind address: {}", addr);
+        println!("   Models: {}", entries.len());
+        for e in &entries {
+            println!(
+                "     {} => {:?} [{}]",
+                e.name,
+                e.base_path,
+                e.template.as_deref().unwrap_or("auto")
+            );
+        }
+        match &problem {
+            Some(problem) => eprintln!("❌ {}", problem),
+            None => println!("✅ Configuration valid"),
+        }
+    }
+
+    if problem.is_some() {
+        1
+    } else {
+        0
+    }
+}
+
+/// `shimmy generate`: hand the output to `emit` in one piece, or token by
+/// token with `stream`. With `echo` the prompt is emitted first.
+async fn run_generate(
+    model: &dyn engine::LoadedModel,
+    prompt: &str,
+    max_tokens: usize,
+    echo: bool,
+    stream: bool,
+    mut emit: impl FnMut(&str) + Send + 'static,
+) -> anyhow::Result<()> {
+    let opts = engine::GenOptions {
+        max_tokens,
+        stream: false,
+        ..Default::default()
+    };
+    if stream {
+        if echo {
+            emit(prompt);
+        }
+        model
+            .generate(prompt, opts, Some(Box::new(move |tok| emit(&tok))))
+            .await?;
+    } else {
+        let out = model.generate(prompt, opts, None).await?;
+        emit(&api::echo_prompt(prompt, out, echo));
+    }
+    Ok(())
+}
+
 /// Runtime version validation - prevents Issue #63 broken binary distribution
 fn validate_runtime_version() {
     let version = env!("CARGO_PKG_VERSION");
@@ -158,8 +274,73 @@ fn print_startup_diagnostics(
     println!("📦 Models: {} available", model_count);
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+/// Print what `--moe-auto` has to work with. Decisions for discovered models
+/// are logged when each model loads.
+#[cfg(feature = "llama")]
+fn print_moe_auto_diagnostics(model_path: Option<&str>) {
+    match engine::moe::free_vram_bytes() {
+        Some(bytes) => println!("🧠 MoE: auto ({} MB VRAM free)", bytes / (1024 * 1024)),
+        None => println!("🧠 MoE: auto (no GPU device reports free VRAM)"),
+    }
+    if let Some(path) = model_path {
+        let decision = engine::moe::auto_decision(std::path::Path::new(path));
+        println!("🧠 MoE: {} → {}", path, decision);
+    }
+}
+
+/// The inference engine configured by the global flags (GPU backend, MoE
+/// offload, load timeout, warm-up)
+fn build_engine(cli: &cli::Cli) -> Box<dyn engine::InferenceEngine> {
+    #[cfg_attr(not(feature = "llama"), allow(unused_mut))]
+    let mut adapter =
+        engine::adapter::InferenceEngineAdapter::new_with_backend(cli.gpu_backend.as_deref());
+
+    #[cfg(feature = "llama")]
+    {
+        // Apply MoE configuration from global flags
+        if cli.cpu_moe || cli.n_cpu_moe.is_some() {
+            adapter = adapter.with_moe_config(cli.cpu_moe, cli.n_cpu_moe);
+        }
+        if cli.moe_auto {
+            adapter = adapter.with_moe_auto(true);
+        }
+        adapter = adapter.with_load_timeout(std::time::Duration::from_secs(cli.load_timeout_secs));
+        if cli.warmup {
+            adapter = adapter.with_warmup(true);
+        }
+    }
+
+    Box::new(adapter)
+}
+
+fn main() -> anyhow::Result<()> {
+    // Environment file first, so everything below (RUST_LOG, NO_COLOR,
+    // SHIMMY_*) sees its values
+    let cli = cli::Cli::parse();
+    let env_file = util::env_file::load(cli.env_file.as_deref())?;
+
+    let worker_threads = util::workers::resolve_worker_threads(
+        cli.worker_threads.or_else(|| {
+            std::env::var("SHIMMY_WORKER_THREADS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+        }),
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1),
+    );
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .enable_all()
+        .build()?
+        .block_on(run(cli, env_file, worker_threads))
+}
+
+async fn run(
+    cli: cli::Cli,
+    env_file: Option<PathBuf>,
+    worker_threads: usize,
+) -> anyhow::Result<()> {
     // Version validation - prevents Issue #63 distribution of broken binaries
     validate_runtime_version();
 
@@ -179,12 +360,19 @@ async fn main() -> anyhow::Result<()> {
     #[cfg(all(target_arch = "aarch64", target_os = "macos", not(feature = "llama")))]
     info!("llama.cpp temporarily disabled on macOS ARM64 due to upstream i8mm build incompatibility; using SafeTensors backend");
 
-    let cli = cli::Cli::parse();
+    if let Some(path) = &env_file {
+        info!("Loaded environment from {}", path.display());
+    }
+    tracing::debug!("Runtime started with {} worker threads", worker_threads);
+    let pretty_json = cli.pretty_json();
 
     // Add custom model directories from command line to environment
     if let Some(model_dirs) = &cli.model_dirs {
         std::env::set_var("SHIMMY_MODEL_PATHS", model_dirs);
     }
+    // --models-dir paths go straight to discovery, no ';' splitting
+    auto_discovery::set_extra_search_paths(cli.models_dir.clone());
+    auto_discovery::set_follow_symlinks(!cli.no_follow_symlinks);
 
     // Initialize registry with auto-discovery
     let mut reg = Registry::with_discovery();
@@ -199,33 +387,13 @@ async fn main() -> anyhow::Result<()> {
         template: Some("chatml".into()),
         ctx_len: Some(4096),
         n_threads: None,
+        tags: Vec::new(),
     });
 
-    // Create engine with MoE configuration if needed
-    let engine: Box<dyn engine::InferenceEngine> = {
-        #[cfg(feature = "llama")]
-        {
-            let mut adapter = engine::adapter::InferenceEngineAdapter::new_with_backend(
-                cli.gpu_backend.as_deref(),
-            );
-
-            // Apply MoE configuration from global flags
-            if cli.cpu_moe || cli.n_cpu_moe.is_some() {
-                adapter = adapter.with_moe_config(cli.cpu_moe, cli.n_cpu_moe);
-            }
-
-            Box::new(adapter)
-        }
-        #[cfg(not(feature = "llama"))]
-        {
-            let adapter = engine::adapter::InferenceEngineAdapter::new_with_backend(
-                cli.gpu_backend.as_deref(),
-            );
-            Box::new(adapter)
-        }
-    };
+    let engine = build_engine(&cli);
 
     // Handle model-path registration for serve command
+    let mut direct_model = None;
     if let cli::Command::Serve {
         model_path: Some(ref path),
         ..
@@ -240,27 +408,114 @@ async fn main() -> anyhow::Result<()> {
                 .to_string();
 
             // Register the direct model before creating AppState
-            reg.register(ModelEntry {
-                name: model_name.clone(),
-                base_path: path_buf.clone(),
-                lora_path: None,
-                template: None,
-                ctx_len: None,
-                n_threads: None,
-            });
+            reg.register(ModelEntry::new(model_name.clone(), path_buf.clone()));
 
             println!("🎯 Direct model loaded: {} -> {}", model_name, path);
+            direct_model = Some(model_name);
         } else {
             eprintln!("❌ Model file not found: {}", path);
             std::process::exit(1);
         }
     }
 
-    let state = AppState::new(engine, reg);
+    let mut state = AppState::new(engine, reg);
+    state.model_pool = model_manager::ModelManager::new()
+        .with_memory_ceiling(cli.memory_ceiling_bytes(), cli.force);
+    // Probes check the ceiling themselves; --force skips the check
+    let probe_ceiling = cli.memory_ceiling_bytes().filter(|_| !cli.force);
+    if let cli::Command::Serve {
+        ref audit_log,
+        audit_include_content,
+        ref redact_config,
+        log_prompt_sample_rate,
+        no_compression,
+        max_prompt_tokens,
+        max_batch_items,
+        idle_unload_secs,
+        sse_keep_alive_secs,
+        open,
+        normalize_messages,
+        ref ready_file,
+        transient_retries,
+        ref metrics_dump,
+        ref default_model,
+        echo_params,
+        strict_sampling,
+        ref api_key,
+        ref auth_exempt,
+        ..
+    } = cli.cmd
+    {
+        let redactor = match redact_config {
+            Some(config) => redact::Redactor::load(config)?,
+            None => redact::Redactor::default(),
+        };
+        if let Some(path) = audit_log {
+            if !cfg!(feature = "audit") {
+                anyhow::bail!("--audit-log needs shimmy built with the `audit` feature");
+            }
+            state.audit_logger = Some(audit::AuditLogger::new(audit::AuditConfig {
+                path: PathBuf::from(path),
+                include_content: audit_include_content,
+                redactor: redactor.clone(),
+            }));
+            println!("📝 Audit log: {}", path);
+        }
+
+        let secs = |secs: u64| (secs > 0).then(|| std::time::Duration::from_secs(secs));
+        let default_model = default_model
+            .clone()
+            .or_else(|| std::env::var("SHIMMY_DEFAULT_MODEL").ok())
+            .filter(|name| !name.trim().is_empty());
+        state.server_config = server::ServerConfig {
+            compression: !no_compression,
+            max_prompt_tokens: max_prompt_tokens.or_else(|| {
+                std::env::var("SHIMMY_MAX_PROMPT_TOKENS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+            }),
+            max_batch_items,
+            idle_unload: secs(idle_unload_secs),
+            sse_keep_alive: secs(sse_keep_alive_secs),
+            open_browser: open,
+            normalize_messages,
+            ready_file: ready_file.clone(),
+            transient_retries,
+            metrics_dump: metrics_dump.clone(),
+            preload_model: direct_model.or_else(|| default_model.clone()),
+            default_model,
+            echo_params,
+            strict_sampling,
+            api_key: api_key
+                .clone()
+                .or_else(|| std::env::var("SHIMMY_API_KEY").ok())
+                .filter(|key| !key.is_empty()),
+            auth_exempt: auth_exempt.clone(),
+            prompt_log: prompt_log::PromptLogger::new(log_prompt_sample_rate, redactor),
+            ..Default::default()
+        };
+
+        if idle_unload_secs > 0 {
+            println!("💤 Idle models unload after {}s", idle_unload_secs);
+        }
+        if state.server_config.api_key.is_some() {
+            println!(
+                "🔑 API key required (exempt: {})",
+                state.server_config.auth_exempt.join(", ")
+            );
+        }
+    }
     let state = Arc::new(state);
 
     match cli.cmd {
-        cli::Command::Serve { ref bind, .. } => {
+        cli::Command::Serve {
+            ref bind,
+            #[cfg_attr(not(feature = "llama"), allow(unused_variables))]
+            ref model_path,
+            dry_run,
+            json,
+            ..
+        } => {
             // Use smart bind address resolution instead of direct parsing
             let addr = port_manager::GLOBAL_PORT_ALLOCATOR
                 .resolve_bind_address(bind)
@@ -276,6 +531,10 @@ async fn main() -> anyhow::Result<()> {
                     std::process::exit(1);
                 });
 
+            if dry_run {
+                std::process::exit(run_dry_run(&state.registry, addr, json, pretty_json));
+            }
+
             // Print startup diagnostics before server starts
             print_startup_diagnostics(
                 env!("CARGO_PKG_VERSION"),
@@ -284,37 +543,22 @@ async fn main() -> anyhow::Result<()> {
                 cli.n_cpu_moe,
                 0, // Will update after model discovery
             );
+            #[cfg(feature = "llama")]
+            if cli.moe_auto && !cli.cpu_moe && cli.n_cpu_moe.is_none() {
+                print_moe_auto_diagnostics(model_path.as_deref());
+            }
             println!("🚀 Starting server on {}", addr);
 
             // Auto-register discovered models if we only have the default
             let manual_count = state.registry.list().len();
             if manual_count <= 1 {
                 // Only the default phi3-lora entry
-                // Create new engine with same configuration (including MoE if set)
-                let enhanced_engine: Box<dyn engine::InferenceEngine> = {
-                    #[cfg(feature = "llama")]
-                    {
-                        let mut adapter = engine::adapter::InferenceEngineAdapter::new_with_backend(
-                            cli.gpu_backend.as_deref(),
-                        );
-
-                        // Apply MoE configuration from global flags
-                        if cli.cpu_moe || cli.n_cpu_moe.is_some() {
-                            adapter = adapter.with_moe_config(cli.cpu_moe, cli.n_cpu_moe);
-                        }
-
-                        Box::new(adapter)
-                    }
-                    #[cfg(not(feature = "llama"))]
-                    {
-                        let adapter = engine::adapter::InferenceEngineAdapter::new_with_backend(
-                            cli.gpu_backend.as_deref(),
-                        );
-                        Box::new(adapter)
-                    }
-                };
+                let enhanced_engine = build_engine(&cli);
 
                 let mut enhanced_state = AppState::new(enhanced_engine, state.registry.clone());
+                enhanced_state.audit_logger = state.audit_logger.clone();
+                enhanced_state.server_config = state.server_config.clone();
+                enhanced_state.model_pool = state.model_pool.clone();
                 enhanced_state.registry.auto_register_discovered();
                 let enhanced_state = Arc::new(enhanced_state);
 
@@ -358,10 +602,36 @@ async fn main() -> anyhow::Result<()> {
             info!(%addr, models=%available_models.len(), "shimmy serving with {} available models", available_models.len());
             server::run(addr, state).await?;
         }
-        cli::Command::List { short } => {
+        cli::Command::List {
+            short,
+            remote: Some(ref addr),
+            ..
+        } => {
+            let snapshot = model_snapshot::fetch(addr).await?;
+            if short {
+                for model in &snapshot {
+                    println!("{}", model.name);
+                }
+            } else {
+                println!("📡 Models on {}:", model_snapshot::base_url(addr));
+                for model in &snapshot {
+                    let state = match model.estimated_memory_bytes {
+                        Some(bytes) if model.loaded => {
+                            format!("loaded, {}MB", bytes / (1024 * 1024))
+                        }
+                        _ if model.loaded => "loaded".to_string(),
+                        _ => "unloaded".to_string(),
+                    };
+                    println!("  {} [{}] ({})", model.name, state, model.source);
+                }
+                let loaded = snapshot.iter().filter(|m| m.loaded).count();
+                println!("\n✅ {} models, {} loaded", snapshot.len(), loaded);
+            }
+        }
+        cli::Command::List { short, sort, .. } => {
             if short {
                 // Short format: just model names for programmatic use
-                let all_available = state.registry.list_all_available();
+                let all_available = state.registry.list_sorted(sort);
                 for model_name in all_available {
                     println!("{}", model_name);
                 }
@@ -377,13 +647,17 @@ async fn main() -> anyhow::Result<()> {
                 }
 
                 // Show auto-discovered models
-                let auto_discovered = state.registry.discovered_models.clone();
+                let auto_discovered = &state.registry.discovered_models;
                 if !auto_discovered.is_empty() {
                     if !manual_models.is_empty() {
                         println!();
                     }
                     println!("🔍 Auto-Discovered Models:");
-                    for (name, model) in auto_discovered {
+                    let sorted = state.registry.list_sorted(sort);
+                    let ordered = sorted
+                        .iter()
+                        .filter_map(|name| Some((name, auto_discovered.get(name)?)));
+                    for (name, model) in ordered {
                         let size_mb = model.size_bytes / (1024 * 1024);
                         let type_info = match (&model.parameter_count, &model.quantization) {
                             (Some(params), Some(quant)) => format!(" ({}·{})", params, quant),
@@ -396,9 +670,14 @@ async fn main() -> anyhow::Result<()> {
                         } else {
                             ""
                         };
+                        let display_info = model
+                            .display_name
+                            .as_ref()
+                            .map(|d| format!(" \"{}\"", d))
+                            .unwrap_or_default();
                         println!(
-                            "  {} => {:?} [{}MB{}{}]",
-                            name, model.path, size_mb, type_info, lora_info
+                            "  {}{} => {:?} [{}MB{}{}]",
+                            name, display_info, model.path, size_mb, type_info, lora_info
                         );
                     }
                 }
@@ -468,22 +747,74 @@ async fn main() -> anyhow::Result<()> {
                     }
                 }
             }
+
+            let checkpoints =
+                crate::auto_discovery::ModelAutoDiscovery::new().discover_unconverted();
+            if !checkpoints.is_empty() {
+                println!(
+                    "\n⚠️  {} PyTorch checkpoint(s) need conversion before they can be served:",
+                    checkpoints.len()
+                );
+                for checkpoint in &checkpoints {
+                    println!(
+                        "  {:?} [{}MB] needs conversion",
+                        checkpoint.path,
+                        checkpoint.size_bytes / (1024 * 1024)
+                    );
+                }
+                println!("💡 {}", checkpoints[0].conversion_hint());
+            }
         }
-        cli::Command::Probe { name } => {
+        cli::Command::Probe {
+            all: true,
+            concurrency,
+            json,
+            ..
+        } => {
+            let specs = state
+                .registry
+                .list_all_available()
+                .iter()
+                .filter_map(|name| state.registry.to_spec(name))
+                .collect::<Vec<_>>();
+            if specs.is_empty() {
+                anyhow::bail!("No models available to probe");
+            }
+            let results =
+                probe::probe_all(state.engine.as_ref(), specs, concurrency, probe_ceiling).await;
+            if json {
+                println!("{}", util::json_output::render(&results, pretty_json)?);
+            } else {
+                print!("{}", probe::render_table(&results));
+            }
+            if !probe::all_passed(&results) {
+                std::process::exit(2);
+            }
+        }
+        cli::Command::Probe { name, .. } => {
+            let name = name.expect("clap requires a name without --all");
             let Some(spec) = state.registry.to_spec(&name) else {
-                anyhow::bail!("no model {name}");
+                anyhow::bail!(state.registry.model_not_found_message(&name));
             };
-            match state.engine.load(&spec).await {
-                Ok(_) => println!("ok: loaded {name}"),
-                Err(e) => {
+            let result = probe::probe_one(state.engine.as_ref(), &spec, probe_ceiling).await;
+            let estimate_mb = result.estimated_memory_bytes / (1024 * 1024);
+            match result.error {
+                None => println!("ok: loaded {name} (estimated memory: {estimate_mb}MB)"),
+                Some(e) => {
                     eprintln!("probe failed: {e}");
                     std::process::exit(2);
                 }
             }
         }
-        cli::Command::Bench { name, max_tokens } => {
+        cli::Command::Bench {
+            name,
+            max_tokens,
+            baseline,
+            save,
+            regression_threshold,
+        } => {
             let Some(spec) = state.registry.to_spec(&name) else {
-                anyhow::bail!("no model {name}");
+                anyhow::bail!(state.registry.model_not_found_message(&name));
             };
             let loaded = state.engine.load(&spec).await?;
             let t0 = std::time::Instant::now();
@@ -501,28 +832,57 @@ async fn main() -> anyhow::Result<()> {
             let elapsed = t0.elapsed();
             println!("bench output (truncated): {}", &out[..out.len().min(120)]);
             println!("elapsed: {:?}", elapsed);
+
+            let result =
+                bench::BenchResult::new(&name, max_tokens, loaded.count_tokens(&out), elapsed);
+            println!(
+                "throughput: {:.1} tok/s ({} tokens)",
+                result.tokens_per_sec, result.completion_tokens
+            );
+            if let Some(path) = &save {
+                result.save(path)?;
+                println!("saved results to {}", path.display());
+            }
+            if let Some(path) = &baseline {
+                let base = bench::BenchResult::load(path)?;
+                if base.model != result.model || base.max_tokens != result.max_tokens {
+                    eprintln!(
+                        "warning: baseline was recorded for {} with --max-tokens {}",
+                        base.model, base.max_tokens
+                    );
+                }
+                let comparison = bench::compare(&result, &base, regression_threshold);
+                println!(
+                    "baseline: {:.1} tok/s, current: {:.1} tok/s ({:+.1}%)",
+                    comparison.baseline_tps, comparison.current_tps, comparison.change_pct
+                );
+                if comparison.regressed {
+                    eprintln!(
+                        "regression: throughput dropped more than {}% below the baseline",
+                        regression_threshold
+                    );
+                    std::process::exit(1);
+                }
+            }
         }
         cli::Command::Generate {
             name,
             prompt,
             max_tokens,
+            echo,
+            stream,
         } => {
             let Some(spec) = state.registry.to_spec(&name) else {
-                anyhow::bail!("no model {name}");
+                anyhow::bail!(state.registry.model_not_found_message(&name));
             };
             let loaded = state.engine.load(&spec).await?;
-            let out = loaded
-                .generate(
-                    &prompt,
-                    engine::GenOptions {
-                        max_tokens,
-                        stream: false,
-                        ..Default::default()
-                    },
-                    None,
-                )
-                .await?;
-            println!("{}", out);
+            run_generate(&*loaded, &prompt, max_tokens, echo, stream, |text| {
+                use std::io::Write;
+                print!("{}", text);
+                let _ = std::io::stdout().flush();
+            })
+            .await?;
+            println!();
         }
         cli::Command::GpuInfo => {
             println!("🖥️  GPU Backend Information");
@@ -614,6 +974,40 @@ async fn main() -> anyhow::Result<()> {
                 println!("   cargo install shimmy --features gpu           # All GPU backends");
             }
         }
+        cli::Command::Features { json } => {
+            let features = util::features::Features::compiled();
+            if json {
+                println!("{}", util::json_output::render(&features, pretty_json)?);
+            } else {
+                let list = |items: &[&str]| {
+                    if items.is_empty() {
+                        "none".to_string()
+                    } else {
+                        items.join(", ")
+                    }
+                };
+                println!("shimmy {}", features.version);
+                println!("Features:     {}", list(&features.features));
+                println!("Backends:     {}", list(&features.backends));
+                println!("GPU backends: {}", list(&features.gpu_backends));
+                println!(
+                    "Vision:       {}",
+                    if features.vision {
+                        "✅"
+                    } else {
+                        "❌ (compile with --features vision)"
+                    }
+                );
+                println!(
+                    "Embeddings:   {}",
+                    if features.embeddings {
+                        "✅"
+                    } else {
+                        "❌ (compile with --features llama)"
+                    }
+                );
+            }
+        }
         cli::Command::Init {
             template,
             output,
@@ -679,14 +1073,11 @@ mod tests {
         let mut reg = model_registry::Registry::with_discovery();
 
         // Test model registration with default values (lines 33-40)
-        reg.register(model_registry::ModelEntry {
-            name: "phi3-lora".into(),
-            base_path: "./models/phi3-mini.gguf".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(4096),
-            n_threads: None,
-        });
+        reg.register(
+            model_registry::ModelEntry::new("phi3-lora", "./models/phi3-mini.gguf")
+                .with_template("chatml")
+                .with_ctx_len(4096),
+        );
 
         // Test engine creation (line 42)
         let engine: Box<dyn engine::InferenceEngine> =
@@ -758,14 +1149,11 @@ mod tests {
         let mut registry = model_registry::Registry::with_discovery();
 
         // Add a test model to exercise manual models display (lines 88-94)
-        registry.register(model_registry::ModelEntry {
-            name: "test-model".into(),
-            base_path: "./test.gguf".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            model_registry::ModelEntry::new("test-model", "./test.gguf")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
 
         let manual_models = registry.list();
         assert!(!manual_models.is_empty());
@@ -810,14 +1198,11 @@ mod tests {
     async fn test_probe_command_execution_logic() {
         // Test Probe command execution logic (lines 148-157)
         let mut registry = model_registry::Registry::with_discovery();
-        registry.register(model_registry::ModelEntry {
-            name: "test-probe-model".into(),
-            base_path: "./test.gguf".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            model_registry::ModelEntry::new("test-probe-model", "./test.gguf")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
 
         let engine = MockEngine;
         let name = "test-probe-model";
@@ -847,14 +1232,11 @@ mod tests {
     async fn test_bench_command_execution_logic() {
         // Test Bench command execution logic (lines 158-170)
         let mut registry = model_registry::Registry::with_discovery();
-        registry.register(model_registry::ModelEntry {
-            name: "test-bench-model".into(),
-            base_path: "./test.gguf".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            model_registry::ModelEntry::new("test-bench-model", "./test.gguf")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
 
         let engine = MockEngine;
         let name = "test-bench-model";
@@ -897,14 +1279,11 @@ mod tests {
     async fn test_generate_command_execution_logic() {
         // Test Generate command execution logic (lines 171-176)
         let mut registry = model_registry::Registry::with_discovery();
-        registry.register(model_registry::ModelEntry {
-            name: "test-gen-model".into(),
-            base_path: "./test.gguf".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            model_registry::ModelEntry::new("test-gen-model", "./test.gguf")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
 
         let engine = MockEngine;
         let name = "test-gen-model";
@@ -957,6 +1336,7 @@ mod tests {
                 name,
                 prompt,
                 max_tokens,
+                ..
             } => {
                 assert_eq!(name, "test-model");
                 assert_eq!(prompt, "Hello");
@@ -966,6 +1346,29 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_generate_echo_prints_prompt_before_completion() {
+        async fn output(echo: bool, stream: bool) -> Vec<String> {
+            let model =
+                engine::mock::MockEngine::new(engine::mock::Reply::Tokens(&[" world", "!"]))
+                    .model("token");
+            let pieces = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let sink = pieces.clone();
+            run_generate(&model, "Hello", 8, echo, stream, move |text| {
+                sink.lock().unwrap().push(text.to_string())
+            })
+            .await
+            .unwrap();
+            let pieces = pieces.lock().unwrap().clone();
+            pieces
+        }
+
+        assert_eq!(output(true, false).await, ["Hello world!"]);
+        assert_eq!(output(false, false).await, [" world!"]);
+        assert_eq!(output(true, true).await, ["Hello", " world", "!"]);
+        assert_eq!(output(false, true).await, [" world", "!"]);
+    }
+
     #[tokio::test]
     async fn test_state_initialization() {
         use crate::engine::adapter::InferenceEngineAdapter;
@@ -1028,6 +1431,7 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(4096),
             n_threads: None,
+            tags: Vec::new(),
         });
 
         let models = reg.list();
@@ -1049,14 +1453,7 @@ mod tests {
         let _all_available = registry.list_all_available();
 
         // Add a model and test again
-        registry.register(model_registry::ModelEntry {
-            name: "test".into(),
-            base_path: "./test.gguf".into(),
-            lora_path: None,
-            template: None,
-            ctx_len: None,
-            n_threads: None,
-        });
+        registry.register(model_registry::ModelEntry::new("test", "./test.gguf"));
 
         let after_count = registry.list().len();
         assert!(after_count > initial_count);
@@ -1108,6 +1505,7 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(4096),
             n_threads: None,
+            tags: Vec::new(),
         });
 
         let engine: Box<dyn engine::InferenceEngine> =
@@ -1169,14 +1567,11 @@ mod tests {
 
         // Create test state
         let mut reg = model_registry::Registry::with_discovery();
-        reg.register(model_registry::ModelEntry {
-            name: "test-model".into(),
-            base_path: "./test.gguf".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        reg.register(
+            model_registry::ModelEntry::new("test-model", "./test.gguf")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
         let _engine = MockEngine;
         let state = Arc::new(AppState::new(
             Box::new(engine::adapter::InferenceEngineAdapter::new()),
@@ -1355,14 +1750,7 @@ mod tests {
         let mut registry = model_registry::Registry::with_discovery();
 
         // Test minimal entry
-        registry.register(model_registry::ModelEntry {
-            name: "minimal".to_string(),
-            base_path: "./minimal.gguf".into(),
-            lora_path: None,
-            template: None,
-            ctx_len: None,
-            n_threads: None,
-        });
+        registry.register(model_registry::ModelEntry::new("minimal", "./minimal.gguf"));
 
         // Test maximal entry
         registry.register(model_registry::ModelEntry {
@@ -1372,6 +1760,7 @@ mod tests {
             template: Some("llama3".to_string()),
             ctx_len: Some(8192),
             n_threads: Some(8),
+            tags: Vec::new(),
         });
 
         let models = registry.list();
@@ -1591,14 +1980,11 @@ mod tests {
     async fn test_probe_command_execution() {
         // Test Probe command execution (lines 148-155)
         let mut registry = model_registry::Registry::with_discovery();
-        registry.register(model_registry::ModelEntry {
-            name: "probe-test".to_string(),
-            base_path: "./probe-test.gguf".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            model_registry::ModelEntry::new("probe-test", "./probe-test.gguf")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
 
         let engine = MockEngine;
         let name = "probe-test";
@@ -1626,14 +2012,11 @@ mod tests {
     async fn test_bench_command_execution() {
         // Test Bench command execution (lines 156-169)
         let mut registry = model_registry::Registry::with_discovery();
-        registry.register(model_registry::ModelEntry {
-            name: "bench-test".to_string(),
-            base_path: "./bench-test.gguf".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            model_registry::ModelEntry::new("bench-test", "./bench-test.gguf")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
 
         let engine = MockEngine;
         let name = "bench-test";
@@ -1742,6 +2125,7 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            tags: Vec::new(),
         });
 
         // Create an engine that might fail
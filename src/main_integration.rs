@@ -52,6 +52,7 @@ mod tests {
             template: Some("chatml".to_string()),
             ctx_len: Some(4096),
             n_threads: Some(4),
+            tags: Vec::new(),
         };
 
         registry.register(test_entry);
@@ -85,14 +86,7 @@ mod tests {
         use crate::model_registry::ModelEntry;
         use std::path::PathBuf;
 
-        let test_entry = ModelEntry {
-            name: "test-model".to_string(),
-            base_path: PathBuf::from("/test"),
-            lora_path: None,
-            template: None,
-            ctx_len: None,
-            n_threads: None,
-        };
+        let test_entry = ModelEntry::new("test-model", PathBuf::from("/test"));
 
         registry1_mut.register(test_entry);
 
@@ -173,6 +167,7 @@ mod tests {
             template: Some("llama3".to_string()),
             ctx_len: Some(8192),
             n_threads: Some(8),
+            tags: Vec::new(),
         };
 
         registry_mut.register(production_model);
@@ -215,6 +210,7 @@ mod tests {
             template: Some("chatml".to_string()),
             ctx_len: Some(2048),
             n_threads: Some(2),
+            tags: Vec::new(),
         };
 
         registry.register(test_model);
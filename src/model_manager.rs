@@ -1,9 +1,11 @@
 #![allow(dead_code)]
 
-use crate::engine::ModelSpec;
+use crate::engine::{InferenceEngine, LoadedModel, ModelSpec};
+use crate::observability::{LoadedModelStatus, ObservabilityManager};
+use crate::util::memory::{check_ceiling, LoadEstimate};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use tokio::sync::RwLock;
@@ -19,6 +21,39 @@ pub struct ModelManager {
     preload_config: PreloadConfig,
     // Background preloading state
     preload_queue: Arc<RwLock<VecDeque<String>>>,
+    // Loaded model handles shared across requests; a handle with other
+    // strong references is in use by a request
+    handles: Arc<RwLock<HashMap<String, Arc<dyn LoadedModel>>>>,
+    // One gate per model name, so concurrent first requests share one load
+    load_gates: Arc<std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+    // Explicitly preloaded models, never unloaded for being idle
+    preloaded: Arc<RwLock<HashSet<String>>>,
+    // Per-model idle timeouts set by requests (`keep_alive`)
+    keep_alive: Arc<RwLock<HashMap<String, KeepAlive>>>,
+    // Refuse loads whose estimated memory would take the pool past this
+    memory_ceiling: Option<u64>,
+    // Load past the ceiling anyway, with a warning
+    force_load: bool,
+}
+
+/// How long a model stays loaded after its last use, as set by a request's
+/// `keep_alive`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAlive {
+    /// Never unloaded for being idle
+    Forever,
+    /// Unloaded once idle this long; zero unloads it as each request finishes
+    For(Duration),
+}
+
+impl KeepAlive {
+    /// Ollama's convention: seconds, negative for forever, 0 for no residency
+    pub fn from_secs(secs: i64) -> Self {
+        match u64::try_from(secs) {
+            Ok(secs) => KeepAlive::For(Duration::from_secs(secs)),
+            Err(_) => KeepAlive::Forever,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -28,6 +63,8 @@ pub struct ModelLoadInfo {
     pub loaded_at: std::time::SystemTime,
     pub last_accessed: std::time::SystemTime,
     pub access_count: u64,
+    /// Memory estimate taken when the model was loaded
+    pub estimate: LoadEstimate,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,10 +120,236 @@ impl ModelManager {
             usage_stats: Arc::new(RwLock::new(HashMap::new())),
             preload_config: config,
             preload_queue: Arc::new(RwLock::new(VecDeque::new())),
+            handles: Arc::new(RwLock::new(HashMap::new())),
+            load_gates: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            preloaded: Arc::new(RwLock::new(HashSet::new())),
+            keep_alive: Arc::new(RwLock::new(HashMap::new())),
+            memory_ceiling: None,
+            force_load: false,
+        }
+    }
+
+    /// Refuse loads that would push estimated pool memory past `ceiling`
+    /// bytes, unless `force` is set
+    pub fn with_memory_ceiling(mut self, ceiling: Option<u64>, force: bool) -> Self {
+        self.memory_ceiling = ceiling;
+        self.force_load = force;
+        self
+    }
+
+    /// Return the pooled handle for `spec`, loading it through `engine` on
+    /// first use. Concurrent callers for the same unloaded model wait for a
+    /// single load (if it fails, the next waiter tries again); loads of
+    /// different models run in parallel.
+    pub async fn get_or_load(
+        &self,
+        engine: &dyn InferenceEngine,
+        spec: &ModelSpec,
+    ) -> Result<Arc<dyn LoadedModel>> {
+        if let Some(handle) = self.pooled(&spec.name).await {
+            return Ok(handle);
+        }
+
+        let gate = {
+            let mut gates = self
+                .load_gates
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            Arc::clone(gates.entry(spec.name.clone()).or_default())
+        };
+        let loading = gate.lock().await;
+        let loaded = self.load_into_pool(engine, spec).await;
+        drop(loading);
+
+        // Drop the gate unless another caller is already waiting on it
+        let mut gates = self
+            .load_gates
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if gates
+            .get(&spec.name)
+            .is_some_and(|g| Arc::ptr_eq(g, &gate) && Arc::strong_count(&gate) == 2)
+        {
+            gates.remove(&spec.name);
+        }
+        loaded
+    }
+
+    /// Load `spec` while holding its gate
+    async fn load_into_pool(
+        &self,
+        engine: &dyn InferenceEngine,
+        spec: &ModelSpec,
+    ) -> Result<Arc<dyn LoadedModel>> {
+        // Whoever held the gate before us may have loaded it already
+        if let Some(handle) = self.pooled(&spec.name).await {
+            return Ok(handle);
+        }
+
+        let estimate = self.check_memory_budget(spec).await?;
+        let handle: Arc<dyn LoadedModel> = Arc::from(engine.load(spec).await?);
+        self.handles
+            .write()
+            .await
+            .insert(spec.name.clone(), Arc::clone(&handle));
+        self.record_loaded(spec.name.clone(), spec.clone(), estimate)
+            .await;
+        Ok(handle)
+    }
+
+    async fn pooled(&self, name: &str) -> Option<Arc<dyn LoadedModel>> {
+        let cached = self.handles.read().await.get(name).cloned();
+        if cached.is_some() {
+            self.touch(name).await;
+        }
+        cached
+    }
+
+    /// Load a model and exempt it from idle unloading
+    pub async fn preload(&self, engine: &dyn InferenceEngine, spec: &ModelSpec) -> Result<()> {
+        self.get_or_load(engine, spec).await?;
+        self.preloaded.write().await.insert(spec.name.clone());
+        Ok(())
+    }
+
+    pub async fn is_preloaded(&self, name: &str) -> bool {
+        self.preloaded.read().await.contains(name)
+    }
+
+    /// Set how long `name` stays loaded when idle, replacing the server-wide
+    /// idle timeout for that model from now on
+    pub async fn set_keep_alive(&self, name: &str, keep_alive: KeepAlive) {
+        self.keep_alive
+            .write()
+            .await
+            .insert(name.to_string(), keep_alive);
+    }
+
+    pub async fn keep_alive(&self, name: &str) -> Option<KeepAlive> {
+        self.keep_alive.read().await.get(name).copied()
+    }
+
+    /// Called when a request using `name` finishes: restarts its idle clock,
+    /// and unloads it right away if its keep-alive is zero. Returns whether it
+    /// was unloaded.
+    pub async fn release(&self, name: &str) -> bool {
+        self.touch(name).await;
+        if self.keep_alive(name).await != Some(KeepAlive::For(Duration::ZERO)) {
+            return false;
+        }
+        self.unload_model(name).await.unwrap_or(false)
+    }
+
+    /// Unload every model idle for longer than its keep-alive, or
+    /// `idle_timeout` for models without one, except preloaded ones and ones
+    /// a request is still using. Returns the unloaded names with how long
+    /// each had been idle.
+    pub async fn unload_idle(&self, idle_timeout: Option<Duration>) -> Vec<(String, Duration)> {
+        let preloaded = self.preloaded.read().await.clone();
+        let keep_alive = self.keep_alive.read().await.clone();
+        let now = SystemTime::now();
+
+        let mut models = self.loaded_models.write().await;
+        let mut handles = self.handles.write().await;
+        // A long generation counts as use: its idle clock starts when it ends
+        for (name, info) in models.iter_mut() {
+            if handles.get(name).is_some_and(|h| Arc::strong_count(h) > 1) {
+                info.last_accessed = now;
+            }
+        }
+        let idle: Vec<(String, Duration)> = models
+            .iter()
+            .filter(|(name, _)| !preloaded.contains(*name))
+            .filter_map(|(name, info)| {
+                let timeout = match keep_alive.get(name) {
+                    Some(KeepAlive::Forever) => return None,
+                    Some(KeepAlive::For(timeout)) => *timeout,
+                    None => idle_timeout?,
+                };
+                let idle_for = now.duration_since(info.last_accessed).unwrap_or_default();
+                (idle_for > timeout).then(|| (name.clone(), idle_for))
+            })
+            .collect();
+
+        for (name, _) in &idle {
+            models.remove(name);
+            handles.remove(name);
+        }
+        idle
+    }
+
+    /// Periodically unload idle models, recording each eviction
+    pub fn start_idle_unload_task(
+        &self,
+        idle_timeout: Option<Duration>,
+        observability: ObservabilityManager,
+    ) {
+        let manager = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                for (name, idle) in manager.unload_idle(idle_timeout).await {
+                    observability.record_idle_unload(&name, idle).await;
+                }
+                tokio::time::sleep(manager.sweep_period(idle_timeout).await).await;
+            }
+        });
+    }
+
+    /// Half the shortest idle timeout in effect, within 1s..=60s
+    async fn sweep_period(&self, idle_timeout: Option<Duration>) -> Duration {
+        let shortest = self
+            .keep_alive
+            .read()
+            .await
+            .values()
+            .filter_map(|keep_alive| match keep_alive {
+                KeepAlive::For(timeout) => Some(*timeout),
+                KeepAlive::Forever => None,
+            })
+            .chain(idle_timeout)
+            .min()
+            .unwrap_or(Duration::MAX);
+        (shortest / 2).clamp(Duration::from_secs(1), Duration::from_secs(60))
+    }
+
+    /// Check `spec`'s estimated memory against the ceiling, counting the
+    /// models already in the pool, and return the estimate
+    async fn check_memory_budget(&self, spec: &ModelSpec) -> Result<LoadEstimate> {
+        let estimate = LoadEstimate::for_spec(spec);
+        let Some(ceiling) = self.memory_ceiling else {
+            return Ok(estimate);
+        };
+        let resident = self
+            .loaded_models
+            .read()
+            .await
+            .values()
+            .map(|info| info.estimate.total_bytes)
+            .fold(0, u64::saturating_add);
+        match check_ceiling(&spec.name, &estimate, resident, ceiling) {
+            Err(e) if self.force_load => warn!("{}; loading anyway (--force)", e),
+            result => result?,
+        }
+        Ok(estimate)
+    }
+
+    /// Mark a pooled model as just used
+    async fn touch(&self, name: &str) {
+        if let Some(info) = self.loaded_models.write().await.get_mut(name) {
+            info.last_accessed = SystemTime::now();
+            info.access_count += 1;
         }
     }
 
     pub async fn load_model(&self, name: String, spec: ModelSpec) -> Result<()> {
+        let estimate = LoadEstimate::for_spec(&spec);
+        self.record_loaded(name, spec, estimate).await;
+        Ok(())
+    }
+
+    /// Add a model to the pool bookkeeping with its memory estimate
+    async fn record_loaded(&self, name: String, spec: ModelSpec, estimate: LoadEstimate) {
         let now = SystemTime::now();
 
         // Create model load info with usage tracking
@@ -96,6 +359,7 @@ impl ModelManager {
             loaded_at: now,
             last_accessed: now,
             access_count: 1,
+            estimate,
         };
 
         // Store the loaded model
@@ -114,8 +378,6 @@ impl ModelManager {
         if self.preload_config.enabled {
             self.evaluate_preloading().await;
         }
-
-        Ok(())
     }
 
     /// Record model access for usage tracking
@@ -317,6 +579,7 @@ impl ModelManager {
     pub async fn unload_model(&self, name: &str) -> Result<bool> {
         let mut models = self.loaded_models.write().await;
         let removed = models.remove(name).is_some();
+        self.handles.write().await.remove(name);
         if removed {
             info!("Model '{}' unloaded", name);
         }
@@ -342,6 +605,29 @@ impl ModelManager {
         let models = self.loaded_models.read().await;
         models.len()
     }
+
+    /// Resident models with estimated memory and access times, sorted by name
+    pub async fn loaded_model_status(&self) -> Vec<LoadedModelStatus> {
+        let models = self.loaded_models.read().await;
+        let mut status: Vec<LoadedModelStatus> = models
+            .values()
+            .map(|info| LoadedModelStatus {
+                name: info.name.clone(),
+                estimated_memory_bytes: info.estimate.total_bytes,
+                loaded_at: unix_secs(info.loaded_at),
+                last_accessed: unix_secs(info.last_accessed),
+                access_count: info.access_count,
+            })
+            .collect();
+        status.sort_by(|a, b| a.name.cmp(&b.name));
+        status
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
 }
 
 impl Default for ModelManager {
@@ -353,6 +639,7 @@ impl Default for ModelManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::mock::{MockEngine, Reply};
     use std::path::PathBuf;
     use std::time::{Duration, SystemTime};
 
@@ -377,6 +664,7 @@ mod tests {
             loaded_at: now,
             last_accessed: now,
             access_count: 1,
+            estimate: LoadEstimate::new(0, 0),
         }
     }
 
@@ -650,6 +938,7 @@ mod tests {
             loaded_at: SystemTime::now(),
             access_count: 0,
             last_accessed: SystemTime::now(),
+            estimate: LoadEstimate::new(0, 0),
         };
 
         let info2 = info1.clone();
@@ -668,6 +957,7 @@ mod tests {
             loaded_at: SystemTime::now(),
             access_count: 0,
             last_accessed: SystemTime::now(),
+            estimate: LoadEstimate::new(0, 0),
         };
 
         let debug_string = format!("{:?}", info);
@@ -893,4 +1183,246 @@ mod tests {
             .to_string_lossy()
             .contains("lora.safetensors"));
     }
+
+    #[tokio::test]
+    async fn test_get_or_load_reuses_pooled_handle() {
+        let manager = ModelManager::new();
+        let engine = MockEngine::new(Reply::Text("ok"));
+        let spec = create_test_spec("pooled", "pooled.gguf", None);
+
+        manager.get_or_load(&engine, &spec).await.unwrap();
+        manager.get_or_load(&engine, &spec).await.unwrap();
+
+        assert_eq!(engine.stats.total_loads(), 1);
+        assert!(manager.is_loaded("pooled").await);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_share_one_load() {
+        let manager = ModelManager::new();
+        let engine = MockEngine::new(Reply::Text("ok")).with_load_delay(Duration::from_millis(50));
+        let shared = create_test_spec("shared", "shared.gguf", None);
+        let other = create_test_spec("other", "other.gguf", None);
+
+        let requests = (0..8)
+            .map(|_| &shared)
+            .chain(std::iter::once(&other))
+            .map(|spec| manager.get_or_load(&engine, spec));
+        let handles = futures_util::future::join_all(requests).await;
+
+        for handle in handles {
+            let handle = handle.unwrap();
+            let out = handle
+                .generate("hi", crate::engine::GenOptions::default(), None)
+                .await
+                .unwrap();
+            assert_eq!(out, "ok");
+        }
+        let loads = engine.stats.loads.lock().unwrap().clone();
+        assert_eq!(loads["shared"], 1);
+        assert_eq!(loads["other"], 1);
+        // The other model loaded alongside, not after
+        assert_eq!(
+            engine
+                .stats
+                .peak_loading
+                .load(std::sync::atomic::Ordering::SeqCst),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_idle_models_unloaded_but_preloaded_exempt() {
+        let manager = ModelManager::new();
+        let engine = MockEngine::new(Reply::Text("ok"));
+        let idle_spec = create_test_spec("idle", "idle.gguf", None);
+        let pinned_spec = create_test_spec("pinned", "pinned.gguf", None);
+
+        manager.get_or_load(&engine, &idle_spec).await.unwrap();
+        manager.preload(&engine, &pinned_spec).await.unwrap();
+
+        // Nothing has been idle long enough yet
+        assert!(manager
+            .unload_idle(Some(Duration::from_secs(60)))
+            .await
+            .is_empty());
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        let unloaded = manager.unload_idle(Some(Duration::from_millis(10))).await;
+
+        let names: Vec<_> = unloaded.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["idle"]);
+        assert!(!manager.is_loaded("idle").await);
+        assert!(manager.is_loaded("pinned").await);
+
+        // A pooled model that was dropped is loaded again on next use
+        manager.get_or_load(&engine, &idle_spec).await.unwrap();
+        assert_eq!(engine.stats.total_loads(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_model_in_use_is_not_unloaded_for_idling() {
+        let manager = ModelManager::new();
+        let engine = MockEngine::new(Reply::Text("ok"));
+        let spec = create_test_spec("busy", "busy.gguf", None);
+
+        let in_use = manager.get_or_load(&engine, &spec).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(manager
+            .unload_idle(Some(Duration::from_millis(10)))
+            .await
+            .is_empty());
+
+        // Its idle time counts from when the request let go of it
+        drop(in_use);
+        manager.release("busy").await;
+        assert!(manager
+            .unload_idle(Some(Duration::from_millis(10)))
+            .await
+            .is_empty());
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(
+            manager
+                .unload_idle(Some(Duration::from_millis(10)))
+                .await
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_gates_dropped_after_load() {
+        let manager = ModelManager::new();
+        let engine = MockEngine::new(Reply::Text("ok")).with_load_delay(Duration::from_millis(50));
+        let specs: Vec<_> = (0..4)
+            .map(|i| create_test_spec(&format!("m{}", i), "m.gguf", None))
+            .collect();
+
+        let loads = specs
+            .iter()
+            .chain(&specs)
+            .map(|spec| manager.get_or_load(&engine, spec));
+        for loaded in futures_util::future::join_all(loads).await {
+            loaded.unwrap();
+        }
+
+        assert!(manager.load_gates.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_zero_unloads_and_negative_pins() {
+        let manager = ModelManager::new();
+        let engine = MockEngine::new(Reply::Text("ok"));
+        let once = create_test_spec("once", "once.gguf", None);
+        let pinned = create_test_spec("pinned", "pinned.gguf", None);
+
+        manager
+            .set_keep_alive("once", KeepAlive::from_secs(0))
+            .await;
+        manager.get_or_load(&engine, &once).await.unwrap();
+        assert!(manager.release("once").await);
+        assert!(!manager.is_loaded("once").await);
+
+        // -1 outlives any idle timeout, and release leaves it loaded
+        manager
+            .set_keep_alive("pinned", KeepAlive::from_secs(-1))
+            .await;
+        manager.get_or_load(&engine, &pinned).await.unwrap();
+        assert!(!manager.release("pinned").await);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(manager
+            .unload_idle(Some(Duration::from_millis(10)))
+            .await
+            .is_empty());
+        assert!(manager.is_loaded("pinned").await);
+
+        // A positive keep-alive replaces the server-wide timeout
+        manager
+            .set_keep_alive("pinned", KeepAlive::from_secs(3600))
+            .await;
+        assert!(manager
+            .unload_idle(Some(Duration::from_millis(10)))
+            .await
+            .is_empty());
+
+        // Sweeps run often enough for the shortest timeout in effect
+        manager
+            .set_keep_alive("once", KeepAlive::from_secs(3600))
+            .await;
+        assert_eq!(
+            manager.sweep_period(Some(Duration::from_secs(10))).await,
+            Duration::from_secs(5)
+        );
+        assert_eq!(manager.sweep_period(None).await, Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_idle_unload_task_records_evictions() {
+        let manager = ModelManager::new();
+        let engine = MockEngine::new(Reply::Text("ok"));
+        let observability = ObservabilityManager::new();
+        manager
+            .get_or_load(&engine, &create_test_spec("idle", "idle.gguf", None))
+            .await
+            .unwrap();
+
+        manager.start_idle_unload_task(Some(Duration::from_millis(10)), observability.clone());
+
+        for _ in 0..50 {
+            if observability.metrics().await.model_evictions == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(observability.metrics().await.model_evictions, 1);
+        assert!(!manager.is_loaded("idle").await);
+    }
+
+    #[tokio::test]
+    async fn test_load_over_memory_ceiling_is_refused_unless_forced() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small.gguf");
+        let large = dir.path().join("large.gguf");
+        std::fs::write(&small, vec![0u8; 600]).unwrap();
+        std::fs::write(&large, vec![0u8; 1500]).unwrap();
+        let small = create_test_spec("small", small.to_str().unwrap(), None);
+        let large = create_test_spec("large", large.to_str().unwrap(), None);
+        let engine = MockEngine::new(Reply::Text("ok"));
+
+        let manager = ModelManager::new().with_memory_ceiling(Some(1000), false);
+        let err = match manager.get_or_load(&engine, &large).await {
+            Err(e) => e,
+            Ok(_) => panic!("a 1500-byte model fits a 1000-byte ceiling"),
+        };
+        assert!(matches!(
+            crate::engine::EngineError::find(&err),
+            Some(crate::engine::EngineError::MemoryExceeded {
+                estimated_bytes: 1500,
+                ceiling_bytes: 1000,
+                ..
+            })
+        ));
+        assert_eq!(engine.stats.total_loads(), 0);
+
+        // Resident models count against the ceiling too
+        manager.get_or_load(&engine, &small).await.unwrap();
+        let mut second = small.clone();
+        second.name = "small-2".to_string();
+        assert!(manager.get_or_load(&engine, &second).await.is_err());
+
+        // The resident estimate is the one taken at load, not the file today
+        std::fs::write(&small.base_path, b"").unwrap();
+        let other = dir.path().join("other.gguf");
+        std::fs::write(&other, vec![0u8; 600]).unwrap();
+        let other = create_test_spec("other", other.to_str().unwrap(), None);
+        assert!(manager.get_or_load(&engine, &other).await.is_err());
+        assert_eq!(
+            manager.loaded_model_status().await[0].estimated_memory_bytes,
+            600
+        );
+
+        let forced = ModelManager::new().with_memory_ceiling(Some(1000), true);
+        forced.get_or_load(&engine, &large).await.unwrap();
+        assert!(forced.is_loaded("large").await);
+    }
 }
@@ -0,0 +1,145 @@
+// Per-model overrides for discovered models
+//
+// Discovered models are rebuilt from disk on every run, so user choices such
+// as a template or context length would otherwise be lost. They are kept in
+// `model_overrides.json`, keyed by model file path, and merged onto each
+// `DiscoveredModel` during discovery.
+
+use crate::auto_discovery::DiscoveredModel;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// User-specified settings for one model file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ctx_len: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_threads: Option<i32>,
+    /// Extra stop sequences added to every request for this model
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+impl ModelOverride {
+    pub fn is_empty(&self) -> bool {
+        *self == ModelOverride::default()
+    }
+}
+
+/// The overlay file, loaded into memory
+#[derive(Debug, Clone, Default)]
+pub struct ModelOverrides {
+    path: PathBuf,
+    entries: BTreeMap<String, ModelOverride>,
+}
+
+impl ModelOverrides {
+    /// `SHIMMY_MODEL_OVERRIDES`, or `model_overrides.json` in the shimmy config dir
+    pub fn default_path() -> PathBuf {
+        if let Ok(path) = std::env::var("SHIMMY_MODEL_OVERRIDES") {
+            if !path.trim().is_empty() {
+                return PathBuf::from(path);
+            }
+        }
+        let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+        path.push("shimmy");
+        path.push("model_overrides.json");
+        path
+    }
+
+    /// Load the overlay. A missing file is empty; an unreadable one is logged
+    /// and treated as empty so discovery still works.
+    pub fn load(path: &Path) -> Self {
+        let entries = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring invalid model overrides {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self {
+            path: path.to_path_buf(),
+            entries,
+        }
+    }
+
+    pub fn get(&self, model_path: &Path) -> Option<&ModelOverride> {
+        self.entries.get(&Self::key(model_path))
+    }
+
+    /// Store (or with an empty override, remove) the entry for a model file and
+    /// write the overlay back to disk
+    pub fn set(&mut self, model_path: &Path, value: ModelOverride) -> Result<()> {
+        if value.is_empty() {
+            self.entries.remove(&Self::key(model_path));
+        } else {
+            self.entries.insert(Self::key(model_path), value);
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.entries)?)?;
+        Ok(())
+    }
+
+    /// Merge stored overrides onto freshly discovered models
+    pub fn apply(&self, models: &mut [DiscoveredModel]) {
+        for model in models {
+            if let Some(value) = self.get(&model.path) {
+                model.overrides = value.clone();
+            }
+        }
+    }
+
+    fn key(model_path: &Path) -> String {
+        model_path.to_string_lossy().replace('\\', "/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_persists_and_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("model_overrides.json");
+        let model = Path::new("/models/phi-3.gguf");
+
+        let mut overrides = ModelOverrides::load(&path);
+        assert!(overrides.get(model).is_none());
+        overrides
+            .set(
+                model,
+                ModelOverride {
+                    template: Some("llama3".into()),
+                    stop: vec!["</s>".into()],
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let reloaded = ModelOverrides::load(&path);
+        let value = reloaded.get(model).unwrap();
+        assert_eq!(value.template.as_deref(), Some("llama3"));
+        assert_eq!(value.stop, vec!["</s>".to_string()]);
+        assert_eq!(value.ctx_len, None);
+
+        let mut reloaded = reloaded;
+        reloaded.set(model, ModelOverride::default()).unwrap();
+        assert!(ModelOverrides::load(&path).get(model).is_none());
+    }
+
+    #[test]
+    fn test_invalid_file_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model_overrides.json");
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(ModelOverrides::load(&path).entries.is_empty());
+    }
+}
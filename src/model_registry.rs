@@ -1,7 +1,12 @@
 use super::engine::ModelSpec;
 use crate::auto_discovery::{DiscoveredModel, ModelAutoDiscovery};
+use crate::engine::gguf::GgufInfo;
+use crate::templates::TemplateFamily;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelEntry {
@@ -11,12 +16,106 @@ pub struct ModelEntry {
     pub template: Option<String>,
     pub ctx_len: Option<usize>,
     pub n_threads: Option<i32>,
+    /// Capability tags (e.g. "code", "vision") used by `auto:<tag>` model selection
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl ModelEntry {
+    /// An entry with no LoRA, template, context length, thread count or tags
+    pub fn new(name: impl Into<String>, base_path: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.into(),
+            base_path: base_path.into(),
+            lora_path: None,
+            template: None,
+            ctx_len: None,
+            n_threads: None,
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn with_lora(mut self, path: impl Into<PathBuf>) -> Self {
+        self.lora_path = Some(path.into());
+        self
+    }
+
+    pub fn with_template(mut self, template: impl Into<String>) -> Self {
+        self.template = Some(template.into());
+        self
+    }
+
+    pub fn with_ctx_len(mut self, ctx_len: usize) -> Self {
+        self.ctx_len = Some(ctx_len);
+        self
+    }
+
+    pub fn with_tags<S: Into<String>>(mut self, tags: impl IntoIterator<Item = S>) -> Self {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+/// Prefix for capability-based model selection, e.g. `"model": "auto:code"`
+pub const AUTO_MODEL_PREFIX: &str = "auto";
+
+/// Order of model listings (`shimmy list --sort`, `/v1/models?sort=`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ModelSort {
+    #[default]
+    Name,
+    /// Largest first
+    Size,
+    /// Most recently modified file first
+    Recency,
+}
+
+impl ModelSort {
+    pub const NAMES: &'static [&'static str] = &["name", "size", "recency"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "name" => Some(ModelSort::Name),
+            "size" => Some(ModelSort::Size),
+            "recency" => Some(ModelSort::Recency),
+            _ => None,
+        }
+    }
+}
+
+/// What a model can be used for, as filtered by `/v1/models?capability=`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelCapability {
+    Generation,
+    Vision,
+    Embedding,
+}
+
+impl ModelCapability {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "generation" => Some(ModelCapability::Generation),
+            "vision" => Some(ModelCapability::Vision),
+            "embedding" => Some(ModelCapability::Embedding),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModelCapability::Generation => "generation",
+            ModelCapability::Vision => "vision",
+            ModelCapability::Embedding => "embedding",
+        }
+    }
 }
 
 #[derive(Default, Clone)]
 pub struct Registry {
     inner: HashMap<String, ModelEntry>,
     pub discovered_models: HashMap<String, DiscoveredModel>,
+    /// Overlay file for discovered-model overrides (default location when `None`)
+    overrides_path: Option<PathBuf>,
 }
 
 // Alias for backward compatibility and mission expectations
@@ -27,9 +126,22 @@ impl Registry {
         Self {
             inner: HashMap::new(),
             discovered_models: HashMap::new(),
+            overrides_path: None,
         }
     }
 
+    /// Use a specific `model_overrides.json` instead of the default location
+    pub fn with_overrides_path(mut self, path: PathBuf) -> Self {
+        self.overrides_path = Some(path);
+        self
+    }
+
+    fn overrides_path(&self) -> PathBuf {
+        self.overrides_path
+            .clone()
+            .unwrap_or_else(crate::model_overrides::ModelOverrides::default_path)
+    }
+
     pub fn with_discovery() -> Self {
         let mut registry = Self::new();
         registry.refresh_discovered_models();
@@ -37,7 +149,8 @@ impl Registry {
     }
 
     pub fn refresh_discovered_models(&mut self) {
-        let discovery = ModelAutoDiscovery::new();
+        let mut discovery = ModelAutoDiscovery::new();
+        discovery.overrides_path = self.overrides_path();
         if let Ok(models) = discovery.discover_models() {
             self.discovered_models.clear();
             for model in models {
@@ -54,9 +167,16 @@ impl Registry {
                     name: name.clone(),
                     base_path: discovered.path.clone(),
                     lora_path: discovered.lora_path.clone(),
-                    template: Some(self.infer_template(name)),
-                    ctx_len: Some(4096),
-                    n_threads: None,
+                    template: Some(
+                        discovered
+                            .overrides
+                            .template
+                            .clone()
+                            .unwrap_or_else(|| self.infer_discovered_template(discovered)),
+                    ),
+                    ctx_len: Some(discovered.overrides.ctx_len.unwrap_or(4096)),
+                    n_threads: discovered.overrides.n_threads,
+                    tags: self.tags(name),
                 };
                 self.inner.insert(name.clone(), entry);
             }
@@ -64,15 +184,156 @@ impl Registry {
     }
 
     pub fn infer_template(&self, model_name: &str) -> String {
-        let name_lower = model_name.to_lowercase();
+        // Default to chatml for families without a dedicated template (mistral, etc.)
+        TemplateFamily::for_model_family(model_name)
+            .unwrap_or(TemplateFamily::ChatML)
+            .name()
+            .to_string()
+    }
 
-        // Check model name patterns for better template detection
-        if name_lower.contains("llama") {
-            "llama3".to_string()
+    /// Default template for a discovered model: the family detected at
+    /// discovery time first, then the model name. Overrides take precedence
+    /// at the call sites.
+    fn infer_discovered_template(&self, discovered: &DiscoveredModel) -> String {
+        // GGUF and Ollama entries record the backend/source in `model_type`
+        // ("Llama", "Ollama"), not the family, so only the name is meaningful
+        let is_gguf = discovered.path.extension().and_then(|e| e.to_str()) == Some("gguf");
+        let family = if is_gguf || discovered.model_type == "Ollama" {
+            None
         } else {
-            // Default to chatml for most models (phi, mistral, qwen, gemma, etc.)
-            "chatml".to_string()
+            TemplateFamily::for_model_family(&discovered.model_type)
+        };
+        match family {
+            Some(family) => family.name().to_string(),
+            None => self.infer_template(&discovered.name),
+        }
+    }
+
+    pub fn infer_tags(&self, model_name: &str) -> Vec<String> {
+        tags_from_name(model_name)
+    }
+
+    /// Tags of a model: registered tags, else those found at discovery, else
+    /// inferred from the name
+    pub fn tags(&self, name: &str) -> Vec<String> {
+        match (self.inner.get(name), self.discovered_models.get(name)) {
+            (Some(entry), _) => entry.tags.clone(),
+            (None, Some(discovered)) if !discovered.tags.is_empty() => discovered.tags.clone(),
+            _ => tags_from_name(name),
+        }
+    }
+
+    /// Capabilities of an available model, from its tags (or name) and, for
+    /// GGUF files, the header metadata. Reads the file header, so call it only
+    /// when filtering.
+    pub fn capabilities(&self, name: &str) -> Vec<ModelCapability> {
+        let tags = self.tags(name);
+        let path = match self.inner.get(name) {
+            Some(entry) => Some(entry.base_path.clone()),
+            None => self.discovered_models.get(name).map(|d| d.path.clone()),
+        };
+        let has_tag = |tag: &str| tags.iter().any(|t| t.eq_ignore_ascii_case(tag));
+        let info = path.and_then(|p| crate::engine::gguf::read_gguf_info(&p).ok());
+
+        let embedding = has_tag("embedding") || info.as_ref().is_some_and(|i| i.is_embedding());
+        let vision = has_tag("vision") || info.as_ref().is_some_and(|i| i.has_vision());
+        // A bare CLIP projector can't generate on its own
+        let projector_only = info
+            .as_ref()
+            .is_some_and(|i| i.architecture() == Some("clip"));
+
+        let mut capabilities = Vec::new();
+        if !embedding && !projector_only {
+            capabilities.push(ModelCapability::Generation);
+        }
+        if vision {
+            capabilities.push(ModelCapability::Vision);
+        }
+        if embedding {
+            capabilities.push(ModelCapability::Embedding);
+        }
+        capabilities
+    }
+
+    /// Resolve a requested model name, expanding `auto` and `auto:<tag>`.
+    ///
+    /// Plain names are returned unchanged. `auto` resolves to the default
+    /// model (see `default_model`, with `configured` as `--default-model`).
+    /// `auto:<tag>` resolves to the best available model carrying that tag
+    /// (see `tags`), or `None` if no model has it.
+    pub fn resolve_model_name(&self, requested: &str, configured: Option<&str>) -> Option<String> {
+        let Some(rest) = requested.strip_prefix(AUTO_MODEL_PREFIX) else {
+            return Some(requested.to_string());
+        };
+        if rest.is_empty() {
+            return self.default_model(configured).ok();
+        }
+        // Names that merely start with "auto" (e.g. "autocoder") are not selectors
+        let Some(tag) = rest.strip_prefix(':') else {
+            return Some(requested.to_string());
+        };
+        if tag.is_empty() {
+            return self.default_model(configured).ok();
+        }
+
+        // Search the same models `/v1/models?tag=` lists, preferring the
+        // largest context window and breaking ties by name so selection is
+        // stable.
+        let mut candidates: Vec<(usize, String)> = self
+            .list_all_available()
+            .into_iter()
+            .filter(|name| self.tags(name).iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            .filter_map(|name| Some((self.to_spec(&name)?.ctx_len, name)))
+            .collect();
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        candidates.into_iter().next().map(|(_, name)| name)
+    }
+
+    /// Model for a request that didn't name one: `configured`
+    /// (`--default-model`) when set, otherwise the only available model.
+    /// The error lists the choices when several models are available.
+    pub fn default_model(&self, configured: Option<&str>) -> Result<String, String> {
+        if let Some(name) = configured.filter(|n| !n.trim().is_empty()) {
+            return Ok(name.to_string());
+        }
+        let available = self.list_all_available();
+        match available.as_slice() {
+            [only] => Ok(only.clone()),
+            [] => Err("No model specified and no models are available".to_string()),
+            _ => Err(format!(
+                "No model specified. Set `model` to one of: {} (or start shimmy with --default-model)",
+                available.join(", ")
+            )),
+        }
+    }
+
+    /// Check that an entry's files exist and its template is known
+    pub fn validate_entry(e: &ModelEntry) -> Result<(), String> {
+        if !e.base_path.exists() {
+            return Err(format!(
+                "model '{}': file not found: {}",
+                e.name,
+                e.base_path.display()
+            ));
+        }
+        if let Some(lora) = &e.lora_path {
+            if !lora.exists() {
+                return Err(format!(
+                    "model '{}': LoRA file not found: {}",
+                    e.name,
+                    lora.display()
+                ));
+            }
+        }
+        if let Some(template) = &e.template {
+            if TemplateFamily::from_name(template).is_none() {
+                return Err(format!(
+                    "model '{}': unknown template '{}'",
+                    e.name, template
+                ));
+            }
         }
+        Ok(())
     }
 
     pub fn register(&mut self, e: ModelEntry) {
@@ -82,8 +343,11 @@ impl Registry {
         // First check manually registered models, then auto-discovered
         self.inner.get(name)
     }
+    /// Registered entries, by name
     pub fn list(&self) -> Vec<&ModelEntry> {
-        self.inner.values().collect()
+        let mut entries: Vec<&ModelEntry> = self.inner.values().collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries
     }
     pub fn list_all_available(&self) -> Vec<String> {
         let mut available = Vec::new();
@@ -94,6 +358,86 @@ impl Registry {
         available
     }
 
+    /// Every available model in `sort` order. Ties, and models whose size or
+    /// modification time can't be read, keep name order.
+    pub fn list_sorted(&self, sort: ModelSort) -> Vec<String> {
+        use std::cmp::Reverse;
+
+        let mut names = self.list_all_available();
+        match sort {
+            ModelSort::Name => {}
+            ModelSort::Size => names.sort_by_cached_key(|name| Reverse(self.size_bytes(name))),
+            ModelSort::Recency => names.sort_by_cached_key(|name| Reverse(self.modified(name))),
+        }
+        names
+    }
+
+    /// On-disk size; discovery already summed the shards of split models
+    fn size_bytes(&self, name: &str) -> Option<u64> {
+        if let Some(discovered) = self.discovered_models.get(name) {
+            return Some(discovered.size_bytes);
+        }
+        let entry = self.inner.get(name)?;
+        std::fs::metadata(&entry.base_path).ok().map(|m| m.len())
+    }
+
+    fn modified(&self, name: &str) -> Option<std::time::SystemTime> {
+        let path = match self.inner.get(name) {
+            Some(entry) => &entry.base_path,
+            None => &self.discovered_models.get(name)?.path,
+        };
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Suggest up to three available model names close to `name`.
+    ///
+    /// Names are compared after normalization (lowercase, separators removed),
+    /// so `llama3` matches `llama-3-8b-instruct` by prefix; otherwise the
+    /// closest names by edit distance are returned.
+    pub fn suggest_similar(&self, name: &str) -> Vec<String> {
+        let query = normalize_model_name(name);
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let max_distance = (query.len() / 3).max(2);
+
+        let mut scored: Vec<(usize, String)> = self
+            .list_all_available()
+            .into_iter()
+            .filter_map(|candidate| {
+                let normalized = normalize_model_name(&candidate);
+                let score = if normalized.starts_with(&query) || query.starts_with(&normalized) {
+                    0
+                } else if normalized.contains(&query) {
+                    1
+                } else {
+                    let distance = edit_distance(&query, &normalized);
+                    if distance > max_distance {
+                        return None;
+                    }
+                    distance + 1
+                };
+                Some((score, candidate))
+            })
+            .collect();
+        scored.sort();
+        scored.into_iter().take(3).map(|(_, name)| name).collect()
+    }
+
+    /// Human-readable "model not found" message with suggestions when available
+    pub fn model_not_found_message(&self, name: &str) -> String {
+        let suggestions = self.suggest_similar(name);
+        if suggestions.is_empty() {
+            format!("Model '{}' not found", name)
+        } else {
+            format!(
+                "Model '{}' not found. Did you mean: {}?",
+                name,
+                suggestions.join(", ")
+            )
+        }
+    }
+
     pub fn to_spec(&self, name: &str) -> Option<ModelSpec> {
         // Try manually registered first
         if let Some(e) = self.inner.get(name) {
@@ -113,14 +457,139 @@ impl Registry {
                 name: discovered.name.clone(),
                 base_path: discovered.path.clone(),
                 lora_path: discovered.lora_path.clone(),
-                template: Some(self.infer_template(&discovered.name)),
-                ctx_len: 4096,
-                n_threads: None,
+                template: Some(
+                    discovered
+                        .overrides
+                        .template
+                        .clone()
+                        .unwrap_or_else(|| self.infer_discovered_template(discovered)),
+                ),
+                ctx_len: discovered.overrides.ctx_len.unwrap_or(4096),
+                n_threads: discovered.overrides.n_threads,
             });
         }
 
         None
     }
+
+    /// Stop sequences the user configured for a discovered model
+    pub fn stop_tokens(&self, name: &str) -> Vec<String> {
+        self.discovered_models
+            .get(name)
+            .map(|d| d.overrides.stop.clone())
+            .unwrap_or_default()
+    }
+
+    /// Persist overrides for a discovered model to `model_overrides.json` and
+    /// apply them immediately. Model add/alias operations should go through
+    /// here so the settings survive the next discovery run.
+    pub fn save_override(
+        &mut self,
+        name: &str,
+        value: crate::model_overrides::ModelOverride,
+    ) -> anyhow::Result<()> {
+        let path = self.overrides_path();
+        let Some(discovered) = self.discovered_models.get_mut(name) else {
+            anyhow::bail!("Model '{}' was not discovered", name);
+        };
+        crate::model_overrides::ModelOverrides::load(&path).set(&discovered.path, value.clone())?;
+        discovered.overrides = value.clone();
+        if let Some(entry) = self.inner.get_mut(name) {
+            if let Some(template) = value.template {
+                entry.template = Some(template);
+            }
+            if let Some(ctx_len) = value.ctx_len {
+                entry.ctx_len = Some(ctx_len);
+            }
+            if value.n_threads.is_some() {
+                entry.n_threads = value.n_threads;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn normalize_model_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+/// Tags for a discovered model file: those implied by its GGUF header (see
+/// `tags_from_gguf`) followed by any extra ones its name suggests
+pub fn tags_for_file(path: &Path, name: &str) -> Vec<String> {
+    let mut tags = crate::engine::gguf::read_gguf_info(path)
+        .map(|info| tags_from_gguf(&info))
+        .unwrap_or_default();
+    for tag in tags_from_name(name) {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Purpose tags implied by GGUF metadata: `code` for code architectures
+/// (`general.architecture`), `vision` and `embedding` from the model's
+/// encoders, and `chat` when the file ships a chat template
+pub fn tags_from_gguf(info: &GgufInfo) -> Vec<String> {
+    const CODE_ARCHS: &[&str] = &["starcoder", "starcoder2", "refact", "codeshell"];
+
+    let mut tags = Vec::new();
+    if info
+        .architecture()
+        .is_some_and(|arch| CODE_ARCHS.contains(&arch))
+    {
+        tags.push("code".to_string());
+    }
+    if info.has_vision() {
+        tags.push("vision".to_string());
+    }
+    if info.is_embedding() {
+        tags.push("embedding".to_string());
+    }
+    if info.metadata_str("tokenizer.chat_template").is_some() {
+        tags.push("chat".to_string());
+    }
+    tags
+}
+
+/// Purpose tags (`code`, `vision`, `embedding`, `chat`) suggested by a model
+/// or file name
+pub fn tags_from_name(model_name: &str) -> Vec<String> {
+    let name_lower = model_name.to_lowercase();
+    let mut tags = Vec::new();
+
+    if name_lower.contains("code") || name_lower.contains("coder") {
+        tags.push("code".to_string());
+    }
+    if name_lower.contains("llava") || name_lower.contains("vision") || name_lower.contains("-vl") {
+        tags.push("vision".to_string());
+    }
+    if name_lower.contains("embed") {
+        tags.push("embedding".to_string());
+    }
+    if name_lower.contains("instruct") || name_lower.contains("chat") {
+        tags.push("chat".to_string());
+    }
+
+    tags
 }
 
 #[cfg(test)]
@@ -150,6 +619,7 @@ mod tests {
             template: Some("chatml".to_string()),
             ctx_len: Some(4096),
             n_threads: Some(4),
+            tags: Vec::new(),
         };
 
         registry.register(entry.clone());
@@ -160,18 +630,390 @@ mod tests {
     #[test]
     fn test_list_models() {
         let mut registry = Registry::new();
-        let entry = ModelEntry {
-            name: "test".to_string(),
-            base_path: PathBuf::from("/test"),
-            lora_path: None,
-            template: None,
-            ctx_len: None,
-            n_threads: None,
-        };
+        let entry = ModelEntry::new("test", PathBuf::from("/test"));
 
         registry.register(entry);
         let models = registry.list();
         assert_eq!(models.len(), 1);
         assert_eq!(models[0].name, "test");
     }
+
+    fn tagged_entry(name: &str, tags: &[&str], ctx_len: Option<usize>) -> ModelEntry {
+        ModelEntry {
+            name: name.to_string(),
+            base_path: PathBuf::from(format!("/models/{}.gguf", name)),
+            lora_path: None,
+            template: None,
+            ctx_len,
+            n_threads: None,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_capabilities_from_tags_and_gguf_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let embed_path = dir.path().join("e5.gguf");
+        std::fs::write(
+            &embed_path,
+            crate::engine::gguf::tests::synthetic_gguf(
+                &[("general.architecture", "bert")],
+                &[],
+                &[("w", 32)],
+            ),
+        )
+        .unwrap();
+
+        let mut registry = Registry::new();
+        registry.register(tagged_entry("llava-7b", &["vision"], None));
+        registry.register(tagged_entry("phi3", &[], None));
+        let mut e5 = tagged_entry("e5", &[], None);
+        e5.base_path = embed_path;
+        registry.register(e5);
+
+        assert_eq!(
+            registry.capabilities("llava-7b"),
+            vec![ModelCapability::Generation, ModelCapability::Vision]
+        );
+        assert_eq!(
+            registry.capabilities("phi3"),
+            vec![ModelCapability::Generation]
+        );
+        assert_eq!(
+            registry.capabilities("e5"),
+            vec![ModelCapability::Embedding]
+        );
+        assert_eq!(
+            ModelCapability::from_name("vision"),
+            Some(ModelCapability::Vision)
+        );
+        assert!(ModelCapability::from_name("audio").is_none());
+    }
+
+    #[test]
+    fn test_resolve_auto_tag_picks_tagged_model() {
+        let mut registry = Registry::new();
+        registry.register(tagged_entry("general", &["chat"], Some(8192)));
+        registry.register(tagged_entry("coder-small", &["code"], Some(4096)));
+        registry.register(tagged_entry("coder-large", &["code"], Some(16384)));
+
+        assert_eq!(
+            registry.resolve_model_name("auto:code", None).as_deref(),
+            Some("coder-large")
+        );
+        assert_eq!(
+            registry.resolve_model_name("auto:CODE", None).as_deref(),
+            Some("coder-large")
+        );
+    }
+
+    #[test]
+    fn test_resolve_auto_tag_covers_discovered_models() {
+        let mut registry = Registry::new();
+        registry.register(tagged_entry("general", &["chat"], None));
+        let mut coder = discovered("starcoder2-7b", "Llama");
+        coder.tags = vec!["code".to_string()];
+        registry.discovered_models.insert(coder.name.clone(), coder);
+
+        // Listed by `?tag=code`, so `auto:code` must find it too
+        assert_eq!(registry.tags("starcoder2-7b"), vec!["code"]);
+        assert_eq!(
+            registry.resolve_model_name("auto:code", None).as_deref(),
+            Some("starcoder2-7b")
+        );
+    }
+
+    #[test]
+    fn test_tags_for_file_reads_gguf_architecture() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.gguf");
+        std::fs::write(
+            &path,
+            crate::engine::gguf::tests::synthetic_gguf(
+                &[
+                    ("general.architecture", "starcoder2"),
+                    ("tokenizer.chat_template", "{{ messages }}"),
+                ],
+                &[],
+                &[("w", 32)],
+            ),
+        )
+        .unwrap();
+
+        // The name says nothing about code; the architecture does
+        assert_eq!(tags_for_file(&path, "my-model"), vec!["code", "chat"]);
+        assert_eq!(
+            tags_for_file(&path, "my-vision-model"),
+            vec!["code", "chat", "vision"]
+        );
+        // Non-GGUF files fall back to the name
+        assert_eq!(
+            tags_for_file(&dir.path().join("coder.safetensors"), "coder"),
+            vec!["code"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_auto_tag_without_match() {
+        let mut registry = Registry::new();
+        registry.register(tagged_entry("general", &["chat"], None));
+
+        assert!(registry.resolve_model_name("auto:code", None).is_none());
+    }
+
+    #[test]
+    fn test_resolve_plain_and_default_names() {
+        let mut registry = Registry::new();
+        registry.register(tagged_entry("b-model", &[], None));
+        registry.register(tagged_entry("a-model", &[], None));
+
+        assert_eq!(
+            registry.resolve_model_name("b-model", None).as_deref(),
+            Some("b-model")
+        );
+        assert_eq!(
+            registry.resolve_model_name("autocoder", None).as_deref(),
+            Some("autocoder")
+        );
+        // `auto` is the default model: the configured one, else the only one
+        assert_eq!(
+            registry
+                .resolve_model_name("auto", Some("b-model"))
+                .as_deref(),
+            Some("b-model")
+        );
+        assert_eq!(registry.resolve_model_name("auto", None), None);
+        assert_eq!(registry.resolve_model_name("auto:", None), None);
+
+        let mut single = Registry::new();
+        single.register(tagged_entry("only", &[], None));
+        assert_eq!(
+            single.resolve_model_name("auto:", None).as_deref(),
+            Some("only")
+        );
+    }
+
+    #[test]
+    fn test_validate_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.gguf");
+        std::fs::write(&path, b"").unwrap();
+
+        let mut entry = tagged_entry("ok", &[], None);
+        entry.base_path = path;
+        entry.template = Some("chatml".to_string());
+        assert!(Registry::validate_entry(&entry).is_ok());
+
+        entry.template = Some("alpaca".to_string());
+        let err = Registry::validate_entry(&entry).unwrap_err();
+        assert!(err.contains("unknown template"));
+
+        let missing = tagged_entry("missing", &[], None);
+        let err = Registry::validate_entry(&missing).unwrap_err();
+        assert!(err.contains("file not found"));
+    }
+
+    #[test]
+    fn test_suggest_similar_near_miss() {
+        let mut registry = Registry::new();
+        registry.register(tagged_entry("llama-3-8b-instruct", &[], None));
+        registry.register(tagged_entry("qwen2.5-coder-7b", &[], None));
+        registry.register(tagged_entry("phi3-mini", &[], None));
+
+        let suggestions = registry.suggest_similar("llama3");
+        assert_eq!(suggestions, vec!["llama-3-8b-instruct"]);
+
+        let suggestions = registry.suggest_similar("phi-3-mni");
+        assert_eq!(suggestions, vec!["phi3-mini"]);
+
+        let message = registry.model_not_found_message("llama3");
+        assert!(message.contains("Did you mean: llama-3-8b-instruct?"));
+    }
+
+    #[test]
+    fn test_suggest_similar_unrelated_name() {
+        let mut registry = Registry::new();
+        registry.register(tagged_entry("llama-3-8b-instruct", &[], None));
+        registry.register(tagged_entry("phi3-mini", &[], None));
+
+        assert!(registry.suggest_similar("stable-diffusion").is_empty());
+        assert_eq!(
+            registry.model_not_found_message("stable-diffusion"),
+            "Model 'stable-diffusion' not found"
+        );
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn test_infer_tags() {
+        let registry = Registry::new();
+        assert_eq!(registry.infer_tags("qwen2.5-coder-7b"), vec!["code"]);
+        assert_eq!(registry.infer_tags("llava-1.6"), vec!["vision"]);
+        assert!(registry.infer_tags("phi3-mini").is_empty());
+    }
+
+    fn discovered(name: &str, model_type: &str) -> DiscoveredModel {
+        DiscoveredModel {
+            name: name.to_string(),
+            path: PathBuf::from(format!("/models/{}.safetensors", name)),
+            lora_path: None,
+            size_bytes: 0,
+            model_type: model_type.to_string(),
+            parameter_count: None,
+            quantization: None,
+            display_name: None,
+            overrides: Default::default(),
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_listing_order_is_stable_and_sortable_by_size() {
+        let mut registry = Registry::new();
+        for (name, size) in [
+            ("mistral-7b", 4_000),
+            ("tinyllama", 600),
+            ("llama-70b", 40_000),
+            ("phi3-mini", 2_000),
+            ("gemma-2b", 2_000),
+        ] {
+            let mut model = discovered(name, "Llama");
+            model.size_bytes = size;
+            registry.discovered_models.insert(name.to_string(), model);
+        }
+
+        let first = registry.list_all_available();
+        for _ in 0..10 {
+            assert_eq!(registry.list_all_available(), first);
+        }
+        assert_eq!(registry.list_sorted(ModelSort::Name), first);
+        assert_eq!(
+            first,
+            vec![
+                "gemma-2b",
+                "llama-70b",
+                "mistral-7b",
+                "phi3-mini",
+                "tinyllama"
+            ]
+        );
+
+        // Largest first; equal sizes keep name order
+        assert_eq!(
+            registry.list_sorted(ModelSort::Size),
+            vec![
+                "llama-70b",
+                "mistral-7b",
+                "gemma-2b",
+                "phi3-mini",
+                "tinyllama"
+            ]
+        );
+        assert_eq!(ModelSort::from_name("size"), Some(ModelSort::Size));
+        assert_eq!(ModelSort::from_name("popularity"), None);
+    }
+
+    #[test]
+    fn test_discovered_template_follows_model_family() {
+        let mut registry = Registry::new();
+        for (name, model_type) in [
+            ("my-finetune", "Gemma"),
+            ("qwen2.5-7b", "Qwen"),
+            ("Phi-3-mini", "Phi"),
+            ("mistral-7b", "Mistral"),
+        ] {
+            registry
+                .discovered_models
+                .insert(name.to_string(), discovered(name, model_type));
+        }
+        // Override wins over the inferred family
+        let mut overridden = discovered("gemma-custom", "Gemma");
+        overridden.overrides.template = Some("chatml".to_string());
+        registry
+            .discovered_models
+            .insert("gemma-custom".to_string(), overridden);
+
+        // GGUF entries are typed by backend, so the name decides
+        let mut gguf = discovered("phi-3-mini-q4", "Llama");
+        gguf.path = PathBuf::from("/models/phi-3-mini-q4.gguf");
+        registry
+            .discovered_models
+            .insert("phi-3-mini-q4".to_string(), gguf);
+
+        let template = |name: &str| registry.to_spec(name).unwrap().template.unwrap();
+        assert_eq!(template("my-finetune"), "gemma");
+        assert_eq!(template("qwen2.5-7b"), "chatml");
+        assert_eq!(template("Phi-3-mini"), "phi3");
+        assert_eq!(template("mistral-7b"), "chatml");
+        assert_eq!(template("gemma-custom"), "chatml");
+        assert_eq!(template("phi-3-mini-q4"), "phi3");
+
+        registry.auto_register_discovered();
+        assert_eq!(
+            registry.get("my-finetune").unwrap().template.as_deref(),
+            Some("gemma")
+        );
+    }
+
+    #[test]
+    fn test_save_override_applies_and_persists() {
+        use crate::model_overrides::{ModelOverride, ModelOverrides};
+
+        let dir = tempfile::tempdir().unwrap();
+        let overrides_path = dir.path().join("model_overrides.json");
+        let mut registry = Registry::new().with_overrides_path(overrides_path.clone());
+        let model_path = dir.path().join("mistral-7b.safetensors");
+        registry.discovered_models.insert(
+            "mistral-7b".to_string(),
+            DiscoveredModel {
+                name: "mistral-7b".to_string(),
+                path: model_path.clone(),
+                lora_path: None,
+                size_bytes: 0,
+                model_type: "SafeTensors".to_string(),
+                parameter_count: None,
+                quantization: None,
+                display_name: None,
+                overrides: Default::default(),
+                tags: Vec::new(),
+            },
+        );
+        assert_eq!(
+            registry.to_spec("mistral-7b").unwrap().template.as_deref(),
+            Some("chatml")
+        );
+
+        registry
+            .save_override(
+                "mistral-7b",
+                ModelOverride {
+                    template: Some("llama3".to_string()),
+                    n_threads: Some(6),
+                    stop: vec!["###".to_string()],
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let spec = registry.to_spec("mistral-7b").unwrap();
+        assert_eq!(spec.template.as_deref(), Some("llama3"));
+        assert_eq!(spec.n_threads, Some(6));
+        assert_eq!(spec.ctx_len, 4096);
+        assert_eq!(registry.stop_tokens("mistral-7b"), vec!["###".to_string()]);
+        let stored = ModelOverrides::load(&overrides_path);
+        assert_eq!(
+            stored.get(&model_path).unwrap().template.as_deref(),
+            Some("llama3")
+        );
+
+        assert!(registry
+            .save_override("missing", ModelOverride::default())
+            .is_err());
+    }
 }
@@ -0,0 +1,167 @@
+// Model list annotated with live load state
+//
+// `shimmy list --remote <addr>` asks a running server for its models
+// (`/api/models`) and for what is resident in its model pool (`/health`),
+// then joins the two so operators can see which models are loaded and how
+// much memory they hold without attaching to the server process.
+
+use crate::api::ModelListResponse;
+use crate::observability::LoadedModelStatus;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// One model on a running server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelSnapshot {
+    pub name: String,
+    /// "registered", "discovered", or "loaded" for pool entries the server
+    /// no longer lists
+    pub source: String,
+    pub size_bytes: Option<u64>,
+    pub loaded: bool,
+    pub estimated_memory_bytes: Option<u64>,
+    pub access_count: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HealthLoaded {
+    #[serde(default)]
+    loaded_models: Vec<LoadedModelStatus>,
+}
+
+/// Join the `/api/models` and `/health` bodies into one list, sorted by name
+pub fn annotate(
+    models: &serde_json::Value,
+    health: &serde_json::Value,
+) -> Result<Vec<ModelSnapshot>> {
+    let models: ModelListResponse =
+        serde_json::from_value(models.clone()).context("parsing /api/models response")?;
+    let health: HealthLoaded =
+        serde_json::from_value(health.clone()).context("parsing /health response")?;
+
+    let mut snapshot: Vec<ModelSnapshot> = models
+        .models
+        .into_iter()
+        .map(|model| ModelSnapshot {
+            name: model.name,
+            source: model.source,
+            size_bytes: model.size_bytes,
+            loaded: false,
+            estimated_memory_bytes: None,
+            access_count: None,
+        })
+        .collect();
+
+    for status in health.loaded_models {
+        let entry = match snapshot.iter_mut().find(|m| m.name == status.name) {
+            Some(entry) => entry,
+            None => {
+                snapshot.push(ModelSnapshot {
+                    name: status.name.clone(),
+                    source: "loaded".to_string(),
+                    size_bytes: None,
+                    loaded: false,
+                    estimated_memory_bytes: None,
+                    access_count: None,
+                });
+                snapshot.last_mut().expect("just pushed")
+            }
+        };
+        entry.loaded = true;
+        entry.estimated_memory_bytes = Some(status.estimated_memory_bytes);
+        entry.access_count = Some(status.access_count);
+    }
+
+    snapshot.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(snapshot)
+}
+
+/// `addr` as a base URL; bare `host:port` means plain HTTP
+pub fn base_url(addr: &str) -> String {
+    let addr = addr.trim_end_matches('/');
+    if addr.starts_with("http://") || addr.starts_with("https://") {
+        addr.to_string()
+    } else {
+        format!("http://{}", addr)
+    }
+}
+
+/// Query a running shimmy at `addr` for its annotated model list
+pub async fn fetch(addr: &str) -> Result<Vec<ModelSnapshot>> {
+    let base = base_url(addr);
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?;
+    let get = |path: &str| {
+        let url = format!("{}{}", base, path);
+        let request = client.get(&url);
+        async move {
+            request
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .with_context(|| format!("requesting {}", url))?
+                .json::<serde_json::Value>()
+                .await
+                .with_context(|| format!("reading {}", url))
+        }
+    };
+    let models = get("/api/models").await?;
+    let health = get("/health").await?;
+    annotate(&models, &health)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_annotate_marks_loaded_models() {
+        let models = json!({"models": [
+            {"name": "phi3", "size_bytes": 2000, "model_type": "llm", "parameter_count": "3B", "source": "discovered"},
+            {"name": "llama", "size_bytes": null, "model_type": null, "parameter_count": null, "source": "registered"}
+        ]});
+        let health = json!({
+            "status": "ok",
+            "loaded_models": [
+                {"name": "phi3", "estimated_memory_bytes": 2048, "loaded_at": 1, "last_accessed": 2, "access_count": 5},
+                {"name": "direct", "estimated_memory_bytes": 10, "loaded_at": 1, "last_accessed": 1, "access_count": 1}
+            ]
+        });
+
+        let snapshot = annotate(&models, &health).unwrap();
+        let names: Vec<&str> = snapshot.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["direct", "llama", "phi3"]);
+
+        assert!(snapshot[2].loaded);
+        assert_eq!(snapshot[2].estimated_memory_bytes, Some(2048));
+        assert_eq!(snapshot[2].access_count, Some(5));
+        assert_eq!(snapshot[2].size_bytes, Some(2000));
+
+        assert!(!snapshot[1].loaded);
+        assert_eq!(snapshot[1].estimated_memory_bytes, None);
+
+        assert!(snapshot[0].loaded);
+        assert_eq!(snapshot[0].source, "loaded");
+    }
+
+    #[test]
+    fn test_annotate_tolerates_missing_pool_and_rejects_bad_models() {
+        let models = json!({"models": [
+            {"name": "phi3", "size_bytes": 1, "model_type": null, "parameter_count": null, "source": "discovered"}
+        ]});
+        let snapshot = annotate(&models, &json!({"status": "ok"})).unwrap();
+        assert_eq!(snapshot.len(), 1);
+        assert!(!snapshot[0].loaded);
+
+        assert!(annotate(&json!({"error": "nope"}), &json!({})).is_err());
+    }
+
+    #[test]
+    fn test_base_url() {
+        assert_eq!(base_url("127.0.0.1:11435"), "http://127.0.0.1:11435");
+        assert_eq!(base_url("https://shimmy.local/"), "https://shimmy.local");
+    }
+}
@@ -53,10 +53,18 @@ pub struct SystemMetrics {
     pub failed_requests: u64,
     pub average_response_time: f64,
     pub current_requests: u32,
+    /// Requests abandoned by their client while the backend was generating
+    #[serde(default)]
+    pub cancelled_requests: u64,
 
     // Model metrics
     pub model_stats: HashMap<String, ModelMetrics>,
 
+    /// Requests per user identity (see `audit::request_identity`), bounded
+    /// to the busiest `MAX_TRACKED_USERS` with the rest under `OTHER_USERS`
+    #[serde(default)]
+    pub user_requests: HashMap<String, u64>,
+
     // Resource metrics
     pub memory_usage_mb: f64,
     pub cpu_usage_percent: f64,
@@ -71,6 +79,10 @@ pub struct SystemMetrics {
     pub preloaded_models: u32,
     pub preload_hit_rate: f64,
 
+    // Model pool metrics
+    pub model_evictions: u64,
+    pub loaded_models: Vec<LoadedModelStatus>,
+
     // System health
     pub uptime_seconds: u64,
     pub last_updated: u64,
@@ -86,10 +98,38 @@ pub struct ModelMetrics {
     pub popularity_score: f64,
 }
 
+/// A model currently resident in the model pool
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LoadedModelStatus {
+    pub name: String,
+    /// Size of the weights (and any LoRA adapter) on disk, which llama.cpp
+    /// keeps resident in RAM or VRAM while the model is loaded, plus the KV
+    /// cache for GGUF models
+    pub estimated_memory_bytes: u64,
+    /// Unix timestamps in seconds
+    pub loaded_at: u64,
+    pub last_accessed: u64,
+    pub access_count: u64,
+}
+
+/// How many applied actions to keep for inspection
+const MAX_RECORDED_ACTIONS: usize = 100;
+
+/// Identities with their own `user_requests` entry, besides `OTHER_USERS`
+const MAX_TRACKED_USERS: usize = 100;
+
+/// `user_requests` bucket for identities squeezed out of the table
+pub const OTHER_USERS: &str = "other";
+
+/// Longest identity kept verbatim as a `user_requests` key; longer ones
+/// (the `user` field is client-supplied) are stored hashed
+const MAX_USER_KEY_LEN: usize = 64;
+
 #[derive(Debug)]
 struct OptimizationState {
     last_optimization: SystemTime,
     optimization_count: u32,
+    recorded_actions: Vec<OptimizationAction>,
 }
 
 impl Default for OptimizationState {
@@ -97,6 +137,7 @@ impl Default for OptimizationState {
         Self {
             last_optimization: SystemTime::UNIX_EPOCH,
             optimization_count: 0,
+            recorded_actions: Vec::new(),
         }
     }
 }
@@ -160,6 +201,57 @@ impl ObservabilityManager {
         );
     }
 
+    /// `CancelGuard` callback that records a cancelled request for
+    /// `model_name` on the current runtime
+    pub fn cancel_recorder(&self, model_name: &str) -> impl FnOnce() + Send + 'static {
+        let observability = self.clone();
+        let model_name = model_name.to_string();
+        move || {
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                runtime.spawn(async move { observability.record_cancelled(&model_name).await });
+            }
+        }
+    }
+
+    /// Record a request whose client disconnected mid-generation
+    pub async fn record_cancelled(&self, model_name: &str) {
+        self.metrics.write().await.cancelled_requests += 1;
+        info!(
+            "Cancelled generation for '{}': client went away",
+            model_name
+        );
+    }
+
+    /// Count a request against a user identity. Once `MAX_TRACKED_USERS`
+    /// are tracked, a new identity displaces the least active one, whose
+    /// count moves to `OTHER_USERS`.
+    pub async fn record_user_request(&self, identity: &str) {
+        let key = if identity.len() > MAX_USER_KEY_LEN {
+            crate::audit::redact_identity(identity)
+        } else {
+            identity.to_string()
+        };
+        let mut metrics = self.metrics.write().await;
+        let users = &mut metrics.user_requests;
+        let tracked = users.len() - usize::from(users.contains_key(OTHER_USERS));
+        if !users.contains_key(&key) && tracked >= MAX_TRACKED_USERS {
+            let least_active = users
+                .iter()
+                .filter(|(name, _)| *name != OTHER_USERS)
+                .min_by_key(|(_, requests)| **requests)
+                .map(|(name, _)| name.clone());
+            if let Some(name) = least_active {
+                let requests = users.remove(&name).unwrap_or_default();
+                *users.entry(OTHER_USERS.to_string()).or_default() += requests;
+            }
+        }
+        *users.entry(key).or_default() += 1;
+        debug!(
+            "Recorded request for {}",
+            crate::audit::redact_identity(identity)
+        );
+    }
+
     /// Update system resource metrics
     pub async fn update_system_metrics(&self) {
         let mut metrics = self.metrics.write().await;
@@ -191,6 +283,37 @@ impl ObservabilityManager {
         metrics.preload_hit_rate = hit_rate;
     }
 
+    /// Replace the snapshot of models resident in the pool
+    pub async fn update_loaded_models(&self, models: Vec<LoadedModelStatus>) {
+        self.metrics.write().await.loaded_models = models;
+    }
+
+    /// Record that the model pool unloaded an idle model
+    pub async fn record_idle_unload(&self, model_name: &str, idle: Duration) {
+        self.metrics.write().await.model_evictions += 1;
+
+        let mut state = self.optimization_state.write().await;
+        state
+            .recorded_actions
+            .push(OptimizationAction::UnloadIdleModel {
+                model: model_name.to_string(),
+                idle_secs: idle.as_secs(),
+            });
+        if state.recorded_actions.len() > MAX_RECORDED_ACTIONS {
+            state.recorded_actions.remove(0);
+        }
+        info!("Unloaded idle model '{}' after {:?}", model_name, idle);
+    }
+
+    /// Actions applied automatically (e.g. idle unloads), oldest first
+    pub async fn recorded_actions(&self) -> Vec<OptimizationAction> {
+        self.optimization_state
+            .read()
+            .await
+            .recorded_actions
+            .clone()
+    }
+
     /// Perform self-optimization based on observed metrics
     pub async fn optimize_system(&self) -> Result<Vec<OptimizationAction>> {
         if !self.config.optimization_enabled {
@@ -211,11 +334,15 @@ impl ObservabilityManager {
             return Ok(actions);
         }
 
+        // Metrics behind this round's decisions, attached to every action
+        let context = OptimizationContext::from_metrics(&metrics);
+
         // Memory optimization
         if metrics.memory_usage_mb > 4096.0 && metrics.cache_size_mb > 1024.0 {
             actions.push(OptimizationAction::ReduceCacheSize {
                 current_mb: metrics.cache_size_mb,
                 recommended_mb: 512.0,
+                context: context.clone(),
             });
         }
 
@@ -231,6 +358,7 @@ impl ObservabilityManager {
         if !popular_models.is_empty() {
             actions.push(OptimizationAction::UpdatePreloadList {
                 models: popular_models,
+                context: context.clone(),
             });
         }
 
@@ -239,13 +367,20 @@ impl ObservabilityManager {
             actions.push(OptimizationAction::TunePerformance {
                 issue: "High response times detected".to_string(),
                 recommendation: "Consider increasing preloaded models or optimizing model selection".to_string(),
+                context: context.clone(),
             });
         }
 
         optimization_state.last_optimization = SystemTime::now();
         optimization_state.optimization_count += 1;
 
-        info!("Generated {} optimization actions", actions.len());
+        if !actions.is_empty() {
+            info!(
+                "Generated {} optimization actions ({})",
+                actions.len(),
+                context
+            );
+        }
         Ok(actions)
     }
 
@@ -281,6 +416,11 @@ impl ObservabilityManager {
             metrics.average_response_time
         ));
 
+        output.push_str(&format!(
+            "shimmy_requests_cancelled_total {}\n",
+            metrics.cancelled_requests
+        ));
+
         // Resource metrics
         output.push_str(&format!(
             "shimmy_memory_usage_mb {}\n",
@@ -296,6 +436,12 @@ impl ObservabilityManager {
         output.push_str(&format!("shimmy_cache_misses {}\n", metrics.cache_misses));
         output.push_str(&format!("shimmy_cache_size_mb {}\n", metrics.cache_size_mb));
 
+        // Model pool metrics
+        output.push_str(&format!(
+            "shimmy_model_evictions_total {}\n",
+            metrics.model_evictions
+        ));
+
         // Model-specific metrics
         for (model, stats) in &metrics.model_stats {
             output.push_str(&format!(
@@ -312,6 +458,16 @@ impl ObservabilityManager {
             ));
         }
 
+        for (user, requests) in &metrics.user_requests {
+            output.push_str(&format!(
+                "shimmy_user_requests{{user=\"{}\"}} {}\n",
+                user.replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n"),
+                requests
+            ));
+        }
+
         output
     }
 
@@ -392,6 +548,23 @@ impl ObservabilityManager {
         self.metrics.read().await.clone()
     }
 
+    /// Write the current snapshot, per-model stats included, to `path` as
+    /// JSON. Written to a temporary file first so readers never see half a
+    /// dump.
+    pub async fn write_snapshot(&self, path: &std::path::Path) -> Result<()> {
+        let mut metrics = self.metrics().await;
+        metrics.last_updated = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = std::path::PathBuf::from(tmp);
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&metrics)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
     // Simplified system monitoring methods (placeholders)
     async fn memory_usage(&self) -> f64 {
         // In production, this would use proper system APIs
@@ -420,18 +593,73 @@ impl Clone for ObservabilityManager {
     }
 }
 
-#[derive(Debug, Serialize)]
+/// Compact snapshot of the metrics an optimization decision was based on
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OptimizationContext {
+    pub memory_usage_mb: f64,
+    pub cache_size_mb: f64,
+    pub average_response_time_ms: f64,
+    /// Most popular models, highest score first
+    pub top_models: Vec<String>,
+}
+
+impl OptimizationContext {
+    /// How many models `top_models` keeps
+    const TOP_MODELS: usize = 3;
+
+    fn from_metrics(metrics: &SystemMetrics) -> Self {
+        let mut models: Vec<_> = metrics.model_stats.iter().collect();
+        models.sort_by(|a, b| {
+            b.1.popularity_score
+                .partial_cmp(&a.1.popularity_score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.cmp(b.0))
+        });
+        Self {
+            memory_usage_mb: metrics.memory_usage_mb,
+            cache_size_mb: metrics.cache_size_mb,
+            average_response_time_ms: metrics.average_response_time,
+            top_models: models
+                .into_iter()
+                .take(Self::TOP_MODELS)
+                .map(|(name, _)| name.clone())
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for OptimizationContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "memory={:.0}MB cache={:.0}MB avg_latency={:.0}ms top=[{}]",
+            self.memory_usage_mb,
+            self.cache_size_mb,
+            self.average_response_time_ms,
+            self.top_models.join(", ")
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub enum OptimizationAction {
     ReduceCacheSize {
         current_mb: f64,
         recommended_mb: f64,
+        context: OptimizationContext,
     },
     UpdatePreloadList {
         models: Vec<String>,
+        context: OptimizationContext,
     },
     TunePerformance {
         issue: String,
         recommendation: String,
+        context: OptimizationContext,
+    },
+    UnloadIdleModel {
+        model: String,
+        idle_secs: u64,
     },
 }
 
@@ -454,6 +682,18 @@ mod tests {
         assert!(metrics.model_stats.contains_key("phi3-mini"));
     }
 
+    #[tokio::test]
+    async fn test_cancelled_requests_are_counted() {
+        let obs = ObservabilityManager::new();
+        obs.record_cancelled("minicpm-v").await;
+
+        assert_eq!(obs.metrics().await.cancelled_requests, 1);
+        assert!(obs
+            .export_metrics()
+            .await
+            .contains("shimmy_requests_cancelled_total 1"));
+    }
+
     #[tokio::test]
     async fn test_metrics_export() {
         let obs = ObservabilityManager::new();
@@ -479,4 +719,78 @@ mod tests {
         let actions = obs.optimize_system().await.unwrap();
         assert!(!actions.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_reduce_cache_action_carries_triggering_metrics() {
+        let obs = ObservabilityManager::new();
+        obs.record_request("phi3", Duration::from_millis(50), true)
+            .await;
+        obs.update_cache_metrics(10, 2, 2048.0).await;
+        obs.metrics.write().await.memory_usage_mb = 6144.0;
+
+        let actions = obs.optimize_system().await.unwrap();
+        let context = actions
+            .iter()
+            .find_map(|action| match action {
+                OptimizationAction::ReduceCacheSize {
+                    current_mb,
+                    context,
+                    ..
+                } => {
+                    assert_eq!(*current_mb, 2048.0);
+                    Some(context)
+                }
+                _ => None,
+            })
+            .expect("memory pressure should suggest shrinking the cache");
+        assert_eq!(context.cache_size_mb, 2048.0);
+        assert_eq!(context.memory_usage_mb, 6144.0);
+        assert_eq!(context.top_models, vec!["phi3".to_string()]);
+        assert_eq!(
+            context.to_string(),
+            "memory=6144MB cache=2048MB avg_latency=50ms top=[phi3]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_user_requests_are_bounded() {
+        let obs = ObservabilityManager::new();
+        for _ in 0..3 {
+            obs.record_user_request("heavy").await;
+        }
+        for i in 0..MAX_TRACKED_USERS + 50 {
+            obs.record_user_request(&format!("user-{}", i)).await;
+        }
+        let long = "x".repeat(10_000);
+        obs.record_user_request(&long).await;
+
+        let users = obs.metrics().await.user_requests;
+        assert_eq!(users.len(), MAX_TRACKED_USERS + 1);
+        assert_eq!(users.get("heavy"), Some(&3));
+        assert_eq!(
+            users.values().sum::<u64>(),
+            3 + MAX_TRACKED_USERS as u64 + 51
+        );
+        assert!(users[OTHER_USERS] > 0);
+        assert!(users.keys().all(|k| k.len() <= MAX_USER_KEY_LEN));
+    }
+
+    #[tokio::test]
+    async fn test_idle_unload_is_recorded() {
+        let obs = ObservabilityManager::new();
+
+        obs.record_idle_unload("big-model", Duration::from_secs(600))
+            .await;
+
+        assert_eq!(obs.metrics().await.model_evictions, 1);
+        let actions = obs.recorded_actions().await;
+        assert!(matches!(
+            &actions[..],
+            [OptimizationAction::UnloadIdleModel { model, idle_secs: 600 }] if model == "big-model"
+        ));
+        assert!(obs
+            .export_metrics()
+            .await
+            .contains("shimmy_model_evictions_total 1"));
+    }
 }
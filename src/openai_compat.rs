@@ -1,12 +1,19 @@
 #![allow(dead_code)]
 
 use crate::{api::ChatMessage, AppState};
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::IntoResponse,
+    Json,
+};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct ChatCompletionRequest {
+    /// Omitted or empty selects the server's default model
+    #[serde(default)]
     pub model: String,
     pub messages: Vec<ChatMessage>,
     #[serde(default)]
@@ -15,10 +22,37 @@ pub struct ChatCompletionRequest {
     pub temperature: Option<f32>,
     #[serde(default)]
     pub max_tokens: Option<usize>,
+    /// Newer OpenAI name for `max_tokens`; wins when both are sent
+    #[serde(default)]
+    pub max_completion_tokens: Option<usize>,
     #[serde(default)]
     pub top_p: Option<f32>,
     #[serde(default)]
     pub stop: Option<StopTokens>,
+    /// Template family to use for this request instead of the model's own
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Strip a leading space/BOS token from the reply (default true)
+    #[serde(default)]
+    pub trim_leading: Option<bool>,
+    /// End-user identifier; keys per-user metrics
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Tokens to buffer per streamed event (default 1)
+    #[serde(default)]
+    pub stream_chunk_tokens: Option<usize>,
+    /// Report the applied sampling settings as `sampling_params` and in
+    /// `X-Shimmy-Params` (default: the server's `--echo-params`)
+    #[serde(default)]
+    pub include_sampling_params: Option<bool>,
+    /// Seconds the model stays loaded once idle; -1 forever, 0 unload after
+    /// this request (see `/api/generate`)
+    #[serde(default)]
+    pub keep_alive: Option<i64>,
+    /// Keep generating past the context window, dropping the oldest tokens
+    /// (see `/api/generate`)
+    #[serde(default)]
+    pub context_shift: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -28,6 +62,13 @@ pub enum StopTokens {
     Multiple(Vec<String>),
 }
 
+impl ChatCompletionRequest {
+    /// Requested completion budget, preferring `max_completion_tokens`
+    pub fn requested_max_tokens(&self) -> Option<usize> {
+        self.max_completion_tokens.or(self.max_tokens)
+    }
+}
+
 impl StopTokens {
     fn into_vec(self) -> Vec<String> {
         match self {
@@ -37,6 +78,223 @@ impl StopTokens {
     }
 }
 
+/// Message content: either a plain string or an array of typed parts
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+/// One element of an OpenAI content array
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+/// Wire format of a chat message before content parts are flattened
+#[derive(Debug, Deserialize)]
+pub struct RawChatMessage {
+    pub role: String,
+    pub content: MessageContent,
+}
+
+impl From<RawChatMessage> for ChatMessage {
+    fn from(raw: RawChatMessage) -> Self {
+        let (content, images) = match raw.content {
+            MessageContent::Text(text) => (text, Vec::new()),
+            MessageContent::Parts(parts) => {
+                let mut texts = Vec::new();
+                let mut images = Vec::new();
+                for part in parts {
+                    match part {
+                        ContentPart::Text { text } => texts.push(text),
+                        ContentPart::ImageUrl { image_url } => images.push(image_url.url),
+                    }
+                }
+                (texts.join("\n"), images)
+            }
+        };
+        ChatMessage {
+            role: raw.role,
+            content,
+            images,
+        }
+    }
+}
+
+/// Roles accepted in chat messages
+const CHAT_ROLES: &[&str] = &["system", "developer", "user", "assistant", "tool"];
+
+/// Check the raw `messages` array before deserializing it, so a malformed
+/// message gets a 400 naming its index rather than a generic parse error
+pub fn validate_messages(messages: Option<&serde_json::Value>) -> Result<(), String> {
+    use serde_json::Value;
+
+    let Some(messages) = messages else {
+        return Err("'messages' is required".to_string());
+    };
+    let Some(messages) = messages.as_array() else {
+        return Err("'messages' must be an array".to_string());
+    };
+    if messages.is_empty() {
+        return Err("'messages' must contain at least one message".to_string());
+    }
+
+    for (i, message) in messages.iter().enumerate() {
+        let Some(message) = message.as_object() else {
+            return Err(format!("messages[{}] must be an object", i));
+        };
+        match message.get("role") {
+            None | Some(Value::Null) => {
+                return Err(format!("messages[{}] is missing 'role'", i));
+            }
+            Some(Value::String(role)) if CHAT_ROLES.contains(&role.as_str()) => {}
+            Some(Value::String(role)) => {
+                return Err(format!(
+                    "messages[{}] has unknown role '{}'; expected one of: {}",
+                    i,
+                    role,
+                    CHAT_ROLES.join(", ")
+                ));
+            }
+            Some(_) => return Err(format!("messages[{}].role must be a string", i)),
+        }
+        match message.get("content") {
+            None | Some(Value::Null) => {
+                return Err(format!("messages[{}] is missing 'content'", i));
+            }
+            Some(Value::String(_)) => {}
+            Some(Value::Array(parts)) => {
+                for (j, part) in parts.iter().enumerate() {
+                    let valid = match part.get("type").and_then(Value::as_str) {
+                        Some("text") => part.get("text").is_some_and(Value::is_string),
+                        Some("image_url") => part
+                            .get("image_url")
+                            .and_then(|image| image.get("url"))
+                            .is_some_and(Value::is_string),
+                        _ => false,
+                    };
+                    if !valid {
+                        return Err(format!(
+                            "messages[{}].content[{}] must be a text or image_url part",
+                            i, j
+                        ));
+                    }
+                }
+            }
+            Some(_) => {
+                return Err(format!(
+                    "messages[{}].content must be a string or an array of content parts",
+                    i
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The image to send to the vision backend: the most recent one in the conversation
+fn latest_image(messages: &[ChatMessage]) -> Option<&str> {
+    messages
+        .iter()
+        .rev()
+        .find_map(|m| m.images.last())
+        .map(String::as_str)
+}
+
+/// Decode the payload of a `data:<mime>;base64,<data>` URL
+#[cfg(feature = "vision")]
+fn decode_data_url(url: &str) -> Result<Vec<u8>, String> {
+    use base64::Engine as _;
+
+    let rest = url
+        .strip_prefix("data:")
+        .ok_or_else(|| "Not a data URL".to_string())?;
+    let (meta, data) = rest
+        .split_once(',')
+        .ok_or_else(|| "Malformed data URL: missing ','".to_string())?;
+    if !meta.ends_with(";base64") {
+        return Err("Only base64-encoded data URLs are supported".to_string());
+    }
+    base64::engine::general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|e| format!("Failed to decode base64 image: {}", e))
+}
+
+/// Fetch or decode an image part and preprocess it for the vision backend
+#[cfg(feature = "vision")]
+async fn load_image_part(url: &str) -> Result<Vec<u8>, String> {
+    let raw = if url.starts_with("data:") {
+        decode_data_url(url)?
+    } else if url.starts_with("http://") || url.starts_with("https://") {
+        crate::vision::fetch_image_from_url(url)
+            .await
+            .map_err(|e| format!("Failed to fetch image from URL: {}", e))?
+    } else {
+        return Err("image_url must be a data: URL or an http(s) URL".to_string());
+    };
+    let cfg = crate::vision::preprocess_config_for_mode(None);
+    crate::vision::preprocess_image(&raw, &cfg)
+        .map(|img| img.bytes)
+        .map_err(|e| format!("Failed to preprocess image: {}", e))
+}
+
+/// Groups streamed tokens so each SSE event carries `size` of them
+/// (`stream_chunk_tokens`)
+struct TokenBatch {
+    size: usize,
+    pending: String,
+    count: usize,
+}
+
+impl TokenBatch {
+    fn new(size: usize) -> Self {
+        Self {
+            size: size.max(1),
+            pending: String::new(),
+            count: 0,
+        }
+    }
+
+    /// Add a token; returns the batch once it is full
+    fn push(&mut self, token: String) -> Option<String> {
+        self.pending.push_str(&token);
+        self.count += 1;
+        if self.count < self.size {
+            return None;
+        }
+        self.flush()
+    }
+
+    /// Whatever is buffered, if anything
+    fn flush(&mut self) -> Option<String> {
+        self.count = 0;
+        (!self.pending.is_empty()).then(|| std::mem::take(&mut self.pending))
+    }
+}
+
+/// Run text generation, or vision generation when an image payload is present
+async fn generate_chat(
+    loaded: &dyn crate::engine::LoadedModel,
+    image: Option<&[u8]>,
+    prompt: &str,
+    opts: crate::engine::GenOptions,
+    on_token: Option<Box<dyn FnMut(String) + Send>>,
+) -> anyhow::Result<(String, crate::engine::FinishReason)> {
+    match image {
+        Some(image) => loaded.generate_vision(image, prompt, opts, on_token).await,
+        None => loaded.generate_with_reason(prompt, opts, on_token).await,
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct ChatCompletionResponse {
     pub id: String,
@@ -45,6 +303,9 @@ pub struct ChatCompletionResponse {
     pub model: String,
     pub choices: Vec<Choice>,
     pub usage: Usage,
+    /// Applied sampling settings, when the request asked for them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampling_params: Option<crate::engine::SamplingReport>,
 }
 
 #[derive(Debug, Serialize)]
@@ -95,6 +356,9 @@ pub struct ListModel {
     pub object: String,
     pub created: u64,
     pub owned_by: String,
+    /// Purpose tags (`code`, `chat`, `vision`, ...) for grouping in UIs
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -111,12 +375,72 @@ pub struct Model {
     pub parent: Option<String>,
 }
 
-pub async fn models(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+/// Query parameters for `GET /v1/models`
+#[derive(Debug, Default, Deserialize)]
+pub struct ModelsQuery {
+    /// Only list models with this capability: `generation`, `vision` or `embedding`
+    pub capability: Option<String>,
+    /// Only list models carrying this tag (case-insensitive)
+    pub tag: Option<String>,
+    /// Listing order: `name` (default), `size` or `recency`
+    pub sort: Option<String>,
+}
+
+pub async fn models(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ModelsQuery>,
+) -> axum::response::Response {
+    use crate::model_registry::{ModelCapability, ModelSort};
+
+    let capability = match query.capability.as_deref() {
+        None => None,
+        Some(name) => match ModelCapability::from_name(name) {
+            Some(capability) => Some(capability),
+            None => {
+                let error_response = serde_json::json!({
+                    "error": {
+                        "message": format!("Unknown capability '{}'. Available capabilities: generation, vision, embedding", name),
+                        "type": "invalid_request_error",
+                        "param": "capability",
+                        "code": "invalid_capability"
+                    }
+                });
+                return (axum::http::StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+            }
+        },
+    };
+
+    let sort = match query.sort.as_deref() {
+        None => ModelSort::default(),
+        Some(name) => match ModelSort::from_name(name) {
+            Some(sort) => sort,
+            None => {
+                let error_response = serde_json::json!({
+                    "error": {
+                        "message": format!("Unknown sort key '{}'. Available keys: {}", name, ModelSort::NAMES.join(", ")),
+                        "type": "invalid_request_error",
+                        "param": "sort",
+                        "code": "invalid_sort"
+                    }
+                });
+                return (axum::http::StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+            }
+        },
+    };
+
     let models = state
         .registry
-        .list_all_available()
+        .list_sorted(sort)
         .into_iter()
-        .map(|name| ListModel {
+        .filter(|name| capability.is_none_or(|c| state.registry.capabilities(name).contains(&c)))
+        .map(|name| (state.registry.tags(&name), name))
+        .filter(|(tags, _)| {
+            query
+                .tag
+                .as_deref()
+                .is_none_or(|tag| tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+        })
+        .map(|(tags, name)| ListModel {
             id: name,
             object: "model".to_string(),
             created: std::time::SystemTime::now()
@@ -124,6 +448,7 @@ pub async fn models(State(state): State<Arc<AppState>>) -> impl IntoResponse {
                 .unwrap_or_default()
                 .as_secs(),
             owned_by: "shimmy".to_string(),
+            tags,
         })
         .collect();
 
@@ -131,21 +456,97 @@ pub async fn models(State(state): State<Arc<AppState>>) -> impl IntoResponse {
         object: "list".to_string(),
         data: models,
     })
+    .into_response()
+}
+
+/// Route entry for `/v1/chat/completions`: validates the raw body, then runs
+/// `chat_completions`
+pub async fn validated_chat_completions(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> axum::response::Response {
+    use axum::http::StatusCode;
+
+    let invalid = |message: String, param: Option<&str>| {
+        let error_response = serde_json::json!({
+            "error": {
+                "message": message,
+                "type": "invalid_request_error",
+                "param": param,
+                "code": "invalid_request"
+            }
+        });
+        (StatusCode::BAD_REQUEST, Json(error_response)).into_response()
+    };
+
+    if let Err(message) = validate_messages(body.get("messages")) {
+        return invalid(message, Some("messages"));
+    }
+    match serde_json::from_value::<ChatCompletionRequest>(body) {
+        Ok(req) => chat_completions(State(state), headers, Json(req))
+            .await
+            .into_response(),
+        Err(e) => invalid(format!("Invalid request body: {}", e), None),
+    }
 }
 
 pub async fn chat_completions(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<ChatCompletionRequest>,
+    headers: HeaderMap,
+    Json(mut req): Json<ChatCompletionRequest>,
 ) -> impl IntoResponse {
     use axum::http::StatusCode;
 
+    let client_id = crate::audit::client_id(&headers);
+    let identity = crate::audit::request_identity(req.user.as_deref(), &headers);
+    state.observability.record_user_request(&identity).await;
+
+    if req.model.trim().is_empty() {
+        match state
+            .registry
+            .default_model(state.server_config.default_model.as_deref())
+        {
+            Ok(name) => req.model = name,
+            Err(message) => {
+                let error_response = serde_json::json!({
+                    "error": {
+                        "message": message,
+                        "type": "invalid_request_error",
+                        "param": "model",
+                        "code": "model_required"
+                    }
+                });
+                return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+            }
+        }
+    }
+
+    // Resolve `auto:<tag>` capability selectors to a concrete model
+    let Some(model_name) = state
+        .registry
+        .resolve_model_name(&req.model, state.server_config.default_model.as_deref())
+    else {
+        tracing::warn!("No model matches capability selector '{}'", req.model);
+        let error_response = serde_json::json!({
+            "error": {
+                "message": format!("No available model matches '{}'", req.model),
+                "type": "invalid_request_error",
+                "param": "model",
+                "code": "model_not_found"
+            }
+        });
+        return (StatusCode::NOT_FOUND, Json(error_response)).into_response();
+    };
+    req.model = model_name;
+
     // Load and validate model
     let Some(spec) = state.registry.to_spec(&req.model) else {
         tracing::warn!("Model '{}' not found in registry", req.model);
         let available_models = state.registry.list_all_available();
         let error_response = serde_json::json!({
             "error": {
-                "message": format!("Model '{}' not found. Available models: {:?}", req.model, available_models),
+                "message": format!("{}. Available models: {:?}", state.registry.model_not_found_message(&req.model), available_models),
                 "type": "invalid_request_error",
                 "param": "model",
                 "code": "model_not_found"
@@ -153,60 +554,157 @@ pub async fn chat_completions(
         });
         return (StatusCode::NOT_FOUND, Json(error_response)).into_response();
     };
-    tracing::debug!("Found model spec for '{}': {:?}", req.model, spec);
-    let engine = &state.engine;
-    let loaded = match engine.load(&spec).await {
-        Ok(loaded) => loaded,
-        Err(e) => {
-            tracing::error!("Failed to load model '{}': {:?}", req.model, e);
-            return StatusCode::BAD_GATEWAY.into_response();
-        }
-    };
 
-    // Construct prompt from messages
-    let fam = match spec.template.as_deref() {
-        Some("chatml") => crate::templates::TemplateFamily::ChatML,
-        Some("llama3") | Some("llama-3") => crate::templates::TemplateFamily::Llama3,
-        _ => {
-            // Auto-detect template based on model name
-            if req.model.to_lowercase().contains("qwen")
-                || req.model.to_lowercase().contains("chatglm")
-            {
-                crate::templates::TemplateFamily::ChatML
-            } else if req.model.to_lowercase().contains("llama") {
-                crate::templates::TemplateFamily::Llama3
-            } else {
-                crate::templates::TemplateFamily::OpenChat
+    // Construct prompt from messages, honouring a per-request template override
+    let fam = match req.template.as_deref() {
+        Some(name) => match crate::templates::TemplateFamily::from_name(name) {
+            Some(fam) => fam,
+            None => {
+                let error_response = serde_json::json!({
+                    "error": {
+                        "message": format!(
+                            "Unknown template '{}'. Available templates: {}",
+                            name,
+                            crate::templates::TemplateFamily::NAMES.join(", ")
+                        ),
+                        "type": "invalid_request_error",
+                        "param": "template",
+                        "code": "invalid_template"
+                    }
+                });
+                return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
             }
-        }
+        },
+        None => match spec
+            .template
+            .as_deref()
+            .and_then(crate::templates::TemplateFamily::from_name)
+        {
+            Some(fam) => fam,
+            None => {
+                // Auto-detect template based on model name
+                if req.model.to_lowercase().contains("qwen")
+                    || req.model.to_lowercase().contains("chatglm")
+                {
+                    crate::templates::TemplateFamily::ChatML
+                } else if req.model.to_lowercase().contains("llama") {
+                    crate::templates::TemplateFamily::Llama3
+                } else {
+                    crate::templates::TemplateFamily::OpenChat
+                }
+            }
+        },
     };
-    let pairs = req
-        .messages
-        .iter()
-        .map(|m| (m.role.clone(), m.content.clone()))
-        .collect::<Vec<_>>();
+    let pairs = state.server_config.prepare_messages(
+        req.messages
+            .iter()
+            .map(|m| (m.role.clone(), m.content.clone()))
+            .collect::<Vec<_>>(),
+    );
 
     // For chat completions, we need to trigger assistant response
     // Extract the last user message to use as input parameter
-    let last_user_message = req
-        .messages
+    let last_user_message = pairs
         .iter()
-        .rfind(|m| m.role == "user")
-        .map(|m| m.content.as_str());
+        .rfind(|(role, _)| role == "user")
+        .map(|(_, content)| content.as_str());
 
     // Build conversation history without the last user message
     let history: Vec<_> = if last_user_message.is_some() {
-        req.messages
-            .iter()
-            .take(req.messages.len().saturating_sub(1))
-            .map(|m| (m.role.clone(), m.content.clone()))
-            .collect()
+        pairs[..pairs.len().saturating_sub(1)].to_vec()
     } else {
         pairs.clone()
     };
 
     let prompt = fam.render(None, &history, last_user_message);
 
+    // Image parts are routed through the vision backend
+    let image: Option<Vec<u8>> = match latest_image(&req.messages) {
+        None => None,
+        #[cfg(feature = "vision")]
+        Some(url) => {
+            if let Err(response) = check_vision_license(&state).await {
+                return response;
+            }
+            match load_image_part(url).await {
+                Ok(bytes) => Some(bytes),
+                Err(message) => {
+                    tracing::warn!("Rejecting image for '{}': {}", req.model, message);
+                    let error_response = serde_json::json!({
+                        "error": {
+                            "message": message,
+                            "type": "invalid_request_error",
+                            "param": "messages",
+                            "code": "invalid_image"
+                        }
+                    });
+                    return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+                }
+            }
+        }
+        #[cfg(not(feature = "vision"))]
+        Some(_) => {
+            let error_response = serde_json::json!({
+                "error": {
+                    "message": "Image content requires the vision feature; rebuild shimmy with --features vision",
+                    "type": "invalid_request_error",
+                    "param": "messages",
+                    "code": "vision_not_enabled"
+                }
+            });
+            return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+        }
+    };
+
+    tracing::debug!("Found model spec for '{}': {:?}", req.model, spec);
+    if let Err(message) = state.server_config.precheck_prompt_length(&prompt) {
+        tracing::warn!("Rejecting request for '{}': {}", req.model, message);
+        let error_response = serde_json::json!({
+            "error": {
+                "message": message,
+                "type": "invalid_request_error",
+                "param": "messages",
+                "code": "prompt_too_long"
+            }
+        });
+        return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+    }
+    if let Some(secs) = req.keep_alive {
+        state
+            .model_pool
+            .set_keep_alive(&req.model, crate::model_manager::KeepAlive::from_secs(secs))
+            .await;
+    }
+    let loaded = match state
+        .server_config
+        .retry_transient("Model load", || {
+            state.model_pool.get_or_load(&*state.engine, &spec)
+        })
+        .await
+    {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            tracing::error!("Failed to load model '{}': {:?}", req.model, e);
+            return engine_error_response(&e);
+        }
+    };
+    if let Err(message) = state
+        .server_config
+        .check_prompt_length(loaded.count_tokens(&prompt))
+    {
+        tracing::warn!("Rejecting request for '{}': {}", req.model, message);
+        state.model_pool.release(&req.model).await;
+        let error_response = serde_json::json!({
+            "error": {
+                "message": message,
+                "type": "invalid_request_error",
+                "param": "messages",
+                "code": "prompt_too_long"
+            }
+        });
+        return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+    }
+
     // Set generation options
     let mut opts = crate::engine::GenOptions::default();
     if let Some(t) = req.temperature {
@@ -215,24 +713,46 @@ pub async fn chat_completions(
     if let Some(p) = req.top_p {
         opts.top_p = p;
     }
-    if let Some(m) = req.max_tokens {
-        opts.max_tokens = m;
+    if let Err(e) = opts.clamp_sampling(state.server_config.strict_sampling) {
+        state.model_pool.release(&req.model).await;
+        return engine_error_response(&e.into());
     }
+    let prompt_tokens = loaded.count_tokens(&prompt);
+    let usage = crate::api::context_usage_headers(&req.model, prompt_tokens, spec.ctx_len);
+    // One id per completion: every streamed chunk, the response body, and
+    // the X-Request-Id header all carry it
+    let id = format!("chatcmpl-{}", uuid::Uuid::new_v4().simple());
+    let request_id = [("x-request-id", id.clone())];
+    opts.context_shift = req.context_shift.unwrap_or(false);
+    opts.max_tokens = crate::engine::resolve_generation_budget(
+        req.requested_max_tokens(),
+        spec.ctx_len,
+        prompt_tokens,
+        opts.context_shift,
+    );
     if let Some(s) = req.stream {
         opts.stream = s;
     }
+    if let Some(trim) = req.trim_leading {
+        opts.trim_leading = trim;
+    }
 
     // Auto-configure stop tokens based on template family
     let mut stop_tokens = fam.stop_tokens();
+    stop_tokens.extend(state.registry.stop_tokens(&req.model));
     // Merge with user-provided stop tokens if any
     if let Some(user_stop) = req.stop {
         stop_tokens.extend(user_stop.into_vec());
     }
     opts.stop_tokens = stop_tokens;
+    let echo_params = req
+        .include_sampling_params
+        .unwrap_or(state.server_config.echo_params);
+    let params = crate::api::sampling_params_headers(&opts, echo_params);
 
     if opts.stream {
         // Handle streaming response with proper OpenAI format
-        use axum::response::sse::{Event, Sse};
+        use axum::response::sse::Event;
         use tokio_stream::wrappers::UnboundedReceiverStream;
         use tokio_stream::StreamExt;
 
@@ -245,21 +765,23 @@ pub async fn chat_completions(
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs();
-        let id = format!("chatcmpl-{}", uuid::Uuid::new_v4().simple());
+        let audit = state.audit_logger.clone();
+        let prompt_log = state.server_config.prompt_log.clone();
+        let model_pool = state.model_pool.clone();
+        let observability = state.observability.clone();
+        let chunk_tokens = req.stream_chunk_tokens.unwrap_or(1);
+        let cancel = opts_clone.cancel.clone();
 
         tokio::spawn(async move {
-            let tx_tokens = tx.clone();
-            let id_for_tokens = id.clone();
-            let model_for_tokens = model_clone.clone();
             let id_for_final = id.clone();
             let model_for_final = model_clone.clone();
 
             // Send initial chunk with role
             let initial_chunk = ChatCompletionChunk {
-                id: id_for_tokens.clone(),
+                id: id.clone(),
                 object: "chat.completion.chunk".to_string(),
                 created: timestamp,
-                model: model_for_tokens.clone(),
+                model: model_clone.clone(),
                 choices: vec![ChunkChoice {
                     index: 0,
                     delta: Delta {
@@ -269,52 +791,101 @@ pub async fn chat_completions(
                     finish_reason: None,
                 }],
             };
-            let _ = tx_tokens.send(serde_json::to_string(&initial_chunk).unwrap_or_else(|e| {
+            let _ = tx.send(serde_json::to_string(&initial_chunk).unwrap_or_else(|e| {
                 tracing::error!("Failed to serialize initial chunk: {}", e);
                 "{}".to_string()
             }));
 
+            let send_content = {
+                let tx = tx.clone();
+                let cancel = cancel.clone();
+                move |content: String| {
+                    let chunk = ChatCompletionChunk {
+                        id: id.clone(),
+                        object: "chat.completion.chunk".to_string(),
+                        created: timestamp,
+                        model: model_clone.clone(),
+                        choices: vec![ChunkChoice {
+                            index: 0,
+                            delta: Delta {
+                                role: None,
+                                content: Some(content),
+                            },
+                            finish_reason: None,
+                        }],
+                    };
+                    let chunk = serde_json::to_string(&chunk).unwrap_or_else(|e| {
+                        tracing::error!("Failed to serialize chunk: {}", e);
+                        "{}".to_string()
+                    });
+                    cancel.send_or_cancel(&tx, chunk);
+                }
+            };
+            let batch = Arc::new(std::sync::Mutex::new(TokenBatch::new(chunk_tokens)));
+
             // Generate and stream tokens
-            let _ = loaded
-                .generate(
+            let result = generate_chat(
+                loaded.as_ref(),
+                image.as_deref(),
+                &prompt_clone,
+                opts_clone,
+                Some(Box::new({
+                    let batch = batch.clone();
+                    let send_content = send_content.clone();
+                    move |tok| {
+                        let full = batch.lock().ok().and_then(|mut b| b.push(tok));
+                        if let Some(content) = full {
+                            send_content(content);
+                        }
+                    }
+                })),
+            )
+            .await;
+
+            model_pool.release(&model_for_final).await;
+            if cancel.is_cancelled() {
+                observability.record_cancelled(&model_for_final).await;
+            }
+
+            // Flush a final partial batch before the finish chunk
+            if let Some(content) = batch.lock().ok().and_then(|mut b| b.flush()) {
+                send_content(content);
+            }
+
+            let (response, status) = match &result {
+                Ok((full, _)) => (full.as_str(), 200),
+                Err(e) => ("", crate::api_errors::status_for(e).as_u16()),
+            };
+            if let Some(audit) = &audit {
+                audit.record(
+                    &model_for_final,
+                    &client_id,
                     &prompt_clone,
-                    opts_clone,
-                    Some(Box::new(move |tok| {
-                        let chunk = ChatCompletionChunk {
-                            id: id_for_tokens.clone(),
-                            object: "chat.completion.chunk".to_string(),
-                            created: timestamp,
-                            model: model_for_tokens.clone(),
-                            choices: vec![ChunkChoice {
-                                index: 0,
-                                delta: Delta {
-                                    role: None,
-                                    content: Some(tok),
-                                },
-                                finish_reason: None,
-                            }],
-                        };
-                        let _ = tx_tokens.send(serde_json::to_string(&chunk).unwrap_or_else(|e| {
-                            tracing::error!("Failed to serialize chunk: {}", e);
-                            "{}".to_string()
-                        }));
-                    })),
-                )
-                .await;
-
-            // Send final chunk
+                    response,
+                    status,
+                    |t| loaded.count_tokens(t),
+                );
+            }
+            prompt_log.record(&model_for_final, &prompt_clone, response, status);
+
+            // Send final chunk with the reason generation ended; a failed
+            // generation ends the stream with "error"
+            let finish_reason = result
+                .as_ref()
+                .map(|(_, reason)| reason.as_str())
+                .unwrap_or("error");
             let final_chunk = ChatCompletionChunk {
                 id: id_for_final,
                 object: "chat.completion.chunk".to_string(),
                 created: timestamp,
-                model: model_for_final,
+                model: model_for_final.clone(),
                 choices: vec![ChunkChoice {
                     index: 0,
                     delta: Delta {
                         role: None,
                         content: None,
                     },
-                    finish_reason: Some("stop".to_string()),
+                    finish_reason: Some(finish_reason.to_string()),
                 }],
             };
             let _ = tx.send(serde_json::to_string(&final_chunk).unwrap_or_else(|e| {
@@ -326,18 +897,41 @@ pub async fn chat_completions(
 
         let stream = UnboundedReceiverStream::new(rx)
             .map(|s| Ok::<Event, std::convert::Infallible>(Event::default().data(s)));
-        Sse::new(stream).into_response()
+        (
+            usage,
+            request_id,
+            params,
+            state.server_config.sse_response(stream),
+        )
+            .into_response()
     } else {
         // Handle non-streaming response
-        match loaded.generate(&prompt, opts, None).await {
-            Ok(content) => {
+        let headers = crate::api::cache_headers(&opts, state.response_cache.default_ttl(), None);
+        let sampling_params = echo_params.then(|| crate::engine::SamplingReport::new(&opts));
+        let cancel = opts
+            .cancel
+            .guard(state.observability.cancel_recorder(&req.model));
+        let result = generate_chat(loaded.as_ref(), image.as_deref(), &prompt, opts, None).await;
+        cancel.disarm();
+        state.model_pool.release(&req.model).await;
+        match result {
+            Ok((content, finish_reason)) => {
                 tracing::debug!(
                     "Generated response for model '{}': {} chars",
                     req.model,
                     content.len()
                 );
+                if let Some(audit) = &state.audit_logger {
+                    audit.record(&req.model, &client_id, &prompt, &content, 200, |t| {
+                        loaded.count_tokens(t)
+                    });
+                }
+                state
+                    .server_config
+                    .prompt_log
+                    .record(&req.model, &prompt, &content, 200);
                 let response = ChatCompletionResponse {
-                    id: format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+                    id,
                     object: "chat.completion".to_string(),
                     created: std::time::SystemTime::now()
                         .duration_since(std::time::UNIX_EPOCH)
@@ -349,16 +943,18 @@ pub async fn chat_completions(
                         message: ChatMessage {
                             role: "assistant".to_string(),
                             content,
+                            images: Vec::new(),
                         },
-                        finish_reason: Some("stop".to_string()),
+                        finish_reason: Some(finish_reason.as_str().to_string()),
                     }],
                     usage: Usage {
                         prompt_tokens: 0, // Token counting not needed for local inference
                         completion_tokens: 0,
                         total_tokens: 0,
                     },
+                    sampling_params,
                 };
-                Json(response).into_response()
+                (headers, usage, request_id, params, Json(response)).into_response()
             }
             Err(e) => {
                 tracing::error!(
@@ -366,16 +962,87 @@ pub async fn chat_completions(
                     req.model,
                     e
                 );
-                StatusCode::BAD_GATEWAY.into_response()
+                let status = crate::api_errors::status_for(&e).as_u16();
+                if let Some(audit) = &state.audit_logger {
+                    audit.record(&req.model, &client_id, &prompt, "", status, |t| {
+                        loaded.count_tokens(t)
+                    });
+                }
+                state
+                    .server_config
+                    .prompt_log
+                    .record(&req.model, &prompt, "", status);
+                engine_error_response(&e)
             }
         }
     }
 }
 
+/// Map an engine failure to an OpenAI-style error with the matching status
+fn engine_error_response(err: &anyhow::Error) -> axum::response::Response {
+    let status = crate::api_errors::status_for(err);
+    let code = crate::engine::EngineError::find(err)
+        .map(|e| e.code())
+        .unwrap_or("engine_error");
+    let error_type = if status.is_client_error() {
+        "invalid_request_error"
+    } else {
+        "server_error"
+    };
+    let error_response = serde_json::json!({
+        "error": {
+            "message": err.to_string(),
+            "type": error_type,
+            "param": null,
+            "code": code
+        }
+    });
+    (status, Json(error_response)).into_response()
+}
+
+/// Image requests use the licensed vision subsystem, same as `/api/vision`
+#[cfg(feature = "vision")]
+async fn check_vision_license(state: &AppState) -> Result<(), axum::response::Response> {
+    use axum::http::StatusCode;
+
+    let Some(license_manager) = state.vision_license_manager.as_ref() else {
+        tracing::error!("Vision license manager not initialized");
+        let error_response = serde_json::json!({
+            "error": {
+                "message": "Vision subsystem not initialized",
+                "type": "server_error",
+                "param": null,
+                "code": "vision_unavailable"
+            }
+        });
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)).into_response());
+    };
+    let license = std::env::var("SHIMMY_LICENSE_KEY").ok();
+    if let Err(e) = license_manager
+        .check_vision_access(license.as_deref())
+        .await
+    {
+        let error_response = serde_json::json!({
+            "error": {
+                "message": e.to_string(),
+                "type": "invalid_request_error",
+                "param": "messages",
+                "code": "vision_license_required"
+            }
+        });
+        return Err((StatusCode::FORBIDDEN, Json(error_response)).into_response());
+    }
+    if let Err(e) = license_manager.record_usage().await {
+        tracing::warn!("Failed to record vision usage: {}", e);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::engine::adapter::InferenceEngineAdapter;
+    use crate::engine::mock::{MockEngine, Reply};
     use crate::model_registry::Registry;
     use crate::AppState;
     use axum::{extract::State, Json};
@@ -390,18 +1057,755 @@ mod tests {
         let request = ChatCompletionRequest {
             model: "test".to_string(),
             messages: vec![],
-            temperature: None,
-            max_tokens: None,
-            top_p: None,
             stream: Some(false),
-            stop: None,
+            ..Default::default()
         };
 
         // Exercise handler code path (will gracefully fail due to no model)
-        let _result = chat_completions(State(state), Json(request)).await;
+        let _result = chat_completions(State(state), HeaderMap::new(), Json(request)).await;
         // Test completed successfully
     }
 
+    #[tokio::test]
+    async fn test_chat_completions_unknown_model_suggests_similar() {
+        use crate::model_registry::ModelEntry;
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new(
+            "llama-3-8b-instruct",
+            "./llama-3-8b-instruct.gguf",
+        ));
+        let engine = Box::new(InferenceEngineAdapter::new());
+        let state = Arc::new(AppState::new(engine, registry));
+
+        let request = ChatCompletionRequest {
+            model: "llama3".to_string(),
+            messages: vec![],
+            stream: Some(false),
+            ..Default::default()
+        };
+        let response = chat_completions(State(state), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let message = parsed["error"]["message"].as_str().unwrap();
+        assert!(message.contains("Did you mean: llama-3-8b-instruct?"));
+    }
+
+    fn words_state() -> Arc<AppState> {
+        use crate::model_registry::ModelEntry;
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("words", "./words.gguf").with_template("chatml"));
+        Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Words)),
+            registry,
+        ))
+    }
+
+    fn words_request(max_tokens: usize, stream: bool) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: "words".to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: "count".to_string(),
+                images: Vec::new(),
+            }],
+            max_tokens: Some(max_tokens),
+            stream: Some(stream),
+            ..Default::default()
+        }
+    }
+
+    async fn response_body(response: axum::response::Response) -> String {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    fn capability_state() -> Arc<AppState> {
+        use crate::model_registry::ModelEntry;
+
+        let mut registry = Registry::default();
+        for (name, tags) in [
+            ("llava-7b", vec!["vision".to_string()]),
+            ("phi3-mini", Vec::new()),
+            ("nomic-embed", vec!["embedding".to_string()]),
+        ] {
+            registry.register(ModelEntry::new(name, format!("./{}.gguf", name)).with_tags(tags));
+        }
+        let engine = Box::new(InferenceEngineAdapter::new());
+        Arc::new(AppState::new(engine, registry))
+    }
+
+    async fn model_ids(capability: Option<&str>) -> (u16, serde_json::Value) {
+        let response = models(
+            State(capability_state()),
+            Query(ModelsQuery {
+                capability: capability.map(str::to_string),
+                tag: None,
+                sort: None,
+            }),
+        )
+        .await;
+        let status = response.status().as_u16();
+        let body = response_body(response).await;
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_models_capability_filter() {
+        let ids = |body: &serde_json::Value| -> Vec<String> {
+            body["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|m| m["id"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let (status, body) = model_ids(Some("vision")).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec!["llava-7b"]);
+
+        let (_, body) = model_ids(Some("embedding")).await;
+        assert_eq!(ids(&body), vec!["nomic-embed"]);
+
+        let (_, body) = model_ids(Some("generation")).await;
+        assert_eq!(ids(&body), vec!["llava-7b", "phi3-mini"]);
+
+        let (_, body) = model_ids(None).await;
+        assert_eq!(ids(&body).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_models_unknown_capability_is_rejected() {
+        let (status, body) = model_ids(Some("audio")).await;
+        assert_eq!(status, 400);
+        assert_eq!(body["error"]["param"], "capability");
+        assert!(body["error"]["message"].as_str().unwrap().contains("audio"));
+    }
+
+    #[tokio::test]
+    async fn test_models_list_tags_and_filter_by_tag() {
+        use crate::auto_discovery::DiscoveredModel;
+
+        let mut registry = Registry::default();
+        registry.discovered_models.insert(
+            "qwen2.5-coder-7b".to_string(),
+            DiscoveredModel {
+                name: "qwen2.5-coder-7b".to_string(),
+                path: "./qwen2.5-coder-7b.gguf".into(),
+                lora_path: None,
+                size_bytes: 0,
+                model_type: "Llama".to_string(),
+                parameter_count: None,
+                quantization: None,
+                display_name: None,
+                overrides: Default::default(),
+                tags: vec!["code".to_string()],
+            },
+        );
+        let state = Arc::new(AppState::new(
+            Box::new(InferenceEngineAdapter::new()),
+            registry,
+        ));
+        let list = |tag: Option<&str>| {
+            models(
+                State(state.clone()),
+                Query(ModelsQuery {
+                    capability: None,
+                    tag: tag.map(str::to_string),
+                    sort: None,
+                }),
+            )
+        };
+
+        let body: serde_json::Value =
+            serde_json::from_str(&response_body(list(Some("CODE")).await).await).unwrap();
+        assert_eq!(body["data"][0]["id"], "qwen2.5-coder-7b");
+        assert_eq!(body["data"][0]["tags"], serde_json::json!(["code"]));
+
+        let body: serde_json::Value =
+            serde_json::from_str(&response_body(list(Some("vision")).await).await).unwrap();
+        assert!(body["data"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_models_sort_by_size() {
+        use crate::auto_discovery::DiscoveredModel;
+
+        let mut registry = Registry::default();
+        for (name, size_bytes) in [("alpha", 10), ("beta", 30), ("gamma", 20)] {
+            registry.discovered_models.insert(
+                name.to_string(),
+                DiscoveredModel {
+                    name: name.to_string(),
+                    path: format!("./{}.gguf", name).into(),
+                    lora_path: None,
+                    size_bytes,
+                    model_type: "Llama".to_string(),
+                    parameter_count: None,
+                    quantization: None,
+                    display_name: None,
+                    overrides: Default::default(),
+                    tags: Vec::new(),
+                },
+            );
+        }
+        let state = Arc::new(AppState::new(
+            Box::new(InferenceEngineAdapter::new()),
+            registry,
+        ));
+        let list = |sort: Option<&str>| {
+            models(
+                State(state.clone()),
+                Query(ModelsQuery {
+                    capability: None,
+                    tag: None,
+                    sort: sort.map(str::to_string),
+                }),
+            )
+        };
+        let ids = |body: serde_json::Value| -> Vec<String> {
+            body["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|m| m["id"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let body = response_body(list(None).await).await;
+        assert_eq!(
+            ids(serde_json::from_str(&body).unwrap()),
+            vec!["alpha", "beta", "gamma"]
+        );
+        let body = response_body(list(Some("size")).await).await;
+        assert_eq!(
+            ids(serde_json::from_str(&body).unwrap()),
+            vec!["beta", "gamma", "alpha"]
+        );
+
+        let response = list(Some("popularity")).await;
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_template_override_is_per_request() {
+        use crate::model_registry::ModelEntry;
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("words", "./words.gguf").with_template("chatml"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Prompt)),
+            registry,
+        ));
+        let content = |body: String| {
+            let parsed: serde_json::Value = serde_json::from_str(&body).unwrap();
+            parsed["choices"][0]["message"]["content"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        };
+
+        let mut request = words_request(16, false);
+        request.template = Some("llama3".to_string());
+        let response = chat_completions(State(state.clone()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let prompt = content(response_body(response).await);
+        assert!(prompt.contains("<|start_header_id|>user<|end_header_id|>"));
+        assert!(!prompt.contains("<|im_start|>"));
+
+        // The next request without an override gets the model's own template
+        let response = chat_completions(
+            State(state.clone()),
+            HeaderMap::new(),
+            Json(words_request(16, false)),
+        )
+        .await
+        .into_response();
+        let prompt = content(response_body(response).await);
+        assert!(prompt.contains("<|im_start|>user"));
+        assert_eq!(
+            state.registry.to_spec("words").unwrap().template.as_deref(),
+            Some("chatml")
+        );
+    }
+
+    async fn post_chat(body: serde_json::Value) -> (axum::http::StatusCode, serde_json::Value) {
+        let response =
+            validated_chat_completions(State(words_state()), HeaderMap::new(), Json(body)).await;
+        let status = response.status();
+        (
+            status,
+            serde_json::from_str(&response_body(response).await).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_malformed_messages_are_400_with_index() {
+        use serde_json::json;
+
+        let cases = [
+            (json!([]), "at least one message"),
+            (
+                json!([{"role": "user", "content": "hi"}, {"role": "wizard", "content": "x"}]),
+                "messages[1] has unknown role 'wizard'",
+            ),
+            (
+                json!([{"role": "system", "content": "be brief"}, {"role": "user"}]),
+                "messages[1] is missing 'content'",
+            ),
+            (json!([{"content": "hi"}]), "messages[0] is missing 'role'"),
+            (
+                json!([{"role": "user", "content": [{"type": "audio"}]}]),
+                "messages[0].content[0]",
+            ),
+            (
+                json!([{"role": "user", "content": 42}]),
+                "messages[0].content must be",
+            ),
+        ];
+        for (messages, expected) in cases {
+            let (status, body) = post_chat(json!({"model": "words", "messages": messages})).await;
+            assert_eq!(status, axum::http::StatusCode::BAD_REQUEST, "{}", expected);
+            let message = body["error"]["message"].as_str().unwrap();
+            assert!(
+                message.contains(expected),
+                "{} not in {}",
+                expected,
+                message
+            );
+            assert_eq!(body["error"]["param"], "messages");
+        }
+
+        let (status, _) = post_chat(json!({"model": "words"})).await;
+        assert_eq!(status, axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_valid_messages_pass_validation() {
+        use serde_json::json;
+
+        let (status, body) = post_chat(json!({
+            "model": "words",
+            "messages": [
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "hello"},
+                    {"type": "text", "text": "there"}
+                ]}
+            ],
+            "max_tokens": 2,
+            "stream": false
+        }))
+        .await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(body["choices"][0]["message"]["content"], "one two");
+    }
+
+    #[tokio::test]
+    async fn test_max_completion_tokens_alias() {
+        use serde_json::json;
+
+        let cases = [
+            (json!({"max_tokens": 2}), "one two"),
+            (json!({"max_completion_tokens": 3}), "one two three"),
+            (
+                json!({"max_tokens": 1, "max_completion_tokens": 4}),
+                "one two three four",
+            ),
+            (json!({}), "one two three four five"),
+        ];
+        for (limits, expected) in cases {
+            let mut request = json!({
+                "model": "words",
+                "messages": [{"role": "user", "content": "count"}],
+                "stream": false
+            });
+            request
+                .as_object_mut()
+                .unwrap()
+                .extend(limits.as_object().unwrap().clone());
+            let (status, body) = post_chat(request).await;
+            assert_eq!(status, axum::http::StatusCode::OK);
+            assert_eq!(body["choices"][0]["message"]["content"], expected);
+        }
+    }
+
+    fn two_model_state(default_model: Option<&str>) -> Arc<AppState> {
+        use crate::model_registry::ModelEntry;
+
+        let mut registry = Registry::default();
+        for name in ["words", "other"] {
+            registry.register(
+                ModelEntry::new(name, format!("./{}.gguf", name)).with_template("chatml"),
+            );
+        }
+        let mut state = AppState::new(Box::new(MockEngine::new(Reply::Words)), registry);
+        state.server_config.default_model = default_model.map(str::to_string);
+        Arc::new(state)
+    }
+
+    #[tokio::test]
+    async fn test_omitted_model_uses_default() {
+        use serde_json::json;
+
+        // Only one model available: picked automatically
+        let (status, parsed) = post_chat(json!({
+            "messages": [{"role": "user", "content": "count"}],
+            "max_tokens": 2,
+            "stream": false
+        }))
+        .await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(parsed["choices"][0]["message"]["content"], "one two");
+
+        let mut request = words_request(2, false);
+        request.model = String::new();
+        let response = chat_completions(
+            State(two_model_state(Some("words"))),
+            HeaderMap::new(),
+            Json(request),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let parsed: serde_json::Value =
+            serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(parsed["model"], "words");
+    }
+
+    #[tokio::test]
+    async fn test_omitted_model_is_ambiguous_without_default() {
+        let mut request = words_request(2, false);
+        request.model = String::new();
+        let response = chat_completions(
+            State(two_model_state(None)),
+            HeaderMap::new(),
+            Json(request),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        let parsed: serde_json::Value =
+            serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(parsed["error"]["code"], "model_required");
+        let message = parsed["error"]["message"].as_str().unwrap();
+        assert!(message.contains("other, words"), "{}", message);
+        assert!(message.contains("--default-model"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_template_override_is_400() {
+        let mut request = words_request(16, false);
+        request.template = Some("alpaca".to_string());
+        let response = chat_completions(State(words_state()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        let parsed: serde_json::Value =
+            serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(parsed["error"]["param"], "template");
+        assert_eq!(parsed["error"]["code"], "invalid_template");
+    }
+
+    #[tokio::test]
+    async fn test_stream_emits_keep_alive_during_token_gap() {
+        use crate::model_registry::ModelEntry;
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("words", "./words.gguf").with_template("chatml"));
+        let mut state = AppState::new(
+            Box::new(
+                MockEngine::new(Reply::Tokens(&["slow ", "reply"]))
+                    .with_token_delay(std::time::Duration::from_millis(200)),
+            ),
+            registry,
+        );
+        state.server_config.sse_keep_alive = Some(std::time::Duration::from_millis(30));
+
+        let response = chat_completions(
+            State(Arc::new(state)),
+            HeaderMap::new(),
+            Json(words_request(16, true)),
+        )
+        .await
+        .into_response();
+        let body = response_body(response).await;
+
+        assert!(body.contains(": keep-alive\n\n"), "body: {}", body);
+        // Keep-alives are comments, so every data line is still a whole chunk
+        let data: Vec<&str> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .collect();
+        assert_eq!(data.last(), Some(&"[DONE]"));
+        for chunk in &data[..data.len() - 1] {
+            serde_json::from_str::<serde_json::Value>(chunk).unwrap();
+        }
+        let content: String = data[..data.len() - 1]
+            .iter()
+            .filter_map(|chunk| {
+                let parsed: serde_json::Value = serde_json::from_str(chunk).unwrap();
+                parsed["choices"][0]["delta"]["content"]
+                    .as_str()
+                    .map(str::to_string)
+            })
+            .collect();
+        assert_eq!(content, "slow reply");
+    }
+
+    #[tokio::test]
+    async fn test_finish_reason_length_and_stop() {
+        let response = chat_completions(
+            State(words_state()),
+            HeaderMap::new(),
+            Json(words_request(3, false)),
+        )
+        .await
+        .into_response();
+        let parsed: serde_json::Value =
+            serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(parsed["choices"][0]["finish_reason"], "length");
+
+        let response = chat_completions(
+            State(words_state()),
+            HeaderMap::new(),
+            Json(words_request(50, false)),
+        )
+        .await
+        .into_response();
+        let parsed: serde_json::Value =
+            serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(parsed["choices"][0]["finish_reason"], "stop");
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_counted_per_user() {
+        let state = words_state();
+        for user in ["alice", "bob", "alice"] {
+            let mut request = words_request(5, false);
+            request.user = Some(user.to_string());
+            chat_completions(State(state.clone()), HeaderMap::new(), Json(request)).await;
+        }
+
+        let users = state.observability.metrics().await.user_requests;
+        assert_eq!(users.get("alice"), Some(&2));
+        assert_eq!(users.get("bob"), Some(&1));
+        assert_eq!(users.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_chat_cache_control_follows_determinism() {
+        let response = chat_completions(
+            State(words_state()),
+            HeaderMap::new(),
+            Json(words_request(5, false)),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.headers()["cache-control"], "no-store");
+
+        let mut request = words_request(5, false);
+        request.temperature = Some(0.0);
+        let response = chat_completions(State(words_state()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.headers()["cache-control"], "private, max-age=3600");
+    }
+
+    #[tokio::test]
+    async fn test_greedy_request_echoes_sampling_params() {
+        let mut request = words_request(3, false);
+        request.temperature = Some(0.0);
+        request.include_sampling_params = Some(true);
+        let response = chat_completions(State(words_state()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        let header: serde_json::Value =
+            serde_json::from_str(response.headers()["x-shimmy-params"].to_str().unwrap()).unwrap();
+        let parsed: serde_json::Value =
+            serde_json::from_str(&response_body(response).await).unwrap();
+        let echoed = &parsed["sampling_params"];
+        assert_eq!(echoed["mode"], "greedy");
+        assert_eq!(echoed["max_tokens"], 3);
+        assert!(echoed["stop_tokens"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("<|im_end|>")));
+        assert_eq!(&header, echoed);
+
+        // Not requested and not enabled server-wide: nothing extra
+        let response = chat_completions(
+            State(words_state()),
+            HeaderMap::new(),
+            Json(words_request(3, false)),
+        )
+        .await
+        .into_response();
+        assert!(response.headers().get("x-shimmy-params").is_none());
+        let parsed: serde_json::Value =
+            serde_json::from_str(&response_body(response).await).unwrap();
+        assert!(parsed.get("sampling_params").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_out_of_range_top_p_is_clamped_and_reported() {
+        let mut request = words_request(3, false);
+        request.top_p = Some(1.5);
+        request.include_sampling_params = Some(true);
+        let response = chat_completions(State(words_state()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let parsed: serde_json::Value =
+            serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(parsed["sampling_params"]["top_p"], 1.0);
+        assert_eq!(
+            parsed["sampling_params"]["clamped"],
+            serde_json::json!(["top_p 1.5 -> 1"])
+        );
+
+        // --strict-sampling rejects the same request
+        let mut state = Arc::try_unwrap(words_state()).ok().unwrap();
+        state.server_config.strict_sampling = true;
+        let state = Arc::new(state);
+        let mut request = words_request(3, false);
+        request.top_p = Some(1.5);
+        request.keep_alive = Some(0);
+        let response = chat_completions(State(state.clone()), HeaderMap::new(), Json(request))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        let parsed: serde_json::Value =
+            serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(parsed["error"]["code"], "invalid_options");
+        // The rejected request still releases the model it loaded
+        assert!(!state.model_pool.is_loaded("words").await);
+    }
+
+    #[tokio::test]
+    async fn test_stream_chunk_tokens_batches_events() {
+        use crate::model_registry::ModelEntry;
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("words", "./words.gguf").with_template("chatml"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Tokens(&[
+                "0", "1", "2", "3", "4", "5", "6", "7", "8", "9",
+            ]))),
+            registry,
+        ));
+
+        let content_events = |chunk_tokens: Option<usize>| {
+            let state = state.clone();
+            async move {
+                let mut request = words_request(16, true);
+                request.stream_chunk_tokens = chunk_tokens;
+                let response = chat_completions(State(state), HeaderMap::new(), Json(request))
+                    .await
+                    .into_response();
+                let body = response_body(response).await;
+                let data: Vec<String> = body
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("data: "))
+                    .map(str::to_string)
+                    .collect();
+                assert_eq!(data.last().map(String::as_str), Some("[DONE]"));
+                data[..data.len() - 1]
+                    .iter()
+                    .filter_map(|chunk| {
+                        let parsed: serde_json::Value = serde_json::from_str(chunk).unwrap();
+                        parsed["choices"][0]["delta"]["content"]
+                            .as_str()
+                            .map(str::to_string)
+                    })
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        // 4 + 4 + a flushed partial batch of 2, in order
+        assert_eq!(content_events(Some(4)).await, vec!["0123", "4567", "89"]);
+        assert_eq!(content_events(None).await.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_completion_id_is_shared_by_chunks_and_header() {
+        let response = chat_completions(
+            State(words_state()),
+            HeaderMap::new(),
+            Json(words_request(3, true)),
+        )
+        .await
+        .into_response();
+        let header = response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(header.starts_with("chatcmpl-"));
+        let body = response_body(response).await;
+        let ids: Vec<String> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|chunk| {
+                let parsed: serde_json::Value = serde_json::from_str(chunk).unwrap();
+                parsed["id"].as_str().unwrap().to_string()
+            })
+            .collect();
+        // Role chunk, three content chunks, finish chunk
+        assert_eq!(ids.len(), 5);
+        assert!(
+            ids.iter().all(|id| *id == header),
+            "{:?} vs {}",
+            ids,
+            header
+        );
+
+        let response = chat_completions(
+            State(words_state()),
+            HeaderMap::new(),
+            Json(words_request(3, false)),
+        )
+        .await
+        .into_response();
+        let header = response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let parsed: serde_json::Value =
+            serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(parsed["id"], header.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_streaming_final_chunk_finish_reason() {
+        let response = chat_completions(
+            State(words_state()),
+            HeaderMap::new(),
+            Json(words_request(2, true)),
+        )
+        .await
+        .into_response();
+        let body = response_body(response).await;
+        let final_chunk = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .rfind(|data| *data != "[DONE]")
+            .unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(final_chunk).unwrap();
+        assert_eq!(parsed["choices"][0]["finish_reason"], "length");
+    }
+
     #[tokio::test]
     async fn test_models_handler_execution() {
         let registry = Registry::default();
@@ -409,7 +1813,7 @@ mod tests {
         let state = Arc::new(AppState::new(engine, registry));
 
         // Exercise models handler code path
-        let _result = models(State(state)).await;
+        let _result = models(State(state), Query(ModelsQuery::default())).await;
         // Test completed successfully
     }
 
@@ -425,6 +1829,7 @@ mod tests {
                 message: ChatMessage {
                     role: "assistant".to_string(),
                     content: "Hello world".to_string(),
+                    images: Vec::new(),
                 },
                 finish_reason: Some("stop".to_string()),
             }],
@@ -433,6 +1838,7 @@ mod tests {
                 completion_tokens: 5,
                 total_tokens: 15,
             },
+            sampling_params: None,
         };
 
         assert_eq!(response.id, "test-id");
@@ -466,15 +1872,13 @@ mod tests {
             messages: vec![ChatMessage {
                 role: "user".to_string(),
                 content: "Hello".to_string(),
+                images: Vec::new(),
             }],
             stream: Some(false),
-            temperature: None,
-            max_tokens: None,
-            top_p: None,
-            stop: None,
+            ..Default::default()
         };
 
-        let _response = chat_completions(State(state), Json(request)).await;
+        let _response = chat_completions(State(state), HeaderMap::new(), Json(request)).await;
         // The response should be a 404 NOT_FOUND (line 107)
         // We can't easily test the exact status without response introspection,
         // but we exercise the code path
@@ -487,14 +1891,11 @@ mod tests {
 
         let mut registry = Registry::default();
         // Add a test model to get past the model not found check (line 106)
-        registry.register(ModelEntry {
-            name: "test-streaming".to_string(),
-            base_path: "./test.safetensors".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            ModelEntry::new("test-streaming", "./test.safetensors")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
 
         let engine = Box::new(InferenceEngineAdapter::new());
         let state = Arc::new(AppState::new(engine, registry));
@@ -504,16 +1905,17 @@ mod tests {
             messages: vec![ChatMessage {
                 role: "user".to_string(),
                 content: "Hello".to_string(),
+                images: Vec::new(),
             }],
             stream: Some(true), // Enable streaming (line 132)
             temperature: Some(0.7),
             max_tokens: Some(100),
             top_p: Some(0.9),
-            stop: None,
+            ..Default::default()
         };
 
         // Exercise streaming path (lines 132-213)
-        let _response = chat_completions(State(state), Json(request)).await;
+        let _response = chat_completions(State(state), HeaderMap::new(), Json(request)).await;
         // Test completed successfully
     }
 
@@ -523,14 +1925,11 @@ mod tests {
 
         let mut registry = Registry::default();
         // Add a test model to get past the model not found check
-        registry.register(ModelEntry {
-            name: "test-non-streaming".to_string(),
-            base_path: "./test.safetensors".into(),
-            lora_path: None,
-            template: Some("llama3".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
+        registry.register(
+            ModelEntry::new("test-non-streaming", "./test.safetensors")
+                .with_template("llama3")
+                .with_ctx_len(2048),
+        );
 
         let engine = Box::new(InferenceEngineAdapter::new());
         let state = Arc::new(AppState::new(engine, registry));
@@ -541,21 +1940,23 @@ mod tests {
                 ChatMessage {
                     role: "user".to_string(),
                     content: "Hello".to_string(),
+                    images: Vec::new(),
                 },
                 ChatMessage {
                     role: "assistant".to_string(),
                     content: "Hi there!".to_string(),
+                    images: Vec::new(),
                 },
             ],
             stream: Some(false), // Disable streaming (line 214)
             temperature: Some(0.5),
             max_tokens: Some(50),
             top_p: Some(0.8),
-            stop: None,
+            ..Default::default()
         };
 
         // Exercise non-streaming path (lines 214-244)
-        let _response = chat_completions(State(state), Json(request)).await;
+        let _response = chat_completions(State(state), HeaderMap::new(), Json(request)).await;
         // Test completed successfully
     }
 
@@ -726,12 +2127,14 @@ mod tests {
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    tags: Vec::new(),
                 },
                 ListModel {
                     id: "model2".to_string(),
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    tags: Vec::new(),
                 },
             ],
         };
@@ -787,6 +2190,7 @@ mod tests {
             message: ChatMessage {
                 role: "assistant".to_string(),
                 content: "Response".to_string(),
+                images: Vec::new(),
             },
             finish_reason: Some("stop".to_string()),
         };
@@ -812,10 +2216,12 @@ mod tests {
             ChatMessage {
                 role: "user".to_string(),
                 content: "Hello".to_string(),
+                images: Vec::new(),
             },
             ChatMessage {
                 role: "assistant".to_string(),
                 content: "Hi there!".to_string(),
+                images: Vec::new(),
             },
         ];
 
@@ -836,28 +2242,22 @@ mod tests {
         use crate::model_registry::ModelEntry;
 
         let mut registry = Registry::default();
-        registry.register(ModelEntry {
-            name: "registered-model".to_string(),
-            base_path: "./test1.gguf".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(2048),
-            n_threads: None,
-        });
-        registry.register(ModelEntry {
-            name: "another-model".to_string(),
-            base_path: "./test2.gguf".into(),
-            lora_path: None,
-            template: Some("llama3".into()),
-            ctx_len: Some(4096),
-            n_threads: None,
-        });
+        registry.register(
+            ModelEntry::new("registered-model", "./test1.gguf")
+                .with_template("chatml")
+                .with_ctx_len(2048),
+        );
+        registry.register(
+            ModelEntry::new("another-model", "./test2.gguf")
+                .with_template("llama3")
+                .with_ctx_len(4096),
+        );
 
         let engine = Box::new(InferenceEngineAdapter::new());
         let state = Arc::new(AppState::new(engine, registry));
 
         // Exercise models endpoint (lines 82-96)
-        let _response = models(State(state)).await;
+        let _response = models(State(state), Query(ModelsQuery::default())).await;
 
         // The response should include the registered models
         // Test completed successfully
@@ -871,29 +2271,23 @@ mod tests {
         let mut registry = Registry::default();
 
         // Add models that are commonly used with these platforms
-        registry.register(ModelEntry {
-            name: "phi3-mini-4k-instruct".to_string(),
-            base_path: "./test-phi3.gguf".into(),
-            lora_path: None,
-            template: Some("chatml".into()),
-            ctx_len: Some(4096),
-            n_threads: None,
-        });
-
-        registry.register(ModelEntry {
-            name: "llama-3-8b-instruct".to_string(),
-            base_path: "./test-llama3.gguf".into(),
-            lora_path: None,
-            template: Some("llama3".into()),
-            ctx_len: Some(8192),
-            n_threads: None,
-        });
+        registry.register(
+            ModelEntry::new("phi3-mini-4k-instruct", "./test-phi3.gguf")
+                .with_template("chatml")
+                .with_ctx_len(4096),
+        );
+
+        registry.register(
+            ModelEntry::new("llama-3-8b-instruct", "./test-llama3.gguf")
+                .with_template("llama3")
+                .with_ctx_len(8192),
+        );
 
         let engine = Box::new(InferenceEngineAdapter::new());
         let state = Arc::new(AppState::new(engine, registry));
 
         // Test models endpoint format required by both platforms
-        let _models_response = models(State(state.clone())).await;
+        let _models_response = models(State(state.clone()), Query(ModelsQuery::default())).await;
         // Both platforms expect this to succeed and return a list
 
         // Test chat completions with system message (common in AnythingLLM)
@@ -903,17 +2297,19 @@ mod tests {
                 ChatMessage {
                     role: "system".to_string(),
                     content: "You are a helpful assistant.".to_string(),
+                    images: Vec::new(),
                 },
                 ChatMessage {
                     role: "user".to_string(),
                     content: "Hello!".to_string(),
+                    images: Vec::new(),
                 },
             ],
             stream: Some(false),
             temperature: Some(0.7),
             max_tokens: Some(100),
             top_p: Some(0.9),
-            stop: None,
+            ..Default::default()
         };
 
         // Skip actual model loading in tests - models don't exist
@@ -925,16 +2321,16 @@ mod tests {
             messages: vec![ChatMessage {
                 role: "user".to_string(),
                 content: "Count to 3".to_string(),
+                images: Vec::new(),
             }],
             stream: Some(true),
             temperature: Some(0.5),
             max_tokens: Some(50),
-            top_p: None,
-            stop: None,
+            ..Default::default()
         };
 
         // Skip actual model loading in tests - models don't exist
-        // let _streaming_response = chat_completions(State(state), Json(streaming_request)).await;
+        // let _streaming_response = chat_completions(State(state), HeaderMap::new(), Json(streaming_request)).await;
         // Test completed successfully - exercises the integration paths
     }
 
@@ -986,15 +2382,14 @@ mod tests {
             messages: vec![ChatMessage {
                 role: "user".to_string(),
                 content: "This should fail".to_string(),
+                images: Vec::new(),
             }],
             stream: Some(false),
-            temperature: None,
-            max_tokens: None,
-            top_p: None,
-            stop: None,
+            ..Default::default()
         };
 
-        let _response = chat_completions(State(state), Json(invalid_request)).await;
+        let _response =
+            chat_completions(State(state), HeaderMap::new(), Json(invalid_request)).await;
 
         // Should return proper HTTP status and error format
         // Both Open WebUI and AnythingLLM expect proper error handling
@@ -1016,6 +2411,7 @@ mod tests {
                 message: ChatMessage {
                     role: "assistant".to_string(),
                     content: "Hello world".to_string(),
+                    images: Vec::new(),
                 },
                 finish_reason: Some("stop".to_string()),
             }],
@@ -1024,6 +2420,7 @@ mod tests {
                 completion_tokens: 5,
                 total_tokens: 15,
             },
+            sampling_params: None,
         };
 
         // Serialize to JSON to verify structure
@@ -1046,12 +2443,14 @@ mod tests {
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    tags: Vec::new(),
                 },
                 ListModel {
                     id: "test-model-2".to_string(),
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    tags: Vec::new(),
                 },
             ],
         };
@@ -1092,4 +2491,140 @@ mod tests {
         assert_eq!(choice["delta"]["content"], "Hello");
         assert!(choice["finish_reason"].is_null());
     }
+
+    #[tokio::test]
+    async fn test_context_exceeded_maps_to_400() {
+        let state = words_state();
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Fail(|| {
+                crate::engine::EngineError::ContextExceeded {
+                    prompt_tokens: 5000,
+                    ctx_len: 4096,
+                }
+            }))),
+            state.registry.clone(),
+        ));
+
+        let response = chat_completions(
+            State(state),
+            HeaderMap::new(),
+            Json(words_request(10, false)),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(body["error"]["code"], "context_length_exceeded");
+        assert!(body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("context window of 4096"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_stream_finishes_with_error() {
+        let state = words_state();
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Fail(|| {
+                crate::engine::EngineError::ContextExceeded {
+                    prompt_tokens: 5000,
+                    ctx_len: 4096,
+                }
+            }))),
+            state.registry.clone(),
+        ));
+
+        let response = chat_completions(
+            State(state),
+            HeaderMap::new(),
+            Json(words_request(10, true)),
+        )
+        .await
+        .into_response();
+        let body = response_body(response).await;
+        let final_chunk = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .rfind(|data| *data != "[DONE]")
+            .unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(final_chunk).unwrap();
+        assert_eq!(parsed["choices"][0]["finish_reason"], "error");
+    }
+
+    #[test]
+    fn test_content_array_parses_text_and_image() {
+        let json = r#"{
+            "model": "minicpm-v",
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "What is in this picture?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+                ]
+            }]
+        }"#;
+
+        let request: ChatCompletionRequest = serde_json::from_str(json).unwrap();
+        let message = &request.messages[0];
+        assert_eq!(message.content, "What is in this picture?");
+        assert_eq!(
+            message.images,
+            vec!["data:image/png;base64,AAAA".to_string()]
+        );
+        assert_eq!(
+            latest_image(&request.messages),
+            Some("data:image/png;base64,AAAA")
+        );
+    }
+
+    #[test]
+    fn test_plain_string_content_has_no_images() {
+        let json = r#"{"model": "m", "messages": [{"role": "user", "content": "hi"}]}"#;
+        let request: ChatCompletionRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.messages[0].content, "hi");
+        assert!(latest_image(&request.messages).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generate_chat_routes_images_to_vision() {
+        let model = MockEngine::new(Reply::Echo).with_vision().model("vision");
+        let opts = crate::engine::GenOptions::default();
+        let (text, _) = generate_chat(&model, None, "hello", opts.clone(), None)
+            .await
+            .unwrap();
+        assert_eq!(text, "vision: hello");
+
+        let (text, reason) = generate_chat(&model, Some(&[1, 2, 3]), "hello", opts, None)
+            .await
+            .unwrap();
+        assert_eq!(text, "vision 3 bytes: hello");
+        // The backend's own reason, not one guessed from the output length
+        assert_eq!(reason, crate::engine::FinishReason::Length);
+    }
+
+    #[cfg(feature = "vision")]
+    #[tokio::test]
+    async fn test_data_url_image_part_is_decoded_and_preprocessed() {
+        use base64::Engine as _;
+
+        let mut png = Vec::new();
+        image::RgbImage::new(4, 4)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+        let url = format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(&png)
+        );
+
+        assert_eq!(decode_data_url(&url).unwrap(), png);
+        let payload = load_image_part(&url).await.unwrap();
+        assert!(image::load_from_memory(&payload).is_ok());
+
+        assert!(decode_data_url("data:image/png,raw").is_err());
+        assert!(load_image_part("file:///etc/passwd").await.is_err());
+    }
 }
@@ -0,0 +1,187 @@
+// Fleet-wide model validation
+//
+// `shimmy probe --all` loads every available model, drops it again, and
+// reports a pass/fail line per model, so a deployment can be checked in one
+// step. Loads run a few at a time (`--concurrency`) rather than all at once,
+// since each one holds its weights in memory until it is dropped. Models whose
+// estimated memory exceeds `--memory-ceiling-mb` fail without being loaded.
+
+use crate::engine::{InferenceEngine, ModelSpec};
+use crate::util::memory::{check_ceiling, LoadEstimate};
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+use std::time::Instant;
+
+/// Outcome of loading one model
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProbeResult {
+    pub name: String,
+    pub ok: bool,
+    pub elapsed_ms: u64,
+    /// Estimated resident memory (weights + KV cache)
+    pub estimated_memory_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Load `spec` and unload it again, refusing up front if its estimate is
+/// over `ceiling` bytes
+pub async fn probe_one(
+    engine: &dyn InferenceEngine,
+    spec: &ModelSpec,
+    ceiling: Option<u64>,
+) -> ProbeResult {
+    let started = Instant::now();
+    let estimate = LoadEstimate::for_spec(spec);
+    let outcome = match ceiling.map(|c| check_ceiling(&spec.name, &estimate, 0, c)) {
+        Some(Err(e)) => Err(e.into()),
+        // Dropping the loaded model right away unloads it
+        _ => engine.load(spec).await.map(drop),
+    };
+    ProbeResult {
+        name: spec.name.clone(),
+        ok: outcome.is_ok(),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        estimated_memory_bytes: estimate.total_bytes,
+        error: outcome.err().map(|e: anyhow::Error| format!("{:#}", e)),
+    }
+}
+
+/// Probe each spec, at most `concurrency` at a time. Results come back in
+/// the order of `specs`.
+pub async fn probe_all(
+    engine: &dyn InferenceEngine,
+    specs: Vec<ModelSpec>,
+    concurrency: usize,
+    ceiling: Option<u64>,
+) -> Vec<ProbeResult> {
+    stream::iter(specs)
+        .map(|spec| async move { probe_one(engine, &spec, ceiling).await })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+pub fn all_passed(results: &[ProbeResult]) -> bool {
+    results.iter().all(|r| r.ok)
+}
+
+/// Pass/fail table with a summary line
+pub fn render_table(results: &[ProbeResult]) -> String {
+    let width = results
+        .iter()
+        .map(|r| r.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("MODEL".len());
+    let mut out = format!(
+        "{:<width$}  {:<6}  {:>8}  {:>9}  ERROR\n",
+        "MODEL", "RESULT", "TIME", "MEMORY"
+    );
+    for r in results {
+        out.push_str(&format!(
+            "{:<width$}  {:<6}  {:>6}ms  {:>7}MB  {}\n",
+            r.name,
+            if r.ok { "pass" } else { "FAIL" },
+            r.elapsed_ms,
+            r.estimated_memory_bytes / (1024 * 1024),
+            r.error.as_deref().unwrap_or(""),
+        ));
+    }
+    let failed = results.iter().filter(|r| !r.ok).count();
+    out.push_str(&format!(
+        "{} passed, {} failed\n",
+        results.len() - failed,
+        failed
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::mock::{MockEngine, Reply};
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    fn spec(name: &str) -> ModelSpec {
+        ModelSpec {
+            name: name.to_string(),
+            base_path: format!("/models/{}.gguf", name).into(),
+            lora_path: None,
+            template: None,
+            ctx_len: 4096,
+            n_threads: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mixed_fleet_reports_each_model_and_fails_overall() {
+        let engine = MockEngine::new(Reply::Text(""))
+            .with_load_delay(Duration::from_millis(10))
+            .with_broken_models("broken");
+        let specs = ["phi3", "broken-llama", "qwen", "broken-gemma", "mistral"]
+            .into_iter()
+            .map(spec)
+            .collect();
+
+        let results = probe_all(&engine, specs, 2, None).await;
+        let outcome: Vec<(&str, bool)> = results.iter().map(|r| (r.name.as_str(), r.ok)).collect();
+        assert_eq!(
+            outcome,
+            vec![
+                ("phi3", true),
+                ("broken-llama", false),
+                ("qwen", true),
+                ("broken-gemma", false),
+                ("mistral", true),
+            ]
+        );
+        assert!(!all_passed(&results));
+        assert!(results[1]
+            .error
+            .as_deref()
+            .is_some_and(|e| e.contains("bad magic")));
+        assert!(engine.stats.peak_live.load(Ordering::SeqCst) <= 2);
+        assert_eq!(
+            engine.stats.live.load(Ordering::SeqCst),
+            0,
+            "models unloaded"
+        );
+
+        let table = render_table(&results);
+        assert!(table.contains("broken-gemma  FAIL"));
+        assert!(table.ends_with("3 passed, 2 failed\n"));
+    }
+
+    #[tokio::test]
+    async fn test_healthy_fleet_passes() {
+        let engine = MockEngine::new(Reply::Text(""))
+            .with_load_delay(Duration::from_millis(10))
+            .with_broken_models("broken");
+        let results = probe_all(&engine, vec![spec("phi3"), spec("qwen")], 4, None).await;
+        assert!(all_passed(&results));
+        let json = serde_json::to_value(&results).unwrap();
+        assert!(json[0].get("error").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_model_over_ceiling_fails_without_loading() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.gguf");
+        std::fs::write(&path, vec![0u8; 4096]).unwrap();
+        let mut big = spec("big");
+        big.base_path = path;
+
+        let engine = MockEngine::new(Reply::Text(""))
+            .with_load_delay(Duration::from_millis(10))
+            .with_broken_models("broken");
+        let result = probe_one(&engine, &big, Some(1024)).await;
+        assert!(!result.ok);
+        assert_eq!(result.estimated_memory_bytes, 4096);
+        assert!(result.error.unwrap().contains("memory ceiling"));
+        assert_eq!(engine.stats.peak_live.load(Ordering::SeqCst), 0);
+
+        assert!(probe_one(&engine, &big, None).await.ok);
+    }
+}
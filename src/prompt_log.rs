@@ -0,0 +1,122 @@
+// Sampled prompt logging
+//
+// Logging every prompt is too verbose (and too sensitive) for production, but
+// logging none makes bad generations hard to debug. With
+// `--log-prompt-sample-rate R`, each finished generation logs its full prompt
+// and response at debug level with probability R, scrubbed by the
+// `--redact-config` patterns; every other request logs only metadata.
+
+use crate::engine::estimate_tokens;
+use crate::redact::Redactor;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::{Arc, Mutex};
+
+/// Decides per request whether prompt/response text is logged. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct PromptLogger {
+    rate: f64,
+    redactor: Redactor,
+    rng: Arc<Mutex<StdRng>>,
+}
+
+impl Default for PromptLogger {
+    fn default() -> Self {
+        Self::new(0.0, Redactor::default())
+    }
+}
+
+impl PromptLogger {
+    /// Log content for a `rate` fraction of requests (clamped to 0.0..=1.0)
+    pub fn new(rate: f64, redactor: Redactor) -> Self {
+        Self::with_rng(rate, redactor, StdRng::from_entropy())
+    }
+
+    /// Like `new`, with a reproducible sampling sequence
+    pub fn with_seed(rate: f64, redactor: Redactor, seed: u64) -> Self {
+        Self::with_rng(rate, redactor, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rate: f64, redactor: Redactor, rng: StdRng) -> Self {
+        Self {
+            rate: if rate.is_nan() {
+                0.0
+            } else {
+                rate.clamp(0.0, 1.0)
+            },
+            redactor,
+            rng: Arc::new(Mutex::new(rng)),
+        }
+    }
+
+    fn sampled(&self) -> bool {
+        if self.rate <= 0.0 {
+            return false;
+        }
+        if self.rate >= 1.0 {
+            return true;
+        }
+        let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+        rng.gen::<f64>() < self.rate
+    }
+
+    /// Log one finished generation. Returns whether the prompt and response
+    /// text were included.
+    pub fn record(&self, model: &str, prompt: &str, response: &str, status: u16) -> bool {
+        let sampled = self.sampled();
+        if sampled {
+            tracing::debug!(
+                model,
+                status,
+                prompt = %self.redactor.redact(prompt),
+                response = %self.redactor.redact(response),
+                "Generation finished"
+            );
+        } else {
+            tracing::debug!(
+                model,
+                status,
+                prompt_tokens = estimate_tokens(prompt),
+                completion_tokens = estimate_tokens(response),
+                "Generation finished"
+            );
+        }
+        sampled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logged(logger: &PromptLogger, requests: usize) -> usize {
+        (0..requests)
+            .filter(|_| logger.record("m", "hello", "world", 200))
+            .count()
+    }
+
+    #[test]
+    fn test_rate_one_always_logs_and_zero_never_does() {
+        let always = PromptLogger::with_seed(1.0, Redactor::default(), 7);
+        assert_eq!(logged(&always, 200), 200);
+        let never = PromptLogger::with_seed(0.0, Redactor::default(), 7);
+        assert_eq!(logged(&never, 200), 0);
+        // Out-of-range rates are clamped
+        assert_eq!(logged(&PromptLogger::new(3.0, Redactor::default()), 10), 10);
+        assert_eq!(logged(&PromptLogger::new(-1.0, Redactor::default()), 10), 0);
+    }
+
+    #[test]
+    fn test_fractional_rate_is_reproducible_with_a_seed() {
+        let sample = |seed| {
+            let logger = PromptLogger::with_seed(0.25, Redactor::default(), seed);
+            (0..400)
+                .map(|_| logger.record("m", "p", "r", 200))
+                .collect::<Vec<_>>()
+        };
+        let first = sample(42);
+        assert_eq!(first, sample(42));
+        let count = first.iter().filter(|&&s| s).count();
+        assert!((60..140).contains(&count), "sampled {} of 400", count);
+    }
+}
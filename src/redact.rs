@@ -0,0 +1,88 @@
+// PII redaction for logged prompt/response text
+//
+// `--redact-config <path>` points at a file with one regex per line (blank
+// lines and `#` comments are ignored). Matches are replaced with
+// `[REDACTED]` before prompt or response text reaches the audit log. The
+// model always sees the original text; only what is written out is scrubbed.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::Arc;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Compiled redaction patterns. Cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    patterns: Arc<Vec<Regex>>,
+}
+
+impl Redactor {
+    pub fn new(patterns: &[&str]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|p| Regex::new(p).with_context(|| format!("invalid redaction pattern '{}'", p)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            patterns: Arc::new(patterns),
+        })
+    }
+
+    /// Load patterns from a file. Unlike most config this fails hard: running
+    /// with a broken pattern would silently log what it was meant to scrub.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading redaction config {}", path.display()))?;
+        let patterns: Vec<&str> = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        Self::new(&patterns)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// `text` with every pattern match replaced by `[REDACTED]`
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut out = Cow::Borrowed(text);
+        for pattern in self.patterns.iter() {
+            if let Cow::Owned(replaced) = pattern.replace_all(&out, REDACTED) {
+                out = Cow::Owned(replaced);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns_are_replaced() {
+        let redactor = Redactor::new(&[r"[\w.+-]+@[\w-]+\.[\w.]+", r"\+?\d[\d -]{7,}\d"]).unwrap();
+        assert_eq!(
+            redactor.redact("mail jane.doe@example.com or call +1 555 123 4567"),
+            "mail [REDACTED] or call [REDACTED]"
+        );
+        assert!(matches!(redactor.redact("nothing here"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_load_skips_comments_and_rejects_bad_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redact.txt");
+        std::fs::write(&path, "# emails\n[\\w.]+@[\\w.]+\n\n").unwrap();
+        let redactor = Redactor::load(&path).unwrap();
+        assert_eq!(redactor.redact("a@b.io"), REDACTED);
+
+        std::fs::write(&path, "(unclosed\n").unwrap();
+        assert!(Redactor::load(&path).is_err());
+        assert!(Redactor::default().is_empty());
+    }
+}
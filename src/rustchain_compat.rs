@@ -25,13 +25,10 @@ pub async fn rustchain_generate(
     let _shimmy_request = crate::api::GenerateRequest {
         model: request.model.unwrap_or_default(),
         prompt: Some(request.prompt),
-        messages: None,
-        system: None,
         max_tokens: request.max_tokens.map(|t| t as usize),
         temperature: request.temperature,
-        top_p: None,
-        top_k: None,
         stream: Some(false),
+        ..Default::default()
     };
 
     // For now, return a placeholder response since we don't have the full server context
@@ -1,15 +1,281 @@
-use crate::{anthropic_compat, api, openai_compat, util::diag::diag_handler, AppState};
+use crate::{
+    anthropic_compat, api, openai_compat,
+    util::diag::diag_handler,
+    util::features::{capabilities_handler, features_handler, Features, CAPABILITIES_HEADER},
+    util::json_output::pretty_json_layer,
+    AppState,
+};
 use axum::extract::Request;
 use axum::{
     extract::State,
     http::{HeaderValue, Method},
     middleware::{self, Next},
-    response::Response,
+    response::{
+        sse::{Event, KeepAlive},
+        Html, IntoResponse, Response, Sse,
+    },
     routing::{get, post},
     Json, Router,
 };
 use serde_json::{json, Value};
 use std::{net::SocketAddr, sync::Arc};
+use tower_http::compression::CompressionLayer;
+
+/// Four times `estimate_tokens`' 4 bytes per token: no tokenizer we load
+/// averages longer tokens than this over a whole prompt
+const MAX_BYTES_PER_TOKEN: usize = 16;
+
+/// Runtime options for the HTTP server, set from `serve` flags
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Compress responses when the client sends `Accept-Encoding`
+    pub compression: bool,
+    /// Reject prompts longer than this many tokens (unlimited when `None`)
+    pub max_prompt_tokens: Option<usize>,
+    /// Reject `/api/batch` bodies with more items than this
+    /// (`--max-batch-items`)
+    pub max_batch_items: usize,
+    /// Unload pooled models idle for longer than this (never when `None`;
+    /// `--idle-unload-secs` defaults to five minutes)
+    pub idle_unload: Option<std::time::Duration>,
+    /// Send an SSE keep-alive comment after this long without an event
+    /// (disabled when `None`)
+    pub sse_keep_alive: Option<std::time::Duration>,
+    /// Print curl examples and open the index page once listening (`--open`)
+    pub open_browser: bool,
+    /// Merge same-role runs and drop repeated system messages before
+    /// rendering chat prompts (`--normalize-messages`)
+    pub normalize_messages: bool,
+    /// Write a JSON readiness file here once listening, removed on shutdown
+    /// (`--ready-file`)
+    pub ready_file: Option<std::path::PathBuf>,
+    /// Extra attempts for transient engine errors (`--transient-retries`)
+    pub transient_retries: u32,
+    /// Pause before retrying a transient engine error
+    pub transient_retry_delay: std::time::Duration,
+    /// Write the final metrics snapshot here as JSON once shutdown has
+    /// drained in-flight requests (`--metrics-dump`)
+    pub metrics_dump: Option<std::path::PathBuf>,
+    /// Model used when a chat request omits `model` (`--default-model`)
+    pub default_model: Option<String>,
+    /// Loaded in the background once listening and never unloaded for being
+    /// idle (the `--model-path` model, else `--default-model`)
+    pub preload_model: Option<String>,
+    /// Report applied sampling settings on every generation unless the
+    /// request says otherwise (`--echo-params`)
+    pub echo_params: bool,
+    /// Reject out-of-range temperature/top_p/top_k instead of clamping them
+    /// (`--strict-sampling`)
+    pub strict_sampling: bool,
+    /// Require this key as `Authorization: Bearer <key>` or `x-api-key`
+    /// (`--api-key`, env SHIMMY_API_KEY); no authentication when `None`
+    pub api_key: Option<String>,
+    /// Paths served without the key (`--auth-exempt`)
+    pub auth_exempt: Vec<String>,
+    /// Debug-logs full prompt/response text for a sample of generations
+    /// (`--log-prompt-sample-rate`)
+    pub prompt_log: crate::prompt_log::PromptLogger,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            compression: true,
+            max_prompt_tokens: None,
+            max_batch_items: 100,
+            idle_unload: Some(std::time::Duration::from_secs(300)),
+            sse_keep_alive: Some(std::time::Duration::from_secs(15)),
+            open_browser: false,
+            normalize_messages: false,
+            ready_file: None,
+            transient_retries: 1,
+            transient_retry_delay: std::time::Duration::from_millis(250),
+            metrics_dump: None,
+            default_model: None,
+            preload_model: None,
+            echo_params: false,
+            strict_sampling: false,
+            api_key: None,
+            auth_exempt: vec!["/health".to_string()],
+            prompt_log: crate::prompt_log::PromptLogger::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Build an SSE response, adding `: keep-alive` comments while the stream
+    /// is idle so reverse proxies don't drop long generations
+    pub fn sse_response<S>(&self, stream: S) -> Response
+    where
+        S: futures_util::Stream<Item = Result<Event, std::convert::Infallible>> + Send + 'static,
+    {
+        match self.sse_keep_alive {
+            Some(interval) => Sse::new(stream)
+                .keep_alive(KeepAlive::new().interval(interval).text("keep-alive"))
+                .into_response(),
+            None => Sse::new(stream).into_response(),
+        }
+    }
+
+    /// Run `attempt`, retrying up to `transient_retries` times after a
+    /// transient `EngineError`. Other errors are returned immediately.
+    pub async fn retry_transient<T, F, Fut>(
+        &self,
+        operation: &str,
+        mut attempt: F,
+    ) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = anyhow::Result<T>>,
+    {
+        let mut retries = 0;
+        loop {
+            match attempt().await {
+                Err(e)
+                    if retries < self.transient_retries
+                        && crate::engine::EngineError::find(&e)
+                            .is_some_and(crate::engine::EngineError::is_transient) =>
+                {
+                    retries += 1;
+                    tracing::warn!(
+                        "{} failed ({}); retrying in {}ms ({}/{})",
+                        operation,
+                        e,
+                        self.transient_retry_delay.as_millis(),
+                        retries,
+                        self.transient_retries
+                    );
+                    tokio::time::sleep(self.transient_retry_delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Chat messages as they should be rendered, normalized when
+    /// `normalize_messages` is on
+    pub fn prepare_messages(&self, messages: Vec<(String, String)>) -> Vec<(String, String)> {
+        if !self.normalize_messages {
+            return messages;
+        }
+        let (normalized, removed) = crate::templates::normalize_messages(&messages);
+        if removed > 0 {
+            tracing::debug!(
+                "Normalized chat messages: {} -> {} ({} merged or dropped)",
+                messages.len(),
+                normalized.len(),
+                removed
+            );
+        }
+        normalized
+    }
+
+    /// Check a rendered prompt's token count, from the loaded model's
+    /// tokenizer, against `max_prompt_tokens`
+    pub fn check_prompt_length(&self, tokens: usize) -> Result<(), String> {
+        let Some(limit) = self.max_prompt_tokens else {
+            return Ok(());
+        };
+        if tokens > limit {
+            return Err(format!(
+                "Prompt is {} tokens, exceeding the maximum of {} tokens (--max-prompt-tokens)",
+                tokens, limit
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check a rendered prompt against `max_prompt_tokens` before loading a
+    /// model to count it: rejects only prompts too long for any tokenizer to
+    /// bring under the limit, so oversized requests don't force a load.
+    /// `check_prompt_length` on the exact count catches the rest.
+    pub fn precheck_prompt_length(&self, prompt: &str) -> Result<(), String> {
+        let Some(limit) = self.max_prompt_tokens else {
+            return Ok(());
+        };
+        let min_tokens = prompt.len().div_ceil(MAX_BYTES_PER_TOKEN);
+        if min_tokens > limit {
+            return Err(format!(
+                "Prompt is at least {} tokens, exceeding the maximum of {} tokens (--max-prompt-tokens)",
+                min_tokens, limit
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reject batches with more than `max_batch_items` items
+    pub fn check_batch_size(&self, items: usize) -> Result<(), String> {
+        if items > self.max_batch_items {
+            return Err(format!(
+                "Batch has {} requests, exceeding the maximum of {} (--max-batch-items)",
+                items, self.max_batch_items
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Reject requests without the configured API key, except CORS preflights
+/// and the `auth_exempt` paths
+async fn api_key_layer(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let config = &state.server_config;
+    let Some(expected) = config.api_key.as_deref() else {
+        return next.run(req).await;
+    };
+    if req.method() == Method::OPTIONS
+        || config
+            .auth_exempt
+            .iter()
+            .any(|path| path == req.uri().path())
+    {
+        return next.run(req).await;
+    }
+    let authorized = {
+        let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok());
+        header("authorization")
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .or_else(|| header("x-api-key"))
+            .is_some_and(|key| keys_match(key, expected))
+    };
+    if authorized {
+        return next.run(req).await;
+    }
+    let error = json!({
+        "error": {
+            "message": "Missing or invalid API key. Send it as `Authorization: Bearer <key>`.",
+            "type": "invalid_request_error",
+            "param": null,
+            "code": "invalid_api_key"
+        }
+    });
+    (
+        axum::http::StatusCode::UNAUTHORIZED,
+        [(axum::http::header::WWW_AUTHENTICATE, "Bearer")],
+        Json(error),
+    )
+        .into_response()
+}
+
+/// Compare without returning early on the first differing byte
+fn keys_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Advertise what this build supports on every response, so clients can pick
+/// request options without probing for 400s
+async fn capabilities_layer(req: Request, next: Next) -> Response {
+    let mut response = next.run(req).await;
+    let capabilities = Features::compiled().capabilities().join(",");
+    if let Ok(value) = HeaderValue::from_str(&capabilities) {
+        response.headers_mut().insert(CAPABILITIES_HEADER, value);
+    }
+    response
+}
 
 /// CORS middleware for better client compatibility
 async fn cors_layer(req: Request, next: Next) -> Response {
@@ -24,9 +290,13 @@ async fn cors_layer(req: Request, next: Next) -> Response {
     );
     headers.insert(
         "Access-Control-Allow-Headers",
-        HeaderValue::from_static("Content-Type, Authorization"),
+        HeaderValue::from_static("Content-Type, Authorization, x-api-key"),
     );
     headers.insert("Access-Control-Max-Age", HeaderValue::from_static("86400"));
+    headers.insert(
+        "Access-Control-Expose-Headers",
+        HeaderValue::from_static(CAPABILITIES_HEADER),
+    );
 
     // Handle preflight OPTIONS requests
     if method == Method::OPTIONS {
@@ -36,11 +306,157 @@ async fn cors_layer(req: Request, next: Next) -> Response {
     response
 }
 
+/// An endpoint listed on the index page and in `--open` output
+struct Endpoint {
+    method: &'static str,
+    path: &'static str,
+    description: &'static str,
+    /// Example JSON body for POST endpoints
+    body: Option<&'static str>,
+}
+
+const ENDPOINTS: &[Endpoint] = &[
+    Endpoint {
+        method: "GET",
+        path: "/health",
+        description: "Health check and loaded models",
+        body: None,
+    },
+    Endpoint {
+        method: "GET",
+        path: "/v1/models",
+        description: "List available models (OpenAI-compatible)",
+        body: None,
+    },
+    Endpoint {
+        method: "POST",
+        path: "/v1/chat/completions",
+        description: "Chat completions (OpenAI-compatible)",
+        body: Some(r#"{"model":"MODEL","messages":[{"role":"user","content":"Hello"}]}"#),
+    },
+    Endpoint {
+        method: "POST",
+        path: "/v1/messages",
+        description: "Messages (Anthropic-compatible)",
+        body: Some(
+            r#"{"model":"MODEL","max_tokens":64,"messages":[{"role":"user","content":"Hello"}]}"#,
+        ),
+    },
+    Endpoint {
+        method: "POST",
+        path: "/api/generate",
+        description: "Native generation, streaming or not",
+        body: Some(r#"{"model":"MODEL","prompt":"Hello","stream":false}"#),
+    },
+    Endpoint {
+        method: "POST",
+        path: "/api/batch",
+        description: "Many generations in one call (JSON array or JSONL)",
+        body: Some(r#"[{"model":"MODEL","prompt":"Hello"},{"model":"MODEL","prompt":"Hi"}]"#),
+    },
+    Endpoint {
+        method: "GET",
+        path: "/metrics",
+        description: "Server and model metrics",
+        body: None,
+    },
+    Endpoint {
+        method: "GET",
+        path: "/api/features",
+        description: "Compiled features and available backends",
+        body: None,
+    },
+    Endpoint {
+        method: "GET",
+        path: "/api/capabilities",
+        description: "Supported request features, overall and per model",
+        body: None,
+    },
+];
+
+/// A curl command line for each endpoint
+pub fn endpoint_examples(base_url: &str) -> Vec<String> {
+    ENDPOINTS
+        .iter()
+        .map(|endpoint| match endpoint.body {
+            Some(body) => format!(
+                "curl {}{} -H 'Content-Type: application/json' -d '{}'",
+                base_url, endpoint.path, body
+            ),
+            None => format!("curl {}{}", base_url, endpoint.path),
+        })
+        .collect()
+}
+
+/// Minimal built-in index page listing the endpoints
+async fn index_page() -> Html<String> {
+    let rows: String = ENDPOINTS
+        .iter()
+        .map(|endpoint| {
+            format!(
+                "<tr><td><code>{}</code></td><td><code>{}</code></td><td>{}</td></tr>\n",
+                endpoint.method, endpoint.path, endpoint.description
+            )
+        })
+        .collect();
+    Html(format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>shimmy</title></head>\n<body>\n\
+         <h1>shimmy {}</h1>\n<p>OpenAI- and Anthropic-compatible local inference server.</p>\n\
+         <table>\n<tr><th>Method</th><th>Path</th><th>Description</th></tr>\n{}</table>\n</body>\n</html>\n",
+        env!("CARGO_PKG_VERSION"),
+        rows
+    ))
+}
+
+/// Print curl examples and open the index page in the default browser
+fn announce_endpoints(addr: SocketAddr) {
+    // A wildcard bind isn't something a browser can connect to
+    let host = if addr.ip().is_unspecified() {
+        "127.0.0.1".to_string()
+    } else {
+        addr.ip().to_string()
+    };
+    let base_url = format!("http://{}:{}", host, addr.port());
+    println!("🌐 Try it:");
+    for example in endpoint_examples(&base_url) {
+        println!("   {}", example);
+    }
+    open_in_browser(&format!("{}/", base_url));
+}
+
+/// Open `url` with the desktop's default browser; skipped on headless Linux
+fn open_in_browser(url: &str) {
+    let (program, args): (&str, Vec<&str>) = if cfg!(target_os = "macos") {
+        ("open", vec![url])
+    } else if cfg!(windows) {
+        ("cmd", vec!["/C", "start", "", url])
+    } else if std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+    {
+        ("xdg-open", vec![url])
+    } else {
+        println!("   No desktop session detected; open {} in a browser", url);
+        return;
+    };
+    if let Err(e) = std::process::Command::new(program)
+        .args(&args)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+    {
+        tracing::warn!("Could not open a browser with {}: {}", program, e);
+    }
+}
+
 /// Enhanced health check endpoint for production use
 async fn health_check(State(state): State<Arc<AppState>>) -> Json<Value> {
     let models = state.registry.list_all_available();
     let discovered = state.registry.discovered_models.len();
     let manual = state.registry.list().len();
+    let loaded = state.model_pool.loaded_model_status().await;
+    state
+        .observability
+        .update_loaded_models(loaded.clone())
+        .await;
 
     Json(json!({
         "status": "ok",
@@ -49,8 +465,11 @@ async fn health_check(State(state): State<Arc<AppState>>) -> Json<Value> {
         "models": {
             "total": models.len(),
             "discovered": discovered,
-            "manual": manual
+            "manual": manual,
+            "loaded": loaded.len()
         },
+        // Resident in the model pool, unlike the available counts above
+        "loaded_models": loaded,
         "endpoints": {
             "health": "/health",
             "models": "/v1/models",
@@ -122,7 +541,10 @@ async fn metrics_endpoint(State(state): State<Arc<AppState>>) -> Json<Value> {
             "/v1/chat/completions",
             "/v1/models",
             "/api/generate",
-            "/api/models"
+            "/api/batch",
+            "/api/models",
+            "/api/features",
+            "/api/capabilities"
         ],
         "timestamp": chrono::Utc::now().to_rfc3339()
     }))
@@ -130,24 +552,175 @@ async fn metrics_endpoint(State(state): State<Arc<AppState>>) -> Json<Value> {
 
 pub async fn run(addr: SocketAddr, state: Arc<AppState>) -> anyhow::Result<()> {
     let listener = tokio::net::TcpListener::bind(addr).await?;
+    serve(listener, state, shutdown_signal()).await
+}
+
+/// Serve on an already-bound listener until `shutdown` resolves, then finish
+/// in-flight requests
+pub async fn serve<F>(
+    listener: tokio::net::TcpListener,
+    state: Arc<AppState>,
+    shutdown: F,
+) -> anyhow::Result<()>
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    let local_addr = listener.local_addr()?;
+    if state.server_config.open_browser {
+        announce_endpoints(local_addr);
+    }
+    // Runs even with --idle-unload-secs 0, for requests that set keep_alive
+    state
+        .model_pool
+        .start_idle_unload_task(state.server_config.idle_unload, state.observability.clone());
+    if let Some(name) = state.server_config.preload_model.clone() {
+        tokio::spawn(preload_startup_model(Arc::clone(&state), name));
+    }
+    let ready_file = state.server_config.ready_file.clone();
+    let metrics_dump = state.server_config.metrics_dump.clone();
+    let observability = state.observability.clone();
+    #[cfg(feature = "vision")]
+    let vision_license_manager = state.vision_license_manager.clone();
+    if let Some(path) = &ready_file {
+        // The listener is already accepting, so this is the ready point
+        write_ready_file(path, local_addr, state.registry.list_all_available().len())?;
+    }
+    let app = router(state);
+
+    // Withdraw readiness as soon as shutdown starts, not once draining ends
+    let shutdown_ready_file = ready_file.clone();
+    let shutdown = async move {
+        shutdown.await;
+        if let Some(path) = shutdown_ready_file {
+            remove_ready_file(&path);
+        }
+    };
+    let result = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await;
+    if let Some(path) = &ready_file {
+        remove_ready_file(path);
+    }
+    // Drained, so the snapshot covers every request this run served
+    if let Some(path) = &metrics_dump {
+        match observability.write_snapshot(path).await {
+            Ok(()) => tracing::info!("Wrote final metrics to {}", path.display()),
+            Err(e) => tracing::warn!("Failed to write metrics to {}: {}", path.display(), e),
+        }
+    }
+    #[cfg(feature = "vision")]
+    if let Some(manager) = &vision_license_manager {
+        if let Err(e) = manager.flush_usage().await {
+            tracing::warn!("Failed to write vision usage stats: {}", e);
+        }
+    }
+    result?;
+    Ok(())
+}
+
+/// Resolve on Ctrl+C or SIGTERM. A second Ctrl+C exits immediately in case
+/// draining hangs on a long stream.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    println!("🛑 Shutting down; waiting for in-flight requests");
+    tokio::spawn(async {
+        let _ = tokio::signal::ctrl_c().await;
+        std::process::exit(130);
+    });
+}
+
+/// Load the startup model and pin it, so the first request doesn't pay for
+/// the load
+async fn preload_startup_model(state: Arc<AppState>, name: String) {
+    let Some(spec) = state.registry.to_spec(&name) else {
+        tracing::warn!("Startup model '{}' is not available; not preloading", name);
+        return;
+    };
+    let loaded = state
+        .server_config
+        .retry_transient("Model preload", || {
+            state.model_pool.preload(&*state.engine, &spec)
+        })
+        .await;
+    match loaded {
+        Ok(()) => tracing::info!("Preloaded startup model '{}'", name),
+        Err(e) => tracing::warn!("Failed to preload startup model '{}': {}", name, e),
+    }
+}
+
+/// Atomically write the `--ready-file` JSON (temp file + rename) so readers
+/// never see a partial file
+fn write_ready_file(
+    path: &std::path::Path,
+    addr: SocketAddr,
+    model_count: usize,
+) -> anyhow::Result<()> {
+    let body = json!({
+        "pid": std::process::id(),
+        "bind": addr.to_string(),
+        "model_count": model_count,
+        "ready_at": chrono::Utc::now().to_rfc3339(),
+    });
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = std::path::PathBuf::from(tmp);
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&body)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn remove_ready_file(path: &std::path::Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("Failed to remove ready file {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Build the application router with all routes and middleware
+pub fn router(state: Arc<AppState>) -> Router {
     #[allow(unused_mut)]
     let mut app = Router::new()
+        .route("/", get(index_page))
         .route("/health", get(health_check))
         .route("/metrics", get(metrics_endpoint))
         .route("/diag", get(diag_handler))
+        .route("/api/features", get(features_handler))
+        .route("/api/capabilities", get(capabilities_handler))
         .route("/api/generate", post(api::generate))
+        .route("/api/batch", post(api::batch))
+        .route("/api/render", post(api::render))
+        .route("/api/warm", post(api::warm))
         .route("/api/models", get(api::list_models))
         .route("/api/models/discover", post(api::discover_models))
         .route("/api/models/:name/load", post(api::load_model))
         .route("/api/models/:name/unload", post(api::unload_model))
         .route("/api/models/:name/status", get(api::model_status))
+        .route("/api/models/:name/metadata", get(api::model_metadata))
         .route("/api/tools", get(api::list_tools))
         .route("/api/tools/:name/execute", post(api::execute_tool))
         .route("/api/workflows/execute", post(api::execute_workflow))
-        .route("/ws/generate", get(api::ws_generate))
         .route(
             "/v1/chat/completions",
-            post(openai_compat::chat_completions),
+            post(openai_compat::validated_chat_completions),
         )
         .route("/v1/models", get(openai_compat::models))
         // Anthropic Claude API compatibility
@@ -158,9 +731,23 @@ pub async fn run(addr: SocketAddr, state: Arc<AppState>) -> anyhow::Result<()> {
         app = app.route("/api/vision", post(api::vision));
     }
 
-    let app = app.layer(middleware::from_fn(cors_layer)).with_state(state);
-    axum::serve(listener, app).await?;
-    Ok(())
+    // `?pretty` reformats JSON bodies; runs inside compression so the
+    // compressed length matches what is sent
+    app = app.layer(middleware::from_fn(pretty_json_layer));
+
+    // The default compression predicate skips `text/event-stream`, so SSE
+    // streams from the generate/chat routes are never buffered.
+    if state.server_config.compression {
+        app = app.layer(CompressionLayer::new());
+    }
+
+    // WebSocket upgrades are added after compression so they bypass it.
+    // Auth sits inside CORS so rejections still carry CORS headers.
+    app.route("/ws/generate", get(api::ws_generate))
+        .layer(middleware::from_fn_with_state(state.clone(), api_key_layer))
+        .layer(middleware::from_fn(cors_layer))
+        .layer(middleware::from_fn(capabilities_layer))
+        .with_state(state)
 }
 
 /// GPU detection for metrics endpoint
@@ -220,6 +807,7 @@ fn detect_intel() -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::mock::{MockEngine, Reply};
     use crate::model_registry::Registry;
     use tokio::time::{timeout, Duration};
 
@@ -459,4 +1047,549 @@ mod tests {
             assert!(vendor == "nvidia" || vendor == "amd" || vendor == "intel");
         }
     }
+
+    #[test]
+    fn test_check_prompt_length() {
+        let config = ServerConfig::default();
+        assert!(config.check_prompt_length(100_000).is_ok());
+
+        let config = ServerConfig {
+            max_prompt_tokens: Some(10),
+            ..Default::default()
+        };
+        assert!(config.check_prompt_length(10).is_ok());
+        let err = config.check_prompt_length(11).unwrap_err();
+        assert!(err.contains("11 tokens"));
+        assert!(err.contains("maximum of 10"));
+    }
+
+    #[test]
+    fn test_precheck_prompt_length() {
+        let config = ServerConfig::default();
+        assert!(config
+            .precheck_prompt_length(&"x".repeat(1_000_000))
+            .is_ok());
+
+        let config = ServerConfig {
+            max_prompt_tokens: Some(10),
+            ..Default::default()
+        };
+        // Up to 16 bytes per allowed token may still fit after tokenizing
+        assert!(config.precheck_prompt_length(&"x".repeat(160)).is_ok());
+        let err = config.precheck_prompt_length(&"x".repeat(161)).unwrap_err();
+        assert!(err.contains("at least 11 tokens"));
+        assert!(err.contains("maximum of 10"));
+    }
+
+    #[test]
+    fn test_check_batch_size() {
+        let config = ServerConfig {
+            max_batch_items: 3,
+            ..Default::default()
+        };
+        assert!(config.check_batch_size(3).is_ok());
+        let err = config.check_batch_size(4).unwrap_err();
+        assert!(err.contains("4 requests"));
+        assert!(err.contains("maximum of 3"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient_is_bounded_and_skips_permanent_errors() {
+        use crate::engine::EngineError;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let config = ServerConfig {
+            transient_retry_delay: std::time::Duration::from_millis(1),
+            ..Default::default()
+        };
+
+        let attempts = AtomicUsize::new(0);
+        let result: anyhow::Result<()> = config
+            .retry_transient("Model load", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(EngineError::InsufficientMemory {
+                    path: "x.gguf".into(),
+                    size_bytes: 8 << 30,
+                }
+                .into())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        // An unclassified load failure is not retried
+        let attempts = AtomicUsize::new(0);
+        let result: anyhow::Result<()> = config
+            .retry_transient("Model load", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(EngineError::LoadFailed {
+                    reason: "null result".into(),
+                }
+                .into())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let attempts = AtomicUsize::new(0);
+        let result: anyhow::Result<()> = config
+            .retry_transient("Model load", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(EngineError::ModelNotFound { name: "x".into() }.into())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_prepare_messages_only_normalizes_when_enabled() {
+        let messages = vec![
+            ("system".to_string(), "Be brief.".to_string()),
+            ("system".to_string(), "Be brief.".to_string()),
+            ("user".to_string(), "Hi".to_string()),
+        ];
+
+        let config = ServerConfig::default();
+        assert_eq!(config.prepare_messages(messages.clone()), messages);
+
+        let config = ServerConfig {
+            normalize_messages: true,
+            ..Default::default()
+        };
+        let prepared = config.prepare_messages(messages);
+        assert_eq!(prepared.len(), 2);
+        assert_eq!(prepared[0], ("system".to_string(), "Be brief.".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_ready_file_written_when_listening_and_removed_on_shutdown() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::Registry;
+
+        let dir = tempfile::tempdir().unwrap();
+        let ready_path = dir.path().join("shimmy.ready");
+        let mut state =
+            crate::AppState::new(Box::new(InferenceEngineAdapter::new()), Registry::default());
+        state.server_config.ready_file = Some(ready_path.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(listener, Arc::new(state), async {
+            let _ = stop_rx.await;
+        }));
+
+        let mut contents = None;
+        for _ in 0..100 {
+            if let Ok(text) = std::fs::read_to_string(&ready_path) {
+                contents = Some(text);
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let ready: Value = serde_json::from_str(&contents.expect("ready file")).unwrap();
+        assert_eq!(ready["bind"], addr.to_string());
+        assert_eq!(ready["pid"], std::process::id());
+        assert!(ready["model_count"].is_u64());
+        assert!(ready["ready_at"].is_string());
+
+        stop_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        assert!(!ready_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_writes_metrics_dump() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::Registry;
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let dump_path = dir.path().join("metrics.json");
+        let mut state =
+            crate::AppState::new(Box::new(InferenceEngineAdapter::new()), Registry::default());
+        state.server_config.metrics_dump = Some(dump_path.clone());
+        for success in [true, true, false] {
+            state
+                .observability
+                .record_request("phi3", Duration::from_millis(20), success)
+                .await;
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(listener, Arc::new(state), async {
+            let _ = stop_rx.await;
+        }));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!dump_path.exists(), "written only on shutdown");
+
+        stop_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        let dump: Value =
+            serde_json::from_str(&std::fs::read_to_string(&dump_path).unwrap()).unwrap();
+        assert_eq!(dump["total_requests"], 3);
+        assert_eq!(dump["failed_requests"], 1);
+        assert_eq!(dump["model_stats"]["phi3"]["requests"], 3);
+        assert_eq!(dump["model_stats"]["phi3"]["errors"], 1);
+    }
+
+    fn state_with_many_models(compression: bool) -> Arc<crate::AppState> {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        for i in 0..200 {
+            registry.register(ModelEntry::new(
+                format!("compression-test-model-{}", i),
+                format!("./models/compression-test-model-{}.gguf", i),
+            ));
+        }
+        let mut state = crate::AppState::new(Box::new(InferenceEngineAdapter::new()), registry);
+        state.server_config.compression = compression;
+        Arc::new(state)
+    }
+
+    async fn get_models(app: Router, accept_encoding: Option<&str>) -> axum::response::Response {
+        use tower::util::ServiceExt;
+
+        let mut request = axum::http::Request::builder().uri("/v1/models");
+        if let Some(encoding) = accept_encoding {
+            request = request.header("accept-encoding", encoding);
+        }
+        app.oneshot(request.body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_models_response_compressed_when_requested() {
+        let response = get_models(router(state_with_many_models(true)), Some("gzip")).await;
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert_eq!(response.headers().get("content-encoding").unwrap(), "gzip");
+
+        let response = get_models(router(state_with_many_models(true)), None).await;
+        assert!(response.headers().get("content-encoding").is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed["data"].as_array().unwrap().len(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_health_lists_loaded_models_with_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Text("ok"))),
+            Registry::default(),
+        ));
+        for (name, size) in [("alpha", 4096usize), ("beta", 1024)] {
+            let path = dir.path().join(format!("{}.gguf", name));
+            std::fs::write(&path, vec![0u8; size]).unwrap();
+            let spec = crate::engine::ModelSpec {
+                name: name.to_string(),
+                base_path: path,
+                lora_path: None,
+                template: None,
+                ctx_len: 2048,
+                n_threads: None,
+            };
+            state
+                .model_pool
+                .get_or_load(&*state.engine, &spec)
+                .await
+                .unwrap();
+        }
+
+        let Json(body) = health_check(State(Arc::clone(&state))).await;
+        assert_eq!(body["models"]["loaded"], 2);
+        let loaded = body["loaded_models"].as_array().unwrap();
+        let names: Vec<&str> = loaded.iter().map(|m| m["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["alpha", "beta"]);
+        assert_eq!(loaded[0]["estimated_memory_bytes"], 4096);
+        assert_eq!(loaded[1]["estimated_memory_bytes"], 1024);
+        assert!(loaded[0]["last_accessed"].as_u64().unwrap() > 0);
+
+        let metrics = state.observability.metrics().await;
+        assert_eq!(metrics.loaded_models.len(), 2);
+        assert!(metrics
+            .loaded_models
+            .iter()
+            .all(|m| m.estimated_memory_bytes > 0));
+    }
+
+    #[tokio::test]
+    async fn test_startup_model_is_preloaded_and_pinned() {
+        use crate::model_registry::ModelEntry;
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("startup", "./models/startup.gguf"));
+        let state = Arc::new(AppState::new(
+            Box::new(MockEngine::new(Reply::Text("ok"))),
+            registry,
+        ));
+
+        preload_startup_model(Arc::clone(&state), "startup".to_string()).await;
+        assert!(state.model_pool.is_loaded("startup").await);
+        assert!(state.model_pool.is_preloaded("startup").await);
+
+        preload_startup_model(Arc::clone(&state), "missing".to_string()).await;
+        assert!(!state.model_pool.is_loaded("missing").await);
+    }
+
+    #[tokio::test]
+    async fn test_index_page_lists_endpoints() {
+        use tower::util::ServiceExt;
+
+        let app = router(state_with_many_models(false));
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert!(response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let html = String::from_utf8(body.to_vec()).unwrap();
+        for endpoint in ENDPOINTS {
+            assert!(html.contains(endpoint.path), "{} missing", endpoint.path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_models_list_is_compact_unless_pretty_requested() {
+        use tower::util::ServiceExt;
+
+        let app = router(state_with_many_models(true));
+        let get_body = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        axum::http::Request::builder()
+                            .uri(uri)
+                            .body(axum::body::Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), axum::http::StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        let compact = get_body("/api/models").await;
+        assert!(!compact.contains('\n'));
+        let pretty = get_body("/api/models?pretty=true").await;
+        assert!(pretty.starts_with("{\n  \"models\": ["));
+        assert_eq!(pretty, crate::util::json_output::prettify(&compact));
+        assert_eq!(
+            serde_json::from_str::<Value>(&pretty).unwrap(),
+            serde_json::from_str::<Value>(&compact).unwrap()
+        );
+    }
+
+    #[cfg(not(feature = "llama"))]
+    #[tokio::test]
+    async fn test_stub_build_explains_missing_llama_and_stays_healthy() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::{ModelEntry, Registry};
+        use tower::util::ServiceExt;
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry::new("phi3", "./phi3.gguf"));
+        let state = Arc::new(crate::AppState::new(
+            Box::new(InferenceEngineAdapter::new()),
+            registry,
+        ));
+        let app = router(state);
+        let send = |request: axum::http::Request<axum::body::Body>| {
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap() }
+        };
+
+        let response = send(
+            axum::http::Request::post("/api/generate")
+                .header("content-type", "application/json")
+                .body(axum::body::Body::from(
+                    r#"{"model":"phi3","prompt":"hi","stream":false}"#,
+                ))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_IMPLEMENTED);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let error = body["error"].as_str().unwrap();
+        assert!(error.contains("without the `llama` feature"), "{}", error);
+        assert!(error.contains("cargo install shimmy --features llama"));
+
+        for uri in ["/health", "/v1/models"] {
+            let response = send(
+                axum::http::Request::get(uri)
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await;
+            assert_eq!(response.status(), axum::http::StatusCode::OK, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_header_and_route_match_compiled_features() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use tower::util::ServiceExt;
+
+        let state = AppState::new(Box::new(InferenceEngineAdapter::new()), Registry::default());
+        let app = router(Arc::new(state));
+        let expected = Features::compiled().capabilities();
+
+        let get = |uri: &str| {
+            axum::http::Request::builder()
+                .uri(uri)
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+        let health = app.clone().oneshot(get("/health")).await.unwrap();
+        let header = health.headers()[CAPABILITIES_HEADER].to_str().unwrap();
+        assert_eq!(header.split(',').collect::<Vec<_>>(), expected);
+        assert_eq!(header.contains("vision"), cfg!(feature = "vision"));
+
+        let response = app.oneshot(get("/api/capabilities")).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["capabilities"], json!(expected));
+        assert_eq!(json["models"], json!({}));
+    }
+
+    #[tokio::test]
+    async fn test_api_key_required_except_on_exempt_paths() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::Registry;
+        use tower::util::ServiceExt;
+
+        let app = |exempt: &[&str]| {
+            let mut state =
+                crate::AppState::new(Box::new(InferenceEngineAdapter::new()), Registry::default());
+            state.server_config.api_key = Some("sk-local".to_string());
+            state.server_config.auth_exempt = exempt.iter().map(|p| p.to_string()).collect();
+            router(Arc::new(state))
+        };
+        async fn status(
+            app: Router,
+            method: &str,
+            uri: &str,
+            key: Option<&str>,
+        ) -> axum::http::StatusCode {
+            let mut request = axum::http::Request::builder().method(method).uri(uri);
+            if let Some(key) = key {
+                request = request.header("authorization", format!("Bearer {}", key));
+            }
+            let body = if method == "POST" {
+                request = request.header("content-type", "application/json");
+                axum::body::Body::from(r#"{"messages":[{"role":"user","content":"hi"}]}"#)
+            } else {
+                axum::body::Body::empty()
+            };
+            app.oneshot(request.body(body).unwrap())
+                .await
+                .unwrap()
+                .status()
+        }
+        let unauthorized = axum::http::StatusCode::UNAUTHORIZED;
+
+        let monitored = app(&["/health", "/metrics"]);
+        assert_eq!(
+            status(monitored.clone(), "GET", "/metrics", None).await,
+            axum::http::StatusCode::OK
+        );
+        assert_eq!(
+            status(monitored.clone(), "GET", "/health", None).await,
+            axum::http::StatusCode::OK
+        );
+        assert_eq!(
+            status(monitored.clone(), "POST", "/v1/chat/completions", None).await,
+            unauthorized
+        );
+        assert_eq!(
+            status(
+                monitored.clone(),
+                "POST",
+                "/v1/chat/completions",
+                Some("sk-wrong")
+            )
+            .await,
+            unauthorized
+        );
+        assert_ne!(
+            status(monitored, "POST", "/v1/chat/completions", Some("sk-local")).await,
+            unauthorized
+        );
+
+        // Default exemptions cover only /health
+        let default_app = app(&["/health"]);
+        assert_eq!(
+            status(default_app.clone(), "GET", "/metrics", None).await,
+            unauthorized
+        );
+        assert_eq!(
+            status(default_app, "GET", "/v1/models", Some("sk-local")).await,
+            axum::http::StatusCode::OK
+        );
+
+        // Browsers may send the key as x-api-key once the preflight allows it
+        let preflight = app(&["/health"])
+            .oneshot(
+                axum::http::Request::builder()
+                    .method("OPTIONS")
+                    .uri("/v1/messages")
+                    .header("access-control-request-headers", "x-api-key")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(preflight.status(), axum::http::StatusCode::OK);
+        let allowed = preflight.headers()["access-control-allow-headers"]
+            .to_str()
+            .unwrap()
+            .to_ascii_lowercase();
+        assert!(allowed.split(", ").any(|h| h == "x-api-key"), "{}", allowed);
+    }
+
+    #[test]
+    fn test_endpoint_examples_are_curl_commands() {
+        let examples = endpoint_examples("http://127.0.0.1:11435");
+        assert_eq!(examples.len(), ENDPOINTS.len());
+        assert_eq!(examples[0], "curl http://127.0.0.1:11435/health");
+        assert!(examples
+            .iter()
+            .any(|e| e.starts_with("curl http://127.0.0.1:11435/v1/chat/completions -H")));
+    }
+
+    #[tokio::test]
+    async fn test_compression_can_be_disabled() {
+        let response = get_models(router(state_with_many_models(false)), Some("gzip")).await;
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert!(response.headers().get("content-encoding").is_none());
+    }
 }
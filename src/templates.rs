@@ -8,9 +8,54 @@ pub enum TemplateFamily {
     ChatML,
     Llama3,
     OpenChat,
+    Gemma,
+    Phi3,
 }
 
 impl TemplateFamily {
+    /// Names accepted by `from_name`, for error messages
+    pub const NAMES: &'static [&'static str] = &["chatml", "llama3", "openchat", "gemma", "phi3"];
+
+    /// Look up a template family by the name used in model configuration
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "chatml" => Some(TemplateFamily::ChatML),
+            "llama3" | "llama-3" => Some(TemplateFamily::Llama3),
+            "openchat" => Some(TemplateFamily::OpenChat),
+            "gemma" => Some(TemplateFamily::Gemma),
+            "phi3" | "phi-3" => Some(TemplateFamily::Phi3),
+            _ => None,
+        }
+    }
+
+    /// Template for a model family such as discovery's `model_type` or a
+    /// model name. `None` when the family isn't recognised.
+    pub fn for_model_family(family: &str) -> Option<Self> {
+        let family = family.to_lowercase();
+        if family.contains("llama") {
+            Some(TemplateFamily::Llama3)
+        } else if family.contains("gemma") {
+            Some(TemplateFamily::Gemma)
+        } else if family.contains("qwen") {
+            Some(TemplateFamily::ChatML)
+        } else if is_phi(&family) {
+            Some(TemplateFamily::Phi3)
+        } else {
+            None
+        }
+    }
+
+    /// Configuration name of this template family
+    pub fn name(&self) -> &'static str {
+        match self {
+            TemplateFamily::ChatML => "chatml",
+            TemplateFamily::Llama3 => "llama3",
+            TemplateFamily::OpenChat => "openchat",
+            TemplateFamily::Gemma => "gemma",
+            TemplateFamily::Phi3 => "phi3",
+        }
+    }
+
     pub fn render(
         &self,
         system: Option<&str>,
@@ -65,6 +110,50 @@ impl TemplateFamily {
                 }
                 s
             }
+            TemplateFamily::Gemma => {
+                // Gemma has no system role; the system prompt and any system
+                // messages open the first user turn
+                let mut s = String::new();
+                let mut system = system
+                    .into_iter()
+                    .chain(
+                        messages
+                            .iter()
+                            .filter(|(role, _)| role == "system")
+                            .map(|(_, content)| content.as_str()),
+                    )
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                let mut turn = |s: &mut String, role: &str, content: &str| {
+                    let role = if role == "assistant" { "model" } else { role };
+                    s.push_str(&format!("<start_of_turn>{}\n", role));
+                    if role == "user" && !system.is_empty() {
+                        s.push_str(&format!("{}\n\n", std::mem::take(&mut system)));
+                    }
+                    s.push_str(&format!("{}<end_of_turn>\n", content));
+                };
+                for (role, content) in messages.iter().filter(|(role, _)| role != "system") {
+                    turn(&mut s, role, content);
+                }
+                if let Some(inp) = input {
+                    turn(&mut s, "user", inp);
+                    s.push_str("<start_of_turn>model\n");
+                }
+                s
+            }
+            TemplateFamily::Phi3 => {
+                let mut s = String::new();
+                if let Some(sys) = system {
+                    s.push_str(&format!("<|system|>\n{}<|end|>\n", sys));
+                }
+                for (role, content) in messages {
+                    s.push_str(&format!("<|{}|>\n{}<|end|>\n", role, content));
+                }
+                if let Some(inp) = input {
+                    s.push_str(&format!("<|user|>\n{}<|end|>\n<|assistant|>\n", inp));
+                }
+                s
+            }
         }
     }
 
@@ -74,10 +163,48 @@ impl TemplateFamily {
             TemplateFamily::ChatML => vec!["<|im_end|>".to_string(), "<|im_start|>".to_string()],
             TemplateFamily::Llama3 => vec!["<|eot_id|>".to_string(), "<|end_of_text|>".to_string()],
             TemplateFamily::OpenChat => vec![],
+            TemplateFamily::Gemma => vec!["<end_of_turn>".to_string()],
+            TemplateFamily::Phi3 => vec!["<|end|>".to_string(), "<|endoftext|>".to_string()],
         }
     }
 }
 
+/// Whether a lowercased family or model name names Phi: a `phi` segment,
+/// optionally with a version (`phi-3`, `phi3`, `phi3.5`), but not a word
+/// that merely contains it like `dolphin`
+fn is_phi(family: &str) -> bool {
+    family
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter_map(|segment| segment.strip_prefix("phi"))
+        .any(|version| version.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Drop system messages that repeat an earlier one word for word, then merge
+/// consecutive messages from the same role (joined by a blank line).
+///
+/// Returns the normalized messages and how many were removed.
+pub fn normalize_messages(messages: &[(String, String)]) -> (Vec<(String, String)>, usize) {
+    let mut seen_system: Vec<&str> = Vec::new();
+    let mut out: Vec<(String, String)> = Vec::with_capacity(messages.len());
+    for (role, content) in messages {
+        if role == "system" {
+            if seen_system.contains(&content.as_str()) {
+                continue;
+            }
+            seen_system.push(content);
+        }
+        match out.last_mut() {
+            Some((last_role, last_content)) if last_role == role => {
+                last_content.push_str("\n\n");
+                last_content.push_str(content);
+            }
+            _ => out.push((role.clone(), content.clone())),
+        }
+    }
+    let removed = messages.len() - out.len();
+    (out, removed)
+}
+
 // Template generation functions for deployment platforms
 
 /// Generate Docker deployment template
@@ -265,6 +392,155 @@ pub fn generate_template(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_template_from_name() {
+        assert!(matches!(
+            TemplateFamily::from_name("chatml"),
+            Some(TemplateFamily::ChatML)
+        ));
+        assert!(matches!(
+            TemplateFamily::from_name("llama-3"),
+            Some(TemplateFamily::Llama3)
+        ));
+        assert!(TemplateFamily::from_name("alpaca").is_none());
+    }
+
+    fn msg(role: &str, content: &str) -> (String, String) {
+        (role.to_string(), content.to_string())
+    }
+
+    #[test]
+    fn test_normalize_messages_drops_duplicate_system() {
+        let messages = vec![
+            msg("system", "Be brief."),
+            msg("user", "Hi"),
+            msg("assistant", "Hello"),
+            msg("system", "Be brief."),
+            msg("user", "Bye"),
+        ];
+        let (normalized, removed) = normalize_messages(&messages);
+        assert_eq!(removed, 1);
+        assert_eq!(
+            normalized,
+            vec![
+                msg("system", "Be brief."),
+                msg("user", "Hi"),
+                msg("assistant", "Hello"),
+                msg("user", "Bye"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_normalize_messages_merges_consecutive_roles() {
+        let messages = vec![
+            msg("system", "Be brief."),
+            msg("system", "Be brief."),
+            msg("system", "Use English."),
+            msg("user", "Hi"),
+            msg("user", "Still there?"),
+        ];
+        let (normalized, removed) = normalize_messages(&messages);
+        assert_eq!(removed, 3);
+        assert_eq!(
+            normalized,
+            vec![
+                msg("system", "Be brief.\n\nUse English."),
+                msg("user", "Hi\n\nStill there?"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_template_name_round_trips() {
+        for fam in [
+            TemplateFamily::ChatML,
+            TemplateFamily::Llama3,
+            TemplateFamily::OpenChat,
+            TemplateFamily::Gemma,
+            TemplateFamily::Phi3,
+        ] {
+            assert_eq!(
+                TemplateFamily::from_name(fam.name()).map(|f| f.name()),
+                Some(fam.name())
+            );
+        }
+    }
+
+    #[test]
+    fn test_for_model_family() {
+        assert_eq!(
+            TemplateFamily::for_model_family("Gemma").map(|f| f.name()),
+            Some("gemma")
+        );
+        assert_eq!(
+            TemplateFamily::for_model_family("Qwen").map(|f| f.name()),
+            Some("chatml")
+        );
+        assert_eq!(
+            TemplateFamily::for_model_family("phi-3-mini").map(|f| f.name()),
+            Some("phi3")
+        );
+        assert_eq!(
+            TemplateFamily::for_model_family("Llama").map(|f| f.name()),
+            Some("llama3")
+        );
+        assert_eq!(
+            TemplateFamily::for_model_family("Phi3").map(|f| f.name()),
+            Some("phi3")
+        );
+        assert!(TemplateFamily::for_model_family("Mistral").is_none());
+        // "dolphin" contains "phi" but dolphin models are ChatML, not Phi
+        assert!(TemplateFamily::for_model_family("dolphin-2.6-mistral-7b").is_none());
+        assert!(TemplateFamily::for_model_family("dolphin-2.7-mixtral-8x7b").is_none());
+    }
+
+    #[test]
+    fn test_gemma_render_folds_system_into_first_user_turn() {
+        let messages = vec![msg("user", "Hi"), msg("assistant", "Hello")];
+        let result = TemplateFamily::Gemma.render(Some("Be brief."), &messages, Some("Bye"));
+        assert_eq!(
+            result,
+            "<start_of_turn>user\nBe brief.\n\nHi<end_of_turn>\n\
+             <start_of_turn>model\nHello<end_of_turn>\n\
+             <start_of_turn>user\nBye<end_of_turn>\n\
+             <start_of_turn>model\n"
+        );
+        assert_eq!(TemplateFamily::Gemma.stop_tokens(), vec!["<end_of_turn>"]);
+    }
+
+    #[test]
+    fn test_gemma_render_folds_system_messages_into_first_user_turn() {
+        // How chat handlers pass system prompts: as history entries
+        let messages = vec![msg("system", "Be brief."), msg("user", "Hi")];
+        let result = TemplateFamily::Gemma.render(None, &messages, None);
+        assert_eq!(
+            result,
+            "<start_of_turn>user\nBe brief.\n\nHi<end_of_turn>\n"
+        );
+        assert!(!result.contains("<start_of_turn>system"));
+
+        let result = TemplateFamily::Gemma.render(Some("Be kind."), &messages, Some("Bye"));
+        assert_eq!(
+            result,
+            "<start_of_turn>user\nBe kind.\n\nBe brief.\n\nHi<end_of_turn>\n\
+             <start_of_turn>user\nBye<end_of_turn>\n\
+             <start_of_turn>model\n"
+        );
+    }
+
+    #[test]
+    fn test_phi3_render() {
+        let result = TemplateFamily::Phi3.render(Some("Be brief."), &[], Some("Hi"));
+        assert_eq!(
+            result,
+            "<|system|>\nBe brief.<|end|>\n<|user|>\nHi<|end|>\n<|assistant|>\n"
+        );
+        assert!(TemplateFamily::Phi3
+            .stop_tokens()
+            .contains(&"<|end|>".to_string()));
+    }
+
     #[test]
     fn test_chatml_render() {
         let template = TemplateFamily::ChatML;
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Load variables from `path`, or from `./.env` when no path is given and the
+/// file exists. Variables already in the process environment are left alone.
+/// Returns the file that was loaded, if any.
+#[cfg(feature = "env-file")]
+pub fn load(path: Option<&Path>) -> Result<Option<PathBuf>> {
+    use anyhow::Context;
+
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let default = PathBuf::from(".env");
+            if !default.is_file() {
+                return Ok(None);
+            }
+            default
+        }
+    };
+    dotenvy::from_path(&path)
+        .with_context(|| format!("loading environment file {}", path.display()))?;
+    Ok(Some(path))
+}
+
+/// Without the `env-file` feature an explicit `--env-file` is an error and
+/// `./.env` is ignored
+#[cfg(not(feature = "env-file"))]
+pub fn load(path: Option<&Path>) -> Result<Option<PathBuf>> {
+    match path {
+        Some(path) => anyhow::bail!(
+            "cannot load {}: shimmy was built without the `env-file` feature",
+            path.display()
+        ),
+        None => Ok(None),
+    }
+}
+
+#[cfg(all(test, feature = "env-file"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_values_fill_in_but_never_override_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shimmy.env");
+        std::fs::write(
+            &path,
+            "# deployment settings\nSHIMMY_TEST_ENV_FILE_NEW=from-file\nSHIMMY_TEST_ENV_FILE_SET=from-file\n",
+        )
+        .unwrap();
+        std::env::set_var("SHIMMY_TEST_ENV_FILE_SET", "from-env");
+
+        assert_eq!(load(Some(&path)).unwrap(), Some(path.clone()));
+        assert_eq!(
+            std::env::var("SHIMMY_TEST_ENV_FILE_NEW").as_deref(),
+            Ok("from-file")
+        );
+        assert_eq!(
+            std::env::var("SHIMMY_TEST_ENV_FILE_SET").as_deref(),
+            Ok("from-env")
+        );
+    }
+
+    #[test]
+    fn test_missing_explicit_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load(Some(&dir.path().join("missing.env"))).is_err());
+    }
+}
+
+#[cfg(all(test, not(feature = "env-file")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explicit_file_needs_the_feature() {
+        assert!(load(Some(Path::new("deploy.env"))).is_err());
+        assert_eq!(load(None).unwrap(), None);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::AppState;
+use axum::{extract::State, Json};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Response header listing `Features::capabilities`, comma-separated
+pub const CAPABILITIES_HEADER: &str = "X-Shimmy-Capabilities";
+
+/// Capabilities compiled into this binary, for `GET /api/features` and
+/// `shimmy features`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Features {
+    pub version: &'static str,
+    /// Cargo features enabled at build time
+    pub features: Vec<&'static str>,
+    /// Inference backends that can load models
+    pub backends: Vec<&'static str>,
+    /// GPU acceleration compiled into llama.cpp
+    pub gpu_backends: Vec<&'static str>,
+    pub vision: bool,
+    /// Embeddings are served by the llama.cpp backend
+    pub embeddings: bool,
+}
+
+impl Features {
+    pub fn compiled() -> Self {
+        let flags = [
+            ("llama", cfg!(feature = "llama")),
+            ("huggingface", cfg!(feature = "huggingface")),
+            ("mlx", cfg!(feature = "mlx")),
+            ("llama-cuda", cfg!(feature = "llama-cuda")),
+            ("llama-vulkan", cfg!(feature = "llama-vulkan")),
+            ("llama-opencl", cfg!(feature = "llama-opencl")),
+            ("vision", cfg!(feature = "vision")),
+            ("env-file", cfg!(feature = "env-file")),
+            ("audit", cfg!(feature = "audit")),
+        ];
+        let features = enabled(&flags);
+
+        // SafeTensors loading is native Rust and always available
+        let backends = enabled(&[
+            ("llama", cfg!(feature = "llama")),
+            ("huggingface", cfg!(feature = "huggingface")),
+            ("mlx", cfg!(feature = "mlx")),
+            ("safetensors", true),
+        ]);
+
+        let gpu_backends = enabled(&[
+            ("cuda", cfg!(feature = "llama-cuda")),
+            ("vulkan", cfg!(feature = "llama-vulkan")),
+            ("opencl", cfg!(feature = "llama-opencl")),
+        ]);
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            features,
+            backends,
+            gpu_backends,
+            vision: cfg!(feature = "vision"),
+            embeddings: cfg!(feature = "llama"),
+        }
+    }
+}
+
+impl Features {
+    /// Request features a client can rely on, by the names OpenAI-compatible
+    /// clients probe for. Tools and logprobs are not implemented, so they are
+    /// never listed.
+    pub fn capabilities(&self) -> Vec<&'static str> {
+        enabled(&[
+            ("streaming", true),
+            ("embeddings", self.embeddings),
+            ("vision", self.vision),
+        ])
+    }
+}
+
+fn enabled(flags: &[(&'static str, bool)]) -> Vec<&'static str> {
+    flags
+        .iter()
+        .filter(|(_, on)| *on)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+pub async fn features_handler() -> Json<Features> {
+    Json(Features::compiled())
+}
+
+/// Body of `GET /api/capabilities`
+#[derive(Debug, Serialize)]
+pub struct CapabilitiesResponse {
+    pub capabilities: Vec<&'static str>,
+    /// Per available model, what it can do in this build
+    pub models: BTreeMap<String, Vec<&'static str>>,
+}
+
+pub async fn capabilities_handler(
+    State(state): State<Arc<AppState>>,
+) -> Json<CapabilitiesResponse> {
+    let features = Features::compiled();
+    let models = state
+        .registry
+        .list_all_available()
+        .into_iter()
+        .map(|name| {
+            // A vision model is only usable for vision if vision is compiled in
+            let caps = state
+                .registry
+                .capabilities(&name)
+                .iter()
+                .map(|c| c.name())
+                .filter(|c| *c != "vision" || features.vision)
+                .collect();
+            (name, caps)
+        })
+        .collect();
+    Json(CapabilitiesResponse {
+        capabilities: features.capabilities(),
+        models,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reported_features_match_cfg() {
+        let features = Features::compiled();
+        assert_eq!(
+            features.features.contains(&"llama"),
+            cfg!(feature = "llama")
+        );
+        assert_eq!(
+            features.features.contains(&"huggingface"),
+            cfg!(feature = "huggingface")
+        );
+        assert_eq!(features.features.contains(&"mlx"), cfg!(feature = "mlx"));
+        assert_eq!(
+            features.features.contains(&"vision"),
+            cfg!(feature = "vision")
+        );
+        assert_eq!(features.vision, cfg!(feature = "vision"));
+        assert_eq!(features.embeddings, cfg!(feature = "llama"));
+        assert_eq!(
+            features.gpu_backends.contains(&"cuda"),
+            cfg!(feature = "llama-cuda")
+        );
+        assert!(features.backends.contains(&"safetensors"));
+        assert_eq!(
+            features.backends.contains(&"llama"),
+            cfg!(feature = "llama")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_features_endpoint_serializes_compiled_set() {
+        let Json(features) = features_handler().await;
+        let json = serde_json::to_value(&features).unwrap();
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["vision"], cfg!(feature = "vision"));
+        let backends: Vec<&str> = json["backends"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|b| b.as_str().unwrap())
+            .collect();
+        assert_eq!(backends, features.backends);
+    }
+
+    #[test]
+    fn test_capabilities_follow_compiled_features() {
+        let capabilities = Features::compiled().capabilities();
+        assert!(capabilities.contains(&"streaming"));
+        assert_eq!(
+            capabilities.contains(&"embeddings"),
+            cfg!(feature = "llama")
+        );
+        assert_eq!(capabilities.contains(&"vision"), cfg!(feature = "vision"));
+        assert!(!capabilities.contains(&"tools"));
+        assert!(!capabilities.contains(&"logprobs"));
+    }
+
+    #[cfg(not(feature = "vision"))]
+    #[test]
+    fn test_vision_absent_without_vision_feature() {
+        let mut features = Features::compiled();
+        assert!(!features.capabilities().contains(&"vision"));
+        features.vision = true;
+        assert!(features.capabilities().contains(&"vision"));
+    }
+}
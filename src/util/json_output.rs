@@ -0,0 +1,168 @@
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::Serialize;
+
+/// Largest JSON body the `?pretty` layer will buffer and reformat
+const MAX_PRETTY_BODY: usize = 16 * 1024 * 1024;
+
+/// Serialize for CLI output: pretty unless `--compact` was given
+pub fn render<T: Serialize>(value: &T, pretty: bool) -> serde_json::Result<String> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+/// Re-indent compact JSON the way `serde_json::to_string_pretty` would.
+/// Works on the text so key order is kept exactly as the handler wrote it.
+pub fn prettify(compact: &str) -> String {
+    let mut out = String::with_capacity(compact.len() * 2);
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = compact.chars().peekable();
+
+    let newline = |out: &mut String, depth: usize| {
+        out.push('\n');
+        for _ in 0..depth {
+            out.push_str("  ");
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '{' | '[' => {
+                out.push(c);
+                let close = if c == '{' { '}' } else { ']' };
+                while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                    chars.next();
+                }
+                if chars.peek() == Some(&close) {
+                    out.push(close);
+                    chars.next();
+                } else {
+                    depth += 1;
+                    newline(&mut out, depth);
+                }
+            }
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                newline(&mut out, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                newline(&mut out, depth);
+            }
+            ':' => out.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether the query string asks for pretty output (`pretty`, `pretty=1`,
+/// `pretty=true`)
+pub fn wants_pretty(query: Option<&str>) -> bool {
+    query.is_some_and(|query| {
+        query.split('&').any(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            key == "pretty" && matches!(value, "" | "1" | "true")
+        })
+    })
+}
+
+/// Pretty-print JSON responses when the request has `?pretty`. API output
+/// stays compact by default to save bandwidth.
+pub async fn pretty_json_layer(req: Request, next: Next) -> Response {
+    let pretty = wants_pretty(req.uri().query());
+    let response = next.run(req).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !pretty || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_PRETTY_BODY).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("Could not buffer JSON response for pretty printing: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let body = match std::str::from_utf8(&bytes) {
+        Ok(text) => prettify(text),
+        Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_prettify_matches_serde_pretty_and_keeps_key_order() {
+        #[derive(Serialize)]
+        struct Sample {
+            zeta: u32,
+            alpha: Vec<serde_json::Value>,
+            empty: Vec<u32>,
+            nested: serde_json::Value,
+        }
+        let sample = Sample {
+            zeta: 1,
+            alpha: vec![json!("a, \"quoted\": {x}"), json!(null)],
+            empty: vec![],
+            nested: json!({"inner": {}, "list": [1, 2]}),
+        };
+        let compact = serde_json::to_string(&sample).unwrap();
+        assert_eq!(
+            prettify(&compact),
+            serde_json::to_string_pretty(&sample).unwrap()
+        );
+        assert!(prettify(&compact).find("zeta") < prettify(&compact).find("alpha"));
+    }
+
+    #[test]
+    fn test_render_and_query_flag() {
+        let value = json!({"a": [1]});
+        assert_eq!(render(&value, false).unwrap(), r#"{"a":[1]}"#);
+        assert_eq!(
+            render(&value, true).unwrap(),
+            "{\n  \"a\": [\n    1\n  ]\n}"
+        );
+
+        assert!(wants_pretty(Some("pretty")));
+        assert!(wants_pretty(Some("x=1&pretty=true")));
+        assert!(!wants_pretty(Some("pretty=false")));
+        assert!(!wants_pretty(Some("prettyish=1")));
+        assert!(!wants_pretty(None));
+    }
+}
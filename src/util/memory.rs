@@ -2,8 +2,114 @@
 ///
 /// Provides memory estimation and warnings to help users understand
 /// system requirements for large language models.
+use crate::engine::gguf::{read_gguf_info, GgufInfo};
+use crate::engine::{EngineError, ModelSpec};
+use serde::Serialize;
 use sysinfo::System;
 
+/// Bytes per KV-cache element; llama.cpp keeps the cache in f16 by default
+const KV_ELEMENT_BYTES: u64 = 2;
+
+/// Layer and attention-head geometry that sizes the KV cache
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KvShape {
+    pub layers: u64,
+    pub kv_heads: u64,
+    pub head_dim: u64,
+}
+
+impl KvShape {
+    /// Read the geometry from `<arch>.*` GGUF metadata
+    pub fn from_gguf(info: &GgufInfo) -> Option<Self> {
+        let arch = info.architecture()?;
+        let key = |name: &str| info.metadata_u64(&format!("{}.{}", arch, name));
+        let layers = key("block_count")?;
+        let heads = key("attention.head_count")?.max(1);
+        let kv_heads = key("attention.head_count_kv").unwrap_or(heads);
+        let head_dim = match key("attention.key_length") {
+            Some(dim) => dim,
+            None => key("embedding_length")? / heads,
+        };
+        Some(Self {
+            layers,
+            kv_heads,
+            head_dim,
+        })
+    }
+}
+
+/// KV cache size for `ctx_len` tokens: a key and a value vector per layer
+/// and KV head. Saturates at `u64::MAX` on absurd metadata, which no
+/// ceiling admits.
+pub fn kv_cache_bytes(ctx_len: u64, shape: KvShape) -> u64 {
+    [
+        ctx_len,
+        shape.layers,
+        shape.kv_heads,
+        shape.head_dim,
+        KV_ELEMENT_BYTES,
+    ]
+    .into_iter()
+    .fold(2u64, u64::saturating_mul)
+}
+
+/// Resident memory a model load is expected to need
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LoadEstimate {
+    /// Base weights plus any LoRA adapter, as sized on disk
+    pub weights_bytes: u64,
+    pub kv_cache_bytes: u64,
+    pub total_bytes: u64,
+}
+
+impl LoadEstimate {
+    pub fn new(weights_bytes: u64, kv_cache_bytes: u64) -> Self {
+        Self {
+            weights_bytes,
+            kv_cache_bytes,
+            total_bytes: weights_bytes.saturating_add(kv_cache_bytes),
+        }
+    }
+
+    /// Estimate for `spec` before loading it. Files without a readable GGUF
+    /// header (SafeTensors, missing files) count their size on disk only.
+    pub fn for_spec(spec: &ModelSpec) -> Self {
+        let weights_bytes = std::iter::once(&spec.base_path)
+            .chain(spec.lora_path.as_ref())
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|meta| meta.len())
+            .fold(0, u64::saturating_add);
+        let kv = read_gguf_info(&spec.base_path)
+            .ok()
+            .and_then(|info| KvShape::from_gguf(&info))
+            .map(|shape| kv_cache_bytes(spec.ctx_len as u64, shape))
+            .unwrap_or(0);
+        Self::new(weights_bytes, kv)
+    }
+}
+
+/// Refuse a load whose estimate, on top of `resident_bytes` already loaded,
+/// would exceed `ceiling_bytes`; a sum too large for `u64` is over any ceiling
+pub fn check_ceiling(
+    model: &str,
+    estimate: &LoadEstimate,
+    resident_bytes: u64,
+    ceiling_bytes: u64,
+) -> Result<(), EngineError> {
+    if resident_bytes
+        .checked_add(estimate.total_bytes)
+        .is_some_and(|total| total <= ceiling_bytes)
+    {
+        return Ok(());
+    }
+    Err(EngineError::MemoryExceeded {
+        model: model.to_string(),
+        estimated_bytes: estimate.total_bytes,
+        resident_bytes,
+        ceiling_bytes,
+    })
+}
+
 /// Get total system memory in bytes
 #[allow(dead_code)] // Placeholder utility for future use
 pub fn get_total_memory() -> u64 {
@@ -132,6 +238,81 @@ impl MemoryAvailability {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_kv_cache_from_synthetic_gguf() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("llama.gguf");
+        // Llama-3-8B geometry: 32 layers, 32 heads (8 KV), 4096 wide
+        let bytes = crate::engine::gguf::tests::synthetic_gguf(
+            &[("general.architecture", "llama")],
+            &[
+                ("llama.block_count", 32),
+                ("llama.attention.head_count", 32),
+                ("llama.attention.head_count_kv", 8),
+                ("llama.embedding_length", 4096),
+            ],
+            &[("token_embd.weight", 1 << 20)],
+        );
+        std::fs::write(&path, &bytes).unwrap();
+
+        let info = read_gguf_info(&path).unwrap();
+        let shape = KvShape::from_gguf(&info).unwrap();
+        assert_eq!(
+            shape,
+            KvShape {
+                layers: 32,
+                kv_heads: 8,
+                head_dim: 128
+            }
+        );
+        // 2 (K, V) x 8192 ctx x 32 layers x 8 heads x 128 dim x 2 bytes = 1 GiB
+        assert_eq!(kv_cache_bytes(8192, shape), 1 << 30);
+
+        let spec = ModelSpec {
+            name: "llama".into(),
+            base_path: path,
+            lora_path: None,
+            template: None,
+            ctx_len: 8192,
+            n_threads: None,
+        };
+        let estimate = LoadEstimate::for_spec(&spec);
+        assert_eq!(estimate.weights_bytes, bytes.len() as u64);
+        assert_eq!(estimate.kv_cache_bytes, 1 << 30);
+        assert_eq!(estimate.total_bytes, bytes.len() as u64 + (1 << 30));
+    }
+
+    #[test]
+    fn test_ceiling_counts_resident_models() {
+        let estimate = LoadEstimate::new(3 << 30, 1 << 30);
+        assert!(check_ceiling("m", &estimate, 0, 4 << 30).is_ok());
+        let err = check_ceiling("m", &estimate, 1 << 30, 4 << 30).unwrap_err();
+        assert!(matches!(
+            err,
+            EngineError::MemoryExceeded {
+                estimated_bytes,
+                resident_bytes,
+                ..
+            } if estimated_bytes == 4 << 30 && resident_bytes == 1 << 30
+        ));
+        assert!(err.to_string().contains("--force"));
+    }
+
+    #[test]
+    fn test_overflowing_estimates_are_over_budget() {
+        let huge = KvShape {
+            layers: u64::MAX / 2,
+            kv_heads: 64,
+            head_dim: 128,
+        };
+        assert_eq!(kv_cache_bytes(1 << 20, huge), u64::MAX);
+
+        let estimate = LoadEstimate::new(u64::MAX - 1, 10);
+        assert_eq!(estimate.total_bytes, u64::MAX);
+        assert!(check_ceiling("m", &estimate, 0, u64::MAX).is_ok());
+        assert!(check_ceiling("m", &estimate, 1, u64::MAX).is_err());
+    }
+
     #[test]
     fn test_memory_estimation() {
         // Test with a typical 7B model file (~4GB)
@@ -0,0 +1,33 @@
+// Tokio worker thread count for the runtime
+//
+// llama.cpp generation runs on the blocking pool (`spawn_blocking`), but the
+// other backends and the vision pipeline still do some of their work on the
+// workers, so the count never drops below `MIN_WORKER_THREADS`: one busy
+// worker must not stall every other request.
+
+/// Fewest worker threads the runtime starts with
+pub const MIN_WORKER_THREADS: usize = 2;
+
+/// Worker threads to start: `requested` (`--worker-threads` or
+/// SHIMMY_WORKER_THREADS) if given, else `available` (the standard
+/// library's `available_parallelism`, which honours container CPU quotas).
+/// Never less than `MIN_WORKER_THREADS`.
+pub fn resolve_worker_threads(requested: Option<usize>, available: usize) -> usize {
+    requested.unwrap_or(available).max(MIN_WORKER_THREADS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worker_threads_follow_flag_then_available_with_floor() {
+        assert_eq!(resolve_worker_threads(None, 64), 64);
+        assert_eq!(resolve_worker_threads(Some(8), 64), 8);
+
+        // A single-CPU quota or a tiny request still gets the floor
+        assert_eq!(resolve_worker_threads(None, 1), MIN_WORKER_THREADS);
+        assert_eq!(resolve_worker_threads(Some(0), 16), MIN_WORKER_THREADS);
+        assert_eq!(resolve_worker_threads(Some(1), 16), MIN_WORKER_THREADS);
+    }
+}
@@ -139,6 +139,10 @@ pub struct VisionRequest {
     /// Viewport dimensions for screenshot
     pub viewport_width: Option<u32>,
     pub viewport_height: Option<u32>,
+    /// Cap on DOM elements returned in web mode (default: SHIMMY_VISION_MAX_DOM_ELEMENTS or 500)
+    pub max_dom_elements: Option<usize>,
+    /// Chrome/Chromium binary for web-mode capture (default: SHIMMY_CHROME_PATH or auto-detect)
+    pub chrome_path: Option<String>,
 }
 
 /// Image preprocessing configuration
@@ -183,6 +187,84 @@ pub fn preprocess_config_for_mode(mode: Option<&str>) -> PreprocessConfig {
     cfg
 }
 
+/// Limits applied to DOM elements captured in web mode
+#[cfg(feature = "vision")]
+pub struct DomLimits {
+    pub max_elements: usize,
+    /// Minimum bounding-box area as a fraction of the viewport
+    pub min_area: f32,
+}
+
+#[cfg(feature = "vision")]
+impl DomLimits {
+    /// Defaults, overridden by environment and then by the request
+    pub fn for_request(max_dom_elements: Option<usize>) -> Self {
+        let mut limits = DomLimits {
+            max_elements: 500,
+            min_area: 0.0001,
+        };
+        if let Some(v) = std::env::var("SHIMMY_VISION_MAX_DOM_ELEMENTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            limits.max_elements = v;
+        }
+        if let Some(v) = std::env::var("SHIMMY_VISION_MIN_DOM_AREA")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            limits.min_area = v;
+        }
+        if let Some(v) = max_dom_elements {
+            limits.max_elements = v;
+        }
+        limits
+    }
+}
+
+/// Truncation priority: interactive elements first, then headings and text,
+/// then generic containers
+#[cfg(feature = "vision")]
+fn dom_priority(el: &DomElement) -> u8 {
+    let interactive_role = matches!(
+        el.attributes.get("role").map(String::as_str),
+        Some("button" | "link" | "textbox" | "combobox" | "listbox")
+    );
+    match el.tag.as_str() {
+        "button" | "input" | "select" | "textarea" | "a" => 0,
+        _ if interactive_role || el.attributes.contains_key("href") => 0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "p" => 1,
+        _ => 2,
+    }
+}
+
+/// Drop tiny elements and cap the list at `max_elements`, keeping the
+/// highest-priority elements in document order. Returns the kept elements and
+/// how many were dropped.
+#[cfg(feature = "vision")]
+pub fn limit_dom_elements(
+    elements: Vec<DomElement>,
+    limits: &DomLimits,
+) -> (Vec<DomElement>, usize) {
+    let total = elements.len();
+    let mut ranked: Vec<(u8, usize, DomElement)> = elements
+        .into_iter()
+        .filter(|el| el.position.width * el.position.height >= limits.min_area)
+        .enumerate()
+        .map(|(index, el)| (dom_priority(&el), index, el))
+        .collect();
+
+    if ranked.len() > limits.max_elements {
+        ranked.sort_by_key(|(priority, index, _)| (*priority, *index));
+        ranked.truncate(limits.max_elements);
+        ranked.sort_by_key(|(_, index, _)| *index);
+    }
+
+    let kept: Vec<DomElement> = ranked.into_iter().map(|(_, _, el)| el).collect();
+    let dropped = total - kept.len();
+    (kept, dropped)
+}
+
 /// Preprocessed image payload passed to mtmd/vision backend
 #[cfg(feature = "vision")]
 pub struct PreprocessedImage {
@@ -243,7 +325,8 @@ pub async fn process_vision_request(
             // Try to capture screenshot and extract DOM
             let viewport_width = req.viewport_width.unwrap_or(1280);
             let viewport_height = req.viewport_height.unwrap_or(720);
-            match capture_screenshot_and_dom(url, viewport_width, viewport_height).await {
+            let launch = BrowserLaunch::for_request(req.chrome_path.as_deref());
+            match capture_screenshot_and_dom(url, viewport_width, viewport_height, &launch).await {
                 Ok((screenshot_data, dom_elements)) => (screenshot_data, Some(dom_elements)),
                 Err(e) => {
                     tracing::warn!(
@@ -338,8 +421,10 @@ pub async fn process_vision_request(
     };
 
     let loaded_model = state
-        .engine
-        .load(&model_spec)
+        .server_config
+        .retry_transient("Vision model load", || {
+            state.model_pool.get_or_load(&*state.engine, &model_spec)
+        })
         .await
         .map_err(|e| format!("Failed to load vision model: {}", e))?;
 
@@ -379,11 +464,14 @@ pub async fn process_vision_request(
         seed: None,
         stream: false,
         stop_tokens: vec!["</s>".to_string(), "<|im_end|>".to_string()],
+        trim_leading: true,
+        samplers: Vec::new(),
+        stop_on_repeat: None,
+        context_shift: false,
+        cancel: crate::engine::CancelToken::new(),
+        clamped: Vec::new(),
     };
 
-    // Run inference with timeout to avoid hanging
-    let generate_future =
-        loaded_model.generate_vision(&preprocessed.bytes, &prompt, gen_options, None);
     let timeout_ms = req.timeout_ms.unwrap_or(60_000);
     if trace {
         info!(
@@ -393,15 +481,15 @@ pub async fn process_vision_request(
             "vision inference starting"
         );
     }
-    let raw_output = match tokio::time::timeout(
+    let raw_output = run_vision_inference(
+        &*loaded_model,
+        &preprocessed.bytes,
+        &prompt,
+        gen_options,
         std::time::Duration::from_millis(timeout_ms),
-        generate_future,
+        state.observability.cancel_recorder(&resolved_model_name),
     )
-    .await
-    {
-        Ok(result) => result.map_err(|e| format!("Vision inference failed: {}", e))?,
-        Err(_) => return Err(format!("Vision inference timed out after {} ms", timeout_ms).into()),
-    };
+    .await?;
 
     if trace {
         info!(
@@ -412,8 +500,18 @@ pub async fn process_vision_request(
         );
     }
 
+    // Keep the DOM map to a manageable size before it goes into the response
+    let limits = DomLimits::for_request(req.max_dom_elements);
+    let (captured_dom, dropped_dom) = match captured_dom {
+        Some(elements) => {
+            let (kept, dropped) = limit_dom_elements(elements, &limits);
+            (Some(kept), dropped)
+        }
+        None => (None, 0),
+    };
+
     // Parse model output into structured response
-    let response = parse_vision_output(
+    let mut response = parse_vision_output(
         &raw_output,
         &req,
         resolved_model_name.as_str(),
@@ -421,6 +519,17 @@ pub async fn process_vision_request(
         captured_dom,
     )?;
 
+    if dropped_dom > 0 {
+        response
+            .meta
+            .parse_warnings
+            .get_or_insert_with(Vec::new)
+            .push(format!(
+                "Dropped {} DOM elements (max_dom_elements={}, min area {})",
+                dropped_dom, limits.max_elements, limits.min_area
+            ));
+    }
+
     if trace {
         info!(
             target: "vision",
@@ -434,9 +543,40 @@ pub async fn process_vision_request(
     Ok(response)
 }
 
+/// Run `generate_vision` under `timeout`. If this future is dropped first
+/// (the client disconnected), `opts.cancel` is flipped so the backend stops,
+/// and `on_cancel` runs; a timeout cancels the backend the same way.
+#[cfg(feature = "vision")]
+async fn run_vision_inference(
+    model: &dyn crate::engine::LoadedModel,
+    image: &[u8],
+    prompt: &str,
+    opts: crate::engine::GenOptions,
+    timeout: std::time::Duration,
+    on_cancel: impl FnOnce() + Send + 'static,
+) -> Result<String, String> {
+    let cancel = opts.cancel.clone();
+    let guard = cancel.guard(on_cancel);
+    let result =
+        tokio::time::timeout(timeout, model.generate_vision(image, prompt, opts, None)).await;
+    guard.disarm();
+    match result {
+        Ok(output) => output
+            .map(|(text, _)| text)
+            .map_err(|e| format!("Vision inference failed: {}", e)),
+        Err(_) => {
+            cancel.cancel();
+            Err(format!(
+                "Vision inference timed out after {} ms",
+                timeout.as_millis()
+            ))
+        }
+    }
+}
+
 /// Fetch image data from URL
 #[cfg(feature = "vision")]
-async fn fetch_image_from_url(url: &str) -> Result<Vec<u8>, anyhow::Error> {
+pub async fn fetch_image_from_url(url: &str) -> Result<Vec<u8>, anyhow::Error> {
     let parsed = validate_remote_url(url).await?;
 
     let client = reqwest::Client::builder()
@@ -474,84 +614,341 @@ async fn fetch_image_from_url(url: &str) -> Result<Vec<u8>, anyhow::Error> {
     Ok(out)
 }
 
+/// Bounded pool of reusable capture resources (headless browsers).
+///
+/// At most `size` leases exist at once; further `acquire` calls wait. A lease
+/// returns its item to the pool only when `release`d, so a capture that errors,
+/// times out or panics drops (and so shuts down) its browser instead.
+#[cfg(feature = "vision")]
+pub struct CapturePool<T> {
+    permits: tokio::sync::Semaphore,
+    idle: std::sync::Mutex<Vec<T>>,
+}
+
+#[cfg(feature = "vision")]
+impl<T> CapturePool<T> {
+    pub fn new(size: usize) -> Self {
+        Self {
+            permits: tokio::sync::Semaphore::new(size.max(1)),
+            idle: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Wait for a free slot, then reuse an idle item or create one with `launch`
+    pub async fn acquire<F, Fut>(&self, launch: F) -> Result<PoolLease<'_, T>, anyhow::Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<T, anyhow::Error>>,
+    {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|_| anyhow::anyhow!("Capture pool is closed"))?;
+        let reused = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let item = match reused {
+            Some(item) => item,
+            None => launch().await?,
+        };
+        Ok(PoolLease {
+            pool: self,
+            item: Some(item),
+            reusable: false,
+            _permit: permit,
+        })
+    }
+
+    /// Items waiting to be reused
+    #[allow(dead_code)]
+    pub fn idle_count(&self) -> usize {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+/// An item checked out of a `CapturePool`
+#[cfg(feature = "vision")]
+pub struct PoolLease<'a, T> {
+    pool: &'a CapturePool<T>,
+    item: Option<T>,
+    reusable: bool,
+    _permit: tokio::sync::SemaphorePermit<'a>,
+}
+
+#[cfg(feature = "vision")]
+impl<T> PoolLease<'_, T> {
+    pub fn get(&self) -> &T {
+        self.item
+            .as_ref()
+            .expect("lease holds an item until dropped")
+    }
+
+    /// Return the item to the pool for reuse
+    pub fn release(mut self) {
+        self.reusable = true;
+    }
+}
+
+#[cfg(feature = "vision")]
+impl<T> Drop for PoolLease<'_, T> {
+    fn drop(&mut self) {
+        if let Some(item) = self.item.take() {
+            if self.reusable {
+                self.pool
+                    .idle
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(item);
+            }
+        }
+    }
+}
+
+/// A launched headless browser and the task driving its CDP connection
+#[cfg(feature = "vision")]
+struct PooledBrowser {
+    browser: chromiumoxide::browser::Browser,
+    handler_task: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "vision")]
+impl Drop for PooledBrowser {
+    fn drop(&mut self) {
+        // The browser process is spawned with kill_on_drop
+        self.handler_task.abort();
+    }
+}
+
+/// Flags every capture browser is started with, ahead of any extra args
+#[cfg(feature = "vision")]
+const HEADLESS_ARGS: &[&str] = &[
+    "--headless=new",
+    "--disable-gpu",
+    "--disable-dev-shm-usage",
+    "--disable-software-rasterizer",
+    "--disable-background-timer-throttling",
+    "--disable-renderer-backgrounding",
+    "--disable-features=TranslateUI",
+    "--hide-scrollbars",
+    "--mute-audio",
+];
+
+#[cfg(feature = "vision")]
+const BROWSER_INSTALL_HINT: &str = "Install Chromium or Google Chrome (e.g. `apt install chromium`, `brew install --cask chromium`) or point SHIMMY_CHROME_PATH at the browser binary";
+
+/// Which browser web-mode captures launch, and with what extra flags
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BrowserLaunch {
+    /// Chrome/Chromium binary; auto-detected when unset (`SHIMMY_CHROME_PATH`)
+    pub executable: Option<std::path::PathBuf>,
+    /// Appended after `HEADLESS_ARGS` (`SHIMMY_CHROME_ARGS`, whitespace-separated)
+    pub extra_args: Vec<String>,
+}
+
+#[cfg(feature = "vision")]
+impl BrowserLaunch {
+    pub fn from_env() -> Self {
+        Self {
+            executable: std::env::var_os("SHIMMY_CHROME_PATH")
+                .filter(|p| !p.is_empty())
+                .map(Into::into),
+            extra_args: std::env::var("SHIMMY_CHROME_ARGS")
+                .map(|v| v.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// `from_env`, with the executable overridden by a request's `chrome_path`
+    pub fn for_request(chrome_path: Option<&str>) -> Self {
+        let mut launch = Self::from_env();
+        if let Some(path) = chrome_path.filter(|p| !p.is_empty()) {
+            launch.executable = Some(path.into());
+        }
+        launch
+    }
+
+    fn config(&self) -> Result<chromiumoxide::browser::BrowserConfig, anyhow::Error> {
+        let mut builder = chromiumoxide::browser::BrowserConfig::builder()
+            .no_sandbox()
+            .disable_default_args()
+            .args(HEADLESS_ARGS.iter().copied())
+            .args(&self.extra_args);
+        if let Some(path) = &self.executable {
+            if !path.is_file() {
+                anyhow::bail!(
+                    "Browser executable {} does not exist. {}",
+                    path.display(),
+                    BROWSER_INSTALL_HINT
+                );
+            }
+            builder = builder.chrome_executable(path);
+        }
+        builder.build().map_err(|e| {
+            anyhow::anyhow!(
+                "No Chrome/Chromium browser found ({}). {}",
+                e,
+                BROWSER_INSTALL_HINT
+            )
+        })
+    }
+}
+
+#[cfg(feature = "vision")]
+impl PooledBrowser {
+    async fn launch(launch: &BrowserLaunch) -> Result<Self, anyhow::Error> {
+        use chromiumoxide::browser::Browser;
+        use futures_util::StreamExt;
+
+        // Configured for headless operation; the viewport is set per page
+        let config = launch.config()?;
+
+        let (browser, mut handler) = Browser::launch(config)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to launch browser: {}", e))?;
+
+        // Spawn handler to process browser events in background
+        let handler_task = tokio::spawn(async move { while (handler.next().await).is_some() {} });
+
+        Ok(Self {
+            browser,
+            handler_task,
+        })
+    }
+}
+
+/// Browsers shared by web-mode captures (`SHIMMY_VISION_BROWSER_POOL`, default 2)
+#[cfg(feature = "vision")]
+fn browser_pool() -> &'static CapturePool<PooledBrowser> {
+    static POOL: std::sync::OnceLock<CapturePool<PooledBrowser>> = std::sync::OnceLock::new();
+    POOL.get_or_init(|| {
+        let size = std::env::var("SHIMMY_VISION_BROWSER_POOL")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(2);
+        CapturePool::new(size)
+    })
+}
+
+/// Upper bound on one capture, including page load (`SHIMMY_VISION_CAPTURE_TIMEOUT_SECS`)
+#[cfg(feature = "vision")]
+fn capture_timeout() -> std::time::Duration {
+    let secs = std::env::var("SHIMMY_VISION_CAPTURE_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60);
+    std::time::Duration::from_secs(secs)
+}
+
+/// Bound a capture by `capture_timeout`
+#[cfg(feature = "vision")]
+async fn with_capture_timeout<T>(
+    capture: impl std::future::Future<Output = Result<T, anyhow::Error>>,
+) -> Result<T, anyhow::Error> {
+    let timeout = capture_timeout();
+    tokio::time::timeout(timeout, capture)
+        .await
+        .map_err(|_| anyhow::anyhow!("Capture timed out after {}s", timeout.as_secs()))
+        .and_then(|r| r)
+}
+
 /// Capture screenshot and extract DOM from URL
 #[cfg(feature = "vision")]
 async fn capture_screenshot_and_dom(
     url: &str,
     viewport_width: u32,
     viewport_height: u32,
+    launch: &BrowserLaunch,
 ) -> Result<(Vec<u8>, Vec<DomElement>), anyhow::Error> {
-    use chromiumoxide::browser::{Browser, BrowserConfig};
-    use chromiumoxide::cdp::browser_protocol::page::CaptureScreenshotFormat;
-    use futures_util::StreamExt;
-
     let parsed = validate_remote_url(url).await?;
+    let url = parsed.as_str();
+
+    // The pool holds browsers started from the server-wide settings; a
+    // request naming its own browser gets a one-off instance
+    if *launch != BrowserLaunch::from_env() {
+        let browser = PooledBrowser::launch(launch).await?;
+        return with_capture_timeout(capture_page(
+            &browser.browser,
+            url,
+            viewport_width,
+            viewport_height,
+        ))
+        .await;
+    }
 
-    // Configure browser for headless operation
-    let config = BrowserConfig::builder()
-        .no_sandbox()
-        .disable_default_args()
-        .arg("--headless=new")
-        .arg("--disable-gpu")
-        .arg("--disable-dev-shm-usage")
-        .arg("--disable-software-rasterizer")
-        .arg("--disable-background-timer-throttling")
-        .arg("--disable-renderer-backgrounding")
-        .arg("--disable-features=TranslateUI")
-        .arg("--hide-scrollbars")
-        .arg("--mute-audio")
-        .window_size(viewport_width, viewport_height)
-        .build()
-        .map_err(|e| anyhow::anyhow!("Failed to build browser config: {}", e))?;
-
-    // Launch browser - this returns a future
-    let (mut browser, mut handler) = Browser::launch(config)
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to launch browser: {}", e))?;
+    let lease = browser_pool()
+        .acquire(|| PooledBrowser::launch(launch))
+        .await?;
+    let result = with_capture_timeout(capture_page(
+        &lease.get().browser,
+        url,
+        viewport_width,
+        viewport_height,
+    ))
+    .await;
+
+    // Only a browser that completed a capture goes back for reuse
+    if result.is_ok() {
+        lease.release();
+    }
+    result
+}
 
-    // Spawn handler to process browser events in background
-    let handler_task = tokio::spawn(async move { while (handler.next().await).is_some() {} });
+#[cfg(feature = "vision")]
+async fn capture_page(
+    browser: &chromiumoxide::browser::Browser,
+    url: &str,
+    viewport_width: u32,
+    viewport_height: u32,
+) -> Result<(Vec<u8>, Vec<DomElement>), anyhow::Error> {
+    use chromiumoxide::cdp::browser_protocol::emulation::SetDeviceMetricsOverrideParams;
+    use chromiumoxide::cdp::browser_protocol::page::CaptureScreenshotFormat;
 
-    // Create new page
     let page = browser
-        .new_page(parsed.as_str())
+        .new_page("about:blank")
         .await
         .map_err(|e| anyhow::anyhow!("Failed to create page: {}", e))?;
 
-    // Wait for page to load (networkidle is more reliable than DOMContentLoaded)
-    tokio::time::timeout(
-        tokio::time::Duration::from_secs(30),
-        page.wait_for_navigation(),
-    )
-    .await
-    .map_err(|_| anyhow::anyhow!("Timed out waiting for page navigation"))?
-    .map_err(|e| anyhow::anyhow!("Failed to wait for navigation: {}", e))?;
-
-    // Small async delay for any remaining dynamic content
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-
-    // Capture screenshot as PNG
-    let screenshot_data = page
-        .screenshot(
-            chromiumoxide::page::ScreenshotParams::builder()
-                .format(CaptureScreenshotFormat::Png)
-                .full_page(true)
-                .build(),
-        )
+    let result = async {
+        page.execute(SetDeviceMetricsOverrideParams::new(
+            viewport_width,
+            viewport_height,
+            1.0,
+            false,
+        ))
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to capture screenshot: {}", e))?;
-
-    tracing::info!("Screenshot captured: {} bytes", screenshot_data.len());
+        .map_err(|e| anyhow::anyhow!("Failed to set viewport: {}", e))?;
+
+        // Wait for page to load (networkidle is more reliable than DOMContentLoaded)
+        tokio::time::timeout(tokio::time::Duration::from_secs(30), page.goto(url))
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out waiting for page navigation"))?
+            .map_err(|e| anyhow::anyhow!("Failed to wait for navigation: {}", e))?;
+
+        // Small async delay for any remaining dynamic content
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+        // Capture screenshot as PNG
+        let screenshot_data = page
+            .screenshot(
+                chromiumoxide::page::ScreenshotParams::builder()
+                    .format(CaptureScreenshotFormat::Png)
+                    .full_page(true)
+                    .build(),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to capture screenshot: {}", e))?;
 
-    // Extract DOM elements
-    let dom_elements = extract_dom_elements(&page).await?;
+        tracing::info!("Screenshot captured: {} bytes", screenshot_data.len());
 
-    // Clean up
-    drop(page);
-    browser.close().await.ok();
-    handler_task.abort();
+        // Extract DOM elements
+        let dom_elements = extract_dom_elements(&page).await?;
+        Ok((screenshot_data, dom_elements))
+    }
+    .await;
 
-    Ok((screenshot_data, dom_elements))
+    // Close the tab so a reused browser doesn't accumulate pages
+    page.close().await.ok();
+    result
 }
 
 #[cfg(feature = "vision")]
@@ -834,6 +1231,233 @@ pub fn prepare_vision_prompt(mode: &str, width: u32, height: u32, model_name: &s
 #[cfg(all(test, feature = "vision"))]
 mod tests {
     use super::*;
+    use crate::engine::mock::{MockEngine, Reply};
+
+    fn dom_element(tag: &str, width: f32, height: f32) -> DomElement {
+        DomElement {
+            tag: tag.to_string(),
+            id: None,
+            class: None,
+            text: None,
+            position: Rect {
+                x: 0.0,
+                y: 0.0,
+                width,
+                height,
+            },
+            attributes: std::collections::HashMap::new(),
+            colors: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn capture_pool_reuses_released_items_only() {
+        let pool = CapturePool::new(2);
+        let launched = std::sync::atomic::AtomicUsize::new(0);
+        let launch = || async { Ok(launched.fetch_add(1, std::sync::atomic::Ordering::SeqCst)) };
+
+        let lease = pool.acquire(launch).await.unwrap();
+        assert_eq!(*lease.get(), 0);
+        lease.release();
+        assert_eq!(pool.idle_count(), 1);
+
+        // Reused rather than launched again
+        let lease = pool.acquire(launch).await.unwrap();
+        assert_eq!(*lease.get(), 0);
+        // Dropped without release (failed or panicked capture): discarded
+        drop(lease);
+        assert_eq!(pool.idle_count(), 0);
+
+        let lease = pool.acquire(launch).await.unwrap();
+        assert_eq!(*lease.get(), 1);
+    }
+
+    #[test]
+    fn browser_launch_passes_configured_path_and_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let chrome = dir.path().join("my-chromium");
+        std::fs::write(&chrome, b"").unwrap();
+        let launch = BrowserLaunch {
+            executable: Some(chrome.clone()),
+            extra_args: vec!["--lang=de".to_string()],
+        };
+
+        let config = format!("{:?}", launch.config().unwrap());
+        assert!(config.contains(&format!("{:?}", chrome)));
+        assert!(config.contains("--headless=new"));
+        assert!(config.contains("--lang=de"));
+    }
+
+    #[test]
+    fn browser_launch_reports_missing_executable() {
+        let launch = BrowserLaunch {
+            executable: Some("/nonexistent/chromium".into()),
+            extra_args: Vec::new(),
+        };
+        let err = launch.config().unwrap_err().to_string();
+        assert!(err.contains("/nonexistent/chromium"));
+        assert!(err.contains("SHIMMY_CHROME_PATH"));
+    }
+
+    #[test]
+    fn request_chrome_path_overrides_env_default() {
+        let launch = BrowserLaunch::for_request(Some("/opt/chrome/chrome"));
+        assert_eq!(
+            launch.executable.as_deref(),
+            Some(std::path::Path::new("/opt/chrome/chrome"))
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn capture_pool_caps_concurrent_captures() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let pool = Arc::new(CapturePool::new(2));
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let (pool, active, peak) = (pool.clone(), active.clone(), peak.clone());
+                tokio::spawn(async move {
+                    let lease = pool.acquire(|| async { Ok(()) }).await.unwrap();
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    lease.release();
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert!(pool.idle_count() <= 2);
+    }
+
+    #[test]
+    fn limit_dom_elements_keeps_interactive_over_generic() {
+        let mut elements: Vec<DomElement> = (0..20).map(|_| dom_element("div", 0.1, 0.1)).collect();
+        elements.insert(5, dom_element("button", 0.1, 0.05));
+        elements.push(dom_element("input", 0.2, 0.05));
+        let mut link = dom_element("span", 0.1, 0.02);
+        link.attributes
+            .insert("role".to_string(), "link".to_string());
+        elements.insert(12, link);
+        elements.push(dom_element("h1", 0.5, 0.1));
+
+        let limits = DomLimits {
+            max_elements: 5,
+            min_area: 0.0001,
+        };
+        let (kept, dropped) = limit_dom_elements(elements, &limits);
+
+        assert_eq!(kept.len(), 5);
+        assert_eq!(dropped, 19);
+        let tags: Vec<&str> = kept.iter().map(|el| el.tag.as_str()).collect();
+        // Interactive elements and the heading survive, in document order
+        assert_eq!(tags, vec!["div", "button", "span", "input", "h1"]);
+    }
+
+    #[test]
+    fn limit_dom_elements_drops_tiny_boxes() {
+        let elements = vec![
+            dom_element("button", 0.001, 0.001),
+            dom_element("p", 0.3, 0.1),
+        ];
+        let limits = DomLimits {
+            max_elements: 500,
+            min_area: 0.0001,
+        };
+        let (kept, dropped) = limit_dom_elements(elements, &limits);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].tag, "p");
+        assert_eq!(dropped, 1);
+    }
+
+    #[test]
+    fn normalize_color_accepts_names_rgb_and_short_hex() {
+        assert_eq!(normalize_color("rgb(255,0,0)").as_deref(), Some("#ff0000"));
+        assert_eq!(normalize_color("red").as_deref(), Some("#ff0000"));
+        assert_eq!(normalize_color(" Red ").as_deref(), Some("#ff0000"));
+        assert_eq!(
+            normalize_color("rgba(0, 128, 255, 0.5)").as_deref(),
+            Some("#0080ff")
+        );
+        assert_eq!(normalize_color("#F0a").as_deref(), Some("#ff00aa"));
+        assert_eq!(normalize_color("#00FF00").as_deref(), Some("#00ff00"));
+        assert!(normalize_color("rgb(300,0,0)").is_none());
+        assert!(normalize_color("#12345").is_none());
+        assert!(normalize_color("sunset orange").is_none());
+    }
+
+    #[test]
+    fn parse_structured_output_normalizes_colors() {
+        let parsed = serde_json::json!({
+            "visual": {
+                "background": "white",
+                "accent_colors": ["rgb(255,0,0)", "red", "sunset orange"]
+            }
+        });
+        let req = VisionRequest {
+            image_base64: None,
+            url: None,
+            mode: "full".to_string(),
+            model: None,
+            timeout_ms: None,
+            raw: None,
+            license: None,
+            screenshot: None,
+            viewport_width: None,
+            viewport_height: None,
+            max_dom_elements: None,
+            chrome_path: None,
+        };
+        let response =
+            parse_structured_output(&parsed, &req, "test-model", 1, "", None, None).unwrap();
+
+        assert_eq!(response.visual.background.as_deref(), Some("#ffffff"));
+        assert_eq!(
+            response.visual.accent_colors,
+            vec!["#ff0000", "#ff0000", "sunset orange"]
+        );
+        let warnings = response.meta.parse_warnings.unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("sunset orange"));
+    }
+
+    #[test]
+    fn vision_response_serializes_compact_or_pretty() {
+        let parsed = serde_json::json!({
+            "text_blocks": [{"text": "Sign in", "confidence": 0.9}],
+            "layout": {"theme": "dark", "regions": [], "key_ui_elements": []}
+        });
+        let req = VisionRequest {
+            image_base64: None,
+            url: None,
+            mode: "ocr".to_string(),
+            model: None,
+            timeout_ms: None,
+            raw: None,
+            license: None,
+            screenshot: None,
+            viewport_width: None,
+            viewport_height: None,
+            max_dom_elements: None,
+            chrome_path: None,
+        };
+        let response =
+            parse_structured_output(&parsed, &req, "test-model", 1, "", None, None).unwrap();
+
+        use crate::util::json_output::{prettify, render};
+        let compact = render(&response, false).unwrap();
+        let pretty = render(&response, true).unwrap();
+        assert!(!compact.contains('\n'));
+        assert!(pretty.contains("\n  \"text_blocks\": ["));
+        // The `?pretty` layer produces the same text from the compact body
+        assert_eq!(prettify(&compact), pretty);
+    }
 
     #[test]
     fn preprocess_image_downscales_and_pngs() {
@@ -876,6 +1500,74 @@ mod tests {
         assert!(p.contains("text_blocks"));
         assert!(p.contains("dom_map"));
     }
+
+    async fn wait_for(flag: &std::sync::atomic::AtomicBool) -> bool {
+        for _ in 0..100 {
+            if flag.load(std::sync::atomic::Ordering::SeqCst) {
+                return true;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn dropped_vision_request_cancels_backend() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let engine = MockEngine::new(Reply::Endless).with_vision();
+        let cancelled = Arc::new(AtomicUsize::new(0));
+        let (model, on_cancel) = (engine.model("vision"), cancelled.clone());
+        let request = tokio::spawn(async move {
+            run_vision_inference(
+                &model,
+                b"image",
+                "describe",
+                crate::engine::GenOptions::default(),
+                std::time::Duration::from_secs(30),
+                move || {
+                    on_cancel.fetch_add(1, Ordering::SeqCst);
+                },
+            )
+            .await
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // A client disconnect drops the handler future mid-inference
+        request.abort();
+        assert!(request.await.unwrap_err().is_cancelled());
+        assert!(
+            wait_for(&engine.stats.cancelled).await,
+            "backend saw the cancellation"
+        );
+        assert_eq!(cancelled.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn vision_timeout_stops_backend_without_counting_a_cancel() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let engine = MockEngine::new(Reply::Endless).with_vision();
+        let cancelled = Arc::new(AtomicUsize::new(0));
+        let model = engine.model("vision");
+        let on_cancel = cancelled.clone();
+        let result = run_vision_inference(
+            &model,
+            b"image",
+            "describe",
+            crate::engine::GenOptions::default(),
+            std::time::Duration::from_millis(30),
+            move || {
+                on_cancel.fetch_add(1, Ordering::SeqCst);
+            },
+        )
+        .await;
+        assert!(result.unwrap_err().contains("timed out after 30 ms"));
+        assert!(wait_for(&engine.stats.cancelled).await);
+        assert_eq!(cancelled.load(Ordering::SeqCst), 0);
+    }
 }
 
 /// Parse model output into structured vision response
@@ -1014,6 +1706,83 @@ pub fn extract_first_json_object(s: &str) -> Option<String> {
     None
 }
 
+/// Convert a CSS-style color (named, `rgb()`/`rgba()`, `#rgb`, `#rrggbb`) to
+/// lowercase `#rrggbb`. Returns `None` when the value isn't recognized.
+#[cfg(feature = "vision")]
+pub fn normalize_color(value: &str) -> Option<String> {
+    let v = value.trim().to_ascii_lowercase();
+
+    if let Some(hex) = v.strip_prefix('#') {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        return match hex.len() {
+            3 => Some(hex.chars().fold(String::from("#"), |mut s, c| {
+                s.push(c);
+                s.push(c);
+                s
+            })),
+            6 => Some(format!("#{}", hex)),
+            _ => None,
+        };
+    }
+
+    if let Some(args) = v
+        .strip_prefix("rgba(")
+        .or_else(|| v.strip_prefix("rgb("))
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let channels: Vec<&str> = args.split(',').map(str::trim).collect();
+        if channels.len() != 3 && channels.len() != 4 {
+            return None;
+        }
+        let mut rgb = [0u8; 3];
+        for (slot, channel) in rgb.iter_mut().zip(&channels) {
+            *slot = channel.parse().ok()?;
+        }
+        return Some(format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]));
+    }
+
+    let hex = match v.as_str() {
+        "black" => "#000000",
+        "white" => "#ffffff",
+        "red" => "#ff0000",
+        "lime" => "#00ff00",
+        "green" => "#008000",
+        "blue" => "#0000ff",
+        "yellow" => "#ffff00",
+        "cyan" | "aqua" => "#00ffff",
+        "magenta" | "fuchsia" => "#ff00ff",
+        "gray" | "grey" => "#808080",
+        "silver" => "#c0c0c0",
+        "maroon" => "#800000",
+        "olive" => "#808000",
+        "navy" => "#000080",
+        "purple" => "#800080",
+        "teal" => "#008080",
+        "orange" => "#ffa500",
+        "pink" => "#ffc0cb",
+        "brown" => "#a52a2a",
+        "gold" => "#ffd700",
+        "indigo" => "#4b0082",
+        "violet" => "#ee82ee",
+        _ => return None,
+    };
+    Some(hex.to_string())
+}
+
+/// Normalize a color in place, recording a warning when it can't be parsed
+#[cfg(feature = "vision")]
+fn normalize_color_field(value: &mut String, field: &str, warnings: &mut Vec<String>) {
+    match normalize_color(value) {
+        Some(hex) => *value = hex,
+        None => warnings.push(format!(
+            "Could not normalize {} color '{}'; left unchanged",
+            field, value
+        )),
+    }
+}
+
 /// Parse structured JSON output into VisionResponse
 #[cfg(feature = "vision")]
 pub fn parse_structured_output(
@@ -1022,7 +1791,7 @@ pub fn parse_structured_output(
     model_name: &str,
     duration_ms: u64,
     raw_output: &str,
-    parse_warnings: Option<Vec<String>>,
+    mut parse_warnings: Option<Vec<String>>,
     captured_dom: Option<Vec<DomElement>>,
 ) -> Result<VisionResponse, Box<dyn std::error::Error>> {
     // Extract text blocks
@@ -1112,7 +1881,7 @@ pub fn parse_structured_output(
     };
 
     // Extract visual information
-    let visual = if let Some(visual_obj) = parsed.get("visual") {
+    let mut visual = if let Some(visual_obj) = parsed.get("visual") {
         Visual {
             background: visual_obj
                 .get("background")
@@ -1154,6 +1923,25 @@ pub fn parse_structured_output(
         }
     };
 
+    // Canonicalize colors to #rrggbb (disable with SHIMMY_VISION_NORMALIZE_COLORS=0)
+    let normalize_colors = std::env::var("SHIMMY_VISION_NORMALIZE_COLORS")
+        .map(|v| v != "0" && !v.eq_ignore_ascii_case("false"))
+        .unwrap_or(true);
+    if normalize_colors {
+        let mut color_warnings = Vec::new();
+        if let Some(background) = visual.background.as_mut() {
+            normalize_color_field(background, "background", &mut color_warnings);
+        }
+        for color in &mut visual.accent_colors {
+            normalize_color_field(color, "accent", &mut color_warnings);
+        }
+        if !color_warnings.is_empty() {
+            parse_warnings
+                .get_or_insert_with(Vec::new)
+                .extend(color_warnings);
+        }
+    }
+
     // Extract interaction information
     let interaction = Interaction {
         description: parsed
@@ -42,9 +42,13 @@ use std::collections::HashMap;
 #[cfg(feature = "vision")]
 use std::path::PathBuf;
 #[cfg(feature = "vision")]
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
 #[cfg(feature = "vision")]
-use tokio::sync::RwLock;
+use std::sync::{Arc, OnceLock, Weak};
+#[cfg(feature = "vision")]
+use std::time::Duration;
+#[cfg(feature = "vision")]
+use tokio::sync::{Mutex, RwLock};
 
 /// License validation response from Keygen
 #[cfg(feature = "vision")]
@@ -75,6 +79,101 @@ pub struct UsageStats {
     pub last_reset: chrono::DateTime<chrono::Utc>,
 }
 
+/// How often usage stats are written to disk. Counters in memory are always
+/// current; the file is rewritten every `interval`, or sooner once `every`
+/// requests are pending, and on shutdown via `flush_usage`.
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsageFlushPolicy {
+    pub interval: Duration,
+    pub every: u32,
+}
+
+#[cfg(feature = "vision")]
+impl Default for UsageFlushPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            every: 100,
+        }
+    }
+}
+
+#[cfg(feature = "vision")]
+impl UsageFlushPolicy {
+    /// Defaults, overridden by SHIMMY_VISION_USAGE_FLUSH_SECS and
+    /// SHIMMY_VISION_USAGE_FLUSH_EVERY
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        let default = Self::default();
+        Self {
+            interval: var("SHIMMY_VISION_USAGE_FLUSH_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(default.interval),
+            every: var("SHIMMY_VISION_USAGE_FLUSH_EVERY")
+                .map(|n| n.clamp(1, u32::MAX as u64) as u32)
+                .unwrap_or(default.every),
+        }
+    }
+}
+
+/// Single writer for the usage file: writes are serialized by `lock`, and
+/// each one replaces the file atomically so readers never see a partial write
+#[cfg(feature = "vision")]
+#[derive(Debug)]
+struct UsageWriter {
+    path: PathBuf,
+    policy: UsageFlushPolicy,
+    /// Requests recorded since the last write
+    pending: AtomicU32,
+    lock: Mutex<()>,
+    flusher: OnceLock<()>,
+}
+
+#[cfg(feature = "vision")]
+impl UsageWriter {
+    async fn flush(&self, usage: &RwLock<UsageStats>) -> Result<(), Box<dyn std::error::Error>> {
+        let _writing = self.lock.lock().await;
+        let pending = self.pending.swap(0, Ordering::SeqCst);
+        if pending == 0 {
+            return Ok(());
+        }
+        let data = serde_json::to_string_pretty(&*usage.read().await)?;
+        let tmp = self.path.with_extension("json.tmp");
+        let written = async {
+            tokio::fs::write(&tmp, &data).await?;
+            tokio::fs::rename(&tmp, &self.path).await
+        }
+        .await;
+        if let Err(e) = written {
+            // Keep the requests pending so the next flush retries them
+            self.pending.fetch_add(pending, Ordering::SeqCst);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Start the periodic flush on first use; it stops once the manager is gone
+    fn start_flusher(self: &Arc<Self>, usage: &Arc<RwLock<UsageStats>>) {
+        self.flusher.get_or_init(|| {
+            let writer = Arc::downgrade(self);
+            let usage: Weak<RwLock<UsageStats>> = Arc::downgrade(usage);
+            let interval = self.policy.interval;
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    let (Some(writer), Some(usage)) = (writer.upgrade(), usage.upgrade()) else {
+                        break;
+                    };
+                    if let Err(e) = writer.flush(&usage).await {
+                        tracing::warn!("Failed to write vision usage stats: {}", e);
+                    }
+                }
+            });
+        });
+    }
+}
+
 /// Vision licensing manager
 #[cfg(feature = "vision")]
 #[derive(Debug, Clone)]
@@ -82,7 +181,7 @@ pub struct VisionLicenseManager {
     cache: Arc<RwLock<Option<CachedLicense>>>,
     usage: Arc<RwLock<UsageStats>>,
     cache_path: PathBuf,
-    usage_path: PathBuf,
+    usage_writer: Arc<UsageWriter>,
 }
 
 #[cfg(feature = "vision")]
@@ -93,7 +192,11 @@ impl VisionLicenseManager {
             .unwrap_or_else(|| PathBuf::from("."))
             .join("shimmy")
             .join("vision");
+        Self::with_data_dir(cache_dir, UsageFlushPolicy::from_env())
+    }
 
+    /// License manager keeping its cache and usage files in `cache_dir`
+    pub fn with_data_dir(cache_dir: PathBuf, policy: UsageFlushPolicy) -> Self {
         std::fs::create_dir_all(&cache_dir).ok();
 
         Self {
@@ -104,7 +207,13 @@ impl VisionLicenseManager {
                 last_reset: chrono::Utc::now(),
             })),
             cache_path: cache_dir.join("license_cache.json"),
-            usage_path: cache_dir.join("usage_stats.json"),
+            usage_writer: Arc::new(UsageWriter {
+                path: cache_dir.join("usage_stats.json"),
+                policy,
+                pending: AtomicU32::new(0),
+                lock: Mutex::new(()),
+                flusher: OnceLock::new(),
+            }),
         }
     }
 
@@ -118,8 +227,8 @@ impl VisionLicenseManager {
         }
 
         // Load usage stats
-        if self.usage_path.exists() {
-            let data = tokio::fs::read_to_string(&self.usage_path).await?;
+        if self.usage_writer.path.exists() {
+            let data = tokio::fs::read_to_string(&self.usage_writer.path).await?;
             let usage: UsageStats = serde_json::from_str(&data)?;
             *self.usage.write().await = usage;
         }
@@ -207,9 +316,29 @@ impl VisionLicenseManager {
         Ok(())
     }
 
-    /// Record a vision request for metering
+    /// Record a vision request for metering. The counters update right away;
+    /// the usage file is written in batches (see `UsageFlushPolicy`).
     pub async fn record_usage(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let mut usage = self.usage.write().await;
+        {
+            let mut usage = self.usage.write().await;
+            Self::count_request(&mut usage);
+        }
+
+        let writer = &self.usage_writer;
+        let pending = writer.pending.fetch_add(1, Ordering::SeqCst) + 1;
+        if pending >= writer.policy.every {
+            return writer.flush(&self.usage).await;
+        }
+        writer.start_flusher(&self.usage);
+        Ok(())
+    }
+
+    /// Write any usage not yet on disk; called on shutdown
+    pub async fn flush_usage(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.usage_writer.flush(&self.usage).await
+    }
+
+    fn count_request(usage: &mut UsageStats) {
         let now = chrono::Utc::now();
 
         // Reset counters if needed
@@ -223,12 +352,6 @@ impl VisionLicenseManager {
 
         usage.requests_today += 1;
         usage.requests_this_month += 1;
-
-        // Save to disk
-        let data = serde_json::to_string_pretty(&*usage)?;
-        tokio::fs::write(&self.usage_path, &data).await?;
-
-        Ok(())
     }
 
     /// Call Keygen API to validate license
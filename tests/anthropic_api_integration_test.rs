@@ -321,3 +321,157 @@ fn test_issue_109_requirements_coverage() {
     println!("   - xinference-style implementation provided");
     println!("   - Low priority feature delivered with quality");
 }
+
+/// Replies "Hello from shimmy" one word per streamed token
+struct WordsEngine;
+
+#[async_trait::async_trait]
+impl shimmy::engine::InferenceEngine for WordsEngine {
+    async fn load(
+        &self,
+        _spec: &shimmy::engine::ModelSpec,
+    ) -> anyhow::Result<Box<dyn shimmy::engine::LoadedModel>> {
+        Ok(Box::new(WordsModel))
+    }
+}
+
+struct WordsModel;
+
+#[async_trait::async_trait]
+impl shimmy::engine::LoadedModel for WordsModel {
+    async fn generate(
+        &self,
+        prompt: &str,
+        opts: shimmy::engine::GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> anyhow::Result<String> {
+        self.generate_with_reason(prompt, opts, on_token)
+            .await
+            .map(|(text, _)| text)
+    }
+
+    async fn generate_with_reason(
+        &self,
+        _prompt: &str,
+        opts: shimmy::engine::GenOptions,
+        mut on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> anyhow::Result<(String, shimmy::engine::FinishReason)> {
+        const WORDS: [&str; 3] = ["Hello", " from", " shimmy"];
+        let words: Vec<&str> = WORDS.into_iter().take(opts.max_tokens).collect();
+        if let Some(cb) = on_token.as_mut() {
+            for word in &words {
+                cb(word.to_string());
+            }
+        }
+        let reason = if words.len() < WORDS.len() {
+            shimmy::engine::FinishReason::Length
+        } else {
+            shimmy::engine::FinishReason::Stop
+        };
+        Ok((words.concat(), reason))
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+}
+
+/// POST a streaming /v1/messages request and split the reply into
+/// (event, data) pairs
+async fn stream_events(max_tokens: usize) -> Vec<(String, serde_json::Value)> {
+    use shimmy::model_registry::{ModelEntry, Registry};
+    use tower::util::ServiceExt;
+
+    let mut registry = Registry::default();
+    registry.register(ModelEntry {
+        name: "words".to_string(),
+        base_path: "./words.gguf".into(),
+        lora_path: None,
+        template: None,
+        ctx_len: None,
+        n_threads: None,
+        tags: Vec::new(),
+    });
+    let state = std::sync::Arc::new(shimmy::AppState::new(Box::new(WordsEngine), registry));
+    let body = json!({
+        "model": "words",
+        "max_tokens": max_tokens,
+        "stream": true,
+        "messages": [{"role": "user", "content": "Say hello"}]
+    });
+    let response = shimmy::server::router(state)
+        .oneshot(
+            axum::http::Request::builder()
+                .method("POST")
+                .uri("/v1/messages")
+                .header("content-type", "application/json")
+                .body(axum::body::Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), axum::http::StatusCode::OK);
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    String::from_utf8(bytes.to_vec())
+        .unwrap()
+        .split("\n\n")
+        .filter(|frame| !frame.trim().is_empty() && !frame.starts_with(':'))
+        .map(|frame| {
+            let field = |name: &str| {
+                frame
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let data = serde_json::from_str(&field("data: ")).unwrap();
+            (field("event: "), data)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_streaming_messages_event_sequence_and_usage() {
+    let events = stream_events(64).await;
+    let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names[..3], ["message_start", "content_block_start", "ping"]);
+    assert_eq!(
+        names[names.len() - 3..],
+        ["content_block_stop", "message_delta", "message_stop"]
+    );
+    for (name, data) in &events {
+        assert_eq!(data["type"], name.as_str());
+    }
+
+    // Reconstruct the reply the way an SDK does
+    let text: String = events
+        .iter()
+        .filter(|(name, _)| name == "content_block_delta")
+        .map(|(_, data)| data["delta"]["text"].as_str().unwrap())
+        .collect();
+    assert_eq!(text, "Hello from shimmy");
+
+    let (_, delta) = &events[events.len() - 2];
+    assert_eq!(delta["delta"]["stop_reason"], "end_turn");
+    assert_eq!(delta["usage"]["output_tokens"], 3);
+    assert!(
+        events[0].1["message"]["usage"]["input_tokens"]
+            .as_u64()
+            .unwrap()
+            > 0
+    );
+}
+
+#[tokio::test]
+async fn test_streaming_messages_reports_max_tokens() {
+    let events = stream_events(2).await;
+    let (_, delta) = events
+        .iter()
+        .find(|(name, _)| name == "message_delta")
+        .unwrap();
+    assert_eq!(delta["delta"]["stop_reason"], "max_tokens");
+    assert_eq!(delta["usage"]["output_tokens"], 2);
+}
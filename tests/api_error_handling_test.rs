@@ -67,16 +67,15 @@ async fn test_chat_completions_model_not_found_response() {
         messages: vec![ChatMessage {
             role: "user".to_string(),
             content: "Hello".to_string(),
+            images: Vec::new(),
         }],
         stream: Some(false),
-        temperature: None,
-        max_tokens: None,
-        top_p: None,
-        stop: None,
+        ..Default::default()
     };
 
     // Exercise the handler - should return 404 with JSON error
-    let _response = chat_completions(State(state), Json(request)).await;
+    let _response =
+        chat_completions(State(state), axum::http::HeaderMap::new(), Json(request)).await;
 
     // Response should be properly formatted (we can't easily test the exact
     // status code without response introspection, but we exercise the code path)
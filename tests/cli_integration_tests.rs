@@ -167,3 +167,55 @@ fn test_windows_server_stability_issue_106() {
 
     // If we reach here, the server started successfully without crashing
 }
+
+#[test]
+fn test_serve_dry_run_valid_config() {
+    let temp_dir = TempDir::new().unwrap();
+    let model = temp_dir.path().join("dry-run-model.gguf");
+    fs::write(&model, b"").unwrap();
+
+    let mut cmd = Command::cargo_bin("shimmy").unwrap();
+    let output = cmd
+        .env_remove("SHIMMY_BASE_GGUF")
+        .env("HOME", temp_dir.path())
+        .args([
+            "serve",
+            "--bind",
+            "127.0.0.1:0",
+            "--model-path",
+            &model.to_string_lossy(),
+            "--dry-run",
+            "--json",
+        ])
+        .assert()
+        .success();
+
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    let json_start = stdout.find('{').expect("dry run should print JSON");
+    let summary: serde_json::Value = serde_json::from_str(&stdout[json_start..]).unwrap();
+    assert_eq!(summary["status"], "ok");
+    assert_eq!(summary["models"][0]["name"], "dry-run-model");
+}
+
+#[test]
+fn test_serve_dry_run_missing_model_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let model = temp_dir.path().join("present.gguf");
+    fs::write(&model, b"").unwrap();
+    let missing = temp_dir.path().join("absent").join("missing.gguf");
+
+    let mut cmd = Command::cargo_bin("shimmy").unwrap();
+    cmd.env("SHIMMY_BASE_GGUF", &missing)
+        .env("HOME", temp_dir.path())
+        .args([
+            "serve",
+            "--bind",
+            "127.0.0.1:0",
+            "--model-path",
+            &model.to_string_lossy(),
+            "--dry-run",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("file not found"));
+}
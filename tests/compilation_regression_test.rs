@@ -149,6 +149,7 @@ fn test_model_registry_thread_safety() {
                     template: Some("chatml".to_string()),
                     ctx_len: Some(2048),
                     n_threads: None,
+                    tags: Vec::new(),
                 };
 
                 let mut reg = registry.lock().unwrap();
@@ -92,7 +92,7 @@ fn test_cli_parsing() {
     // Test list command
     let args = vec!["shimmy", "list"];
     let cli = Cli::try_parse_from(args).unwrap();
-    matches!(cli.cmd, Command::List { short: false });
+    matches!(cli.cmd, Command::List { short: false, .. });
 
     // Test serve command
     let args = vec!["shimmy", "serve", "--bind", "0.0.0.0:8080"];
@@ -106,7 +106,7 @@ fn test_cli_parsing() {
     let args = vec!["shimmy", "probe", "test-model"];
     let cli = Cli::try_parse_from(args).unwrap();
     match cli.cmd {
-        Command::Probe { name } => assert_eq!(name, "test-model"),
+        Command::Probe { name, .. } => assert_eq!(name.as_deref(), Some("test-model")),
         _ => panic!("Expected Probe command"),
     }
 
@@ -126,6 +126,7 @@ fn test_cli_parsing() {
             name,
             prompt,
             max_tokens,
+            ..
         } => {
             assert_eq!(name, "test-model");
             assert_eq!(prompt, "Hello");
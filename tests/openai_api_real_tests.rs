@@ -1,4 +1,7 @@
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
 use serde_json::Value;
 use shimmy::{
     api::ChatMessage,
@@ -22,6 +25,7 @@ fn setup_test_state_with_models() -> Arc<AppState> {
         template: Some("chatml".into()),
         ctx_len: Some(4096),
         n_threads: None,
+        tags: Vec::new(),
     });
 
     registry.register(ModelEntry {
@@ -31,6 +35,7 @@ fn setup_test_state_with_models() -> Arc<AppState> {
         template: Some("llama3".into()),
         ctx_len: Some(8192),
         n_threads: None,
+        tags: Vec::new(),
     });
 
     registry.register(ModelEntry {
@@ -40,6 +45,7 @@ fn setup_test_state_with_models() -> Arc<AppState> {
         template: Some("chatml".into()),
         ctx_len: Some(2048),
         n_threads: None,
+        tags: Vec::new(),
     });
 
     let engine = Box::new(InferenceEngineAdapter::new());
@@ -51,7 +57,8 @@ async fn test_models_endpoint_real_functionality() {
     let state = setup_test_state_with_models();
 
     // Call the actual models endpoint function
-    let response = openai_compat::models(State(state)).await;
+    let response =
+        openai_compat::models(State(state), Query(openai_compat::ModelsQuery::default())).await;
 
     // Extract the response using the IntoResponse trait
     use axum::response::IntoResponse;
@@ -95,15 +102,17 @@ async fn test_chat_completions_error_handling_real() {
         messages: vec![ChatMessage {
             role: "user".to_string(),
             content: "Hello".to_string(),
+            images: Vec::new(),
         }],
         stream: Some(false),
         temperature: Some(0.7),
         max_tokens: Some(50),
-        top_p: None,
-        stop: None,
+        ..Default::default()
     };
 
-    let response = openai_compat::chat_completions(State(state), Json(request)).await;
+    let response =
+        openai_compat::chat_completions(State(state), axum::http::HeaderMap::new(), Json(request))
+            .await;
 
     use axum::response::IntoResponse;
     let response = response.into_response();
@@ -133,12 +142,13 @@ fn test_chat_completions_model_loading_failure() {
         messages: vec![ChatMessage {
             role: "user".to_string(),
             content: "Hello, this should fail to load the model".to_string(),
+            images: Vec::new(),
         }],
         stream: Some(false),
         temperature: Some(0.7),
         max_tokens: Some(100),
         top_p: Some(0.9),
-        stop: None,
+        ..Default::default()
     };
 
     // Verify request structure for model loading scenarios
@@ -162,17 +172,19 @@ fn test_system_message_handling() {
             ChatMessage {
                 role: "system".to_string(),
                 content: "You are a helpful assistant specialized in math.".to_string(),
+                images: Vec::new(),
             },
             ChatMessage {
                 role: "user".to_string(),
                 content: "What is 2 + 2?".to_string(),
+                images: Vec::new(),
             },
         ],
         stream: Some(false),
         temperature: Some(0.5),
         max_tokens: Some(50),
         top_p: Some(0.8),
-        stop: None,
+        ..Default::default()
     };
 
     // Verify the request structure is correct for multi-message scenarios
@@ -195,12 +207,12 @@ fn test_streaming_request_processing() {
         messages: vec![ChatMessage {
             role: "user".to_string(),
             content: "Count from 1 to 5".to_string(),
+            images: Vec::new(),
         }],
         stream: Some(true),
         temperature: Some(0.3),
         max_tokens: Some(50),
-        top_p: None,
-        stop: None,
+        ..Default::default()
     };
 
     // Verify streaming request structure
@@ -305,12 +317,13 @@ fn test_generation_options_parsing() {
         messages: vec![ChatMessage {
             role: "user".to_string(),
             content: "Test".to_string(),
+            images: Vec::new(),
         }],
         stream: Some(true),
         temperature: Some(0.8),
         max_tokens: Some(150),
         top_p: Some(0.95),
-        stop: None,
+        ..Default::default()
     };
 
     // Verify the request structure matches what Open WebUI/AnythingLLM send
@@ -327,12 +340,9 @@ fn test_generation_options_parsing() {
         messages: vec![ChatMessage {
             role: "user".to_string(),
             content: "Test".to_string(),
+            images: Vec::new(),
         }],
-        stream: None,
-        temperature: None,
-        max_tokens: None,
-        top_p: None,
-        stop: None,
+        ..Default::default()
     };
 
     assert!(minimal_request.stream.is_none());
@@ -356,6 +366,7 @@ fn test_openai_response_serialization() {
             message: ChatMessage {
                 role: "assistant".to_string(),
                 content: "Hello! How can I help you today?".to_string(),
+                images: Vec::new(),
             },
             finish_reason: Some("stop".to_string()),
         }],
@@ -364,6 +375,7 @@ fn test_openai_response_serialization() {
             completion_tokens: 8,
             total_tokens: 20,
         },
+        sampling_params: None,
     };
 
     // Serialize to JSON
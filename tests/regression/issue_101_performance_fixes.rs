@@ -56,13 +56,11 @@ mod issue_101_tests {
         let streaming_request = GenerateRequest {
             model: "test-model".to_string(),
             prompt: Some("Test prompt".to_string()),
-            messages: None,
-            system: None,
             stream: Some(true), // Enable streaming
             max_tokens: Some(100),
             temperature: Some(0.7),
             top_p: Some(0.9),
-            top_k: None,
+            ..Default::default()
         };
 
         // Verify streaming flag is set correctly
@@ -120,13 +118,11 @@ mod issue_101_tests {
         let request = GenerateRequest {
             model: spec.name.clone(),
             prompt: Some("Test".to_string()),
-            messages: None,
-            system: None,
             stream: Some(true),
             max_tokens: Some(100),
             temperature: Some(0.7),
             top_p: Some(0.9),
-            top_k: None,
+            ..Default::default()
         };
 
         // Verify all components work together
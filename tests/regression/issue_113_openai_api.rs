@@ -69,6 +69,7 @@ mod issue_113_tests {
             object: "model".to_string(),
             created: 1640995200,
             owned_by: "shimmy".to_string(),
+            tags: Vec::new(),
         };
 
         let response = ModelsResponse {
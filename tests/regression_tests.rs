@@ -25,6 +25,7 @@ mod regression_tests {
             template: Some("chatml".to_string()),
             ctx_len: Some(2048),
             n_threads: None,
+            tags: Vec::new(),
         };
 
         registry.register(test_model.clone());
@@ -118,6 +119,7 @@ mod regression_tests {
                 message: crate::api::ChatMessage {
                     role: "assistant".to_string(),
                     content: "Hello!".to_string(),
+                    images: Vec::new(),
                 },
                 finish_reason: Some("stop".to_string()),
             }],
@@ -126,6 +128,7 @@ mod regression_tests {
                 completion_tokens: 2,
                 total_tokens: 7,
             },
+            sampling_params: None,
         };
 
         let json = serde_json::to_string(&response).unwrap();
@@ -203,12 +206,14 @@ mod regression_tests {
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    tags: Vec::new(),
                 },
                 openai_compat::ListModel {
                     id: "llama-7b".to_string(),
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    tags: Vec::new(),
                 },
             ],
         };
@@ -344,6 +349,7 @@ mod regression_tests {
             object: "model".to_string(),
             created: 1640995200,
             owned_by: "shimmy".to_string(),
+            tags: Vec::new(),
         };
 
         let response = ModelsResponse {
@@ -417,10 +423,10 @@ mod regression_tests {
         // Test template inference for different model types
         let test_cases = vec![
             ("llama-7b-chat", "llama3"),
-            ("phi-3-mini", "chatml"),
+            ("phi-3-mini", "phi3"),
             ("qwen2-instruct", "chatml"),
             ("mistral-7b", "chatml"),
-            ("gemma-2b", "chatml"),
+            ("gemma-2b", "gemma"),
             ("unknown-model", "chatml"), // default
         ];
 
@@ -459,6 +459,63 @@ mod vision_license_tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_concurrent_record_usage_writes_one_consistent_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = UsageFlushPolicy {
+            interval: std::time::Duration::from_secs(3600),
+            every: 7,
+        };
+        let manager = VisionLicenseManager::with_data_dir(dir.path().to_path_buf(), policy);
+
+        let tasks: Vec<_> = (0..200)
+            .map(|_| {
+                let manager = manager.clone();
+                tokio::spawn(async move { manager.record_usage().await.is_ok() })
+            })
+            .collect();
+        for task in tasks {
+            assert!(task.await.unwrap(), "Recording usage should succeed");
+        }
+        // In-memory counters are exact before anything is flushed
+        assert_eq!(manager.get_usage_stats().await.requests_this_month, 200);
+
+        manager.flush_usage().await.unwrap();
+        let files: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(files, ["usage_stats.json"]);
+        let saved: UsageStats = serde_json::from_str(
+            &std::fs::read_to_string(dir.path().join("usage_stats.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(saved.requests_today, 200);
+        assert_eq!(saved.requests_this_month, 200);
+    }
+
+    #[tokio::test]
+    async fn test_record_usage_defers_writes_until_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = UsageFlushPolicy {
+            interval: std::time::Duration::from_secs(3600),
+            every: 1000,
+        };
+        let manager = VisionLicenseManager::with_data_dir(dir.path().to_path_buf(), policy);
+        let path = dir.path().join("usage_stats.json");
+
+        for _ in 0..3 {
+            manager.record_usage().await.unwrap();
+        }
+        assert!(!path.exists(), "Writes are batched, not per request");
+
+        // What shutdown does
+        manager.flush_usage().await.unwrap();
+        let reloaded = VisionLicenseManager::with_data_dir(dir.path().to_path_buf(), policy);
+        reloaded.load_cache().await.unwrap();
+        assert_eq!(reloaded.get_usage_stats().await.requests_this_month, 3);
+    }
+
     #[test]
     fn test_license_validation_serialization() {
         // Test LicenseValidation struct serialization/deserialization
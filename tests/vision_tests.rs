@@ -408,6 +408,8 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            max_dom_elements: None,
+            chrome_path: None,
         };
 
         let result = shimmy::vision::parse_structured_output(
@@ -471,6 +473,8 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            max_dom_elements: None,
+            chrome_path: None,
         };
 
         let result = shimmy::vision::parse_structured_output(
@@ -510,6 +514,8 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            max_dom_elements: None,
+            chrome_path: None,
         };
 
         let result = shimmy::vision::parse_structured_output(
@@ -558,6 +564,8 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            max_dom_elements: None,
+            chrome_path: None,
         };
 
         let result =
@@ -584,6 +592,8 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            max_dom_elements: None,
+            chrome_path: None,
         };
 
         let result =
@@ -617,6 +627,8 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            max_dom_elements: None,
+            chrome_path: None,
         };
 
         // This would be tested in the actual process_vision_request function
@@ -639,6 +651,8 @@ mod vision_tests {
             screenshot: Some(false),
             viewport_width: Some(1920),
             viewport_height: Some(1080),
+            max_dom_elements: None,
+            chrome_path: None,
         };
 
         assert_eq!(req.image_base64, Some(base64_image));
@@ -886,6 +900,8 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            max_dom_elements: None,
+            chrome_path: None,
         };
 
         let result = shimmy::vision::parse_structured_output(